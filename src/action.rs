@@ -0,0 +1,124 @@
+//! feh-style `--action` commands run on the current image.
+//!
+//! `%f` in the command is replaced by the path of the image, `%n` by its file name, `%d` by its
+//! directory and `%%` by a literal `%`. The image is reloaded once the command exits, unless the
+//! command is prefixed with `;`.
+
+use std::path::Path;
+use std::process::Command;
+
+use anyhow::{Context, Result};
+use winit::event_loop::EventLoopProxy;
+
+use crate::UserEvent;
+
+#[derive(Debug, Clone)]
+pub struct Action {
+    command: String,
+    reload: bool,
+}
+
+impl Action {
+    pub fn parse(spec: &str) -> Action {
+        match spec.strip_prefix(';') {
+            Some(command) => Action { command: command.to_string(), reload: false },
+            None => Action { command: spec.to_string(), reload: true },
+        }
+    }
+
+    /// The command as given, with its placeholders
+    pub fn command(&self) -> &str {
+        &self.command
+    }
+
+    /// The command line to run for the image at `path`
+    pub fn command_for(&self, path: &Path) -> String {
+        expand(&self.command, path)
+    }
+
+    /// Run the action on `path` in the background. When it exits, an
+    /// [`UserEvent::ActionFinished`] is sent through `proxy`.
+    pub fn spawn(&self, path: &Path, proxy: EventLoopProxy<UserEvent>) -> Result<()> {
+        let command = self.command_for(path);
+        let mut child = shell(&command)
+            .spawn()
+            .with_context(|| format!("failed to run `{command}`"))?;
+
+        let path = path.to_path_buf();
+        let reload = self.reload;
+        std::thread::spawn(move || {
+            match child.wait() {
+                Ok(status) if !status.success() => eprintln!("veh: `{command}` exited with {status}"),
+                Ok(_) => {}
+                Err(err) => eprintln!("veh: failed to wait for `{command}`: {err}"),
+            }
+            // The event loop may already be gone if veh was closed in the meantime
+            let _ = proxy.send_event(UserEvent::ActionFinished { path, reload });
+        });
+        Ok(())
+    }
+}
+
+/// `template` with the `%` placeholders replaced for the image at `path`
+pub fn expand(template: &str, path: &Path) -> String {
+    let mut command = String::new();
+    let mut chars = template.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            command.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('f') => command.push_str(&shell_quote(&path.to_string_lossy())),
+            Some('n') => command.push_str(&shell_quote(
+                &path.file_name().unwrap_or_default().to_string_lossy(),
+            )),
+            Some('d') => command.push_str(&shell_quote(
+                &path.parent().unwrap_or(Path::new(".")).to_string_lossy(),
+            )),
+            Some('%') => command.push('%'),
+            Some(other) => {
+                command.push('%');
+                command.push(other);
+            }
+            None => command.push('%'),
+        }
+    }
+    command
+}
+
+/// A process running `command` in the system shell
+#[cfg(unix)]
+pub fn shell(command: &str) -> Command {
+    let mut shell = Command::new("sh");
+    shell.arg("-c").arg(command);
+    shell
+}
+
+#[cfg(windows)]
+pub fn shell(command: &str) -> Command {
+    let mut shell = Command::new("cmd");
+    shell.arg("/C").arg(command);
+    shell
+}
+
+/// Quote `arg` so that the shell passes it on as a single argument
+#[cfg(unix)]
+fn shell_quote(arg: &str) -> String {
+    format!("'{}'", arg.replace('\'', r"'\''"))
+}
+
+#[cfg(windows)]
+fn shell_quote(arg: &str) -> String {
+    format!("\"{arg}\"")
+}
+
+/// The actions given on the command line: `--action` bound to Enter at index 0,
+/// followed by `--action1` to `--action9` bound to the number keys.
+pub fn actions_from_args(args: &crate::cli::Args) -> [Option<Action>; 10] {
+    [
+        &args.action, &args.action1, &args.action2, &args.action3, &args.action4,
+        &args.action5, &args.action6, &args.action7, &args.action8, &args.action9,
+    ]
+    .map(|spec| spec.as_deref().map(Action::parse))
+}
@@ -0,0 +1,77 @@
+//! Choosing the GPU adapter veh renders with.
+//!
+//! vello creates its devices on the adapter named by `WGPU_ADAPTER_NAME`, or else on the default
+//! adapter, so the choice is made by setting that variable before the first device is created.
+//! Likewise it takes the graphics APIs to use from `WGPU_BACKEND`.
+
+use std::sync::Arc;
+
+use anyhow::{bail, Context, Result};
+use wgpu::{Adapter, DeviceType, Instance};
+use winit::window::Window;
+
+/// The longest texture `--compat` lets images take, which GLES drivers of small GPUs support
+pub const COMPAT_MAX_TEXTURE_SIZE: u32 = 4096;
+
+/// Render through OpenGL (ES) rather than Vulkan, Metal or DX12 for `--compat`, on GPUs like those of the
+/// Raspberry Pi or older Intel iGPUs where those fail. Must be called before vello's render context is created.
+pub fn use_gl_backend() {
+    // An explicit choice of the user wins
+    if std::env::var_os("WGPU_BACKEND").is_none() {
+        std::env::set_var("WGPU_BACKEND", "gl");
+    }
+}
+
+/// Print the adapters available to veh, numbered as `--adapter` expects them
+pub fn list_adapters(instance: &Instance) {
+    for (i, adapter) in instance.enumerate_adapters(wgpu::Backends::all()).iter().enumerate() {
+        let info = adapter.get_info();
+        println!("{i}: {} ({:?}, {:?})", info.name, info.backend, info.device_type);
+    }
+}
+
+/// Choose the adapter that draws to `window`: the one `requested` by index or name if it can, or else
+/// the default one. If there is no default adapter for the window (e.g. on headless boxes or in VMs),
+/// fall back to a software adapter such as llvmpipe with a warning.
+pub fn choose_adapter(instance: &Instance, window: Arc<Window>, requested: Option<&str>) -> Result<()> {
+    let surface = instance.create_surface(window).context("failed to create a surface for the window")?;
+    let adapters = instance.enumerate_adapters(wgpu::Backends::all());
+    let compatible: Vec<&Adapter> = adapters.iter().filter(|adapter| adapter.is_surface_supported(&surface)).collect();
+    if compatible.is_empty() {
+        bail!("no GPU adapter can draw to the window");
+    }
+
+    if let Some(requested) = requested {
+        let chosen = match requested.parse::<usize>() {
+            Ok(index) => adapters.get(index).filter(|adapter| adapter.is_surface_supported(&surface)),
+            Err(_) => {
+                let requested = requested.to_lowercase();
+                compatible.iter().copied().find(|adapter| adapter.get_info().name.to_lowercase().contains(&requested))
+            }
+        };
+        match chosen {
+            Some(adapter) => {
+                std::env::set_var("WGPU_ADAPTER_NAME", adapter.get_info().name);
+                return Ok(());
+            }
+            None => eprintln!("veh: no adapter {requested} that can draw to the window, see --list-adapters"),
+        }
+    }
+
+    let default = pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+        compatible_surface: Some(&surface),
+        ..Default::default()
+    }));
+    if default.is_some() {
+        std::env::remove_var("WGPU_ADAPTER_NAME");
+        return Ok(());
+    }
+    let fallback = compatible
+        .iter()
+        .find(|adapter| adapter.get_info().device_type == DeviceType::Cpu)
+        .unwrap_or(&compatible[0]);
+    let name = fallback.get_info().name;
+    eprintln!("veh: no default GPU adapter, falling back to {name}");
+    std::env::set_var("WGPU_ADAPTER_NAME", name);
+    Ok(())
+}
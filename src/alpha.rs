@@ -0,0 +1,18 @@
+//! Highlighting semi-transparent pixels, such as fringes around icons and sprites that should be either opaque
+//! or clear, before they are exported.
+
+use std::sync::Arc;
+
+use vello::peniko::{Blob, Format, Image};
+
+/// A mask for `image` that is magenta where its pixels are neither fully transparent nor at least `threshold`
+/// opaque, and transparent everywhere else
+pub fn fringe_mask(image: &Image, threshold: u8) -> Image {
+    let data: Vec<u8> = image
+        .data
+        .data()
+        .chunks_exact(4)
+        .flat_map(|pixel| if (1..threshold).contains(&pixel[3]) { [0xff, 0x00, 0xff, 0xff] } else { [0; 4] })
+        .collect();
+    Image::new(Blob::new(Arc::new(data)), Format::Rgba8, image.width, image.height)
+}
@@ -0,0 +1,130 @@
+//! Animated GIF, PNG (APNG) and WebP images, decoded into all their frames up front so that they can be
+//! played and scrubbed through like a sequence.
+
+use std::io::Cursor;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Context, Result};
+use image::codecs::gif::GifDecoder;
+use image::codecs::png::PngDecoder;
+use image::codecs::webp::WebPDecoder;
+use image::{AnimationDecoder, Frames, RgbaImage};
+
+/// Shorter frame delays than this are taken for none given, which browsers show at 100 ms
+const MIN_DELAY: Duration = Duration::from_millis(20);
+const DEFAULT_DELAY: Duration = Duration::from_millis(100);
+
+/// Whether images of `format` may be animated
+pub fn may_be_animated(format: &str) -> bool {
+    matches!(format, "gif" | "png" | "webp")
+}
+
+/// The frames of the animated image of `format` in `data` and how long each is shown, or `None` if the image
+/// isn't animated
+pub fn decode(data: &[u8], format: &str) -> Result<Option<Vec<(RgbaImage, Duration)>>> {
+    let frames = match format {
+        "gif" => GifDecoder::new(Cursor::new(data)).context("decode image failed")?.into_frames(),
+        "png" => {
+            let decoder = PngDecoder::new(Cursor::new(data)).context("decode image failed")?;
+            if !decoder.is_apng().context("decode image failed")? {
+                return Ok(None);
+            }
+            decoder.apng().context("decode image failed")?.into_frames()
+        }
+        "webp" => {
+            let decoder = WebPDecoder::new(Cursor::new(data)).context("decode image failed")?;
+            if !decoder.has_animation() {
+                return Ok(None);
+            }
+            decoder.into_frames()
+        }
+        _ => return Ok(None),
+    };
+    let frames = collect(frames)?;
+    // A GIF of a single frame is a still image
+    Ok(Some(frames).filter(|frames| frames.len() > 1))
+}
+
+/// How many frames the image of `format` in `data` has, counted by walking its blocks without decoding them
+pub fn frame_count(data: &[u8], format: &str) -> Result<usize> {
+    match format {
+        "gif" => gif_frame_count(data),
+        // The acTL chunk of an APNG comes before its image data and holds the number of frames
+        "png" => {
+            let mut offset = 8;
+            while let Some(header) = data.get(offset..offset + 8) {
+                let length = u32::from_be_bytes(header[..4].try_into()?) as usize;
+                match &header[4..] {
+                    b"acTL" => {
+                        let frames = data.get(offset + 8..offset + 12).context("the acTL chunk is truncated")?;
+                        return Ok(u32::from_be_bytes(frames.try_into()?) as usize);
+                    }
+                    b"IDAT" => break,
+                    _ => offset += 12 + length,
+                }
+            }
+            Ok(1)
+        }
+        // Every frame of an animated WebP is an ANMF chunk of its RIFF container
+        "webp" => {
+            let mut offset = 12;
+            let mut frames = 0;
+            while let Some(header) = data.get(offset..offset + 8) {
+                let length = u32::from_le_bytes(header[4..].try_into()?) as usize;
+                if &header[..4] == b"ANMF" {
+                    frames += 1;
+                }
+                offset += 8 + length + length % 2;
+            }
+            Ok(frames.max(1))
+        }
+        _ => Ok(1),
+    }
+}
+
+fn gif_frame_count(data: &[u8]) -> Result<usize> {
+    let truncated = || anyhow!("the GIF is truncated");
+    // Skip the data sub-blocks starting at `offset`, which end with an empty one
+    let skip_sub_blocks = |mut offset: usize| -> Result<usize> {
+        loop {
+            let size = *data.get(offset).ok_or_else(truncated)? as usize;
+            offset += 1 + size;
+            if size == 0 {
+                return Ok(offset);
+            }
+        }
+    };
+    let color_table_size = |flags: u8| if flags & 0x80 != 0 { 3 << ((flags & 7) + 1) } else { 0 };
+
+    let flags = *data.get(10).ok_or_else(truncated)?;
+    let mut offset = 13 + color_table_size(flags);
+    let mut frames = 0;
+    loop {
+        match data.get(offset) {
+            // Extension
+            Some(0x21) => offset = skip_sub_blocks(offset + 2)?,
+            // Image descriptor, followed by a local color table, the LZW code size and the image data
+            Some(0x2c) => {
+                let flags = *data.get(offset + 9).ok_or_else(truncated)?;
+                offset = skip_sub_blocks(offset + 10 + color_table_size(flags) + 1)?;
+                frames += 1;
+            }
+            // Trailer, or the end of a GIF that lacks one
+            Some(0x3b) | None => return Ok(frames),
+            Some(byte) => bail!("unexpected block {byte:#04x} in the GIF"),
+        }
+    }
+}
+
+fn collect(frames: Frames) -> Result<Vec<(RgbaImage, Duration)>> {
+    frames
+        .map(|frame| {
+            let frame = frame.context("decode image failed")?;
+            let delay = Duration::from(frame.delay());
+            let delay = if delay < MIN_DELAY { DEFAULT_DELAY } else { delay };
+            Ok((frame.into_buffer(), delay))
+        })
+        .collect()
+}
+
+
@@ -0,0 +1,1862 @@
+//! The viewer application: an [`App`] owns all state of the viewer and goes through the phases of a frame.
+//!
+//! Events are first turned into what they ask for (see [`key_intent`] and [`scroll_zoom`]), which doesn't
+//! need a window, and then applied to the window they are meant for. `update` runs once the pending events
+//! are handled, and `render` draws a window when it asks for it.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Write;
+use std::num::NonZeroUsize;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, bail, Context, Result};
+use vello::kurbo::{Affine, Rect, Vec2};
+use vello::peniko::Color;
+use vello::util::RenderContext;
+use vello::{AaConfig, Renderer, RendererOptions, Scene};
+use winit::dpi::{LogicalSize, PhysicalPosition, PhysicalSize};
+use winit::event::*;
+use winit::event_loop::{ControlFlow, EventLoopProxy, EventLoopWindowTarget};
+use winit::keyboard::{KeyCode, ModifiersState, PhysicalKey};
+use winit::monitor::MonitorHandle;
+use winit::window::{Window, WindowBuilder, WindowId, WindowLevel};
+
+use crate::action::{actions_from_args, Action};
+use crate::cli::Args;
+use crate::clipboard::Clipboard;
+use crate::compare::Comparison;
+use crate::cue::Cues;
+use crate::faces::Faces;
+use crate::fileops::{Journal, Operation};
+use crate::hdr;
+use crate::loader::{self, ImageCache, VehImage};
+use crate::manga::Bookmarks;
+use crate::overlay::{OverlayContext, Overlays};
+use crate::palette::{self, Palette};
+use crate::playlist::Playlist;
+use crate::prefetch::Prefetcher;
+use crate::present::{self, Output};
+use crate::render_ahead::RenderAhead;
+use crate::scenes::SceneCache;
+use crate::sequence::{self, Playback};
+use crate::session::{Geometry, Session};
+use crate::slideshow::Pacing;
+use crate::stats::Stats;
+use crate::svg_tiles::{self, SvgTiles};
+use crate::watch::Watch;
+use crate::watchdog::Watchdog;
+use crate::{action_index, capture, config, crop, hotkey, ipc, jump, opacity, overlay, viewport, ActiveRenderState, RenderOptions, RenderState, Shared, UserEvent};
+
+/// The smallest rubber band in logical pixels that is zoomed in on, as smaller ones are likely slips of a click
+const MIN_SELECTION: f64 = 8.0;
+
+/// How soon and how close in logical pixels a second click has to follow the first to make a double-click
+const DOUBLE_CLICK_TIME: Duration = Duration::from_millis(400);
+const DOUBLE_CLICK_DISTANCE: f64 = 4.0;
+
+/// Where the app is in its lifecycle
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Phase {
+    /// Waiting for the first `Resumed` event to open the windows
+    Starting,
+    /// The windows are shown and handle input
+    Running,
+    /// The windows are kept, but their surfaces are gone until the app is resumed
+    Suspended,
+    /// The event loop has been asked to exit, so further events are ignored
+    Exiting,
+}
+
+/// What a key press asks the window it was pressed in to do
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum KeyIntent {
+    CancelCrop,
+    Close,
+    /// Move the image in `direction`, by a long way if `fast`
+    Pan { direction: Vec2, fast: bool },
+    Navigate { forward: bool },
+    /// Go to the first image, or the last one if `last`, or with a count to the image at that position
+    JumpToEnd { last: bool },
+    OpenPrompt,
+    /// Open the command palette, which runs any of these by name
+    OpenPalette,
+    /// Type a new caption for the image with `--caption-path`
+    EditCaption,
+    /// A digit of the count typed before a key; the digits 1 to 9 are only counts if they run no action
+    CountDigit(u32),
+    TurnPage { forward: bool },
+    ToggleStatusBar,
+    Unstretch,
+    CaptureFrame,
+    OpenWindow,
+    Trash,
+    MoveAway,
+    Undo,
+    FlushMarks,
+    ToggleZoomLock,
+    /// Resize the window to show the image at 100%, as far as the monitor allows
+    FitWindowToImage,
+    /// Enlarge small images to fill the window, or stop it
+    ToggleUpscale,
+    /// Show images at integer scales only, or at any scale
+    ToggleIntegerScaling,
+    /// Show the statistics of the pixels of the image, or hide them
+    ToggleStats,
+    /// Zoom to the next integer scale, or the next smaller one unless `zoom_in`
+    StepZoom { zoom_in: bool },
+    ToggleCompareMode,
+    /// Propose or apply a crop, or with `save` write the cropped image next to the original
+    Crop { save: bool },
+    /// Turn the image a quarter turn clockwise, or counter-clockwise
+    Rotate { clockwise: bool },
+    SaveRotation,
+    ToggleMark,
+    TogglePlayback,
+    ToggleSlideshow,
+    /// Copy the image shown to the clipboard, or with `path` its path
+    Copy { path: bool },
+    Paste,
+    NextFace,
+    ToggleAlphaCheck,
+    /// Preview the image as a lit normal map, then split into its channels, then as it is again
+    CycleTexturePreview,
+    /// Make the window this much more opaque, or more transparent if negative
+    ChangeOpacity(f64),
+    /// Show HDR images this many stops brighter, or darker if negative
+    Expose(f32),
+    AutoExpose,
+    RunAction(usize),
+}
+
+/// What pressing `keycode` with `modifiers` held asks for. Navigation and file operations aren't available
+/// while `comparing`, Escape dismisses a crop proposal first if `cropping`, and the arrow keys turn pages
+/// from right to left in `manga` mode.
+pub fn key_intent(keycode: KeyCode, modifiers: ModifiersState, comparing: bool, cropping: bool, manga: bool) -> Option<KeyIntent> {
+    let intent = match keycode {
+        KeyCode::Escape if cropping => KeyIntent::CancelCrop,
+        KeyCode::Escape => KeyIntent::Close,
+        KeyCode::ArrowLeft if manga && !comparing => KeyIntent::Navigate { forward: true },
+        KeyCode::ArrowRight if manga && !comparing => KeyIntent::Navigate { forward: false },
+        KeyCode::ArrowUp | KeyCode::KeyK => KeyIntent::Pan { direction: Vec2::new(0.0, -1.0), fast: modifiers.shift_key() },
+        KeyCode::ArrowDown | KeyCode::KeyJ => KeyIntent::Pan { direction: Vec2::new(0.0, 1.0), fast: modifiers.shift_key() },
+        KeyCode::ArrowLeft | KeyCode::KeyH => KeyIntent::Pan { direction: Vec2::new(-1.0, 0.0), fast: modifiers.shift_key() },
+        KeyCode::ArrowRight | KeyCode::KeyL => KeyIntent::Pan { direction: Vec2::new(1.0, 0.0), fast: modifiers.shift_key() },
+        KeyCode::KeyN | KeyCode::Space if !comparing => KeyIntent::Navigate { forward: true },
+        KeyCode::KeyP if modifiers.control_key() => KeyIntent::OpenPalette,
+        KeyCode::KeyP | KeyCode::Backspace if !comparing => KeyIntent::Navigate { forward: false },
+        KeyCode::KeyG if !comparing => KeyIntent::JumpToEnd { last: modifiers.shift_key() },
+        KeyCode::Slash if !comparing => KeyIntent::OpenPrompt,
+        KeyCode::KeyT if modifiers.shift_key() => KeyIntent::CycleTexturePreview,
+        KeyCode::KeyT if !comparing => KeyIntent::EditCaption,
+        KeyCode::Digit0 => KeyIntent::CountDigit(0),
+        KeyCode::PageDown => KeyIntent::TurnPage { forward: true },
+        KeyCode::PageUp => KeyIntent::TurnPage { forward: false },
+        KeyCode::KeyB => KeyIntent::ToggleStatusBar,
+        KeyCode::KeyA if !comparing => KeyIntent::ToggleAlphaCheck,
+        KeyCode::Equal if modifiers.shift_key() => KeyIntent::StepZoom { zoom_in: true },
+        KeyCode::Equal => KeyIntent::Unstretch,
+        KeyCode::Minus => KeyIntent::StepZoom { zoom_in: false },
+        KeyCode::F12 => KeyIntent::CaptureFrame,
+        KeyCode::KeyO => KeyIntent::OpenWindow,
+        KeyCode::Delete if !comparing => KeyIntent::Trash,
+        KeyCode::KeyX if !comparing => KeyIntent::MoveAway,
+        KeyCode::KeyU => KeyIntent::Undo,
+        KeyCode::KeyZ if modifiers.control_key() => KeyIntent::Undo,
+        KeyCode::KeyZ if modifiers.shift_key() && !comparing => KeyIntent::FitWindowToImage,
+        // Comparisons have no single image to fit the window to, and Shift+Z isn't plain z
+        KeyCode::KeyZ if modifiers.shift_key() => return None,
+        KeyCode::KeyZ => KeyIntent::ToggleZoomLock,
+        KeyCode::KeyE => KeyIntent::ToggleUpscale,
+        KeyCode::KeyI if modifiers.shift_key() => KeyIntent::ToggleStats,
+        KeyCode::KeyI => KeyIntent::ToggleIntegerScaling,
+        KeyCode::KeyM if modifiers.shift_key() => KeyIntent::FlushMarks,
+        KeyCode::KeyM => KeyIntent::ToggleMark,
+        KeyCode::KeyW => KeyIntent::ToggleCompareMode,
+        KeyCode::KeyC if modifiers.control_key() && !comparing => KeyIntent::Copy { path: modifiers.shift_key() },
+        KeyCode::KeyV if modifiers.control_key() && !comparing => KeyIntent::Paste,
+        KeyCode::KeyC => KeyIntent::Crop { save: modifiers.shift_key() },
+        KeyCode::KeyR if modifiers.control_key() && !comparing => KeyIntent::SaveRotation,
+        KeyCode::KeyR => KeyIntent::Rotate { clockwise: !modifiers.shift_key() },
+        KeyCode::KeyV if !comparing => KeyIntent::TogglePlayback,
+        KeyCode::KeyS if !comparing => KeyIntent::ToggleSlideshow,
+        KeyCode::KeyF if cfg!(feature = "faces") && !comparing => KeyIntent::NextFace,
+        KeyCode::Comma => KeyIntent::ChangeOpacity(-opacity::STEP),
+        KeyCode::Period => KeyIntent::ChangeOpacity(opacity::STEP),
+        KeyCode::BracketLeft => KeyIntent::Expose(-hdr::EXPOSURE_STEP),
+        KeyCode::BracketRight => KeyIntent::Expose(hdr::EXPOSURE_STEP),
+        KeyCode::Backslash => KeyIntent::AutoExpose,
+        _ => KeyIntent::RunAction(action_index(keycode)?),
+    };
+    Some(intent)
+}
+
+/// How far the arrow keys move the image by default, in logical pixels
+pub const PAN_STEP: f64 = 40.0;
+/// How much faster the image moves with Shift held
+const FAST_PAN: f64 = 5.0;
+/// How much a notch of the mouse wheel zooms by default
+pub const ZOOM_STEP: f64 = 1.05;
+
+/// How panning and zooming respond to keys and the mouse wheel
+#[derive(Debug, Clone, Copy)]
+pub struct Controls {
+    pub pan_step: f64,
+    pub zoom_step: f64,
+    pub invert_scroll: bool,
+}
+
+impl Default for Controls {
+    fn default() -> Self {
+        Self { pan_step: PAN_STEP, zoom_step: ZOOM_STEP, invert_scroll: false }
+    }
+}
+
+/// How much a scroll of `delta` zooms the x and y axis. Ctrl and Alt stretch the image horizontally and
+/// vertically only.
+pub fn scroll_zoom(delta: MouseScrollDelta, modifiers: ModifiersState, scale_factor: f64, controls: &Controls) -> (f64, f64) {
+    let factor = controls.zoom_step.powf(scroll_steps(delta, scale_factor, controls));
+    if modifiers.control_key() {
+        (factor, 1.0)
+    } else if modifiers.alt_key() {
+        (1.0, factor)
+    } else {
+        (factor, factor)
+    }
+}
+
+/// How many notches of the mouse wheel a scroll of `delta` is, positive to zoom in. Touchpads scroll by
+/// fractions of a notch.
+pub fn scroll_steps(delta: MouseScrollDelta, scale_factor: f64, controls: &Controls) -> f64 {
+    const PIXELS_PER_LINE: f64 = 20.0;
+
+    let steps = match delta {
+        MouseScrollDelta::PixelDelta(delta) => delta.y / (PIXELS_PER_LINE * scale_factor),
+        MouseScrollDelta::LineDelta(_, y) => y as f64,
+    };
+    if controls.invert_scroll {
+        -steps
+    } else {
+        steps
+    }
+}
+
+/// Changes to the set of windows an event asks for, which are made once the state of the window it
+/// was meant for is no longer borrowed
+#[derive(Default)]
+struct Followup {
+    open_window_at: Option<usize>,
+    close_window: bool,
+    files_changed: bool,
+    toggle_click_through: bool,
+    exit: bool,
+}
+
+/// All state of the viewer
+pub struct App<'s> {
+    phase: Phase,
+    shared: Shared,
+    options: RenderOptions,
+    // Marked images are written here on demand and when veh exits
+    mark_output: Box<dyn Write>,
+    // The playlist indices of the windows opened on the first Resumed event
+    initial_windows: Vec<usize>,
+    actions: [Option<Action>; 10],
+    move_to: Option<PathBuf>,
+    frame_interval: Option<Duration>,
+    // Time between the frames of a playing image sequence
+    play_interval: Duration,
+    // The exposure and tone mapping new windows show HDR images with
+    hdr_view: hdr::View,
+    // Whether windows start out running a slideshow
+    slideshow: bool,
+    // Whether windows start out playing the images as a timelapse, which skips frames to keep to its frame rate
+    timelapse: bool,
+    window_options: WindowOptions,
+    controls: Controls,
+    // Whether windows enlarge small images to fit, unless --no-upscale
+    upscale: bool,
+    // Whether windows start out showing images at integer scales only, with --integer-scaling
+    integer_scaling: bool,
+    // The modification times of the images shown, with --auto-reload
+    watch: Option<Watch>,
+    // How opaque new windows are, with --opacity
+    opacity: f64,
+    // Whether clicks and keys pass through the windows, with --click-through
+    click_through: bool,
+    // Whether the next window opened shows the hint of the first launch
+    welcome: bool,
+    proxy: EventLoopProxy<UserEvent>,
+
+    // The vello RenderContext which is a global context that lasts for the lifetime of the application
+    render_cx: RenderContext,
+    // An array of renderers, one per wgpu device
+    renderers: Vec<Option<Renderer>>,
+    // Watchdogs for the wgpu devices, to recreate the GPU state when a device hangs or is lost
+    watchdogs: Vec<Option<Watchdog>>,
+    gpu_failed: bool,
+
+    // State for every window, where we store the winit Window and the wgpu Surface
+    windows: HashMap<WindowId, RenderState<'s>>,
+    // IPC clients send their commands to the window that was focused last
+    focused_window: Option<WindowId>,
+
+    // A vello Scene which is a data structure which allows one to build up a description a scene to be drawn
+    // (with paths, fills, images, text, etc) which is then passed to a renderer for rendering
+    scene: Scene,
+}
+
+impl<'s> App<'s> {
+    /// Set up the viewer for `paths`. Fails if none of the images can be loaded.
+    pub fn new(paths: Vec<PathBuf>, args: &Args, proxy: EventLoopProxy<UserEvent>) -> Result<Self> {
+        let mark_output: Box<dyn Write> = match &args.mark_output {
+            Some(path) => Box::new(
+                File::create(path).with_context(|| format!("failed to create {}", path.display()))?,
+            ),
+            None => Box::new(std::io::stdout()),
+        };
+        // With --new-window every image starts out in a window of its own
+        // With --manga reading goes on at the page of the archive last read, otherwise at the image last viewed
+        let bookmarks = if args.manga { Bookmarks::load() } else { Bookmarks::default() };
+        let session = if args.fresh { Session::default() } else { Session::load() };
+        let initial_windows: Vec<usize> = if args.new_window {
+            (0..paths.len()).collect()
+        } else if args.manga {
+            vec![bookmarks.start_index(&paths)]
+        } else {
+            vec![session.start_index(&paths)]
+        };
+        let hdr_view = hdr::View { exposure: args.exposure, tonemap: args.tonemap };
+        let mut shared = Shared {
+            comparison: if args.compare { Some(Comparison::load(&paths, hdr_view)?) } else { None },
+            sequences: if args.timelapse { std::iter::once(0..paths.len()).collect() } else { sequence::find_sequences(&paths) },
+            playlist: Playlist::new(paths),
+            cache: ImageCache::default(),
+            scenes: SceneCache::default(),
+            render_ahead: RenderAhead::new(proxy.clone()),
+            locked_scale: args.lock_scale.map(|percent| percent / 100.0),
+            gravity: args.gravity,
+            tile_size: args.tile_size.map(Vec2::from),
+            alpha_threshold: args.alpha_threshold,
+            journal: Journal::default(),
+            subscriptions: ipc::Subscriptions::default(),
+            overlays: Overlays::with_defaults(args.status_bar),
+            manga: args.manga,
+            bookmarks,
+            cues: Cues::new(&args.cue),
+            session,
+            clipboard: Clipboard::default(),
+            faces: Faces::default(),
+            pacing: Pacing {
+                delay: args.slide_delay,
+                per_megapixel: args.delay_per_megapixel,
+            },
+            caption_path: args.caption_path.clone(),
+            auto_resize: args.auto_resize,
+            prefetcher: (!args.low_memory).then(|| Prefetcher::new(proxy.clone())),
+            monitor_profiles: args.monitor_profile.clone(),
+        };
+        // Decode the first image up front, so that we fail before opening a window if nothing can be shown
+        if shared.load(0, true).is_none() {
+            bail!("no image could be loaded");
+        }
+        if args.click_through {
+            if let Err(err) = hotkey::listen(proxy.clone()) {
+                eprintln!("veh: {err:#}");
+            }
+        }
+
+        Ok(Self {
+            phase: Phase::Starting,
+            shared,
+            options: RenderOptions::new(args),
+            mark_output,
+            initial_windows,
+            actions: actions_from_args(args),
+            move_to: args.move_to.clone(),
+            frame_interval: args.fps.map(|fps| Duration::from_secs(1) / fps),
+            play_interval: Duration::from_secs(1) / args.play_fps,
+            hdr_view,
+            slideshow: args.slideshow && !args.compare,
+            timelapse: args.timelapse && !args.compare,
+            window_options: WindowOptions::new(args),
+            upscale: !args.no_upscale,
+            integer_scaling: args.integer_scaling,
+            watch: args.auto_reload.then(Watch::new),
+            controls: Controls { pan_step: args.pan_step, zoom_step: args.zoom_step, invert_scroll: args.invert_scroll },
+            opacity: args.opacity.unwrap_or(if args.click_through { opacity::CLICK_THROUGH } else { 1.0 }),
+            click_through: args.click_through,
+            welcome: !args.no_config && config::first_run(),
+            proxy,
+            render_cx: RenderContext::new().unwrap(),
+            renderers: vec![],
+            watchdogs: vec![],
+            gpu_failed: false,
+            windows: HashMap::new(),
+            focused_window: None,
+            scene: Scene::new(),
+        })
+    }
+
+    /// Write the images still marked, the session and in --manga mode the last-read pages, when the viewer is done
+    pub fn finish(mut self) -> Result<()> {
+        // Windows still open when veh is quit, e.g. over IPC, are remembered as they are
+        for state in self.windows.values() {
+            if let RenderState::Active(render_state) = state {
+                render_state.remember(&mut self.shared);
+            }
+        }
+        if let Err(err) = self.shared.session.save() {
+            eprintln!("veh: failed to remember the session: {err:#}");
+        }
+        if self.shared.manga {
+            if let Err(err) = self.shared.bookmarks.save() {
+                eprintln!("veh: failed to remember the last-read pages: {err:#}");
+            }
+        }
+        self.shared.playlist.flush_marked(&mut self.mark_output).context("failed to write marked images")
+    }
+
+    pub fn handle_event(&mut self, event: Event<UserEvent>, event_loop: &EventLoopWindowTarget<UserEvent>) {
+        if self.phase == Phase::Exiting {
+            return;
+        }
+        match event {
+            // Setup renderer. In winit apps it is recommended to do setup in Event::Resumed
+            // for best cross-platform compatibility
+            Event::Resumed => self.resume(event_loop),
+            Event::Suspended => self.suspend(event_loop),
+            Event::WindowEvent { event: WindowEvent::RedrawRequested, window_id } => self.render(window_id),
+            Event::WindowEvent { event, window_id } => {
+                let followup = self.handle_window_event(window_id, event);
+                self.apply(window_id, followup, event_loop);
+            }
+            Event::UserEvent(UserEvent::Ipc(request)) => self.handle_ipc(request, event_loop),
+            Event::UserEvent(UserEvent::ActionFinished { path, reload: true }) => self.reload(path),
+            Event::UserEvent(UserEvent::ToggleClickThrough) => self.set_click_through(!self.click_through),
+            Event::UserEvent(UserEvent::Prefetched { path, fetch, image }) => {
+                let wanted = self.shared.prefetcher.as_mut().is_some_and(|prefetcher| prefetcher.finished(&path, fetch));
+                // A window may have loaded the image in the meantime, and its scenes are kept for that copy
+                if let Some(image) = image.filter(|_| wanted && !self.shared.cache.contains(&path, 0)) {
+                    self.shared.cache.insert(&path, 0, Rc::new(image.into_image()));
+                }
+            }
+            Event::UserEvent(UserEvent::RenderedAhead { id, scene }) => {
+                self.shared.render_ahead.finished(id, *scene, &mut self.shared.scenes);
+            }
+            Event::AboutToWait => self.update(event_loop),
+            _ => {}
+        }
+    }
+
+    fn exit(&mut self, event_loop: &EventLoopWindowTarget<UserEvent>) {
+        self.phase = Phase::Exiting;
+        event_loop.exit();
+    }
+
+    /// Create the initial windows, or get back the ones cached in a previous Suspended event
+    fn resume(&mut self, event_loop: &EventLoopWindowTarget<UserEvent>) {
+        let mut pending: Vec<(Option<Arc<Window>>, usize)> =
+            self.initial_windows.drain(..).map(|index| (None, index)).collect();
+        for state in self.windows.values() {
+            if let RenderState::Suspended(window, index) = state {
+                pending.push((Some(window.clone()), *index));
+            }
+        }
+
+        for (window, index) in pending {
+            // The first window opens where the window of the last session was
+            let geometry = if self.windows.is_empty() { self.shared.session.window } else { None };
+            let image_size = self.shared.load(index, true).map(|(_, image)| image.size());
+            let window = window.unwrap_or_else(|| create_winit_window(event_loop, &self.window_options, geometry, image_size));
+            let Some(state) = self.activate_window(window, index) else {
+                self.exit(event_loop);
+                return;
+            };
+            self.windows.insert(state.window.id(), RenderState::Active(Box::new(state)));
+        }
+        self.phase = Phase::Running;
+
+        // Nothing is drawn unless something changes, so that an idle viewer doesn't use the CPU or GPU
+        event_loop.set_control_flow(ControlFlow::Wait);
+    }
+
+    /// Save window state on suspend
+    fn suspend(&mut self, event_loop: &EventLoopWindowTarget<UserEvent>) {
+        for state in self.windows.values_mut() {
+            if let RenderState::Active(active) = state {
+                let suspended = RenderState::Suspended(active.window.clone(), active.index);
+                *state = suspended;
+            }
+        }
+        self.phase = Phase::Suspended;
+        event_loop.set_control_flow(ControlFlow::Wait);
+    }
+
+    fn handle_window_event(&mut self, window_id: WindowId, event: WindowEvent) -> Followup {
+        let mut followup = Followup::default();
+        // Ignore the event if the window it is meant for isn't active
+        let Some(RenderState::Active(render_state)) = self.windows.get_mut(&window_id) else {
+            return followup;
+        };
+        let shared = &mut self.shared;
+
+        match event {
+            WindowEvent::MouseInput { state, button: MouseButton::Left, .. } => {
+                render_state.mouse_down = state == ElementState::Pressed;
+                // A click dismisses the hint of the first launch, while dragging goes on as it says
+                if render_state.mouse_down && std::mem::take(&mut render_state.welcome) {
+                    render_state.window.request_redraw();
+                }
+                // With --tile-size a dragged image settles on the grid of tiles
+                if let Some(tile) = shared.tile_size.filter(|_| !render_state.mouse_down && !render_state.fitted) {
+                    render_state.transform = viewport::snap_to_grid(render_state.transform, tile);
+                    render_state.window.request_redraw();
+                }
+                // Pressing on the timeline of a sequence or animation pauses it and scrubs through it instead of panning
+                let size = render_state.window.inner_size();
+                let on_timeline = render_state.prior_position.filter(|position| {
+                    overlay::is_on_timeline((position.x, position.y), size, render_state.scale_factor)
+                });
+                render_state.scrubbing = false;
+                if let Some(position) = on_timeline.filter(|_| render_state.mouse_down) {
+                    if render_state.timeline_position(shared, self.play_interval).is_some() {
+                        render_state.scrubbing = true;
+                        render_state.playback = None;
+                        render_state.animation = None;
+                        render_state.scrub(position.x, shared);
+                        render_state.window.request_redraw();
+                    }
+                }
+                // A double-click toggles between fitting the image into the window and 100% about the cursor
+                if let Some(position) = render_state.prior_position.filter(|_| render_state.mouse_down && !render_state.scrubbing) {
+                    let now = Instant::now();
+                    if is_double_click(render_state.last_click, now, position, render_state.scale_factor) {
+                        render_state.last_click = None;
+                        if render_state.fitted {
+                            let position = match &shared.comparison {
+                                Some(comparison) => comparison.viewport_position(position, size),
+                                None => position,
+                            };
+                            render_state.transform = viewport::actual_size_about(render_state.transform, position);
+                            render_state.fitted = false;
+                        } else {
+                            render_state.fit(shared);
+                        }
+                        render_state.window.request_redraw();
+                    } else {
+                        render_state.last_click = Some((now, position));
+                    }
+                }
+                if let Some(comparison) = &mut shared.comparison {
+                    match render_state.prior_position {
+                        Some(position) if render_state.mouse_down => {
+                            comparison.grab_divider(position, render_state.window.inner_size());
+                        }
+                        _ => comparison.release_divider(),
+                    }
+                }
+            }
+            // Dragging with the right mouse button selects a region to zoom in on
+            WindowEvent::MouseInput { state, button: MouseButton::Right, .. } if shared.comparison.is_none() => {
+                match (state, render_state.prior_position) {
+                    (ElementState::Pressed, Some(position)) => render_state.selection = Some((position, position)),
+                    (ElementState::Released, _) => {
+                        if let Some((start, end)) = render_state.selection.take() {
+                            let selection = Rect::from_points(start.to_point(), end.to_point());
+                            // A mere click zooms nowhere
+                            let min_size = MIN_SELECTION * render_state.scale_factor;
+                            if selection.width() >= min_size && selection.height() >= min_size {
+                                let size = render_state.window.inner_size();
+                                render_state.transform = viewport::zoom_to(render_state.transform, selection, size);
+                                render_state.fitted = false;
+                            }
+                            render_state.window.request_redraw();
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            WindowEvent::MouseWheel { delta, .. } => {
+                if let Some(mut prior_position) = render_state.prior_position {
+                    // Zoom about the cursor position within the compared image under it
+                    if let Some(comparison) = &shared.comparison {
+                        prior_position = comparison.viewport_position(prior_position, render_state.window.inner_size());
+                    }
+                    // With integer scaling, every notch zooms to the next integer scale
+                    if render_state.integer_scaling {
+                        render_state.scroll_steps += scroll_steps(delta, render_state.scale_factor, &self.controls);
+                        if render_state.scroll_steps.abs() >= 1.0 {
+                            render_state.step_integer_scale(prior_position, render_state.scroll_steps > 0.0);
+                            render_state.scroll_steps = 0.0;
+                            render_state.update_title(shared);
+                            render_state.window.request_redraw();
+                        }
+                    } else {
+                        let (x_factor, y_factor) = scroll_zoom(delta, render_state.modifiers, render_state.scale_factor, &self.controls);
+                        render_state.transform = viewport::zoom_about(render_state.transform, prior_position, x_factor, y_factor);
+                        render_state.fitted = false;
+                        if x_factor != y_factor {
+                            render_state.update_title(shared);
+                        }
+                        render_state.window.request_redraw();
+                    }
+                }
+            }
+            WindowEvent::CursorLeft { .. } => {
+                render_state.prior_position = None;
+            }
+            WindowEvent::CursorMoved { position, .. } => {
+                let position = Vec2::new(position.x, position.y);
+                let size = render_state.window.inner_size();
+                if shared.comparison.as_mut().is_some_and(|comparison| comparison.drag_divider(position, size)) {
+                    // The divider of the wipe moves instead of the images
+                    render_state.window.request_redraw();
+                } else if let Some((_, end)) = &mut render_state.selection {
+                    *end = position;
+                    render_state.window.request_redraw();
+                } else if render_state.scrubbing {
+                    render_state.scrub(position.x, shared);
+                } else if render_state.mouse_down {
+                    if let Some(prior) = render_state.prior_position {
+                        render_state.transform = viewport::pan(render_state.transform, position - prior);
+                        if render_state.integer_scaling {
+                            render_state.transform = viewport::snap_to_pixels(render_state.transform);
+                        }
+                        render_state.fitted = false;
+                        render_state.window.request_redraw();
+                    }
+                }
+                render_state.prior_position = Some(position);
+            }
+            WindowEvent::Focused(true) => self.focused_window = Some(window_id),
+            WindowEvent::ModifiersChanged(modifiers) => {
+                render_state.modifiers = modifiers.state();
+            }
+            // The first key pressed dismisses the hint of the first launch and does nothing else
+            WindowEvent::KeyboardInput { event: KeyEvent { state: ElementState::Pressed, .. }, .. } if render_state.welcome => {
+                render_state.welcome = false;
+                render_state.window.request_redraw();
+            }
+            // Keys are typed into the goto prompt while it is open
+            WindowEvent::KeyboardInput { event: KeyEvent { state: ElementState::Pressed, logical_key, text, .. }, .. }
+                if render_state.prompt.is_some() =>
+            {
+                followup.exit = !render_state.edit_prompt(&logical_key, text.as_deref(), shared);
+            }
+            // and into the command palette, which runs the command chosen as if its key was pressed
+            WindowEvent::KeyboardInput { event: KeyEvent { state: ElementState::Pressed, logical_key, text, .. }, .. }
+                if render_state.palette.is_some() =>
+            {
+                if let Some(intent) = render_state.edit_palette(&logical_key, text.as_deref()) {
+                    return self.handle_key(window_id, intent);
+                }
+            }
+            // and into the caption while it is edited
+            WindowEvent::KeyboardInput { event: KeyEvent { state: ElementState::Pressed, logical_key, text, .. }, .. }
+                if render_state.caption_edit.is_some() =>
+            {
+                render_state.edit_caption(&logical_key, text.as_deref(), shared);
+            }
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        state: ElementState::Pressed,
+                        physical_key: PhysicalKey::Code(keycode),
+                        ..
+                    },
+                ..
+            } => {
+                let comparing = shared.comparison.is_some();
+                let cropping = render_state.crop_proposal.is_some();
+                if let Some(intent) = key_intent(keycode, render_state.modifiers, comparing, cropping, shared.manga) {
+                    return self.handle_key(window_id, intent);
+                }
+            }
+            // Close the window when a close is requested (e.g. window's close button is pressed)
+            WindowEvent::CloseRequested => followup.close_window = true,
+            // Keep the logical size of the image when the window moves to a monitor with another scale factor.
+            // The window itself is resized next, which refits the image if it is still fit into the window.
+            WindowEvent::ScaleFactorChanged { scale_factor, .. } => {
+                let ratio = scale_factor / render_state.scale_factor;
+                render_state.scale_factor = scale_factor;
+                render_state.prior_position = render_state.prior_position.map(|position| position * ratio);
+                if !render_state.fitted {
+                    render_state.transform = viewport::rescale(render_state.transform, ratio);
+                }
+                render_state.update_display(shared);
+            }
+            // Colors are converted for the monitor the window is on
+            WindowEvent::Moved(_) => render_state.update_display(shared),
+            WindowEvent::Resized(_size) => {
+                let size = render_state.window.inner_size();
+                render_state.output.resize(&self.render_cx, size.width, size.height);
+                if render_state.fitted {
+                    render_state.fit(shared);
+                } else if std::mem::take(&mut render_state.actual_size) {
+                    render_state.show_actual_size(shared);
+                }
+                render_state.window.request_redraw();
+            }
+            _ => {}
+        }
+        followup
+    }
+
+    /// Do what a key press in the window with `window_id` asks for
+    fn handle_key(&mut self, window_id: WindowId, intent: KeyIntent) -> Followup {
+        let mut followup = Followup::default();
+        let Some(RenderState::Active(render_state)) = self.windows.get_mut(&window_id) else {
+            return followup;
+        };
+        let shared = &mut self.shared;
+        // A count applies to the next key only
+        let count = render_state.count.take();
+
+        match intent {
+            KeyIntent::CancelCrop => {
+                render_state.crop_proposal = None;
+                render_state.window.request_redraw();
+            }
+            KeyIntent::Close => followup.close_window = true,
+            KeyIntent::Pan { direction, fast } => {
+                // A count moves that many steps, which are whole tiles with --tile-size
+                let steps = count.unwrap_or(1) as f64;
+                render_state.transform = match shared.tile_size {
+                    Some(tile) => {
+                        let offset = Vec2::new(direction.x * tile.x, direction.y * tile.y);
+                        viewport::snap_to_grid(viewport::pan_image(render_state.transform, offset * steps), tile)
+                    }
+                    // Steps are window pixels rather than image pixels, so that panning goes as fast at any zoom
+                    None => {
+                        let step = self.controls.pan_step * render_state.scale_factor * if fast { FAST_PAN } else { 1.0 };
+                        viewport::pan(render_state.transform, direction * step * steps)
+                    }
+                };
+                if render_state.integer_scaling {
+                    render_state.transform = viewport::snap_to_pixels(render_state.transform);
+                }
+                render_state.fitted = false;
+                render_state.window.request_redraw();
+            }
+            KeyIntent::Navigate { forward } => {
+                let shown = match count {
+                    Some(count) => render_state.step(count, forward, shared),
+                    None => render_state.navigate(forward, shared),
+                };
+                if !shown {
+                    followup.exit = true;
+                }
+            }
+            KeyIntent::JumpToEnd { last } => {
+                let end = if last { shared.playlist.len() - 1 } else { 0 };
+                let index = count.map_or(end, |count| count.min(shared.playlist.len()) - 1);
+                if !render_state.go_to(index, !last, shared) {
+                    followup.exit = true;
+                }
+            }
+            KeyIntent::OpenPrompt => {
+                render_state.prompt = Some(String::new());
+                render_state.window.request_redraw();
+            }
+            KeyIntent::OpenPalette => {
+                render_state.palette = Some(Palette::new(palette::commands(&self.actions, shared.comparison.is_some())));
+                render_state.window.request_redraw();
+            }
+            KeyIntent::EditCaption => {
+                if shared.caption_path.is_none() {
+                    eprintln!("veh: captions are off, turn them on with --caption-path");
+                    return followup;
+                }
+                render_state.caption_edit = Some(render_state.caption.clone().unwrap_or_default());
+                render_state.window.request_redraw();
+            }
+            KeyIntent::CountDigit(digit) => render_state.count = jump::push_digit(count, digit),
+            KeyIntent::TurnPage { forward } => render_state.turn_page(forward, shared),
+            KeyIntent::ToggleStatusBar => {
+                shared.overlays.toggle("status-bar");
+                render_state.window.request_redraw();
+            }
+            KeyIntent::ToggleUpscale => {
+                render_state.upscale = !render_state.upscale;
+                render_state.fit(shared);
+                render_state.update_title(shared);
+                render_state.window.request_redraw();
+            }
+            KeyIntent::ToggleStats => {
+                render_state.stats = match render_state.stats.take() {
+                    Some(_) => None,
+                    None => match Stats::read(shared.playlist.get(render_state.index)) {
+                        Ok(stats) => Some(stats),
+                        Err(err) => {
+                            eprintln!("veh: failed to compute the statistics: {err:#}");
+                            None
+                        }
+                    },
+                };
+                render_state.window.request_redraw();
+            }
+            KeyIntent::ToggleIntegerScaling => {
+                render_state.toggle_integer_scaling(shared);
+                render_state.window.request_redraw();
+            }
+            // Zoom about the middle of the window, or of the compared image there
+            KeyIntent::StepZoom { zoom_in } => {
+                let size = render_state.window.inner_size();
+                let mut center = Vec2::new(size.width as f64 / 2.0, size.height as f64 / 2.0);
+                if let Some(comparison) = &shared.comparison {
+                    center = comparison.viewport_position(center, size);
+                }
+                for _ in 0..count.unwrap_or(1) {
+                    render_state.step_integer_scale(center, zoom_in);
+                }
+                render_state.update_title(shared);
+                render_state.window.request_redraw();
+            }
+            // Reset a stretched image to the same scale on both axes
+            KeyIntent::Unstretch => {
+                render_state.unstretch();
+                render_state.update_title(shared);
+                render_state.window.request_redraw();
+            }
+            // Capture the next frame for a bug report
+            KeyIntent::CaptureFrame => {
+                render_state.capture_next_frame = true;
+                render_state.window.request_redraw();
+            }
+            // Open another window on the current image, which can then be navigated independently
+            KeyIntent::OpenWindow => followup.open_window_at = Some(render_state.index),
+            // Move the current image to the trash, or into the --move-to directory
+            KeyIntent::Trash | KeyIntent::MoveAway => {
+                let index = render_state.index;
+                let path = shared.playlist.get(index).to_path_buf();
+                let done = if intent == KeyIntent::Trash {
+                    shared.journal.trash(index, &path).map(|()| shared.playlist.remove(index))
+                } else if let Some(dir) = &self.move_to {
+                    shared.journal.move_into(index, &path, dir).map(|to| shared.playlist.set_path(index, to))
+                } else {
+                    Err(anyhow::anyhow!("no directory to move to, use --move-to"))
+                };
+                match done {
+                    Ok(()) => followup.files_changed = true,
+                    Err(err) => eprintln!("veh: {err:#}"),
+                }
+            }
+            // Undo the last file operation and show the image it was done on
+            KeyIntent::Undo => match shared.journal.undo() {
+                Ok(Some(operation)) => {
+                    let index = match operation {
+                        Operation::Trashed { index, .. } => {
+                            shared.playlist.restore(index);
+                            index
+                        }
+                        Operation::Moved { index, from, .. } => {
+                            shared.playlist.set_path(index, from);
+                            index
+                        }
+                    };
+                    if let Some((index, image)) = shared.load(index, true) {
+                        render_state.show(index, image, shared);
+                    }
+                    followup.files_changed = true;
+                }
+                Ok(None) => eprintln!("veh: nothing to undo"),
+                Err(err) => eprintln!("veh: failed to undo: {err:#}"),
+            },
+            // Shift+M emits the marked images right away instead of waiting for exit
+            KeyIntent::FlushMarks => {
+                if let Err(err) = shared.playlist.flush_marked(&mut self.mark_output) {
+                    eprintln!("veh: failed to write marked images: {err:#}");
+                }
+                render_state.update_title(shared);
+                render_state.window.request_redraw();
+            }
+            KeyIntent::ToggleZoomLock => render_state.toggle_zoom_lock(shared),
+            KeyIntent::FitWindowToImage => {
+                render_state.fit_window_to_image(shared);
+                render_state.window.request_redraw();
+            }
+            // Switch the comparison between side by side and wipe
+            KeyIntent::ToggleCompareMode => {
+                if let Some(comparison) = &mut shared.comparison {
+                    comparison.toggle_mode();
+                    render_state.fit(shared);
+                    render_state.window.request_redraw();
+                }
+            }
+            // C proposes to crop away uniform borders, a second C applies the crop to the view
+            // and Shift+C saves the cropped image next to the original
+            KeyIntent::Crop { save } => {
+                let VehImage::Image(raster) = &*render_state.image else {
+                    eprintln!("veh: only raster images can be cropped");
+                    return followup;
+                };
+                let proposal = render_state.crop_proposal;
+                let Some(crop) = proposal.or_else(|| crop::detect_borders(raster)) else {
+                    eprintln!("veh: no uniform border found");
+                    return followup;
+                };
+                if save && loader::limits_size() {
+                    eprintln!("veh: crops can't be saved while images are downscaled by --low-memory or --compat");
+                } else if save {
+                    match crop::export(raster, crop, shared.playlist.get(render_state.index)) {
+                        Ok(path) => println!("{}", path.display()),
+                        Err(err) => eprintln!("veh: failed to export the crop: {err:#}"),
+                    }
+                    render_state.crop_proposal = None;
+                } else if proposal.is_some() {
+                    // Keep the cropped region where it is on screen
+                    let cropped = crop::apply(raster, crop);
+                    render_state.transform = viewport::pan_image(render_state.transform, crop.rect().origin().to_vec2());
+                    render_state.fitted = false;
+                    render_state.replace_image(Rc::new(VehImage::Image(cropped)), shared);
+                } else {
+                    render_state.crop_proposal = Some(crop);
+                }
+                render_state.window.request_redraw();
+            }
+            KeyIntent::ToggleMark => render_state.toggle_mark(shared),
+            KeyIntent::ToggleSlideshow => render_state.toggle_slideshow(shared),
+            KeyIntent::Copy { path } => {
+                let copied = if path {
+                    shared.clipboard.copy_text(&shared.playlist.get(render_state.index).to_string_lossy())
+                } else {
+                    match render_state.shown_pixels() {
+                        Some(image) => shared.clipboard.copy_image(&image),
+                        None => Err(anyhow!("only raster images can be copied")),
+                    }
+                };
+                if let Err(err) = copied {
+                    eprintln!("veh: {err:#}");
+                }
+            }
+            KeyIntent::NextFace => render_state.next_face(shared),
+            KeyIntent::ToggleAlphaCheck => render_state.toggle_alpha_check(shared),
+            KeyIntent::CycleTexturePreview => render_state.cycle_texture_preview(),
+            KeyIntent::ChangeOpacity(change) => {
+                let opacity = (render_state.opacity + change).clamp(opacity::MIN, 1.0);
+                match opacity::set(&render_state.window, opacity) {
+                    Ok(()) => render_state.opacity = opacity,
+                    Err(err) => eprintln!("veh: {err:#}"),
+                }
+            }
+            // Pasted images are added to the end of the playlist and shown
+            KeyIntent::Paste => match shared.clipboard.paste() {
+                Ok(paths) => {
+                    let index = shared.playlist.len();
+                    for path in paths {
+                        shared.playlist.push(path);
+                    }
+                    followup.exit = !render_state.go_to(index, true, shared);
+                }
+                Err(err) => eprintln!("veh: {err:#}"),
+            },
+            // Play the animated image or the numbered sequence the image is a frame of from here, or pause it
+            KeyIntent::TogglePlayback => {
+                if let VehImage::Animation(frames) = &*render_state.image {
+                    if render_state.animation.take().is_none() {
+                        let delay = frames[render_state.frame].1;
+                        render_state.animation = Some(Playback::new(0..frames.len(), delay));
+                    }
+                } else if render_state.playback.take().is_none() {
+                    match sequence::containing(&shared.sequences, render_state.index) {
+                        Some(frames) => render_state.playback = Some(Playback { catch_up: self.timelapse, ..Playback::new(frames, self.play_interval) }),
+                        None => eprintln!("veh: the image isn't a frame of a numbered sequence"),
+                    }
+                }
+                render_state.window.request_redraw();
+            }
+            KeyIntent::Expose(ev) => render_state.expose(ev, shared),
+            KeyIntent::AutoExpose => render_state.auto_expose(shared),
+            KeyIntent::Rotate { clockwise } => render_state.rotate(clockwise, shared),
+            KeyIntent::SaveRotation => render_state.save_rotation(shared),
+            KeyIntent::RunAction(index) => match &self.actions[index] {
+                Some(action) => {
+                    let path = shared.playlist.get(render_state.index);
+                    if let Err(err) = action.spawn(path, self.proxy.clone()) {
+                        eprintln!("veh: {err:#}");
+                    }
+                }
+                // Number keys without an action type a count
+                None if index > 0 => render_state.count = jump::push_digit(count, index as u32),
+                None => {}
+            },
+        }
+        followup
+    }
+
+    /// Open, close and update windows as asked for by an event meant for the window with `window_id`
+    fn apply(&mut self, window_id: WindowId, followup: Followup, event_loop: &EventLoopWindowTarget<UserEvent>) {
+        if followup.exit {
+            self.exit(event_loop);
+            return;
+        }
+        if let Some(index) = followup.open_window_at {
+            self.open_window(index, event_loop);
+        }
+        if followup.toggle_click_through {
+            self.set_click_through(!self.click_through);
+        }
+        // Move windows off images that are gone and show where moved images are now
+        if followup.files_changed {
+            for state in self.windows.values_mut() {
+                let RenderState::Active(render_state) = state else {
+                    continue;
+                };
+                if self.shared.playlist.is_available(render_state.index) {
+                    render_state.update_title(&self.shared);
+                    continue;
+                }
+                let Some((index, image)) = self.shared.load(render_state.index, true) else {
+                    // Every image is gone
+                    self.exit(event_loop);
+                    return;
+                };
+                render_state.show(index, image, &mut self.shared);
+            }
+        }
+        if followup.close_window {
+            self.close(window_id, event_loop);
+        }
+    }
+
+    /// This is where all the rendering happens
+    fn render(&mut self, window_id: WindowId) {
+        let Some(RenderState::Active(render_state)) = self.windows.get_mut(&window_id) else {
+            return;
+        };
+        let scene = &mut self.scene;
+
+        // Hold the redraw back until the next frame is due if --fps caps the frame rate
+        if let (Some(interval), Some(last_frame)) = (self.frame_interval, render_state.last_frame) {
+            if last_frame.elapsed() < interval {
+                render_state.redraw_at = Some(last_frame + interval);
+                return;
+            }
+        }
+        render_state.last_frame = Some(Instant::now());
+        render_state.enlarge_pixels(&mut self.shared.scenes);
+        if self.options.svg_rerender {
+            render_state.rerender_svg(&mut self.shared);
+        }
+        let shared = &self.shared;
+
+        // Empty the scene of objects to draw. You could create a new Scene each time, but in this case
+        // the same Scene is reused so that the underlying memory allocation can also be reused.
+        scene.reset();
+
+        // Catch up with resizes that came without a Resized event, e.g. those requested by --auto-resize on Wayland
+        let size = render_state.window.inner_size();
+        if render_state.output.size() != (size.width, size.height) && size.width > 0 && size.height > 0 {
+            render_state.output.resize(&self.render_cx, size.width, size.height);
+        }
+
+        // Get the window size
+        let (width, height) = render_state.output.size();
+        let dev_id = render_state.output.dev_id();
+
+        match &shared.comparison {
+            Some(comparison) => comparison.draw(scene, render_state.transform, PhysicalSize::new(width, height)),
+            None => {
+                // Complex SVGs are drawn from tiles while they move, with --svg-tiles
+                let size = PhysicalSize::new(width, height);
+                let tiled = self.options.svg_tiles
+                    && matches!(*render_state.image, VehImage::Svg(_))
+                    && render_state.svg_tiles.observe(render_state.transform)
+                    && render_state.svg_tiles.draw(scene, render_state.transform, size, render_state.image.size());
+                if !tiled {
+                    scene.append(&render_state.subscene, Some(render_state.transform * Affine::scale(1.0 / render_state.subscene_scale)));
+                }
+            }
+        }
+
+        let context = OverlayContext {
+            window_size: PhysicalSize::new(width, height),
+            scale_factor: render_state.scale_factor,
+            transform: render_state.transform,
+            image_size: render_state.image.size(),
+            path: shared.playlist.get(render_state.index),
+            index: render_state.index,
+            count: shared.playlist.len(),
+            page: render_state.page,
+            page_count: render_state.page_count,
+            marked: shared.playlist.is_marked(render_state.index),
+            crop_proposal: render_state.crop_proposal,
+            alpha_mask: render_state.alpha_mask.as_ref(),
+            texture: render_state.texture.zip(render_state.texture_image.as_ref()),
+            selection: render_state.selection.map(|(start, end)| Rect::from_points(start.to_point(), end.to_point())),
+            timeline: render_state.timeline_position(shared, self.play_interval),
+            prompt: render_state.prompt.as_deref(),
+            palette: render_state.palette.as_ref(),
+            welcome: render_state.welcome,
+            stats: render_state.stats.as_ref(),
+            caption: render_state.caption_edit.as_deref().or(render_state.caption.as_deref()).filter(|_| shared.comparison.is_none()),
+            editing_caption: render_state.caption_edit.is_some(),
+            load_error: render_state.load_error.as_deref(),
+            exposure: render_state.image.is_hdr().then_some(render_state.hdr_view.exposure),
+        };
+        shared.overlays.draw(scene, &context);
+
+        // Get a handle to the device
+        let device_handle = &self.render_cx.devices[dev_id];
+
+        let params = vello::RenderParams {
+            base_color: Color::BLACK, // Background color
+            width,
+            height,
+            antialiasing_method: self.options.antialiasing,
+        };
+        if render_state.capture_next_frame {
+            render_state.capture_next_frame = false;
+            match capture::capture(scene, &params, render_state.output.config()) {
+                Ok(path) => eprintln!("veh: captured the frame to {}", path.display()),
+                Err(err) => eprintln!("veh: failed to capture the frame: {err:#}"),
+            }
+        }
+
+        let renderer = self.renderers[dev_id].as_mut().unwrap();
+        match render_state.output.present(&self.render_cx, renderer, scene, &params, render_state.window.inner_size()) {
+            Ok(true) => {}
+            Ok(false) => {
+                render_state.window.request_redraw();
+                return;
+            }
+            Err(err) => {
+                eprintln!("veh: {err:#}");
+                self.gpu_failed = true;
+                return;
+            }
+        }
+        if let Some(watchdog) = &mut self.watchdogs[dev_id] {
+            watchdog.frame_submitted(&device_handle.queue);
+        }
+
+        device_handle.device.poll(wgpu::Maintain::Poll);
+    }
+
+    /// Run a command of an IPC client in the focused window
+    fn handle_ipc(&mut self, request: ipc::Request, event_loop: &EventLoopWindowTarget<UserEvent>) {
+        let windows = &mut self.windows;
+        let shared = &mut self.shared;
+        let target = self
+            .focused_window
+            .filter(|id| matches!(windows.get(id), Some(RenderState::Active(_))))
+            .or_else(|| windows.iter().find(|(_, state)| matches!(state, RenderState::Active(_))).map(|(id, _)| *id));
+        let mut followup = Followup::default();
+        let result = match (&request.command, target.and_then(|id| windows.get_mut(&id))) {
+            (ipc::Command::ListCommands, _) => Ok(ipc::list_commands()),
+            (ipc::Command::Subscribe { events }, _) => {
+                shared.subscriptions.subscribe(&request.client, events.clone());
+                Ok(serde_json::Value::Null)
+            }
+            (ipc::Command::Unsubscribe, _) => {
+                shared.subscriptions.unsubscribe(&request.client);
+                Ok(serde_json::Value::Null)
+            }
+            (ipc::Command::Windows, _) => {
+                let states: Vec<serde_json::Value> = windows
+                    .values()
+                    .filter_map(|state| match state {
+                        RenderState::Active(render_state) => Some(render_state.state(shared)),
+                        RenderState::Suspended(..) => None,
+                    })
+                    .collect();
+                Ok(serde_json::json!(states))
+            }
+            (ipc::Command::Playlist, _) => {
+                let images: Vec<serde_json::Value> = (0..shared.playlist.len())
+                    .map(|index| {
+                        serde_json::json!({
+                            "path": shared.playlist.get(index),
+                            "marked": shared.playlist.is_marked(index),
+                            "available": shared.playlist.is_available(index),
+                        })
+                    })
+                    .collect();
+                Ok(serde_json::json!(images))
+            }
+            (ipc::Command::Quit, _) => {
+                followup.exit = true;
+                Ok(serde_json::Value::Null)
+            }
+            (ipc::Command::ClickThrough, _) => {
+                followup.toggle_click_through = true;
+                Ok(serde_json::json!({ "click_through": !self.click_through }))
+            }
+            (command, Some(RenderState::Active(render_state))) => match command {
+                ipc::Command::State => Ok(render_state.state(shared)),
+                ipc::Command::Next
+                | ipc::Command::Prev
+                | ipc::Command::Goto { .. }
+                | ipc::Command::Open { .. }
+                | ipc::Command::Append { .. }
+                | ipc::Command::Slideshow
+                    if shared.comparison.is_some() =>
+                {
+                    Err(ipc::RpcError::new(ipc::COMMAND_FAILED, "can't navigate while comparing"))
+                }
+                ipc::Command::Goto { index } if *index >= shared.playlist.len() => {
+                    Err(ipc::RpcError::new(ipc::INVALID_PARAMS, format!("there are only {} images", shared.playlist.len())))
+                }
+                ipc::Command::Next | ipc::Command::Prev | ipc::Command::Goto { .. } => {
+                    let shown = match command {
+                        ipc::Command::Goto { index } => render_state.go_to(*index, true, shared),
+                        _ => render_state.navigate(*command == ipc::Command::Next, shared),
+                    };
+                    if !shown {
+                        followup.exit = true;
+                    }
+                    Ok(serde_json::json!({ "index": render_state.index }))
+                }
+                ipc::Command::Open { path } if !path.is_file() => {
+                    Err(ipc::RpcError::new(ipc::INVALID_PARAMS, format!("{} is not a file", path.display())))
+                }
+                ipc::Command::Open { path } => {
+                    let index = shared.playlist.len();
+                    shared.playlist.push(path.clone());
+                    if !render_state.go_to(index, true, shared) {
+                        followup.exit = true;
+                    }
+                    Ok(serde_json::json!({ "index": render_state.index }))
+                }
+                ipc::Command::Append { paths, show } => {
+                    let first = shared.playlist.len();
+                    for path in paths {
+                        shared.playlist.push(path.clone());
+                    }
+                    if !show {
+                        // The title and status bar count the images
+                        render_state.update_title(shared);
+                        render_state.window.request_redraw();
+                    } else if !render_state.go_to(first, true, shared) {
+                        followup.exit = true;
+                    }
+                    Ok(serde_json::json!({ "first": first, "count": shared.playlist.len() }))
+                }
+                ipc::Command::Zoom { percent } => {
+                    let size = render_state.window.inner_size();
+                    let center = Vec2::new(size.width as f64, size.height as f64) / 2.0;
+                    let (x_scale, y_scale) = viewport::axis_scales(render_state.transform);
+                    let scale = percent / 100.0;
+                    render_state.transform = viewport::zoom_about(render_state.transform, center, scale / x_scale, scale / y_scale);
+                    render_state.fitted = false;
+                    render_state.update_title(shared);
+                    render_state.window.request_redraw();
+                    Ok(serde_json::Value::Null)
+                }
+                ipc::Command::Slideshow => {
+                    render_state.toggle_slideshow(shared);
+                    Ok(serde_json::json!({ "running": render_state.next_slide_at.is_some() }))
+                }
+                ipc::Command::ToggleMark => {
+                    render_state.toggle_mark(shared);
+                    Ok(serde_json::json!({ "marked": shared.playlist.is_marked(render_state.index) }))
+                }
+                ipc::Command::Fit => {
+                    render_state.fit(shared);
+                    render_state.update_title(shared);
+                    render_state.window.request_redraw();
+                    Ok(serde_json::Value::Null)
+                }
+                ipc::Command::LockZoom => {
+                    render_state.toggle_zoom_lock(shared);
+                    Ok(serde_json::json!({ "locked": shared.locked_scale.is_some() }))
+                }
+                ipc::Command::NewWindow => {
+                    followup.open_window_at = Some(render_state.index);
+                    Ok(serde_json::Value::Null)
+                }
+                ipc::Command::CloseWindow => {
+                    followup.close_window = true;
+                    Ok(serde_json::Value::Null)
+                }
+                ipc::Command::ListCommands
+                | ipc::Command::Subscribe { .. }
+                | ipc::Command::Unsubscribe
+                | ipc::Command::Windows
+                | ipc::Command::Playlist
+                | ipc::Command::ClickThrough
+                | ipc::Command::Quit => {
+                    unreachable!("handled without a window")
+                }
+            },
+            _ => Err(ipc::RpcError::new(ipc::COMMAND_FAILED, "there is no window to run the command in")),
+        };
+        request.client.reply(&request.id, result);
+
+        match target {
+            Some(window_id) => self.apply(window_id, followup, event_loop),
+            None if followup.exit => self.exit(event_loop),
+            None => {}
+        }
+    }
+
+    /// Let clicks and keys pass through every window to the windows below if `click_through`, or let the windows
+    /// take them again
+    fn set_click_through(&mut self, click_through: bool) {
+        self.click_through = click_through;
+        for state in self.windows.values() {
+            if let RenderState::Active(render_state) = state {
+                if let Err(err) = render_state.window.set_cursor_hittest(!click_through) {
+                    eprintln!("veh: failed to let clicks pass through the window: {err}");
+                }
+            }
+        }
+    }
+
+    /// Reload the image at `path` in every window showing it, as an action run on it or an editor has likely
+    /// modified it. Windows keep showing the last version that loaded if it fails, with the error over it.
+    fn reload(&mut self, path: PathBuf) {
+        self.shared.invalidate(&path);
+        for state in self.windows.values_mut() {
+            let RenderState::Active(render_state) = state else {
+                continue;
+            };
+            if self.shared.playlist.get(render_state.index) != path {
+                continue;
+            }
+            let reloaded = match self.shared.cache.get(&path, render_state.page) {
+                Ok(reloaded) => reloaded,
+                Err(err) => {
+                    eprintln!("veh: {}: {err:#}", path.display());
+                    render_state.load_error = Some(format!("{err:#}"));
+                    render_state.window.request_redraw();
+                    continue;
+                }
+            };
+            render_state.load_error = None;
+            render_state.rotation = 0;
+            render_state.rotation_warned = false;
+            // Only refit if the command changed the size of the image, e.g. by cropping it
+            let resized = reloaded.size() != render_state.image.size();
+            render_state.replace_image(reloaded, &mut self.shared);
+            if resized {
+                render_state.fit(&self.shared);
+            }
+            render_state.window.request_redraw();
+        }
+    }
+
+    /// Once the pending events are handled, show the frames of playing sequences and animations and draw the frames held back by
+    /// --fps that are due, and check on the GPU. The event loop then sleeps until the next of these is due, or
+    /// until the next event.
+    fn update(&mut self, event_loop: &EventLoopWindowTarget<UserEvent>) {
+        let mut wake_at: Option<Instant> = None;
+        let mut exit = false;
+        let changed = match &mut self.watch {
+            Some(watch) if watch.next_check() <= Instant::now() => {
+                let shown = self.windows.values().filter_map(|state| match state {
+                    RenderState::Active(render_state) => Some(self.shared.playlist.get(render_state.index)),
+                    RenderState::Suspended(..) => None,
+                });
+                watch.changed(shown)
+            }
+            _ => vec![],
+        };
+        for path in changed {
+            self.reload(path);
+        }
+        if let Some(watch) = &self.watch {
+            wake_at = Some(watch.next_check());
+        }
+        for state in self.windows.values_mut() {
+            let RenderState::Active(render_state) = state else {
+                continue;
+            };
+            if render_state.next_slide_at.is_some_and(|next_slide_at| next_slide_at <= Instant::now()) {
+                exit |= !render_state.navigate(true, &mut self.shared);
+            }
+            if let Some(next_slide_at) = render_state.next_slide_at {
+                wake_at = Some(wake_at.map_or(next_slide_at, |wake_at| wake_at.min(next_slide_at)));
+            }
+            // Playback stops once the window is navigated away from the sequence
+            let index = render_state.index;
+            match render_state.playback.as_mut().filter(|playback| playback.frames.contains(&index)) {
+                Some(playback) => {
+                    if playback.next_frame_at <= Instant::now() {
+                        let next = playback.advance(index, self.play_interval);
+                        render_state.show_frame(next, &mut self.shared);
+                    }
+                    if let Some(next_frame_at) = render_state.playback.as_ref().map(|playback| playback.next_frame_at) {
+                        wake_at = Some(wake_at.map_or(next_frame_at, |wake_at| wake_at.min(next_frame_at)));
+                    }
+                }
+                None => render_state.playback = None,
+            }
+            if render_state.next_slide_at.is_some() || render_state.playback.is_some() {
+                render_state.render_next_ahead(&mut self.shared, self.options.svg_rerender);
+            }
+            let image = render_state.image.clone();
+            if let (Some(animation), VehImage::Animation(frames)) = (render_state.animation.as_mut(), &*image) {
+                if animation.next_frame_at <= Instant::now() {
+                    let next = animation.next(render_state.frame);
+                    animation.advance(render_state.frame, frames[next].1);
+                    render_state.show_animation_frame(next);
+                }
+            }
+            if let Some(next_frame_at) = render_state.animation.as_ref().map(|animation| animation.next_frame_at) {
+                wake_at = Some(wake_at.map_or(next_frame_at, |wake_at| wake_at.min(next_frame_at)));
+            }
+            // Draw a settled SVG again, or render a tile of it in the meantime
+            if self.options.svg_tiles && matches!(*render_state.image, VehImage::Svg(_)) {
+                match render_state.svg_tiles.settles_at() {
+                    Some(settles_at) if settles_at <= Instant::now() => {
+                        render_state.svg_tiles.settle();
+                        render_state.window.request_redraw();
+                    }
+                    Some(settles_at) => wake_at = Some(wake_at.map_or(settles_at, |wake_at| wake_at.min(settles_at))),
+                    None => {
+                        let dev_id = render_state.output.dev_id();
+                        let size = render_state.window.inner_size();
+                        let missing = render_state.svg_tiles.next_missing(render_state.transform, size, render_state.image.size());
+                        if let (Some((tile, scale)), Some(Some(renderer))) = (missing, self.renderers.get_mut(dev_id)) {
+                            let device_handle = &self.render_cx.devices[dev_id];
+                            let scene = SvgTiles::tile_scene(&render_state.subscene, render_state.subscene_scale, tile, scale);
+                            let params = vello::RenderParams {
+                                base_color: Color::TRANSPARENT,
+                                width: svg_tiles::TILE_SIZE,
+                                height: svg_tiles::TILE_SIZE,
+                                antialiasing_method: self.options.antialiasing,
+                            };
+                            match present::render_to_image(renderer, &device_handle.device, &device_handle.queue, &scene, &params) {
+                                Ok(image) => render_state.svg_tiles.insert(tile, scale, image),
+                                Err(err) => {
+                                    eprintln!("veh: failed to render a tile of the SVG: {err:#}");
+                                    self.gpu_failed = true;
+                                }
+                            }
+                            // The next tile is rendered once pending events are handled
+                            wake_at = Some(Instant::now());
+                        }
+                    }
+                }
+            }
+            match render_state.redraw_at {
+                Some(redraw_at) if redraw_at <= Instant::now() => {
+                    render_state.redraw_at = None;
+                    render_state.window.request_redraw();
+                }
+                Some(redraw_at) => wake_at = Some(wake_at.map_or(redraw_at, |wake_at| wake_at.min(redraw_at))),
+                None => {}
+            }
+        }
+
+        for device_handle in &self.render_cx.devices {
+            device_handle.device.poll(wgpu::Maintain::Poll);
+        }
+        for watchdog in self.watchdogs.iter_mut().flatten() {
+            self.gpu_failed |= watchdog.has_failed();
+            if let Some(check_at) = watchdog.next_check() {
+                wake_at = Some(wake_at.map_or(check_at, |wake_at| wake_at.min(check_at)));
+            }
+        }
+        event_loop.set_control_flow(wake_at.map_or(ControlFlow::Wait, ControlFlow::WaitUntil));
+        if exit {
+            self.exit(event_loop);
+            return;
+        }
+
+        if self.gpu_failed {
+            self.reset_gpu(event_loop);
+        }
+    }
+
+    /// Start over with fresh GPU state after the GPU hung or was lost. The windows keep their image and view.
+    fn reset_gpu(&mut self, event_loop: &EventLoopWindowTarget<UserEvent>) {
+        self.gpu_failed = false;
+        eprintln!("veh: resetting the renderer");
+
+        // The surfaces have to be dropped before the devices they were created on
+        let mut views = vec![];
+        self.windows.retain(|_, state| match state {
+            RenderState::Active(active) => {
+                views.push((active.window.clone(), active.index, active.transform, active.fitted));
+                false
+            }
+            RenderState::Suspended(..) => true,
+        });
+        self.renderers.clear();
+        self.watchdogs.clear();
+        self.render_cx = RenderContext::new().unwrap();
+
+        for (window, index, transform, fitted) in views {
+            let Some(mut state) = self.activate_window(window, index) else {
+                self.exit(event_loop);
+                return;
+            };
+            if !fitted {
+                state.transform = transform;
+                state.fitted = false;
+            }
+            self.windows.insert(state.window.id(), RenderState::Active(Box::new(state)));
+        }
+    }
+
+    /// Open a new window on the image at `index` of the playlist
+    fn open_window(&mut self, index: usize, event_loop: &EventLoopWindowTarget<UserEvent>) {
+        let image_size = self.shared.load(index, true).map(|(_, image)| image.size());
+        let window = create_winit_window(event_loop, &self.window_options, None, image_size);
+        if let Some(state) = self.activate_window(window, index) {
+            self.shared.subscriptions.emit("window-opened", serde_json::json!({ "window": u64::from(state.window.id()) }));
+            self.windows.insert(state.window.id(), RenderState::Active(Box::new(state)));
+        }
+    }
+
+    /// Close the window with `window_id` and exit the event loop once the last window is closed
+    fn close(&mut self, window_id: WindowId, event_loop: &EventLoopWindowTarget<UserEvent>) {
+        if let Some(RenderState::Active(render_state)) = self.windows.remove(&window_id) {
+            render_state.remember(&mut self.shared);
+        }
+        self.shared.subscriptions.emit("window-closed", serde_json::json!({ "window": u64::from(window_id) }));
+        if self.windows.is_empty() {
+            self.exit(event_loop);
+        }
+    }
+
+    /// Create a surface for `window` and show the image at `index` of the playlist in it, or the next one that can be loaded.
+    /// Returns `None` if no image can be loaded at all, or if there is no GPU adapter to draw with.
+    fn activate_window(&mut self, window: Arc<Window>, index: usize) -> Option<ActiveRenderState<'s>> {
+        let (index, image) = self.shared.load(index, true)?;
+        let render_cx = &mut self.render_cx;
+
+        // Choose the adapter before vello creates the first device on it. Without one that can draw to the
+        // window, the frames are rendered on the CPU and shown through softbuffer.
+        if render_cx.devices.is_empty() {
+            if let Err(err) = crate::adapter::choose_adapter(&render_cx.instance, window.clone(), self.options.adapter.as_deref()) {
+                eprintln!("veh: {err:#}");
+            }
+        }
+
+        // Create a surface in a format the window supports
+        let size = window.inner_size();
+        let output_future = present::create_output(
+            render_cx,
+            window.clone(),
+            size.width,
+            size.height,
+            self.options.present_mode,
+        );
+        let output = match pollster::block_on(output_future) {
+            Ok(output) => output,
+            Err(err) => {
+                eprintln!("veh: failed to create a surface: {err:#}");
+                return None;
+            }
+        };
+
+        // Create a vello Renderer for the device of the output, rendering on the CPU if it has no surface
+        let dev_id = output.dev_id();
+        let use_cpu = self.options.use_cpu || matches!(output, Output::Software(_));
+        self.renderers.resize_with(render_cx.devices.len(), || None);
+        self.renderers[dev_id].get_or_insert_with(|| create_vello_renderer(render_cx, dev_id, &self.options, use_cpu));
+        self.watchdogs.resize_with(render_cx.devices.len(), || None);
+        self.watchdogs[dev_id].get_or_insert_with(|| Watchdog::new(&render_cx.devices[dev_id].device));
+
+        let scale_factor = window.scale_factor();
+        let mut state = ActiveRenderState {
+            window,
+            output,
+            transform: Affine::IDENTITY,
+            prior_position: None,
+            mouse_down: false,
+            modifiers: ModifiersState::empty(),
+            scale_factor,
+            fitted: true,
+            actual_size: false,
+            last_frame: None,
+            redraw_at: None,
+            index,
+            page: 0,
+            page_count: 1,
+            image: image.clone(),
+            subscene: Rc::default(),
+            subscene_scale: 1.0,
+            last_scale: None,
+            capture_next_frame: false,
+            crop_proposal: None,
+            selection: None,
+            last_click: None,
+            spread_with: None,
+            playback: None,
+            scrubbing: false,
+            frame: 0,
+            animation: None,
+            hdr_view: self.hdr_view,
+            count: None,
+            prompt: None,
+            palette: None,
+            // Only the first window shows the hint
+            welcome: std::mem::take(&mut self.welcome),
+            caption: None,
+            caption_edit: None,
+            next_slide_at: None,
+            face: None,
+            alpha_check: None,
+            alpha_mask: None,
+            texture: None,
+            texture_image: None,
+            opacity: self.opacity,
+            upscale: self.upscale,
+            integer_scaling: self.integer_scaling,
+            scroll_steps: 0.0,
+            load_error: None,
+            svg_tiles: SvgTiles::new(),
+            monitor: None,
+            display: None,
+            rotation: 0,
+            rotation_warned: false,
+            stats: None,
+        };
+        if self.opacity < 1.0 {
+            if let Err(err) = opacity::set(&state.window, self.opacity) {
+                eprintln!("veh: {err:#}");
+            }
+        }
+        if self.click_through {
+            if let Err(err) = state.window.set_cursor_hittest(false) {
+                eprintln!("veh: failed to let clicks pass through the window: {err}");
+            }
+        }
+        state.update_display(&mut self.shared);
+        state.show(index, image, &mut self.shared);
+        if self.slideshow {
+            state.toggle_slideshow(&self.shared);
+        }
+        if self.timelapse {
+            let frames = sequence::containing(&self.shared.sequences, state.index);
+            state.playback = frames.map(|frames| Playback { catch_up: true, ..Playback::new(frames, self.play_interval) });
+        }
+        Some(state)
+    }
+}
+
+/// How windows are opened, from the command line
+struct WindowOptions {
+    borderless: bool,
+    on_top: bool,
+    // Overrides the geometry of the last session
+    geometry: Option<Geometry>,
+    class: Option<String>,
+}
+
+impl WindowOptions {
+    fn new(args: &Args) -> Self {
+        Self {
+            borderless: args.borderless || args.click_through,
+            on_top: args.on_top || args.click_through,
+            geometry: args.geometry,
+            class: args.class.clone(),
+        }
+    }
+}
+
+/// Windows opened on smaller images are this big in logical pixels
+const MIN_WINDOW_SIZE: f64 = 200.0;
+/// The share of the monitor a window opened at the size of its image takes at most, leaving room for panels
+const MAX_WINDOW_SHARE: f64 = 0.9;
+
+/// The size of a window on `monitor` that shows an image of `image_size` at 100%, within the bounds of the monitor
+pub fn window_size_on(monitor: &MonitorHandle, image_size: (f64, f64)) -> PhysicalSize<u32> {
+    let screen = monitor.size();
+    let screen = PhysicalSize::new(screen.width as f64 * MAX_WINDOW_SHARE, screen.height as f64 * MAX_WINDOW_SHARE);
+    viewport::window_size_for(image_size, screen.cast(), MIN_WINDOW_SIZE * monitor.scale_factor())
+}
+
+/// Helper function that creates a Winit window and returns it (wrapped in an Arc for sharing between threads).
+/// Without a `geometry` it is as big as the image of `image_size` at 100%, within the bounds of the monitor.
+fn create_winit_window(
+    event_loop: &EventLoopWindowTarget<UserEvent>,
+    options: &WindowOptions,
+    geometry: Option<Geometry>,
+    image_size: Option<(f64, f64)>,
+) -> Arc<Window> {
+    let mut builder = WindowBuilder::new()
+        .with_inner_size(LogicalSize::new(1044, 800))
+        .with_resizable(true)
+        .with_title("veh")
+        .with_decorations(!options.borderless);
+    if options.on_top {
+        builder = builder.with_window_level(WindowLevel::AlwaysOnTop);
+    }
+    #[cfg(all(unix, not(target_os = "macos")))]
+    if let Some(class) = &options.class {
+        use winit::platform::x11::WindowBuilderExtX11;
+        // The class also becomes the app ID on Wayland
+        builder = builder.with_name(class, class);
+    }
+    let monitor = event_loop.primary_monitor().or_else(|| event_loop.available_monitors().next());
+    if let (Some(image_size), Some(monitor)) = (image_size, monitor) {
+        builder = builder.with_inner_size(window_size_on(&monitor, image_size));
+    }
+    let geometry = options.geometry.or(geometry);
+    if let Some(geometry) = geometry {
+        builder = builder.with_inner_size(PhysicalSize::new(geometry.size.0, geometry.size.1));
+        if let Some((x, y)) = geometry.position {
+            builder = builder.with_position(PhysicalPosition::new(x, y));
+        }
+    }
+    Arc::new(builder.build(event_loop).unwrap())
+}
+
+/// Helper function that creates a vello `Renderer` for the device `dev_id` of a `RenderContext`
+pub fn create_vello_renderer(render_cx: &RenderContext, dev_id: usize, options: &RenderOptions, use_cpu: bool) -> Renderer {
+    // The frames are copied onto the surface by the presenter rather than by vello
+    Renderer::new(
+        &render_cx.devices[dev_id].device,
+        RendererOptions {
+            surface_format: None,
+            use_cpu,
+            // Only the shaders of the method in use are compiled
+            antialiasing_support: vello::AaSupport {
+                area: options.antialiasing == AaConfig::Area,
+                msaa8: options.antialiasing == AaConfig::Msaa8,
+                msaa16: options.antialiasing == AaConfig::Msaa16,
+            },
+            num_init_threads: NonZeroUsize::new(1),
+        },
+    )
+    .expect("Couldn't create renderer")
+}
+
+/// Whether a click at `position` at `time` makes a double-click with the `last_click` before it
+pub fn is_double_click(last_click: Option<(Instant, Vec2)>, time: Instant, position: Vec2, scale_factor: f64) -> bool {
+    last_click.is_some_and(|(last_time, last_position)| {
+        time.duration_since(last_time) <= DOUBLE_CLICK_TIME && (position - last_position).hypot() <= DOUBLE_CLICK_DISTANCE * scale_factor
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use winit::dpi::PhysicalPosition;
+
+    #[test]
+    fn navigation_keys() {
+        let none = ModifiersState::empty();
+        assert_eq!(key_intent(KeyCode::KeyN, none, false, false, false), Some(KeyIntent::Navigate { forward: true }));
+        assert_eq!(key_intent(KeyCode::Backspace, none, false, false, false), Some(KeyIntent::Navigate { forward: false }));
+        // Comparisons show every image at once
+        assert_eq!(key_intent(KeyCode::KeyN, none, true, false, false), None);
+        assert_eq!(key_intent(KeyCode::Delete, none, true, false, false), None);
+    }
+
+    #[test]
+    fn modifiers_change_keys() {
+        assert_eq!(key_intent(KeyCode::KeyZ, ModifiersState::empty(), false, false, false), Some(KeyIntent::ToggleZoomLock));
+        assert_eq!(key_intent(KeyCode::KeyZ, ModifiersState::CONTROL, false, false, false), Some(KeyIntent::Undo));
+        assert_eq!(key_intent(KeyCode::KeyZ, ModifiersState::SHIFT, false, false, false), Some(KeyIntent::FitWindowToImage));
+        assert_eq!(key_intent(KeyCode::KeyM, ModifiersState::SHIFT, false, false, false), Some(KeyIntent::FlushMarks));
+        assert_eq!(key_intent(KeyCode::KeyC, ModifiersState::SHIFT, false, false, false), Some(KeyIntent::Crop { save: true }));
+        let copy_path = ModifiersState::CONTROL | ModifiersState::SHIFT;
+        assert_eq!(key_intent(KeyCode::KeyC, copy_path, false, false, false), Some(KeyIntent::Copy { path: true }));
+        assert_eq!(key_intent(KeyCode::KeyV, ModifiersState::CONTROL, false, false, false), Some(KeyIntent::Paste));
+        // `+` is Shift+= on most layouts
+        assert_eq!(key_intent(KeyCode::Equal, ModifiersState::SHIFT, false, false, false), Some(KeyIntent::StepZoom { zoom_in: true }));
+        assert_eq!(key_intent(KeyCode::Equal, ModifiersState::empty(), false, false, false), Some(KeyIntent::Unstretch));
+    }
+
+    #[test]
+    fn manga_arrows_turn_pages_right_to_left() {
+        let none = ModifiersState::empty();
+        assert_eq!(key_intent(KeyCode::ArrowLeft, none, false, false, true), Some(KeyIntent::Navigate { forward: true }));
+        assert_eq!(key_intent(KeyCode::ArrowRight, none, false, false, true), Some(KeyIntent::Navigate { forward: false }));
+        assert_eq!(key_intent(KeyCode::KeyH, none, false, false, true), Some(KeyIntent::Pan { direction: Vec2::new(-1.0, 0.0), fast: false }));
+    }
+
+    #[test]
+    fn escape_cancels_crop_before_closing() {
+        let none = ModifiersState::empty();
+        assert_eq!(key_intent(KeyCode::Escape, none, false, true, false), Some(KeyIntent::CancelCrop));
+        assert_eq!(key_intent(KeyCode::Escape, none, false, false, false), Some(KeyIntent::Close));
+    }
+
+    #[test]
+    fn g_jumps_to_the_ends_unless_comparing() {
+        let none = ModifiersState::empty();
+        assert_eq!(key_intent(KeyCode::KeyG, none, false, false, false), Some(KeyIntent::JumpToEnd { last: false }));
+        assert_eq!(key_intent(KeyCode::KeyG, ModifiersState::SHIFT, false, false, false), Some(KeyIntent::JumpToEnd { last: true }));
+        assert_eq!(key_intent(KeyCode::Slash, none, false, false, false), Some(KeyIntent::OpenPrompt));
+        assert_eq!(key_intent(KeyCode::KeyG, none, true, false, false), None);
+    }
+
+    #[test]
+    fn brackets_expose_in_thirds_of_a_stop() {
+        let none = ModifiersState::empty();
+        assert_eq!(key_intent(KeyCode::BracketRight, none, false, false, false), Some(KeyIntent::Expose(1.0 / 3.0)));
+        assert_eq!(key_intent(KeyCode::BracketLeft, none, false, false, false), Some(KeyIntent::Expose(-1.0 / 3.0)));
+        assert_eq!(key_intent(KeyCode::Backslash, none, false, false, false), Some(KeyIntent::AutoExpose));
+    }
+
+    #[test]
+    fn shift_z_fits_the_window_unless_comparing() {
+        assert_eq!(key_intent(KeyCode::KeyZ, ModifiersState::SHIFT, false, false, false), Some(KeyIntent::FitWindowToImage));
+        assert_eq!(key_intent(KeyCode::KeyZ, ModifiersState::SHIFT, true, false, false), None);
+        assert_eq!(key_intent(KeyCode::KeyZ, ModifiersState::empty(), true, false, false), Some(KeyIntent::ToggleZoomLock));
+    }
+
+    #[test]
+    fn shift_i_shows_statistics() {
+        assert_eq!(key_intent(KeyCode::KeyI, ModifiersState::SHIFT, false, false, false), Some(KeyIntent::ToggleStats));
+        assert_eq!(key_intent(KeyCode::KeyI, ModifiersState::empty(), false, false, false), Some(KeyIntent::ToggleIntegerScaling));
+    }
+
+    #[test]
+    fn shift_t_previews_textures() {
+        // The preview applies to what is shown, so it is also available while comparing, unlike captions
+        for comparing in [false, true] {
+            assert_eq!(key_intent(KeyCode::KeyT, ModifiersState::SHIFT, comparing, false, false), Some(KeyIntent::CycleTexturePreview));
+        }
+        assert_eq!(key_intent(KeyCode::KeyT, ModifiersState::empty(), false, false, false), Some(KeyIntent::EditCaption));
+        assert_eq!(key_intent(KeyCode::KeyT, ModifiersState::empty(), true, false, false), None);
+    }
+
+    #[test]
+    fn ctrl_p_opens_the_palette() {
+        assert_eq!(key_intent(KeyCode::KeyP, ModifiersState::CONTROL, false, false, false), Some(KeyIntent::OpenPalette));
+        // The palette runs what is available while comparing, so it opens then too
+        assert_eq!(key_intent(KeyCode::KeyP, ModifiersState::CONTROL, true, false, false), Some(KeyIntent::OpenPalette));
+        assert_eq!(key_intent(KeyCode::KeyP, ModifiersState::empty(), false, false, false), Some(KeyIntent::Navigate { forward: false }));
+    }
+
+    #[test]
+    fn number_keys_run_actions() {
+        let none = ModifiersState::empty();
+        assert_eq!(key_intent(KeyCode::Enter, none, false, false, false), Some(KeyIntent::RunAction(0)));
+        assert_eq!(key_intent(KeyCode::Digit7, none, false, false, false), Some(KeyIntent::RunAction(7)));
+        assert_eq!(key_intent(KeyCode::F1, none, false, false, false), None);
+    }
+
+    #[test]
+    fn scrolling_zooms_evenly_unless_stretching() {
+        let up = MouseScrollDelta::LineDelta(0.0, 1.0);
+        let controls = Controls::default();
+        assert_eq!(scroll_zoom(up, ModifiersState::empty(), 1.0, &controls), (1.05, 1.05));
+        assert_eq!(scroll_zoom(up, ModifiersState::CONTROL, 1.0, &controls), (1.05, 1.0));
+        assert_eq!(scroll_zoom(up, ModifiersState::ALT, 1.0, &controls), (1.0, 1.05));
+        let inverted = Controls { invert_scroll: true, ..controls };
+        assert_eq!(scroll_zoom(up, ModifiersState::empty(), 1.0, &inverted), (1.0 / 1.05, 1.0 / 1.05));
+    }
+
+    #[test]
+    fn pixel_scrolling_is_independent_of_scale_factor() {
+        let controls = Controls::default();
+        let lines = scroll_zoom(MouseScrollDelta::PixelDelta(PhysicalPosition::new(0.0, 20.0)), ModifiersState::empty(), 1.0, &controls);
+        let hidpi = scroll_zoom(MouseScrollDelta::PixelDelta(PhysicalPosition::new(0.0, 40.0)), ModifiersState::empty(), 2.0, &controls);
+        assert_eq!(lines, hidpi);
+        assert_eq!(lines, scroll_zoom(MouseScrollDelta::LineDelta(0.0, 1.0), ModifiersState::empty(), 1.0, &controls));
+    }
+
+    #[test]
+    fn double_clicks_are_quick_and_in_place() {
+        let start = Instant::now();
+        let click = Some((start, Vec2::new(100.0, 100.0)));
+        assert!(is_double_click(click, start + Duration::from_millis(200), Vec2::new(102.0, 101.0), 1.0));
+        assert!(!is_double_click(click, start + Duration::from_millis(600), Vec2::new(100.0, 100.0), 1.0));
+        assert!(!is_double_click(click, start + Duration::from_millis(200), Vec2::new(110.0, 100.0), 1.0));
+        assert!(is_double_click(click, start + Duration::from_millis(200), Vec2::new(106.0, 100.0), 2.0));
+        assert!(!is_double_click(None, start, Vec2::new(100.0, 100.0), 1.0));
+    }
+}
@@ -0,0 +1,83 @@
+//! Images inside ZIP and RAR archives, e.g. comic books (CBZ and CBR). An archive given to veh is
+//! replaced by the images in it, which are addressed as if the archive was a directory, like
+//! `comic.cbz/pages/001.jpg`. Each image is only decompressed when it is shown.
+
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use anyhow::{bail, Context, Result};
+use zip::ZipArchive;
+
+use crate::loader::is_supported;
+
+/// Extensions of the archives veh looks into
+pub const ARCHIVE_FORMATS: &[&str] = &["zip", "cbz", "rar", "cbr"];
+
+/// Whether `path` names an archive, judging by its extension
+pub fn is_archive(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| ARCHIVE_FORMATS.contains(&ext.to_ascii_lowercase().as_str()))
+}
+
+fn is_rar(archive: &Path) -> bool {
+    archive.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("rar") || ext.eq_ignore_ascii_case("cbr"))
+}
+
+/// The supported images in the archive at `path`, sorted by path
+pub fn list_images(path: &Path) -> Result<Vec<PathBuf>> {
+    let names = if is_rar(path) {
+        let listing = run_rar_tool(&["lb", "-p-"], &["-tf"], path, None)?;
+        String::from_utf8_lossy(&listing).lines().map(str::to_string).collect()
+    } else {
+        let file = File::open(path).with_context(|| format!("failed to open {}", path.display()))?;
+        let archive = ZipArchive::new(BufReader::new(file)).context("failed to read the ZIP directory")?;
+        archive.file_names().map(str::to_string).collect::<Vec<_>>()
+    };
+    let mut images: Vec<PathBuf> = names
+        .iter()
+        .filter(|name| !name.ends_with('/'))
+        .map(|name| path.join(name))
+        .filter(|image| is_supported(image))
+        .collect();
+    images.sort();
+    Ok(images)
+}
+
+/// Split the path of an image inside an archive into the path of the archive and the name of the entry
+pub fn split(path: &Path) -> Option<(&Path, String)> {
+    let archive = path.ancestors().skip(1).find(|ancestor| is_archive(ancestor) && ancestor.is_file())?;
+    let entry = path.strip_prefix(archive).ok()?;
+    // Archives separate directories with slashes on every platform
+    let names: Vec<_> = entry.components().map(|component| component.as_os_str().to_string_lossy()).collect();
+    Some((archive, names.join("/")))
+}
+
+/// Decompress the entry `entry` of the archive at `archive`
+pub fn read(archive: &Path, entry: &str) -> Result<Vec<u8>> {
+    if is_rar(archive) {
+        return run_rar_tool(&["p", "-inul", "-p-"], &["-xOf"], archive, Some(entry));
+    }
+    let file = File::open(archive).with_context(|| format!("failed to open {}", archive.display()))?;
+    let mut archive = ZipArchive::new(BufReader::new(file)).context("failed to read the ZIP directory")?;
+    let mut file = archive.by_name(entry).with_context(|| format!("no {entry} in the archive"))?;
+    let mut data = Vec::with_capacity(file.size() as usize);
+    file.read_to_end(&mut data).context("failed to decompress the image")?;
+    Ok(data)
+}
+
+/// Run unrar with `unrar_args`, or bsdtar with `bsdtar_args` if unrar isn't installed, on `archive` and return its output
+fn run_rar_tool(unrar_args: &[&str], bsdtar_args: &[&str], archive: &Path, entry: Option<&str>) -> Result<Vec<u8>> {
+    for (tool, args) in [("unrar", unrar_args), ("bsdtar", bsdtar_args)] {
+        let output = Command::new(tool).args(args).arg(archive).args(entry).stdin(Stdio::null()).stderr(Stdio::null()).output();
+        match output {
+            Ok(output) if output.status.success() => return Ok(output.stdout),
+            Ok(output) => bail!("{tool} failed to read {} ({})", archive.display(), output.status),
+            // Try the next tool if this one isn't installed
+            Err(_) => continue,
+        }
+    }
+    bail!("unrar or bsdtar is needed to open RAR archives")
+}
@@ -0,0 +1,288 @@
+//! `veh audit <images>`: a check before assembling sprite atlases or print sheets that all images are alike.
+//! Every image whose dimensions, resolution in DPI or color profile differ from those of the first image is
+//! reported, with the values that differ highlighted, and `--open` shows the first image and the outliers.
+
+use std::fmt;
+use std::fs::File;
+use std::io::{IsTerminal, Read};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use exif::{In, Tag};
+use image::ImageDecoder;
+use rayon::prelude::*;
+
+use crate::errors;
+use crate::metadata::Metadata;
+
+/// How much of a file is searched for the resolution of PNG and JFIF files, which comes before the pixels
+const HEADER_SIZE: u64 = 64 * 1024;
+
+/// What images are compared by
+#[derive(Debug, PartialEq)]
+struct Properties {
+    width: u32,
+    height: u32,
+    /// Horizontal and vertical
+    dpi: Option<(f64, f64)>,
+    /// The description of the ICC profile, or its size if it has none
+    profile: Option<String>,
+}
+
+impl Properties {
+    fn read(path: &Path) -> Result<Self> {
+        let metadata = Metadata::read(path)?;
+        let dpi = exif_dpi(&metadata).or_else(|| header_dpi(path));
+        Ok(Self { width: metadata.width, height: metadata.height, dpi, profile: profile(path) })
+    }
+
+    /// The properties, with those that differ from `reference` highlighted if `highlight`
+    fn describe(&self, reference: &Properties, highlight: bool) -> String {
+        let mark = |differs: bool, text: String| if differs && highlight { format!("\x1b[1;31m{text}\x1b[0m") } else { text };
+        let size = mark((self.width, self.height) != (reference.width, reference.height), format!("{}×{}", self.width, self.height));
+        let dpi = mark(self.dpi != reference.dpi, Dpi(self.dpi).to_string());
+        let profile = mark(self.profile != reference.profile, self.profile.clone().unwrap_or_else(|| "no color profile".to_string()));
+        format!("{size}, {dpi}, {profile}")
+    }
+}
+
+struct Dpi(Option<(f64, f64)>);
+
+impl fmt::Display for Dpi {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            None => write!(f, "no DPI"),
+            Some((x, y)) if x == y => write!(f, "{x:.0} DPI"),
+            Some((x, y)) => write!(f, "{x:.0}×{y:.0} DPI"),
+        }
+    }
+}
+
+/// Report the images among `paths` that differ from the first one. Returns the first image and those that
+/// differ from it, or fails if an image can't be read.
+pub fn audit(paths: &[PathBuf]) -> Result<Vec<PathBuf>> {
+    let (first, rest) = paths.split_first().context("no images to audit")?;
+    let reference = Properties::read(first).with_context(|| format!("failed to read {}", first.display()))?;
+    let highlight = std::io::stdout().is_terminal();
+    println!("{}: {}", first.display(), reference.describe(&reference, false));
+
+    let results: Vec<Result<Properties>> = rest.par_iter().map(|path| Properties::read(path)).collect();
+    let mut outliers = vec![first.clone()];
+    let mut first_error = None;
+    for (path, result) in rest.iter().zip(results) {
+        match result {
+            Ok(properties) if properties != reference => {
+                println!("{}: {}", path.display(), properties.describe(&reference, highlight));
+                outliers.push(path.clone());
+            }
+            Ok(_) => {}
+            Err(err) => {
+                errors::report(Some(path), &err);
+                first_error.get_or_insert(err);
+            }
+        }
+    }
+    eprintln!("veh: {} of {} images differ from {}", outliers.len() - 1, rest.len(), first.display());
+    match first_error {
+        Some(err) => Err(err.context("not every image could be read")),
+        None => Ok(outliers),
+    }
+}
+
+/// The resolution in the EXIF of an image, as cameras and image editors store it
+fn exif_dpi(metadata: &Metadata) -> Option<(f64, f64)> {
+    let exif = metadata.exif.as_ref()?;
+    let resolution = |tag| match &exif.get_field(tag, In::PRIMARY)?.value {
+        exif::Value::Rational(values) => Some(values.first()?.to_f64()),
+        _ => None,
+    };
+    // Inches unless the unit says centimeters
+    let unit = exif.get_field(Tag::ResolutionUnit, In::PRIMARY).and_then(|field| field.value.get_uint(0));
+    let per_inch = if unit == Some(3) { 2.54 } else { 1.0 };
+    Some((resolution(Tag::XResolution)? * per_inch, resolution(Tag::YResolution)? * per_inch))
+}
+
+/// The resolution in the pHYs chunk of a PNG file or the JFIF segment of a JPEG file
+fn header_dpi(path: &Path) -> Option<(f64, f64)> {
+    let mut data = vec![];
+    File::open(path).ok()?.take(HEADER_SIZE).read_to_end(&mut data).ok()?;
+    dpi_of_header(&data)
+}
+
+/// The resolution in the pHYs chunk of a PNG or the JFIF segment of a JPEG starting with `data`
+fn dpi_of_header(data: &[u8]) -> Option<(f64, f64)> {
+    let u16_at = |offset: usize| Some(u16::from_be_bytes(data.get(offset..offset + 2)?.try_into().ok()?) as f64);
+    let u32_at = |offset: usize| Some(u32::from_be_bytes(data.get(offset..offset + 4)?.try_into().ok()?) as f64);
+    if data.starts_with(b"\x89PNG") {
+        let mut position = 8;
+        while position + 8 <= data.len() {
+            let length = u32_at(position)? as usize;
+            match &data[position + 4..position + 8] {
+                // Only pixels per meter can be converted
+                b"pHYs" if data.get(position + 16) == Some(&1) => {
+                    return Some((u32_at(position + 8)? * 0.0254, u32_at(position + 12)? * 0.0254));
+                }
+                b"IDAT" | b"IEND" => return None,
+                _ => position += 12 + length,
+            }
+        }
+    } else if data.starts_with(&[0xff, 0xd8]) && data.get(6..11) == Some(b"JFIF\0") {
+        // Units of 1 are per inch and 2 per centimeter; 0 is only an aspect ratio
+        let per_inch = match data.get(13)? {
+            1 => 1.0,
+            2 => 2.54,
+            _ => return None,
+        };
+        return Some((u16_at(14)? * per_inch, u16_at(16)? * per_inch));
+    }
+    None
+}
+
+/// The description of the ICC profile of the image at `path`, if it has one
+fn profile(path: &Path) -> Option<String> {
+    let reader = image::ImageReader::open(path).ok()?.with_guessed_format().ok()?;
+    let profile = reader.into_decoder().ok()?.icc_profile().ok()??;
+    Some(description(&profile).unwrap_or_else(|| format!("unnamed color profile of {} bytes", profile.len())))
+}
+
+/// The text of the `desc` tag of an ICC `profile`, which is ASCII in version 2 profiles and UTF-16 in version 4
+fn description(profile: &[u8]) -> Option<String> {
+    let u32_at = |offset: usize| Some(u32::from_be_bytes(profile.get(offset..offset + 4)?.try_into().ok()?) as usize);
+    let tags = u32_at(128)?;
+    // The count of a broken profile may be of more tags than it has room for
+    let entry = (0..tags)
+        .map(|index| 132 + 12 * index)
+        .take_while(|&entry| entry + 12 <= profile.len())
+        .find(|&entry| profile.get(entry..entry + 4) == Some(b"desc"))?;
+    let (offset, size) = (u32_at(entry + 4)?, u32_at(entry + 8)?);
+    let tag = profile.get(offset..offset + size)?;
+    let text = match tag.get(..4)? {
+        b"desc" => {
+            let length = u32::from_be_bytes(tag.get(8..12)?.try_into().ok()?) as usize;
+            String::from_utf8_lossy(tag.get(12..12 + length)?).trim_end_matches('\0').to_string()
+        }
+        b"mluc" => {
+            // The first of the translations
+            let length = u32::from_be_bytes(tag.get(20..24)?.try_into().ok()?) as usize;
+            let start = u32::from_be_bytes(tag.get(24..28)?.try_into().ok()?) as usize;
+            let units: Vec<u16> = tag.get(start..start + length)?.chunks_exact(2).map(|unit| u16::from_be_bytes([unit[0], unit[1]])).collect();
+            String::from_utf16_lossy(&units)
+        }
+        _ => return None,
+    };
+    let text = text.trim();
+    (!text.is_empty()).then(|| text.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn png(chunks: &[(&[u8; 4], &[u8])]) -> Vec<u8> {
+        let mut data = b"\x89PNG\r\n\x1a\n".to_vec();
+        for (kind, contents) in chunks {
+            data.extend((contents.len() as u32).to_be_bytes());
+            data.extend(*kind);
+            data.extend(*contents);
+            data.extend([0; 4]);
+        }
+        data
+    }
+
+    /// A pHYs chunk of 3780 by 7560 pixels per meter, 96 by 192 DPI
+    const PHYS: &[u8] = &[0, 0, 0x0e, 0xc4, 0, 0, 0x1d, 0x88, 1];
+
+    fn jfif(units: u8) -> Vec<u8> {
+        let mut data = vec![0xff, 0xd8, 0xff, 0xe0, 0, 16];
+        data.extend(b"JFIF\0");
+        data.extend([1, 2, units, 0, 72, 0, 144, 0, 0]);
+        data
+    }
+
+    fn close(dpi: Option<(f64, f64)>, expected: (f64, f64)) -> bool {
+        dpi.is_some_and(|(x, y)| (x - expected.0).abs() < 0.01 && (y - expected.1).abs() < 0.01)
+    }
+
+    #[test]
+    fn png_resolution() {
+        assert!(close(dpi_of_header(&png(&[(b"IHDR", &[0; 13]), (b"pHYs", PHYS)])), (96.012, 192.024)));
+        // Without a unit the chunk only gives the aspect ratio
+        let mut aspect = PHYS.to_vec();
+        aspect[8] = 0;
+        assert_eq!(dpi_of_header(&png(&[(b"pHYs", &aspect)])), None);
+        // The chunk has to come before the image data
+        assert_eq!(dpi_of_header(&png(&[(b"IDAT", &[0; 4]), (b"pHYs", PHYS)])), None);
+    }
+
+    #[test]
+    fn jfif_resolution() {
+        assert_eq!(dpi_of_header(&jfif(1)), Some((72.0, 144.0)));
+        assert!(close(dpi_of_header(&jfif(2)), (182.88, 365.76)));
+        assert_eq!(dpi_of_header(&jfif(0)), None);
+    }
+
+    #[test]
+    fn truncated_headers_have_no_resolution() {
+        // Cut anywhere before the end of the resolution, which is all that is read
+        let png = png(&[(b"pHYs", PHYS)]);
+        for data in [&png[..25], &jfif(1)[..18]] {
+            assert!(dpi_of_header(data).is_some());
+            for length in 0..data.len() {
+                assert_eq!(dpi_of_header(&data[..length]), None, "{length} bytes");
+            }
+        }
+    }
+
+    /// A profile with a single tag, `desc`, holding `tag`
+    fn profile(tag: &[u8]) -> Vec<u8> {
+        let mut data = vec![0; 128];
+        data.extend(1u32.to_be_bytes());
+        data.extend(b"desc");
+        data.extend(144u32.to_be_bytes());
+        data.extend((tag.len() as u32).to_be_bytes());
+        data.extend(tag);
+        data
+    }
+
+    fn desc(text: &str) -> Vec<u8> {
+        let mut tag = b"desc\0\0\0\0".to_vec();
+        tag.extend((text.len() as u32 + 1).to_be_bytes());
+        tag.extend(text.as_bytes());
+        tag.push(0);
+        tag
+    }
+
+    fn mluc(text: &str) -> Vec<u8> {
+        let units: Vec<u8> = text.encode_utf16().flat_map(u16::to_be_bytes).collect();
+        let mut tag = b"mluc\0\0\0\0".to_vec();
+        tag.extend(1u32.to_be_bytes());
+        tag.extend(12u32.to_be_bytes());
+        tag.extend(b"enUS");
+        tag.extend((units.len() as u32).to_be_bytes());
+        tag.extend(28u32.to_be_bytes());
+        tag.extend(units);
+        tag
+    }
+
+    #[test]
+    fn profile_descriptions() {
+        assert_eq!(description(&profile(&desc("Adobe RGB (1998)"))).as_deref(), Some("Adobe RGB (1998)"));
+        assert_eq!(description(&profile(&mluc("Display P3"))).as_deref(), Some("Display P3"));
+        assert_eq!(description(&profile(&desc("  "))), None);
+        assert_eq!(description(&profile(b"text\0\0\0\0sRGB")), None);
+    }
+
+    #[test]
+    fn truncated_profiles_have_no_description() {
+        for profile in [profile(&desc("Adobe RGB (1998)")), profile(&mluc("Display P3"))] {
+            for length in 0..profile.len() {
+                assert_eq!(description(&profile[..length]), None, "{length} bytes");
+            }
+        }
+        // A count of more tags than there is room for
+        let mut profile = profile(&desc("sRGB"));
+        profile[128..132].copy_from_slice(&u32::MAX.to_be_bytes());
+        profile[132..136].copy_from_slice(b"none");
+        assert_eq!(description(&profile), None);
+    }
+}
@@ -0,0 +1,42 @@
+//! Captions kept in text files like feh's: with `--caption-path captions`, the caption of `photos/cat.jpg` is
+//! read from `photos/captions/cat.txt`. `--caption-path .` keeps captions next to the images.
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+
+use crate::export::{self, Conflict};
+
+/// The caption file of the image at `image` in the caption directory `dir`, which is relative to the image's
+/// directory
+pub fn file(image: &Path, dir: &Path) -> PathBuf {
+    let mut name = image.file_stem().unwrap_or_default().to_owned();
+    name.push(".txt");
+    image.parent().unwrap_or(Path::new("")).join(dir).join(name)
+}
+
+/// The caption in `file`, if there is one
+pub fn read(file: &Path) -> Option<String> {
+    let caption = std::fs::read_to_string(file).ok()?;
+    let caption = caption.trim_end();
+    (!caption.is_empty()).then(|| caption.to_owned())
+}
+
+/// Save `caption` to `file`, or remove the file if the caption is empty
+pub fn write(file: &Path, caption: &str) -> Result<()> {
+    if caption.is_empty() {
+        return match std::fs::remove_file(file) {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => {
+                Err(err).with_context(|| format!("failed to remove {}", file.display()))
+            }
+            _ => Ok(()),
+        };
+    }
+    if let Some(dir) = file.parent() {
+        std::fs::create_dir_all(dir).with_context(|| format!("failed to create {}", dir.display()))?;
+    }
+    // Replaced rather than rewritten, so that a crash can't lose the old caption
+    export::save_with(file, Conflict::Overwrite, |writer| Ok(writeln!(writer, "{caption}")?))?;
+    Ok(())
+}
@@ -0,0 +1,100 @@
+//! Frame capture for bug reports: the encoded scene of a frame together with the render parameters
+//! and surface configuration it was drawn with, written to a JSON file.
+//!
+//! The pixels of images are left out, only their size and format are recorded, so that captures
+//! of private images can be shared. A placeholder of the same size reproduces the frame.
+
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use serde_json::{json, Value};
+use vello::peniko::Color;
+use vello::{AaConfig, RenderParams, Scene};
+use vello_encoding::Patch;
+
+use crate::export;
+
+/// Write a capture of `scene` to `veh-capture-<timestamp>.json` in the current directory and return where it was
+/// saved.
+/// `config` is `None` for windows whose frames are shown through softbuffer.
+pub fn capture(scene: &Scene, params: &RenderParams, config: Option<&wgpu::SurfaceConfiguration>) -> Result<PathBuf> {
+    let encoding = scene.encoding();
+    let resources = &encoding.resources;
+    let patches: Vec<Value> = resources
+        .patches
+        .iter()
+        .map(|patch| match patch {
+            Patch::Ramp { draw_data_offset, stops, .. } => json!({
+                "ramp": { "draw_data_offset": draw_data_offset, "stops": [stops.start, stops.end] },
+            }),
+            Patch::GlyphRun { index } => json!({ "glyph_run": index }),
+            Patch::Image { draw_data_offset, image } => json!({
+                "image": {
+                    "draw_data_offset": draw_data_offset,
+                    "width": image.width,
+                    "height": image.height,
+                    "format": format!("{:?}", image.format),
+                },
+            }),
+        })
+        .collect();
+
+    let capture = json!({
+        "veh": env!("CARGO_PKG_VERSION"),
+        "render_params": {
+            "base_color": color(params.base_color),
+            "width": params.width,
+            "height": params.height,
+            "antialiasing": match params.antialiasing_method {
+                AaConfig::Area => "area",
+                AaConfig::Msaa8 => "msaa8",
+                AaConfig::Msaa16 => "msaa16",
+            },
+        },
+        "surface": config.map(|config| json!({
+            "format": format!("{:?}", config.format),
+            "width": config.width,
+            "height": config.height,
+            "present_mode": format!("{:?}", config.present_mode),
+            "alpha_mode": format!("{:?}", config.alpha_mode),
+            "usage": format!("{:?}", config.usage),
+        })),
+        "encoding": {
+            "n_paths": encoding.n_paths,
+            "n_path_segments": encoding.n_path_segments,
+            "n_clips": encoding.n_clips,
+            "n_open_clips": encoding.n_open_clips,
+            "flags": encoding.flags,
+            "path_tags": encoding.path_tags.iter().map(|tag| tag.0).collect::<Vec<_>>(),
+            "path_data": encoding.path_data,
+            "draw_tags": encoding.draw_tags.iter().map(|tag| tag.0).collect::<Vec<_>>(),
+            "draw_data": encoding.draw_data,
+            "transforms": encoding
+                .transforms
+                .iter()
+                .map(|transform| [transform.matrix.as_slice(), transform.translation.as_slice()].concat())
+                .collect::<Vec<_>>(),
+            "styles": encoding
+                .styles
+                .iter()
+                .map(|style| json!({ "flags_and_miter_limit": style.flags_and_miter_limit, "line_width": style.line_width }))
+                .collect::<Vec<_>>(),
+            "patches": patches,
+            "color_stops": resources
+                .color_stops
+                .iter()
+                .map(|stop| json!({ "offset": stop.offset, "color": color(stop.color) }))
+                .collect::<Vec<_>>(),
+            "glyph_runs": resources.glyph_runs.len(),
+        },
+    });
+
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.as_secs()).unwrap_or_default();
+    let path = PathBuf::from(format!("veh-capture-{timestamp}.json"));
+    export::save(&path, |writer| serde_json::to_writer(writer, &capture).context("failed to write the capture"))
+}
+
+fn color(color: Color) -> [u8; 4] {
+    [color.r, color.g, color.b, color.a]
+}
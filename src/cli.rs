@@ -0,0 +1,569 @@
+use std::path::PathBuf;
+use std::time::Duration;
+
+use clap::{Parser, Subcommand, ValueEnum};
+
+use crate::app;
+use crate::cue::{parse_cue, CueEvent};
+use crate::dropzone::TargetFormat;
+use crate::errors::ErrorFormat;
+use crate::export::Conflict;
+use crate::hdr::Tonemap;
+use crate::monitor::parse_monitor_profile;
+use crate::opacity;
+use crate::query::Filter;
+use crate::session::Geometry;
+use crate::slideshow::parse_seconds;
+use crate::sort::{parse_sort, parse_sorter, Sort};
+use crate::wallpaper;
+
+/// Command line arguments of veh
+#[derive(Parser, Debug)]
+#[command(name = "veh", version, about = "A fast, light-weight image viewer")]
+#[command(subcommand_negates_reqs = true)]
+// Options on the command line override those of the config file
+#[command(args_override_self = true)]
+pub struct Args {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// Images to view, in playlist order
+    #[arg(required_unless_present = "remote", value_name = "IMAGE")]
+    pub paths: Vec<PathBuf>,
+
+    /// Write the marked images to this file instead of stdout
+    #[arg(long, value_name = "FILE")]
+    pub mark_output: Option<PathBuf>,
+
+    /// Directory images are moved into with `x`
+    #[arg(long, value_name = "DIR")]
+    pub move_to: Option<PathBuf>,
+
+    /// Show every image at this zoom percentage instead of fitting it into the window,
+    /// e.g. 100 to compare images of different sizes at their true size
+    #[arg(long, value_name = "PERCENT", value_parser = parse_percent)]
+    pub lock_scale: Option<f64>,
+
+    /// How far the arrow keys move the image, in logical pixels of the window; Shift moves five times as far
+    #[arg(long, value_name = "PIXELS", default_value_t = app::PAN_STEP, value_parser = parse_positive)]
+    pub pan_step: f64,
+
+    /// How much a notch of the mouse wheel zooms, e.g. 1.2 for 20%
+    #[arg(long, value_name = "FACTOR", default_value_t = app::ZOOM_STEP, value_parser = parse_zoom_step)]
+    pub zoom_step: f64,
+
+    /// Zoom in by scrolling down instead of up
+    #[arg(long)]
+    pub invert_scroll: bool,
+
+    /// Ignore the default options in $XDG_CONFIG_HOME/veh/config
+    #[arg(long)]
+    pub no_config: bool,
+
+    /// Fit images into the window at 100% at most, rather than enlarging small ones such as icons; `e` enlarges
+    /// them anyway
+    #[arg(long)]
+    pub no_upscale: bool,
+
+    /// Show images only at integer scales such as 2× and 3×, enlarged without smoothing, for pixel art and
+    /// emulator screenshots; `i` turns it on and off
+    #[arg(long)]
+    pub integer_scaling: bool,
+
+    /// Pan in steps of tiles of this size in image pixels, as WIDTHxHEIGHT or a single size for square tiles,
+    /// e.g. 16 to step through the cells of a tile sheet with the arrow keys
+    #[arg(long, value_name = "SIZE", value_parser = parse_tile_size)]
+    pub tile_size: Option<(f64, f64)>,
+
+    /// Opacity from 1 to 255 below which pixels that aren't fully transparent are painted magenta by the alpha
+    /// check (`a`), e.g. 128 to find only the faintest fringes
+    #[arg(long, value_name = "ALPHA", default_value_t = 255, value_parser = clap::value_parser!(u8).range(1..))]
+    pub alpha_threshold: u8,
+
+    /// Directory with additional fonts for the text in SVG images, besides the system fonts
+    #[arg(long, value_name = "DIR")]
+    pub font_dir: Vec<PathBuf>,
+
+    /// Font family for SVG text that doesn't specify one
+    #[arg(long, value_name = "FAMILY")]
+    pub default_font: Option<String>,
+
+    /// Render SVGs again whenever the zoom changes substantially, encoding their geometry at screen
+    /// resolution so that strokes and hairlines stay crisp at extreme zoom
+    #[arg(long)]
+    pub svg_rerender: bool,
+
+    /// Rasterize SVGs into tiles at the current zoom while the view is idle, and draw the tiles instead of the SVG
+    /// while it is panned or zoomed, for SVGs too complex to be drawn smoothly every frame
+    #[arg(long)]
+    pub svg_tiles: bool,
+
+    /// Show images with an embedded ICC profile as their values are stored, instead of converting them to sRGB
+    #[arg(long)]
+    pub no_color_management: bool,
+
+    /// ICC profile of a monitor, as NAME=FILE, e.g. DP-1=/usr/share/color/icc/wide-gamut.icc. Images are converted to the
+    /// profile of the monitor their window is on; on X11 the profiles set by color managers are used otherwise.
+    /// May be repeated
+    #[arg(long, value_name = "NAME=FILE", value_parser = parse_monitor_profile)]
+    pub monitor_profile: Vec<(String, PathBuf)>,
+
+    /// Exposure HDR and 16-bit images are shown with at first, in EV; `[` and `]` change it
+    #[arg(long, value_name = "EV", default_value_t = 0.0, allow_hyphen_values = true)]
+    pub exposure: f32,
+
+    /// How the highlights of HDR images (OpenEXR, Radiance HDR) are brought into the range of the display
+    #[arg(long, value_enum, default_value_t = Tonemap::Aces)]
+    pub tonemap: Tonemap,
+
+    /// Ignore the window size and position, last image and zoom of the last session, which are restored otherwise
+    #[arg(long)]
+    pub fresh: bool,
+
+    /// Keep memory use down on small devices: downscale large images when they are loaded and don't keep
+    /// images that are no longer shown
+    #[arg(long)]
+    pub low_memory: bool,
+
+    /// How many threads decode images in the background, by default one per CPU core
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+    pub threads: Option<u32>,
+
+    /// Decode images in the background at a lower priority, from 1 to 19 as for `nice`, to stay out of the way of
+    /// builds and renders on the same machine (Linux only)
+    #[arg(long, value_name = "NICENESS", value_parser = clap::value_parser!(i32).range(1..=19))]
+    pub nice: Option<i32>,
+
+    /// Develop camera RAW files from their sensor data with dcraw, instead of showing the preview embedded in them
+    #[arg(long)]
+    pub raw_demosaic: bool,
+
+    /// Read comics: show pages as two-page spreads from right to left, fit to the window height, with the
+    /// arrow keys turning pages, and continue archives at the page last read
+    #[arg(long)]
+    pub manga: bool,
+
+    /// Show captions under the images, read from text files in this directory relative to the directory of every
+    /// image, e.g. `captions/cat.txt` for `cat.jpg`, or `.` for captions next to the images; `t` edits them
+    #[arg(long, value_name = "DIR")]
+    pub caption_path: Option<PathBuf>,
+
+    /// Show the status bar with the file name, position, size and zoom; `b` toggles it
+    #[arg(long)]
+    pub status_bar: bool,
+
+    /// Make windows translucent, from 10 to 100 percent opaque, to float them over other windows as a reference to
+    /// trace; `,` and `.` change it. Only X11 with a compositor lets windows be translucent.
+    #[arg(long, value_name = "PERCENT", value_parser = opacity::parse_percent)]
+    pub opacity: Option<f64>,
+
+    /// Where to place images that don't fill the window, e.g. top-left to use veh as a pinned reference panel
+    #[arg(long, value_enum, default_value_t = Gravity::Center)]
+    pub gravity: Gravity,
+
+    /// Redraw each window at most this many times per second
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+    pub fps: Option<u32>,
+
+    /// Frame rate numbered image sequences (frame_0001.png, frame_0002.png, ...) are played at with `v`
+    #[arg(long, value_name = "N", default_value_t = 24, value_parser = clap::value_parser!(u32).range(1..=1000))]
+    pub play_fps: u32,
+
+    /// Play all images as one sequence at --play-fps, ordered by the date they were taken unless --sort is
+    /// given, skipping frames that can't be decoded in time to keep to the frame rate
+    #[arg(long)]
+    pub timelapse: bool,
+
+    /// Start a slideshow; `s` starts or stops it
+    #[arg(long)]
+    pub slideshow: bool,
+
+    /// Seconds every image is shown in a slideshow
+    #[arg(long, value_name = "SECONDS", default_value = "5", value_parser = parse_seconds)]
+    pub slide_delay: Duration,
+
+    /// Show every image of a slideshow this many seconds longer per megapixel. A file next to an image named
+    /// like it with `.delay` appended, e.g. chart.png.delay, gives the seconds to show that image instead
+    #[arg(long, value_name = "SECONDS", default_value = "0", value_parser = parse_seconds)]
+    pub delay_per_megapixel: Duration,
+
+    /// GPU adapter to render with, by index or (part of its) name as printed by --list-adapters
+    #[arg(long, value_name = "INDEX|NAME")]
+    pub adapter: Option<String>,
+
+    /// Print the available GPU adapters and exit
+    #[arg(long, exclusive = true)]
+    pub list_adapters: bool,
+
+    /// Antialiasing method; area is the cheapest, msaa16 (the default unless --compat) the most accurate
+    #[arg(long, value_enum)]
+    pub aa: Option<Antialiasing>,
+
+    /// When frames are presented; immediate and mailbox don't wait for the vertical blank
+    #[arg(long, value_enum, default_value_t = PresentMode::Vsync)]
+    pub present_mode: PresentMode,
+
+    /// Render on the CPU instead of with GPU compute shaders, for GPUs or VMs where those fail
+    #[arg(long)]
+    pub cpu: bool,
+
+    /// Compatibility profile for the Raspberry Pi and older Intel iGPUs: render through OpenGL ES, antialias
+    /// by area unless --aa says otherwise, and downscale images to what small GPUs can hold
+    #[arg(long)]
+    pub compat: bool,
+
+    /// Open every image in a window of its own. More windows can be opened with `o`
+    #[arg(long)]
+    pub new_window: bool,
+
+    /// Size and position of windows in pixels as WIDTHxHEIGHT+X+Y, e.g. 400x300+1500+20, or only their size as
+    /// WIDTHxHEIGHT
+    #[arg(long, value_name = "GEOMETRY", value_parser = parse_geometry)]
+    pub geometry: Option<Geometry>,
+
+    /// Resize the window to every image shown, like feh does, so that images are shown at 100% as far as the
+    /// monitor allows
+    #[arg(long)]
+    pub auto_resize: bool,
+
+    /// Reload images when they change on disk, e.g. to preview an SVG while editing it. Images that fail to load
+    /// keep showing their last version, with the error over it.
+    #[arg(long)]
+    pub auto_reload: bool,
+
+    /// Open windows without title bar and borders
+    #[arg(long)]
+    pub borderless: bool,
+
+    /// Keep windows above all others, e.g. to float a reference image over a tiling layout
+    #[arg(long)]
+    pub on_top: bool,
+
+    /// Pin a reference image over another program: open windows borderless, on top and translucent, and let
+    /// clicks and keys pass through them. Ctrl+Alt+O (or `veh --remote click-through`) makes them take input again.
+    #[arg(long)]
+    pub click_through: bool,
+
+    /// Window class (app ID on Wayland) to match veh's windows by in window manager rules
+    #[arg(long, value_name = "CLASS")]
+    pub class: Option<String>,
+
+    /// Accept JSON-RPC commands on a Unix socket in the runtime directory, see `list-commands`
+    #[arg(long)]
+    pub ipc: bool,
+
+    /// Accept JSON-RPC commands on this socket instead of the default one
+    #[arg(long, value_name = "PATH")]
+    pub ipc_socket: Option<PathBuf>,
+
+    /// Print the format, size, color type, frame count and EXIF summary of the images, read from their headers,
+    /// and exit instead of showing them
+    #[arg(long)]
+    pub info: bool,
+
+    /// Print the information of --info as JSON
+    #[arg(long, requires = "info")]
+    pub json: bool,
+
+    /// Add the minimum, maximum, mean and standard deviation of every channel and the number of unique colors to
+    /// the information of --info, which takes decoding the images
+    #[arg(long, requires = "info")]
+    pub stats: bool,
+
+    /// How to print errors where veh runs without a window, e.g. with --info: as text, or as a line of JSON with
+    /// the category, path and detail of every error, for scripts. The exit code tells the category either way.
+    #[arg(long, value_enum, value_name = "FORMAT", default_value_t = ErrorFormat::Text)]
+    pub error_format: ErrorFormat,
+
+    /// What to do when a file saved by veh, e.g. a crop or a conversion, would replace an existing one: save it
+    /// as NAME (1).EXT instead, overwrite the existing file, or refuse to save it
+    #[arg(long, value_enum, value_name = "POLICY", default_value_t = Conflict::Rename)]
+    pub on_conflict: Conflict,
+
+    /// Run a command in the veh started with --ipc (or --ipc-socket) and exit, e.g. `next`, `goto 3`,
+    /// `open photo.jpg` or `zoom 200`. `--remote list-commands` lists the commands
+    #[arg(long, value_name = "COMMAND", num_args = 1.., conflicts_with = "paths")]
+    pub remote: Option<Vec<String>>,
+
+    /// Add the images to the playlist of the veh started with --ipc (or --ipc-socket) instead of opening them, and
+    /// exit
+    #[arg(long, conflicts_with = "info")]
+    pub append: bool,
+
+    /// With --append, show the first of the images added
+    #[arg(long, requires = "append")]
+    pub jump: bool,
+
+    /// Compare the images side by side with synchronized pan and zoom; `w` switches to a split
+    /// wipe of the first two images
+    #[arg(long)]
+    pub compare: bool,
+
+    /// Set the image as desktop wallpaper, scaled to cover the screen, and exit
+    #[arg(long, group = "background")]
+    pub bg_fill: bool,
+
+    /// Set the image as desktop wallpaper, centered without scaling, and exit
+    #[arg(long, group = "background")]
+    pub bg_center: bool,
+
+    /// Set the image as desktop wallpaper, stretched to the screen size, and exit
+    #[arg(long, group = "background")]
+    pub bg_scale: bool,
+
+    /// Command to run on an event, as EVENT=COMMAND, e.g. 'mark=paplay ding.oga'; `bell` as the command rings
+    /// the terminal bell. Events are advance, error and mark; %f, %n and %d work as in --action. May be repeated
+    #[arg(long, value_name = "EVENT=CMD", value_parser = parse_cue)]
+    pub cue: Vec<(CueEvent, String)>,
+
+    /// Order of the images: name, mtime (oldest first), size (smallest first), taken (by EXIF date, earliest
+    /// first), or custom:NAME for the order of a --sorter. Without it, images are shown in the order they are given
+    #[arg(long, value_name = "ORDER", value_parser = parse_sort)]
+    pub sort: Option<Sort>,
+
+    /// Sort order of one's own for --sort custom:NAME, as NAME=COMMAND, e.g. "aspect=identify -format
+    /// '%[fx:w/h]' %f". The command prints the sort key of an image; %f, %n and %d work as in --action. Keys that
+    /// are numbers are compared as numbers. May be repeated
+    #[arg(long, value_name = "NAME=CMD", value_parser = parse_sorter)]
+    pub sorter: Vec<(String, String)>,
+
+    /// Command to run on the current image with Enter, e.g. 'gimp %f'. `%f` is replaced by the
+    /// image path, `%n` by its file name and `%d` by its directory. The image is reloaded once the
+    /// command exits, unless the command starts with ';'
+    #[arg(long, value_name = "CMD")]
+    pub action: Option<String>,
+
+    /// Like --action, bound to the 1 key
+    #[arg(long, value_name = "CMD")]
+    pub action1: Option<String>,
+
+    /// Like --action, bound to the 2 key
+    #[arg(long, value_name = "CMD")]
+    pub action2: Option<String>,
+
+    /// Like --action, bound to the 3 key
+    #[arg(long, value_name = "CMD")]
+    pub action3: Option<String>,
+
+    /// Like --action, bound to the 4 key
+    #[arg(long, value_name = "CMD")]
+    pub action4: Option<String>,
+
+    /// Like --action, bound to the 5 key
+    #[arg(long, value_name = "CMD")]
+    pub action5: Option<String>,
+
+    /// Like --action, bound to the 6 key
+    #[arg(long, value_name = "CMD")]
+    pub action6: Option<String>,
+
+    /// Like --action, bound to the 7 key
+    #[arg(long, value_name = "CMD")]
+    pub action7: Option<String>,
+
+    /// Like --action, bound to the 8 key
+    #[arg(long, value_name = "CMD")]
+    pub action8: Option<String>,
+
+    /// Like --action, bound to the 9 key
+    #[arg(long, value_name = "CMD")]
+    pub action9: Option<String>,
+}
+
+impl Args {
+    /// The antialiasing method: `--aa`, else area with `--compat` and msaa16 otherwise
+    pub fn antialiasing(&self) -> Antialiasing {
+        self.aa.unwrap_or(if self.compat { Antialiasing::Area } else { Antialiasing::Msaa16 })
+    }
+
+    /// The wallpaper mode requested with one of the `--bg-*` flags
+    pub fn background_mode(&self) -> Option<wallpaper::Mode> {
+        if self.bg_fill {
+            Some(wallpaper::Mode::Fill)
+        } else if self.bg_center {
+            Some(wallpaper::Mode::Center)
+        } else if self.bg_scale {
+            Some(wallpaper::Mode::Scale)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Gravity {
+    Center,
+    TopLeft,
+    Top,
+    TopRight,
+    Left,
+    Right,
+    BottomLeft,
+    Bottom,
+    BottomRight,
+}
+
+impl Gravity {
+    /// The point an image is anchored at, as fractions of its width and height
+    pub fn anchor(self) -> (f64, f64) {
+        match self {
+            Gravity::Center => (0.5, 0.5),
+            Gravity::TopLeft => (0.0, 0.0),
+            Gravity::Top => (0.5, 0.0),
+            Gravity::TopRight => (1.0, 0.0),
+            Gravity::Left => (0.0, 0.5),
+            Gravity::Right => (1.0, 0.5),
+            Gravity::BottomLeft => (0.0, 1.0),
+            Gravity::Bottom => (0.5, 1.0),
+            Gravity::BottomRight => (1.0, 1.0),
+        }
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Antialiasing {
+    Area,
+    Msaa8,
+    Msaa16,
+}
+
+impl From<Antialiasing> for vello::AaConfig {
+    fn from(aa: Antialiasing) -> Self {
+        match aa {
+            Antialiasing::Area => vello::AaConfig::Area,
+            Antialiasing::Msaa8 => vello::AaConfig::Msaa8,
+            Antialiasing::Msaa16 => vello::AaConfig::Msaa16,
+        }
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PresentMode {
+    Vsync,
+    Immediate,
+    Mailbox,
+}
+
+impl From<PresentMode> for wgpu::PresentMode {
+    fn from(mode: PresentMode) -> Self {
+        match mode {
+            PresentMode::Vsync => wgpu::PresentMode::AutoVsync,
+            PresentMode::Immediate => wgpu::PresentMode::Immediate,
+            PresentMode::Mailbox => wgpu::PresentMode::Mailbox,
+        }
+    }
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Print the images of a directory whose metadata matches a query
+    Find {
+        /// Directory to search
+        dir: PathBuf,
+
+        /// Query to match, e.g. 'iso>1600 && width>4000'. Available fields: width, height,
+        /// megapixels, aspect, filesize, format, iso, aperture, exposure, focal, make, model,
+        /// date and orientation
+        #[arg(long = "where", value_name = "QUERY", value_parser = Filter::parse)]
+        filter: Filter,
+
+        /// Also search subdirectories
+        #[arg(short, long)]
+        recursive: bool,
+
+        /// Open the matching images in the viewer instead of printing them
+        #[arg(long)]
+        open: bool,
+    },
+    /// Open a small window that converts every image dropped on it and saves the result next to the
+    /// original as NAME-converted.EXT, printing its path
+    DropZone {
+        /// Format to convert to
+        #[arg(long, value_enum, default_value_t = TargetFormat::Png)]
+        to: TargetFormat,
+
+        /// Downscale images whose longer side is larger than this many pixels
+        #[arg(long, value_name = "PIXELS")]
+        max_size: Option<u32>,
+
+        /// JPEG quality, from 1 to 100
+        #[arg(long, default_value_t = 90, value_parser = clap::value_parser!(u8).range(1..=100))]
+        quality: u8,
+    },
+    /// Decode every image of a directory completely and report the ones that are truncated or
+    /// damaged
+    Verify {
+        /// Directory to check
+        dir: PathBuf,
+
+        /// Also check subdirectories
+        #[arg(short, long)]
+        recursive: bool,
+
+        /// Move the broken images to the trash
+        #[arg(long, conflicts_with = "quarantine")]
+        delete: bool,
+
+        /// Move the broken images into this directory, keeping their paths relative to DIR
+        #[arg(long, value_name = "QUARANTINE")]
+        quarantine: Option<PathBuf>,
+    },
+    /// Report the images whose dimensions, DPI or color profile differ from those of the first one
+    Audit {
+        /// Images to compare, the first one with all others
+        #[arg(required = true)]
+        images: Vec<PathBuf>,
+
+        /// Open the first image and those that differ from it in the viewer
+        #[arg(long)]
+        open: bool,
+    },
+}
+
+fn parse_tile_size(value: &str) -> Result<(f64, f64), String> {
+    let (width, height) = value.split_once('x').unwrap_or((value, value));
+    match (width.parse::<u32>(), height.parse::<u32>()) {
+        (Ok(width), Ok(height)) if width > 0 && height > 0 => Ok((width as f64, height as f64)),
+        _ => Err(format!("`{value}` is not a tile size such as 16 or 32x16")),
+    }
+}
+
+fn parse_geometry(value: &str) -> Result<Geometry, String> {
+    let error = || format!("`{value}` is not a geometry such as 400x300 or 400x300+1500+20");
+    let (size, position) = value.split_at(value.find(['+', '-']).unwrap_or(value.len()));
+    let (width, height) = size.split_once('x').ok_or_else(error)?;
+    let size = match (width.parse::<u32>(), height.parse::<u32>()) {
+        (Ok(width), Ok(height)) if width > 0 && height > 0 => (width, height),
+        _ => return Err(error()),
+    };
+    if position.is_empty() {
+        return Ok(Geometry { position: None, size });
+    }
+    // Both offsets start with their sign, e.g. +1500+20 or +1500-20
+    let (x, y) = position.split_at(position[1..].find(['+', '-']).ok_or_else(error)? + 1);
+    match (x.parse::<i32>(), y.parse::<i32>()) {
+        (Ok(x), Ok(y)) => Ok(Geometry { position: Some((x, y)), size }),
+        _ => Err(error()),
+    }
+}
+
+fn parse_positive(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(number) if number > 0.0 && number.is_finite() => Ok(number),
+        _ => Err(format!("`{value}` is not a positive number")),
+    }
+}
+
+fn parse_zoom_step(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(factor) if factor > 1.0 && factor.is_finite() => Ok(factor),
+        _ => Err(format!("`{value}` is not a factor above 1, such as 1.2")),
+    }
+}
+
+fn parse_percent(value: &str) -> Result<f64, String> {
+    match value.trim_end_matches('%').parse::<f64>() {
+        Ok(percent) if percent > 0.0 && percent.is_finite() => Ok(percent),
+        _ => Err(format!("`{value}` is not a positive percentage")),
+    }
+}
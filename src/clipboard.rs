@@ -0,0 +1,94 @@
+//! Copying the image shown or its path to the system clipboard, and viewing images pasted from it, e.g.
+//! screenshots.
+//!
+//! On X11 and Wayland the copied contents are served by veh itself, so they are gone once veh exits unless a
+//! clipboard manager takes them over.
+
+use std::borrow::Cow;
+use std::path::PathBuf;
+
+use anyhow::{anyhow, bail, Context, Result};
+use image::RgbaImage;
+use vello::peniko::Image;
+
+use crate::loader;
+
+/// The system clipboard, connected to on first use
+#[derive(Default)]
+pub struct Clipboard {
+    clipboard: Option<arboard::Clipboard>,
+    /// How many images have been pasted, which numbers their temporary files
+    pasted: usize,
+}
+
+impl Clipboard {
+    fn connect(&mut self) -> Result<&mut arboard::Clipboard> {
+        if self.clipboard.is_none() {
+            let clipboard = arboard::Clipboard::new().map_err(|err| anyhow!("failed to open the clipboard: {err}"))?;
+            self.clipboard = Some(clipboard);
+        }
+        Ok(self.clipboard.as_mut().unwrap())
+    }
+
+    pub fn copy_image(&mut self, image: &Image) -> Result<()> {
+        let data = arboard::ImageData {
+            width: image.width as usize,
+            height: image.height as usize,
+            bytes: Cow::Borrowed(image.data.data()),
+        };
+        self.connect()?.set_image(data).map_err(|err| anyhow!("failed to copy the image: {err}"))
+    }
+
+    pub fn copy_text(&mut self, text: &str) -> Result<()> {
+        self.connect()?.set_text(text).map_err(|err| anyhow!("failed to copy the path: {err}"))
+    }
+
+    /// The images on the clipboard: files copied by path or as `file://` URIs, or else image data, which is
+    /// saved to a temporary PNG file to be viewed
+    pub fn paste(&mut self) -> Result<Vec<PathBuf>> {
+        let clipboard = self.connect()?;
+        if let Ok(text) = clipboard.get_text() {
+            let paths: Vec<PathBuf> = text
+                .lines()
+                .map(|line| {
+                    let line = line.trim();
+                    line.strip_prefix("file://").map_or_else(|| PathBuf::from(line), |path| PathBuf::from(percent_decode(path)))
+                })
+                .filter(|path| path.is_file() && loader::is_supported(path))
+                .collect();
+            if !paths.is_empty() {
+                return Ok(paths);
+            }
+        }
+        let Ok(data) = clipboard.get_image() else {
+            bail!("the clipboard holds neither an image nor the path of one");
+        };
+        let image = RgbaImage::from_raw(data.width as u32, data.height as u32, data.bytes.into_owned())
+            .context("the image on the clipboard has the wrong size")?;
+        self.pasted += 1;
+        let path = std::env::temp_dir().join(format!("veh-paste-{}-{}.png", std::process::id(), self.pasted));
+        image.save(&path).with_context(|| format!("failed to save {}", path.display()))?;
+        Ok(vec![path])
+    }
+}
+
+/// Decode the %XX escapes of a `file://` URI
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = bytes.get(i + 1..i + 3).and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+        match (bytes[i], escaped) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
@@ -0,0 +1,104 @@
+//! Color management. Images with an embedded ICC profile, e.g. photos in Adobe RGB or Display P3, are
+//! converted to sRGB, the color space veh draws in, when they are decoded. Without this their colors
+//! would be taken for sRGB and look dull or shifted. `--no-color-management` turns it off.
+
+use std::cell::RefCell;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::VecDeque;
+use std::hash::{Hash, Hasher};
+use std::io::{BufRead, Seek};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use image::metadata::Orientation;
+use image::{DynamicImage, ImageDecoder, ImageReader};
+use qcms::{DataType, Intent, Profile, Transform};
+use vello::peniko::{Blob, Format, Image};
+
+/// How many images converted for a display are kept, e.g. the frames of an animation
+const CONVERTED_CAPACITY: usize = 32;
+
+/// Whether embedded profiles are applied
+static ENABLED: AtomicBool = AtomicBool::new(true);
+
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Decode the image of `reader`, turned upright as its orientation tag says and converted to sRGB if it embeds an
+/// ICC profile
+pub fn decode<R: BufRead + Seek>(reader: ImageReader<R>) -> Result<DynamicImage> {
+    let mut decoder = reader.into_decoder().context("decode image failed")?;
+    let profile = if ENABLED.load(Ordering::Relaxed) { decoder.icc_profile().ok().flatten() } else { None };
+    // Cameras and phones store photos as the sensor was turned, with a tag that tells how to turn them upright
+    let orientation = decoder.orientation().unwrap_or(Orientation::NoTransforms);
+    let mut image = DynamicImage::from_decoder(decoder).context("decode image failed")?;
+    image.apply_orientation(orientation);
+    Ok(match profile {
+        Some(profile) => to_srgb(image, &profile),
+        None => image,
+    })
+}
+
+/// Convert `image` from the color space of the ICC `profile` to sRGB. Images whose profile can't be read or
+/// applied, e.g. that of a CMYK image already converted to RGB by the decoder, are left as they are.
+pub fn to_srgb(image: DynamicImage, profile: &[u8]) -> DynamicImage {
+    let Some(input) = Profile::new_from_slice(profile, false) else {
+        return image;
+    };
+    if input.is_sRGB() {
+        return image;
+    }
+    let Some(transform) = Transform::new(&input, &Profile::new_sRGB(), DataType::RGBA8, Intent::default()) else {
+        return image;
+    };
+    // Images are drawn as 8-bit RGBA anyway
+    let mut rgba = image.into_rgba8();
+    transform.apply(&mut rgba);
+    DynamicImage::ImageRgba8(rgba)
+}
+
+/// The color profile of a monitor other than sRGB. Raster images, which are decoded to sRGB, are converted to it
+/// before they are drawn on the monitor.
+pub struct Display {
+    /// Tells the profiles apart, e.g. the scenes drawn for them
+    pub id: u64,
+    transform: Transform,
+    // The images converted last, by the ID of their pixel data
+    converted: RefCell<VecDeque<(u64, Image)>>,
+}
+
+impl Display {
+    /// `None` if the ICC `profile` is sRGB already, or can't be read
+    pub fn new(profile: &[u8]) -> Option<Self> {
+        let output = Profile::new_from_slice(profile, false)?;
+        if output.is_sRGB() {
+            return None;
+        }
+        let transform = Transform::new(&Profile::new_sRGB(), &output, DataType::RGBA8, Intent::default())?;
+        let mut hasher = DefaultHasher::new();
+        profile.hash(&mut hasher);
+        Some(Self { id: hasher.finish(), transform, converted: RefCell::default() })
+    }
+
+    /// `image` converted from sRGB to the profile of the display
+    pub fn convert(&self, image: &Image) -> Image {
+        let mut converted = self.converted.borrow_mut();
+        if let Some((_, image)) = converted.iter().find(|(id, _)| *id == image.data.id()) {
+            return image.clone();
+        }
+        let mut pixels = image.data.data().to_vec();
+        self.transform.apply(&mut pixels);
+        let result = Image::new(Blob::new(Arc::new(pixels)), Format::Rgba8, image.width, image.height);
+        if converted.len() >= CONVERTED_CAPACITY {
+            converted.pop_front();
+        }
+        converted.push_back((image.data.id(), result.clone()));
+        result
+    }
+}
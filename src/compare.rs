@@ -0,0 +1,133 @@
+//! `--compare` view: all given images are shown at once with a shared pan and zoom, either side by
+//! side or as a split "wipe" of the first two over the same viewport.
+
+use std::path::PathBuf;
+
+use anyhow::{bail, Context, Result};
+use vello::kurbo::{Affine, Line, Rect, Stroke, Vec2};
+use vello::peniko::{Color, Mix};
+use vello::Scene;
+use winit::dpi::PhysicalSize;
+
+use crate::hdr;
+use crate::loader::open_page;
+
+/// How close (in pixels) the cursor has to be to the wipe divider to grab it
+const DIVIDER_GRAB_DISTANCE: f64 = 8.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompareMode {
+    SideBySide,
+    Wipe,
+}
+
+pub struct Comparison {
+    // Every image is scaled to the width of the first one, so that e.g. a downscaled
+    // render lines up with its original
+    images: Vec<(Scene, Affine)>,
+    mode: CompareMode,
+    /// Position of the wipe divider as a fraction of the window width
+    split: f64,
+    dragging_divider: bool,
+}
+
+impl Comparison {
+    pub fn load(paths: &[PathBuf], hdr_view: hdr::View) -> Result<Self> {
+        if paths.len() < 2 {
+            bail!("--compare needs at least two images");
+        }
+        let mut images = vec![];
+        for path in paths {
+            let image = open_page(path, 0).with_context(|| format!("failed to open {}", path.display()))?;
+            images.push(image);
+        }
+        let reference_width = images[0].size().0;
+        let images = images
+            .iter()
+            .map(|image| {
+                let mut subscene = Scene::new();
+                crate::add_image_to_subscene(&mut subscene, image, hdr_view, None);
+                (subscene, Affine::scale(reference_width / image.size().0))
+            })
+            .collect();
+        Ok(Self { images, mode: CompareMode::SideBySide, split: 0.5, dragging_divider: false })
+    }
+
+    pub fn toggle_mode(&mut self) {
+        self.mode = match self.mode {
+            CompareMode::SideBySide => CompareMode::Wipe,
+            CompareMode::Wipe => CompareMode::SideBySide,
+        };
+    }
+
+    /// The size of the area every image is shown in, which the shared transform is relative to
+    pub fn viewport_size(&self, size: PhysicalSize<u32>) -> PhysicalSize<u32> {
+        match self.mode {
+            CompareMode::SideBySide => PhysicalSize::new(size.width / self.images.len() as u32, size.height),
+            CompareMode::Wipe => size,
+        }
+    }
+
+    /// Convert a window position into the coordinates of the viewport under it
+    pub fn viewport_position(&self, position: Vec2, size: PhysicalSize<u32>) -> Vec2 {
+        match self.mode {
+            CompareMode::SideBySide => {
+                let column_width = self.viewport_size(size).width.max(1) as f64;
+                Vec2::new(position.x.rem_euclid(column_width), position.y)
+            }
+            CompareMode::Wipe => position,
+        }
+    }
+
+    /// Start dragging the wipe divider if `position` is on it. Returns whether it was grabbed.
+    pub fn grab_divider(&mut self, position: Vec2, size: PhysicalSize<u32>) -> bool {
+        let divider = self.split * size.width as f64;
+        self.dragging_divider =
+            self.mode == CompareMode::Wipe && (position.x - divider).abs() <= DIVIDER_GRAB_DISTANCE;
+        self.dragging_divider
+    }
+
+    pub fn release_divider(&mut self) {
+        self.dragging_divider = false;
+    }
+
+    /// Move the wipe divider along with the cursor if it is being dragged. Returns whether it moved.
+    pub fn drag_divider(&mut self, position: Vec2, size: PhysicalSize<u32>) -> bool {
+        if self.dragging_divider {
+            self.split = (position.x / size.width as f64).clamp(0.0, 1.0);
+        }
+        self.dragging_divider
+    }
+
+    pub fn draw(&self, scene: &mut Scene, transform: Affine, size: PhysicalSize<u32>) {
+        let (width, height) = (size.width as f64, size.height as f64);
+        match self.mode {
+            CompareMode::SideBySide => {
+                let column_width = self.viewport_size(size).width as f64;
+                for (i, (subscene, normalize)) in self.images.iter().enumerate() {
+                    let x0 = i as f64 * column_width;
+                    let column = Rect::new(x0, 0.0, x0 + column_width, height);
+                    scene.push_layer(Mix::Clip, 1.0, Affine::IDENTITY, &column);
+                    scene.append(subscene, Some(Affine::translate((x0, 0.0)) * transform * *normalize));
+                    scene.pop_layer();
+                }
+            }
+            CompareMode::Wipe => {
+                let divider = self.split * width;
+                let halves = [Rect::new(0.0, 0.0, divider, height), Rect::new(divider, 0.0, width, height)];
+                for ((subscene, normalize), half) in self.images.iter().zip(halves) {
+                    scene.push_layer(Mix::Clip, 1.0, Affine::IDENTITY, &half);
+                    scene.append(subscene, Some(transform * *normalize));
+                    scene.pop_layer();
+                }
+                scene.stroke(
+                    &Stroke::new(2.0),
+                    Affine::IDENTITY,
+                    Color::WHITE,
+                    None,
+                    &Line::new((divider, 0.0), (divider, height)),
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,112 @@
+//! Default options kept in `$XDG_CONFIG_HOME/veh/config`, one per line as it is given on the command line, e.g.
+//! `--pan-step 80`. Options on the command line override them, and `--no-config` ignores the file. Lines starting
+//! with `#` are comments.
+//!
+//! On the first launch, a config file with the most common options commented out is written, and the first
+//! window shows the basic controls.
+
+use std::ffi::OsString;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+
+use crate::export::{self, Conflict};
+use crate::state;
+
+/// The config file written on the first launch
+const DEFAULT: &str = "\
+# Default options of veh, one per line as on the command line. Options given on the command line override them.
+# Remove the # in front of an option to use it.
+
+# How many logical pixels the arrow keys move the image, and how much a notch of the mouse wheel zooms
+# --pan-step 40
+# --zoom-step 1.05
+# Zoom in by scrolling down
+# --invert-scroll
+
+# Show the status bar with the file name, size and zoom
+# --status-bar
+# Don't enlarge images smaller than the window
+# --no-upscale
+# Seconds each image of a slideshow is shown
+# --slide-delay 5
+
+# The commands Enter and 1 to 9 run on the image shown, %f being its path
+# --action gimp %f
+# --action1 ;echo %f >> ~/picked.txt
+
+# Save files under a new name (rename), replace existing files (overwrite) or don't save (refuse)
+# --on-conflict rename
+";
+
+/// The config file, `$XDG_CONFIG_HOME/veh/config`
+pub fn file() -> Option<PathBuf> {
+    let config = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(config.join("veh").join("config"))
+}
+
+/// Whether veh is launched for the first time, which is remembered once this returns `true`. That is the case
+/// unless a config file exists or an earlier launch has been remembered; the default config file is written then.
+pub fn first_run() -> bool {
+    let (Some(file), Some(flag)) = (file(), state::file("launched")) else {
+        return false;
+    };
+    if file.exists() || flag.exists() {
+        return false;
+    }
+    for (path, contents) in [(&file, DEFAULT), (&flag, "")] {
+        if let Err(err) = write_new(path, contents) {
+            eprintln!("veh: {err:#}");
+        }
+    }
+    true
+}
+
+/// Save `contents` at `path` unless a file exists there, e.g. written by another veh launched at the same time
+fn write_new(path: &Path, contents: &str) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).with_context(|| format!("failed to create {}", dir.display()))?;
+    }
+    export::save_with(path, Conflict::Refuse, |writer| Ok(writer.write_all(contents.as_bytes())?))?;
+    Ok(())
+}
+
+/// The command line `args` with the options of the config file inserted after the program name, so that the
+/// options given on the command line come later and win
+pub fn with_defaults(args: Vec<OsString>) -> Vec<OsString> {
+    if args.iter().any(|arg| arg == "--no-config") {
+        return args;
+    }
+    let Some(file) = file() else {
+        return args;
+    };
+    let text = match std::fs::read_to_string(&file) {
+        Ok(text) => text,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return args,
+        Err(err) => {
+            eprintln!("veh: ignoring {}: {err}", file.display());
+            return args;
+        }
+    };
+    let mut args = args.into_iter();
+    args.next().into_iter().chain(parse(&text).into_iter().map(OsString::from)).chain(args).collect()
+}
+
+/// The options in the text of a config file. The value of an option is the rest of its line, so that it may
+/// contain spaces, e.g. `--action gimp %f`.
+fn parse(text: &str) -> Vec<String> {
+    let mut args = vec![];
+    for line in text.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        match line.split_once(char::is_whitespace) {
+            Some((option, value)) => args.extend([option.to_string(), value.trim().to_string()]),
+            None => args.push(line.to_string()),
+        }
+    }
+    args
+}
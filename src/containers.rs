@@ -0,0 +1,88 @@
+//! Image formats that can hold several images: the pages of a multi-page TIFF and the icons of an ICO
+//! at its different resolutions. The image crate only decodes one of them.
+
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+use image::{DynamicImage, ImageBuffer, ImageFormat, Luma, LumaA, Rgb, Rgba};
+use tiff::decoder::{Decoder, DecodingResult};
+use tiff::ColorType;
+
+fn tiff_decoder(path: &Path) -> Result<Decoder<BufReader<File>>> {
+    let file = File::open(path).context("open image failed")?;
+    Decoder::new(BufReader::new(file)).context("failed to read the TIFF header")
+}
+
+/// How many pages the TIFF at `path` has
+pub fn tiff_page_count(path: &Path) -> Result<usize> {
+    let mut decoder = tiff_decoder(path)?;
+    let mut count = 1;
+    while decoder.more_images() {
+        decoder.next_image().context("failed to read a TIFF page")?;
+        count += 1;
+    }
+    Ok(count)
+}
+
+/// Decode page `page` of the TIFF at `path`
+pub fn tiff_page(path: &Path, page: usize) -> Result<DynamicImage> {
+    let mut decoder = tiff_decoder(path)?;
+    decoder.seek_to_image(page).context("no such page")?;
+    let (width, height) = decoder.dimensions()?;
+    let image = match (decoder.colortype()?, decoder.read_image().context("decode image failed")?) {
+        (ColorType::Gray(8), DecodingResult::U8(data)) => ImageBuffer::<Luma<u8>, _>::from_raw(width, height, data).map(DynamicImage::from),
+        (ColorType::GrayA(8), DecodingResult::U8(data)) => ImageBuffer::<LumaA<u8>, _>::from_raw(width, height, data).map(DynamicImage::from),
+        (ColorType::RGB(8), DecodingResult::U8(data)) => ImageBuffer::<Rgb<u8>, _>::from_raw(width, height, data).map(DynamicImage::from),
+        (ColorType::RGBA(8), DecodingResult::U8(data)) => ImageBuffer::<Rgba<u8>, _>::from_raw(width, height, data).map(DynamicImage::from),
+        (ColorType::Gray(16), DecodingResult::U16(data)) => ImageBuffer::<Luma<u16>, _>::from_raw(width, height, data).map(DynamicImage::from),
+        (ColorType::GrayA(16), DecodingResult::U16(data)) => ImageBuffer::<LumaA<u16>, _>::from_raw(width, height, data).map(DynamicImage::from),
+        (ColorType::RGB(16), DecodingResult::U16(data)) => ImageBuffer::<Rgb<u16>, _>::from_raw(width, height, data).map(DynamicImage::from),
+        (ColorType::RGBA(16), DecodingResult::U16(data)) => ImageBuffer::<Rgba<u16>, _>::from_raw(width, height, data).map(DynamicImage::from),
+        (color_type, _) => bail!("unsupported TIFF color type {color_type:?}"),
+    };
+    image.context("the page has less data than its size needs")
+}
+
+/// Where the icons are in an ICO file: its directory entries, largest first like the image crate picks them
+fn ico_entries(data: &[u8]) -> Result<Vec<&[u8]>> {
+    let header = data.get(..6).context("not an ICO file")?;
+    if header[..4] != [0, 0, 1, 0] {
+        bail!("not an ICO file");
+    }
+    let count = u16::from_le_bytes([header[4], header[5]]) as usize;
+    let entries = data.get(6..6 + 16 * count).context("the ICO directory is truncated")?;
+    let mut entries: Vec<&[u8]> = entries.chunks_exact(16).collect();
+    // A width or height of 0 stands for 256
+    let area = |entry: &[u8]| {
+        let side = |byte: u8| if byte == 0 { 256 } else { byte as u32 };
+        side(entry[0]) * side(entry[1])
+    };
+    let bits_per_pixel = |entry: &[u8]| u16::from_le_bytes([entry[6], entry[7]]);
+    entries.sort_by_key(|entry| std::cmp::Reverse((area(entry), bits_per_pixel(entry))));
+    Ok(entries)
+}
+
+/// How many icons the ICO at `path` holds
+pub fn ico_entry_count(path: &Path) -> Result<usize> {
+    let data = std::fs::read(path).context("open image failed")?;
+    Ok(ico_entries(&data)?.len())
+}
+
+/// Decode icon `index` of the ICO at `path`, counting from the largest
+pub fn ico_entry(path: &Path, index: usize) -> Result<DynamicImage> {
+    let data = std::fs::read(path).context("open image failed")?;
+    let entry = *ico_entries(&data)?.get(index).context("no such page")?;
+    let size = u32::from_le_bytes(entry[8..12].try_into()?) as usize;
+    let offset = u32::from_le_bytes(entry[12..16].try_into()?) as usize;
+    let icon = data.get(offset..offset + size).context("the icon data is truncated")?;
+
+    // The image crate decodes an ICO with just this one icon in it
+    let mut single = vec![0, 0, 1, 0, 1, 0];
+    single.extend_from_slice(&entry[..12]);
+    single.extend_from_slice(&22u32.to_le_bytes());
+    single.extend_from_slice(icon);
+    image::load_from_memory_with_format(&single, ImageFormat::Ico).context("decode image failed")
+}
+
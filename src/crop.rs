@@ -0,0 +1,112 @@
+//! Detection and removal of uniform or transparent borders, e.g. letterboxing in screenshots and
+//! the scanner lid around scans.
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use image::{DynamicImage, RgbaImage};
+use vello::kurbo::Rect;
+use vello::peniko::{Blob, Format, Image};
+
+use crate::export;
+
+/// How far each channel of a pixel may be off the border color while still counting as border,
+/// so that scanner noise and JPEG artifacts don't stop the detection
+const TOLERANCE: u8 = 12;
+
+/// The region of an image that is kept when cropping, in pixels
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Crop {
+    pub x0: u32,
+    pub y0: u32,
+    pub x1: u32,
+    pub y1: u32,
+}
+
+impl Crop {
+    pub fn width(&self) -> u32 {
+        self.x1 - self.x0
+    }
+
+    pub fn height(&self) -> u32 {
+        self.y1 - self.y0
+    }
+
+    /// The kept region in image coordinates
+    pub fn rect(&self) -> Rect {
+        Rect::new(self.x0 as f64, self.y0 as f64, self.x1 as f64, self.y1 as f64)
+    }
+}
+
+/// Find the content of `image` inside its uniform border. The color of the top left pixel is
+/// taken as border color; if it is transparent, any transparent pixel counts as border.
+/// Returns `None` if there is no border, or if the image is nothing but border.
+pub fn detect_borders(image: &Image) -> Option<Crop> {
+    let (width, height) = (image.width, image.height);
+    let pixels = image.data.data();
+    let pixel = |x: u32, y: u32| {
+        let i = (y as usize * width as usize + x as usize) * 4;
+        [pixels[i], pixels[i + 1], pixels[i + 2], pixels[i + 3]]
+    };
+    let border = pixel(0, 0);
+    let is_border = |p: [u8; 4]| {
+        if border[3] == 0 {
+            p[3] == 0
+        } else {
+            p.iter().zip(border).all(|(&a, b)| a.abs_diff(b) <= TOLERANCE)
+        }
+    };
+
+    let row_is_border = |y| (0..width).all(|x| is_border(pixel(x, y)));
+    let y0 = (0..height).find(|&y| !row_is_border(y))?;
+    let y1 = (0..height).rev().find(|&y| !row_is_border(y))? + 1;
+    let column_is_border = |x| (y0..y1).all(|y| is_border(pixel(x, y)));
+    let x0 = (0..width).find(|&x| !column_is_border(x))?;
+    let x1 = (0..width).rev().find(|&x| !column_is_border(x))? + 1;
+
+    let crop = Crop { x0, y0, x1, y1 };
+    if crop == (Crop { x0: 0, y0: 0, x1: width, y1: height }) {
+        return None;
+    }
+    Some(crop)
+}
+
+fn cropped_pixels(image: &Image, crop: Crop) -> Vec<u8> {
+    let pixels = image.data.data();
+    let stride = image.width as usize * 4;
+    (crop.y0..crop.y1)
+        .flat_map(|y| {
+            let start = y as usize * stride + crop.x0 as usize * 4;
+            &pixels[start..start + crop.width() as usize * 4]
+        })
+        .copied()
+        .collect()
+}
+
+/// A copy of `image` with only the region of `crop`
+pub fn apply(image: &Image, crop: Crop) -> Image {
+    let blob = Blob::new(Arc::new(cropped_pixels(image, crop)));
+    Image::new(blob, Format::Rgba8, crop.width(), crop.height())
+}
+
+/// Save the region of `crop` next to the image it was taken from, as `<name>-cropped.<ext>`. Returns where it was
+/// saved.
+pub fn export(image: &Image, crop: Crop, source: &Path) -> Result<PathBuf> {
+    let stem = source.file_stem().context("no file name")?.to_string_lossy();
+    let extension = source.extension().context("no format given")?.to_string_lossy();
+    let target = source.with_file_name(format!("{stem}-cropped.{extension}"));
+    let format = image::ImageFormat::from_path(&target)?;
+
+    let cropped = RgbaImage::from_raw(crop.width(), crop.height(), cropped_pixels(image, crop))
+        .context("cropped image has the wrong size")?;
+    let cropped = DynamicImage::ImageRgba8(cropped);
+    export::save(&target, |writer| {
+        // Not every format can store an alpha channel (JPEG for one)
+        match format {
+            image::ImageFormat::Jpeg => DynamicImage::ImageRgb8(cropped.to_rgb8()).write_to(writer, format)?,
+            _ => cropped.write_to(writer, format)?,
+        }
+        Ok(())
+    })
+}
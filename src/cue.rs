@@ -0,0 +1,61 @@
+//! `--cue` commands, or the terminal bell, fired on events such as marking an image, for feedback that
+//! doesn't rely on looking at the window, e.g. for accessibility or in photo booths.
+
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::Path;
+
+use clap::ValueEnum;
+
+use crate::action;
+
+/// What `--cue` commands can be fired on
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum CueEvent {
+    /// Going on to the next or previous image, by hand or in a slideshow
+    Advance,
+    /// An image failing to load
+    Error,
+    /// Marking or unmarking an image
+    Mark,
+}
+
+/// Parse a `--cue` of the form EVENT=COMMAND
+pub fn parse_cue(value: &str) -> Result<(CueEvent, String), String> {
+    let (event, command) = value.split_once('=').ok_or_else(|| format!("`{value}` is not of the form EVENT=COMMAND"))?;
+    let event = CueEvent::from_str(event, true)?;
+    Ok((event, command.to_string()))
+}
+
+/// The commands to run on every event; `bell` rings the terminal bell instead of running a command
+#[derive(Default)]
+pub struct Cues {
+    commands: HashMap<CueEvent, Vec<String>>,
+}
+
+impl Cues {
+    pub fn new(cues: &[(CueEvent, String)]) -> Self {
+        let mut commands = HashMap::<_, Vec<_>>::new();
+        for (event, command) in cues {
+            commands.entry(*event).or_default().push(command.clone());
+        }
+        Self { commands }
+    }
+
+    /// Fire the cues of `event`, which happened to the image at `path`. Commands run in the background.
+    pub fn fire(&self, event: CueEvent, path: &Path) {
+        for command in self.commands.get(&event).into_iter().flatten() {
+            if command == "bell" {
+                // Terminals play the system alert sound for it
+                let _ = std::io::stderr().write_all(b"\x07");
+                continue;
+            }
+            let command = action::expand(command, path);
+            match action::shell(&command).spawn() {
+                // Reap the command once it exits
+                Ok(mut child) => drop(std::thread::spawn(move || child.wait())),
+                Err(err) => eprintln!("veh: failed to run `{command}`: {err}"),
+            }
+        }
+    }
+}
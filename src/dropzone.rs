@@ -0,0 +1,307 @@
+//! The `drop-zone` window: a small window that converts every image dropped on it according to a recipe, e.g.
+//! to JPEG at most 1920 pixels wide, and saves the result next to the original.
+
+use std::fmt;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Context, Result};
+use clap::ValueEnum;
+use image::codecs::jpeg::JpegEncoder;
+use image::imageops::FilterType;
+use image::{DynamicImage, ImageFormat};
+use vello::kurbo::{Affine, Rect, RoundedRect, Stroke};
+use vello::peniko::{Color, Fill};
+use vello::util::RenderContext;
+use vello::Scene;
+use winit::dpi::{LogicalSize, PhysicalSize};
+use winit::event::{ElementState, Event, KeyEvent, WindowEvent};
+use winit::event_loop::{ControlFlow, EventLoopBuilder};
+use winit::keyboard::{KeyCode, PhysicalKey};
+use winit::window::{WindowBuilder, WindowLevel};
+
+use crate::app::create_vello_renderer;
+use crate::cli::Args;
+use crate::present::{self, Output};
+use crate::text::Text;
+use crate::{adapter, export, loader, RenderOptions};
+
+/// Size of the window in logical pixels
+const WIDTH: f64 = 360.0;
+const HEIGHT: f64 = 220.0;
+/// Space between the dashed border and the edge of the window in logical pixels
+const MARGIN: f64 = 12.0;
+/// Font sizes in logical pixels
+const TITLE_SIZE: f64 = 16.0;
+const FONT_SIZE: f64 = 12.0;
+/// Height of the toast along the bottom of the window in logical pixels
+const TOAST_HEIGHT: f64 = 28.0;
+/// How long a toast is shown
+const TOAST_DURATION: Duration = Duration::from_secs(4);
+
+/// The formats dropped images can be converted to
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TargetFormat {
+    Png,
+    Jpeg,
+    /// Lossless WebP
+    Webp,
+}
+
+impl TargetFormat {
+    fn extension(self) -> &'static str {
+        match self {
+            TargetFormat::Png => "png",
+            TargetFormat::Jpeg => "jpg",
+            TargetFormat::Webp => "webp",
+        }
+    }
+}
+
+/// How dropped images are converted
+#[derive(Debug, Clone, Copy)]
+pub struct Recipe {
+    pub format: TargetFormat,
+    /// The longest side images are downscaled to, in pixels
+    pub max_size: Option<u32>,
+    /// JPEG quality from 1 to 100
+    pub quality: u8,
+}
+
+impl fmt::Display for Recipe {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.format {
+            TargetFormat::Png => write!(f, "to PNG")?,
+            TargetFormat::Jpeg => write!(f, "to JPEG at quality {}", self.quality)?,
+            TargetFormat::Webp => write!(f, "to lossless WebP")?,
+        }
+        if let Some(max_size) = self.max_size {
+            write!(f, ", at most {max_size} px")?;
+        }
+        Ok(())
+    }
+}
+
+impl Recipe {
+    /// Convert the image at `source` and save it next to it as NAME-converted.EXT. Returns where it was saved.
+    pub fn apply(&self, source: &Path) -> Result<PathBuf> {
+        let stem = source.file_stem().context("no file name")?.to_string_lossy();
+        let target = source.with_file_name(format!("{stem}-converted.{}", self.format.extension()));
+
+        let mut image = loader::decode_still(source)?;
+        if let Some(max_size) = self.max_size {
+            if image.width().max(image.height()) > max_size {
+                image = image.resize(max_size, max_size, FilterType::Lanczos3);
+            }
+        }
+        export::save(&target, |writer| self.encode(&image, writer))
+    }
+
+    fn encode(&self, image: &DynamicImage, writer: &mut BufWriter<File>) -> Result<()> {
+        match self.format {
+            // JPEG has neither an alpha channel nor more than 8 bits
+            TargetFormat::Jpeg => JpegEncoder::new_with_quality(writer, self.quality).encode_image(&image.to_rgb8())?,
+            // PNG can't store floats, and the WebP encoder only takes 8 bits
+            TargetFormat::Png if matches!(image, DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_)) => {
+                DynamicImage::ImageRgba16(image.to_rgba16()).write_to(writer, ImageFormat::Png)?
+            }
+            TargetFormat::Png => image.write_to(writer, ImageFormat::Png)?,
+            TargetFormat::Webp => DynamicImage::ImageRgba8(image.to_rgba8()).write_to(writer, ImageFormat::WebP)?,
+        }
+        Ok(())
+    }
+}
+
+/// A dropped image that has been converted, or failed to be
+struct Converted {
+    source: PathBuf,
+    result: Result<PathBuf, String>,
+}
+
+/// A message along the bottom of the window about the image converted last
+struct Toast {
+    message: String,
+    failed: bool,
+    until: Instant,
+}
+
+/// What the window shows
+struct DropZone {
+    recipe: Recipe,
+    /// `None` if there is no font, in which case only the border is drawn
+    text: Option<Text>,
+    /// Whether a file is dragged over the window
+    hovering: bool,
+    /// How many dropped images are still being converted
+    pending: usize,
+    toast: Option<Toast>,
+}
+
+impl DropZone {
+    fn draw(&self, scene: &mut Scene, size: PhysicalSize<u32>, scale_factor: f64) {
+        let (width, height) = (size.width as f64, size.height as f64);
+        let background = if self.hovering { Color::rgb8(0x2a, 0x3a, 0x4a) } else { Color::rgb8(0x20, 0x20, 0x20) };
+        scene.fill(Fill::NonZero, Affine::IDENTITY, background, None, &Rect::new(0.0, 0.0, width, height));
+        let margin = MARGIN * scale_factor;
+        let border = RoundedRect::new(margin, margin, width - margin, height - margin, margin);
+        let stroke = Stroke::new(2.0 * scale_factor).with_dashes(0.0, [8.0 * scale_factor, 6.0 * scale_factor]);
+        let color = if self.hovering { Color::rgb8(0xff, 0xb0, 0x00) } else { Color::rgb8(0x80, 0x80, 0x80) };
+        scene.stroke(&stroke, Affine::IDENTITY, color, None, &border);
+
+        let Some(text) = &self.text else {
+            return;
+        };
+        let title_size = (TITLE_SIZE * scale_factor) as f32;
+        let font_size = (FONT_SIZE * scale_factor) as f32;
+        let title = match self.pending {
+            0 => "Drop images here".to_string(),
+            pending => format!("Converting {pending}…"),
+        };
+        let recipe = self.recipe.to_string();
+        let centered = |label: &str, size: f32, baseline: f64| {
+            Affine::translate(((width - text.width(label, size)) / 2.0, baseline))
+        };
+        let middle = height / 2.0;
+        text.draw(scene, &title, title_size, centered(&title, title_size, middle), Color::WHITE);
+        let (ascent, _) = text.vertical_metrics(font_size);
+        let subtitle_baseline = middle + ascent * 1.8;
+        text.draw(scene, &recipe, font_size, centered(&recipe, font_size, subtitle_baseline), Color::rgb8(0xb0, 0xb0, 0xb0));
+
+        if let Some(toast) = &self.toast {
+            let top = height - TOAST_HEIGHT * scale_factor;
+            scene.fill(Fill::NonZero, Affine::IDENTITY, Color::rgba8(0, 0, 0, 0xc0), None, &Rect::new(0.0, top, width, height));
+            let padding = FONT_SIZE * scale_factor;
+            let message = elide_start(text, &toast.message, font_size, width - 2.0 * padding);
+            let color = if toast.failed { Color::rgb8(0xff, 0x80, 0x80) } else { Color::rgb8(0x80, 0xe0, 0x80) };
+            let (ascent, descent) = text.vertical_metrics(font_size);
+            let baseline = (top + height + ascent - descent) / 2.0;
+            text.draw(scene, &message, font_size, Affine::translate((padding, baseline)), color);
+        }
+    }
+
+    fn finish(&mut self, converted: Converted) {
+        self.pending = self.pending.saturating_sub(1);
+        let (message, failed) = match converted.result {
+            Ok(target) => {
+                // For scripts waiting on the converted images
+                println!("{}", target.display());
+                (format!("Saved {}", target.display()), false)
+            }
+            Err(err) => {
+                eprintln!("veh: {}: {err}", converted.source.display());
+                let name = converted.source.file_name().unwrap_or_default().to_string_lossy().into_owned();
+                (format!("{name}: {err}"), true)
+            }
+        };
+        self.toast = Some(Toast { message, failed, until: Instant::now() + TOAST_DURATION });
+    }
+}
+
+/// `message`, with as much of its start replaced by an ellipsis as needed to fit into `available` pixels, so
+/// that the file name at the end of a path stays readable
+fn elide_start(text: &Text, message: &str, size: f32, available: f64) -> String {
+    let mut start = 0;
+    let mut elided = message.to_string();
+    while text.width(&elided, size) > available {
+        let Some((next, _)) = message[start..].char_indices().nth(1) else {
+            break;
+        };
+        start += next;
+        elided = format!("…{}", &message[start..]);
+    }
+    elided
+}
+
+/// Open the drop zone window and convert the images dropped on it with `recipe` until it is closed
+pub fn run(recipe: Recipe, args: &Args) -> Result<()> {
+    let event_loop = EventLoopBuilder::<Converted>::with_user_event().build()?;
+    let proxy = event_loop.create_proxy();
+    let window = WindowBuilder::new()
+        .with_title("veh drop zone")
+        .with_inner_size(LogicalSize::new(WIDTH, HEIGHT))
+        .with_window_level(WindowLevel::AlwaysOnTop)
+        .build(&event_loop)?;
+    let window = Arc::new(window);
+
+    let options = RenderOptions::new(args);
+    let mut render_cx = RenderContext::new().map_err(|err| anyhow!("failed to set up wgpu: {err}"))?;
+    if let Err(err) = adapter::choose_adapter(&render_cx.instance, window.clone(), options.adapter.as_deref()) {
+        eprintln!("veh: {err:#}");
+    }
+    let size = window.inner_size();
+    let output_future = present::create_output(&mut render_cx, window.clone(), size.width, size.height, options.present_mode);
+    let mut output = pollster::block_on(output_future).context("failed to create a surface")?;
+    let dev_id = output.dev_id();
+    let use_cpu = options.use_cpu || matches!(output, Output::Software(_));
+    let mut renderer = create_vello_renderer(&render_cx, dev_id, &options, use_cpu);
+
+    let mut zone = DropZone { recipe, text: Text::load(), hovering: false, pending: 0, toast: None };
+    let mut scene = Scene::new();
+    let mut failed = None;
+    event_loop.run(|event, event_loop| match event {
+        Event::UserEvent(converted) => {
+            zone.finish(converted);
+            window.request_redraw();
+        }
+        Event::AboutToWait => {
+            if zone.toast.as_ref().is_some_and(|toast| toast.until <= Instant::now()) {
+                zone.toast = None;
+                window.request_redraw();
+            }
+            match &zone.toast {
+                Some(toast) => event_loop.set_control_flow(ControlFlow::WaitUntil(toast.until)),
+                None => event_loop.set_control_flow(ControlFlow::Wait),
+            }
+        }
+        Event::WindowEvent { event, .. } => match event {
+            WindowEvent::CloseRequested
+            | WindowEvent::KeyboardInput {
+                event: KeyEvent { physical_key: PhysicalKey::Code(KeyCode::Escape), state: ElementState::Pressed, .. },
+                ..
+            } => event_loop.exit(),
+            WindowEvent::HoveredFile(_) | WindowEvent::HoveredFileCancelled => {
+                zone.hovering = matches!(event, WindowEvent::HoveredFile(_));
+                window.request_redraw();
+            }
+            WindowEvent::DroppedFile(path) => {
+                zone.hovering = false;
+                zone.pending += 1;
+                let proxy = proxy.clone();
+                // Decoding and encoding large images takes a while, during which more can be dropped
+                std::thread::spawn(move || {
+                    let result = recipe.apply(&path).map_err(|err| format!("{err:#}"));
+                    let _ = proxy.send_event(Converted { source: path, result });
+                });
+                window.request_redraw();
+            }
+            WindowEvent::Resized(size) => {
+                output.resize(&render_cx, size.width, size.height);
+                window.request_redraw();
+            }
+            WindowEvent::RedrawRequested => {
+                let (width, height) = output.size();
+                scene.reset();
+                zone.draw(&mut scene, PhysicalSize::new(width, height), window.scale_factor());
+                let params = vello::RenderParams { base_color: Color::BLACK, width, height, antialiasing_method: options.antialiasing };
+                match output.present(&render_cx, &mut renderer, &scene, &params, window.inner_size()) {
+                    Ok(true) => {}
+                    Ok(false) => window.request_redraw(),
+                    Err(err) => {
+                        failed = Some(err);
+                        event_loop.exit();
+                    }
+                }
+                render_cx.devices[dev_id].device.poll(wgpu::Maintain::Poll);
+            }
+            _ => {}
+        },
+        _ => {}
+    })?;
+    match failed {
+        Some(err) => Err(err),
+        None => Ok(()),
+    }
+}
@@ -0,0 +1,115 @@
+//! How errors are reported where veh runs without a window, e.g. `--info` and `find`. By default they are
+//! printed for people to read; `--error-format json` prints every error as a line of JSON with its category, the
+//! path of the file it is about and its detail instead, for scripts. Either way the exit code tells the category
+//! of the error that made veh fail.
+
+use std::fmt;
+use std::path::Path;
+use std::process::ExitCode;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use clap::ValueEnum;
+use serde_json::json;
+
+static JSON: AtomicBool = AtomicBool::new(false);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ErrorFormat {
+    Text,
+    Json,
+}
+
+pub fn set_format(format: ErrorFormat) {
+    JSON.store(format == ErrorFormat::Json, Ordering::Relaxed);
+}
+
+/// What kind of failure an error is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Category {
+    NotFound,
+    PermissionDenied,
+    /// The file is truncated or damaged
+    Corrupt,
+    /// The format or a feature of the file isn't supported
+    Unsupported,
+    /// Reading or writing failed otherwise
+    Io,
+    Other,
+}
+
+impl Category {
+    /// The category of the innermost error in the chain of `err` that has one
+    pub fn of(err: &anyhow::Error) -> Self {
+        err.chain().rev().find_map(Self::of_cause).unwrap_or(Category::Other)
+    }
+
+    fn of_cause(cause: &(dyn std::error::Error + 'static)) -> Option<Self> {
+        if let Some(err) = cause.downcast_ref::<std::io::Error>() {
+            return Some(match err.kind() {
+                std::io::ErrorKind::NotFound => Category::NotFound,
+                std::io::ErrorKind::PermissionDenied => Category::PermissionDenied,
+                std::io::ErrorKind::InvalidData | std::io::ErrorKind::UnexpectedEof => Category::Corrupt,
+                _ => Category::Io,
+            });
+        }
+        if let Some(err) = cause.downcast_ref::<image::ImageError>() {
+            return match err {
+                image::ImageError::Decoding(_) | image::ImageError::Limits(_) => Some(Category::Corrupt),
+                image::ImageError::Unsupported(_) => Some(Category::Unsupported),
+                // The I/O error is the source of the image error, and comes next in the chain
+                _ => None,
+            };
+        }
+        if cause.is::<vello_svg::usvg::Error>() || cause.is::<exif::Error>() {
+            return Some(Category::Corrupt);
+        }
+        cause.is::<Unsupported>().then_some(Category::Unsupported)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Category::NotFound => "not-found",
+            Category::PermissionDenied => "permission-denied",
+            Category::Corrupt => "corrupt",
+            Category::Unsupported => "unsupported",
+            Category::Io => "io",
+            Category::Other => "other",
+        }
+    }
+
+    /// The exit code of veh when an error of this category makes it fail. 2 is taken by usage errors.
+    pub fn exit_code(self) -> ExitCode {
+        ExitCode::from(match self {
+            Category::Other => 1,
+            Category::NotFound => 3,
+            Category::PermissionDenied => 4,
+            Category::Corrupt => 5,
+            Category::Unsupported => 6,
+            Category::Io => 7,
+        })
+    }
+}
+
+/// A format or a feature of a file that veh can't handle
+#[derive(Debug)]
+pub struct Unsupported(pub String);
+
+impl fmt::Display for Unsupported {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for Unsupported {}
+
+/// Report `err`, about the file at `path` if there is one, on stderr
+pub fn report(path: Option<&Path>, err: &anyhow::Error) {
+    if JSON.load(Ordering::Relaxed) {
+        let error = json!({ "category": Category::of(err).name(), "path": path, "detail": format!("{err:#}") });
+        eprintln!("{error}");
+    } else if let Some(path) = path {
+        eprintln!("veh: {}: {err:#}", path.display());
+    } else {
+        eprintln!("veh: {err:#}");
+    }
+}
@@ -0,0 +1,159 @@
+//! How files the user asked for are saved, e.g. crops, drop zone conversions and render captures. Every file is
+//! written to a temporary file next to where it goes and moved into place once it is complete, so that a crash or
+//! a full disk never leaves a truncated file behind, let alone truncates an existing one. `--on-conflict` decides
+//! what happens when a file of the same name exists already.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU32, AtomicU8, Ordering};
+
+use anyhow::{bail, Context, Result};
+use clap::ValueEnum;
+
+static CONFLICT: AtomicU8 = AtomicU8::new(Conflict::Rename as u8);
+/// How many temporary files this process has made, which tells apart those written at the same time
+static TEMPORARIES: AtomicU32 = AtomicU32::new(0);
+
+/// What to do when a file is saved where one exists already
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Conflict {
+    /// Save it as `NAME (1).EXT`, or `NAME (2).EXT` if that exists too, and so on
+    Rename,
+    /// Replace the existing file
+    Overwrite,
+    /// Fail, leaving the existing file alone
+    Refuse,
+}
+
+pub fn set_conflict(conflict: Conflict) {
+    CONFLICT.store(conflict as u8, Ordering::Relaxed);
+}
+
+fn conflict() -> Conflict {
+    match CONFLICT.load(Ordering::Relaxed) {
+        value if value == Conflict::Overwrite as u8 => Conflict::Overwrite,
+        value if value == Conflict::Refuse as u8 => Conflict::Refuse,
+        _ => Conflict::Rename,
+    }
+}
+
+/// Save a file at `target` with the contents `write` writes, handling an existing file as `--on-conflict` says.
+/// Returns where the file was saved.
+pub fn save(target: &Path, write: impl FnOnce(&mut BufWriter<File>) -> Result<()>) -> Result<PathBuf> {
+    save_with(target, conflict(), write)
+}
+
+/// [`save`], handling an existing file as `conflict` says
+pub fn save_with(target: &Path, conflict: Conflict, write: impl FnOnce(&mut BufWriter<File>) -> Result<()>) -> Result<PathBuf> {
+    // A symlink that is overwritten keeps pointing at the file, which is replaced instead
+    let resolved;
+    let target = if conflict == Conflict::Overwrite && target.is_symlink() {
+        resolved = fs::canonicalize(target).with_context(|| format!("failed to resolve {}", target.display()))?;
+        &resolved
+    } else {
+        target
+    };
+    let temporary = temporary_path(target)?;
+    let saved = write_temporary(&temporary, write).and_then(|()| place(&temporary, target, conflict));
+    if saved.is_err() {
+        // Don't leave a broken file behind
+        let _ = fs::remove_file(&temporary);
+    }
+    saved.with_context(|| format!("failed to save {}", target.display()))
+}
+
+/// A hidden file next to `target`, on the same file system so that it can be renamed into place
+fn temporary_path(target: &Path) -> Result<PathBuf> {
+    let name = target.file_name().context("no file name")?.to_string_lossy();
+    let count = TEMPORARIES.fetch_add(1, Ordering::Relaxed);
+    Ok(target.with_file_name(format!(".{name}.{}-{count}.tmp", std::process::id())))
+}
+
+fn write_temporary(temporary: &Path, write: impl FnOnce(&mut BufWriter<File>) -> Result<()>) -> Result<()> {
+    let file = OpenOptions::new().write(true).create_new(true).open(temporary)?;
+    let mut writer = BufWriter::new(file);
+    write(&mut writer)?;
+    let file = writer.into_inner().map_err(|err| err.into_error())?;
+    // The data has to be on the disk before the file is renamed, or a crash may leave an empty file in its place
+    file.sync_all()?;
+    Ok(())
+}
+
+/// Move the complete `temporary` file to `target`, or next to it as `conflict` says. Returns where it went.
+fn place(temporary: &Path, target: &Path, conflict: Conflict) -> Result<PathBuf> {
+    if conflict == Conflict::Overwrite {
+        // The replaced file keeps its permissions, e.g. a photo only its owner may read
+        match fs::metadata(target) {
+            Ok(metadata) => fs::set_permissions(temporary, metadata.permissions())?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+            Err(err) => return Err(err.into()),
+        }
+        fs::rename(temporary, target)?;
+        return Ok(target.to_path_buf());
+    }
+    let mut candidate = target.to_path_buf();
+    let mut number = 0;
+    loop {
+        match move_new(temporary, &candidate) {
+            Ok(()) => return Ok(candidate),
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists && conflict == Conflict::Rename => {
+                number += 1;
+                candidate = numbered(target, number);
+            }
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => bail!("the file exists already"),
+            Err(err) => return Err(err.into()),
+        }
+    }
+}
+
+/// Move `from` to `to` unless `to` exists. Linking fails if it exists, so that two files saved at the same time
+/// can't take the same name, except on file systems without hard links, e.g. FAT.
+fn move_new(from: &Path, to: &Path) -> io::Result<()> {
+    match fs::hard_link(from, to) {
+        Ok(()) => fs::remove_file(from),
+        Err(err) if err.kind() == io::ErrorKind::AlreadyExists => Err(err),
+        Err(_) if to.exists() => Err(io::ErrorKind::AlreadyExists.into()),
+        Err(_) => fs::rename(from, to),
+    }
+}
+
+/// `NAME (number).EXT` for a `target` of `NAME.EXT`
+fn numbered(target: &Path, number: u32) -> PathBuf {
+    let stem = target.file_stem().unwrap_or_default().to_string_lossy();
+    match target.extension() {
+        Some(extension) => target.with_file_name(format!("{stem} ({number}).{}", extension.to_string_lossy())),
+        None => target.with_file_name(format!("{stem} ({number})")),
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::io::Write;
+    use std::os::unix::fs::PermissionsExt;
+
+    use super::*;
+
+    fn save_text(target: &Path, text: &str) -> PathBuf {
+        save_with(target, Conflict::Overwrite, |writer| Ok(writer.write_all(text.as_bytes())?)).unwrap()
+    }
+
+    #[test]
+    fn overwriting_keeps_permissions_and_symlinks() {
+        let dir = std::env::temp_dir().join(format!("veh-export-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("photo.jpg");
+        let link = dir.join("link.jpg");
+        fs::write(&file, "old").unwrap();
+        fs::set_permissions(&file, fs::Permissions::from_mode(0o640)).unwrap();
+        std::os::unix::fs::symlink(&file, &link).unwrap();
+
+        save_text(&file, "new");
+        assert_eq!(fs::metadata(&file).unwrap().permissions().mode() & 0o777, 0o640);
+        save_text(&link, "newer");
+        assert!(link.is_symlink());
+        assert_eq!(fs::read_to_string(&file).unwrap(), "newer");
+        assert_eq!(fs::metadata(&file).unwrap().permissions().mode() & 0o777, 0o640);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,105 @@
+//! Formats decoded outside of the image crate, each behind a cargo feature: HEIC/HEIF with libheif (feature
+//! `heic`), AVIF with `avifdec` (feature `avif`) and JPEG XL with `djxl` (feature `jxl`). libheif decodes in
+//! process, keeping the precision of 10- and 12-bit photos. The tools convert the image to a 16-bit PNG in a
+//! directory of veh's own, which is then decoded like any other.
+
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use anyhow::{Context, Result};
+use image::DynamicImage;
+
+/// Whether veh was built with the feature that decodes `format`
+pub fn supports(format: &str) -> bool {
+    match format {
+        "heic" | "heif" => cfg!(feature = "heic"),
+        _ => tool(format).is_some(),
+    }
+}
+
+/// The tool that decodes `format` and its arguments for a 16-bit PNG, if veh was built with the feature for it
+fn tool(format: &str) -> Option<(&'static str, &'static [&'static str])> {
+    match format {
+        "avif" if cfg!(feature = "avif") => Some(("avifdec", &["--depth", "16"])),
+        "jxl" if cfg!(feature = "jxl") => Some(("djxl", &["--bits_per_sample=16"])),
+        _ => None,
+    }
+}
+
+/// Decode the image at `path` in `format`
+pub fn decode(path: &Path, format: &str) -> Result<DynamicImage> {
+    #[cfg(feature = "heic")]
+    if matches!(format, "heic" | "heif") {
+        return heif::decode(path);
+    }
+    let (tool, arguments) = tool(format).context("invalid format given")?;
+    // No other user can put a file or a link where the image is written, and images decoded on several threads
+    // at once get a directory each
+    let dir = tempfile::Builder::new().prefix("veh-").tempdir().context("failed to create a temporary directory")?;
+    let output = dir.path().join("image.png");
+    let status = Command::new(tool)
+        .args(arguments)
+        .arg(path)
+        .arg(&output)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .with_context(|| format!("failed to run {tool}, which is needed to open {format} images"))?;
+    if !status.success() {
+        anyhow::bail!("{tool} failed to decode the image ({status})");
+    }
+    image::ImageReader::open(&output)
+        .and_then(|reader| reader.with_guessed_format())
+        .context("decode image failed")
+        .and_then(crate::color::decode)
+}
+
+#[cfg(feature = "heic")]
+mod heif {
+    use std::path::Path;
+
+    use anyhow::{Context, Result};
+    use image::{DynamicImage, ImageBuffer, Rgba};
+    use libheif_rs::{ColorSpace, HeifContext, LibHeif, RgbChroma};
+
+    use crate::color;
+
+    /// Decode the primary image of the HEIF file at `path`, turned upright as the file says
+    pub fn decode(path: &Path) -> Result<DynamicImage> {
+        let context = HeifContext::read_from_file(&path.to_string_lossy()).context("open image failed")?;
+        let handle = context.primary_image_handle().context("decode image failed")?;
+        let bits = handle.luma_bits_per_pixel();
+        let chroma = if bits > 8 { RgbChroma::HdrRgbaLe } else { RgbChroma::Rgba };
+        let decoded = LibHeif::new().decode(&handle, ColorSpace::Rgb(chroma), None).context("decode image failed")?;
+        let plane = decoded.planes().interleaved.context("decode image failed")?;
+        let (width, height) = (plane.width, plane.height);
+        let row = width as usize * if bits > 8 { 8 } else { 4 };
+        let rows = plane.data.chunks(plane.stride).take(height as usize).map(|data| &data[..row]);
+        let image = if bits > 8 {
+            // The samples are the low bits of 16, which are scaled to all of them
+            let samples = rows
+                .flat_map(|data| data.chunks_exact(2))
+                .map(|sample| widen(u16::from_le_bytes([sample[0], sample[1]]), bits))
+                .collect();
+            let image = ImageBuffer::<Rgba<u16>, _>::from_raw(width, height, samples).context("decode image failed")?;
+            DynamicImage::ImageRgba16(image)
+        } else {
+            let samples = rows.flatten().copied().collect();
+            DynamicImage::ImageRgba8(ImageBuffer::from_raw(width, height, samples).context("decode image failed")?)
+        };
+        Ok(match handle.color_profile_raw() {
+            Some(profile) if color::is_enabled() => color::to_srgb(image, &profile.data),
+            _ => image,
+        })
+    }
+
+    /// A sample of `bits` scaled to 16 bits, with its highest bits repeated in the lowest so that white stays white
+    fn widen(sample: u16, bits: u8) -> u16 {
+        if bits >= 16 {
+            return sample;
+        }
+        let shift = 16 - u32::from(bits);
+        (sample << shift) | (sample >> (u32::from(bits) - shift))
+    }
+}
@@ -0,0 +1,54 @@
+//! Faces in photos, detected with the `facedetect` tool (feature `faces`), for checking the sharpness of
+//! portraits one face after the other while culling.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use anyhow::{bail, Context, Result};
+use vello::kurbo::Rect;
+
+/// The faces found in every image looked at so far, as detection takes a while
+#[derive(Default)]
+pub struct Faces {
+    found: HashMap<PathBuf, Vec<Rect>>,
+}
+
+impl Faces {
+    /// The faces in the image at `path` in its pixels, from left to right
+    pub fn get(&mut self, path: &Path) -> Result<&[Rect]> {
+        if !self.found.contains_key(path) {
+            let faces = detect(path)?;
+            self.found.insert(path.to_path_buf(), faces);
+        }
+        Ok(&self.found[path])
+    }
+}
+
+fn detect(path: &Path) -> Result<Vec<Rect>> {
+    if !cfg!(feature = "faces") {
+        bail!("veh was built without face detection");
+    }
+    let output = Command::new("facedetect")
+        .arg(path)
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .context("failed to run facedetect, which is needed to find faces")?;
+    if !output.status.success() {
+        bail!("facedetect failed to look for faces ({})", output.status);
+    }
+    // Every face is printed as a line of x, y, width and height
+    let mut faces: Vec<Rect> = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| {
+            let numbers: Vec<f64> = line.split_whitespace().filter_map(|number| number.parse().ok()).collect();
+            let [x, y, width, height] = numbers[..] else {
+                return None;
+            };
+            Some(Rect::new(x, y, x + width, y + height))
+        })
+        .collect();
+    faces.sort_by(|a, b| a.x0.total_cmp(&b.x0));
+    Ok(faces)
+}
@@ -0,0 +1,105 @@
+//! File operations done from within veh (moving images to the trash or into another directory),
+//! journaled so that they can be undone one after the other in case of a culling mistake.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+
+/// A file operation on the playlist entry at `index`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Operation {
+    Trashed { index: usize, path: PathBuf },
+    Moved { index: usize, from: PathBuf, to: PathBuf },
+}
+
+/// The file operations of the current session, most recent last
+#[derive(Default)]
+pub struct Journal {
+    done: Vec<Operation>,
+}
+
+impl Journal {
+    /// Move `path` to the trash of the desktop
+    pub fn trash(&mut self, index: usize, path: &Path) -> Result<()> {
+        ensure_not_archived(path)?;
+        trash::delete(path).with_context(|| format!("failed to move {} to the trash", path.display()))?;
+        self.done.push(Operation::Trashed { index, path: path.to_path_buf() });
+        Ok(())
+    }
+
+    /// Move `path` into `dir`, keeping its name. Returns the new path.
+    pub fn move_into(&mut self, index: usize, path: &Path, dir: &Path) -> Result<PathBuf> {
+        ensure_not_archived(path)?;
+        let name = path.file_name().context("no file name")?;
+        let target = dir.join(name);
+        move_file(path, &target)?;
+        self.done.push(Operation::Moved { index, from: path.to_path_buf(), to: target.clone() });
+        Ok(target)
+    }
+
+    /// Revert the most recent operation that hasn't been undone yet and return it,
+    /// or `None` if there is nothing left to undo
+    pub fn undo(&mut self) -> Result<Option<Operation>> {
+        let Some(operation) = self.done.pop() else {
+            return Ok(None);
+        };
+        let undone = match &operation {
+            Operation::Trashed { path, .. } => restore_from_trash(path),
+            Operation::Moved { from, to, .. } => move_file(to, from),
+        };
+        if let Err(err) = undone {
+            // Keep the operation around, so that undoing can be retried once the problem is fixed
+            self.done.push(operation);
+            return Err(err);
+        }
+        Ok(Some(operation))
+    }
+}
+
+/// Rename `from` to `to`, copying the file if they are on different file systems
+pub fn move_file(from: &Path, to: &Path) -> Result<()> {
+    if to.exists() {
+        bail!("{} already exists", to.display());
+    }
+    if fs::rename(from, to).is_err() {
+        fs::copy(from, to).with_context(|| format!("failed to move {} to {}", from.display(), to.display()))?;
+        fs::remove_file(from).with_context(|| format!("failed to remove {}", from.display()))?;
+    }
+    Ok(())
+}
+
+#[cfg(any(
+    target_os = "windows",
+    all(unix, not(target_os = "macos"), not(target_os = "ios"), not(target_os = "android"))
+))]
+fn restore_from_trash(path: &Path) -> Result<()> {
+    if path.exists() {
+        bail!("{} already exists", path.display());
+    }
+    // The same path may have been trashed several times, the latest one is ours
+    let item = trash::os_limited::list()
+        .context("failed to list the trash")?
+        .into_iter()
+        .filter(|item| item.original_path() == path)
+        .max_by_key(|item| item.time_deleted)
+        .with_context(|| format!("{} is no longer in the trash", path.display()))?;
+    trash::os_limited::restore_all([item])
+        .with_context(|| format!("failed to restore {} from the trash", path.display()))
+}
+
+#[cfg(not(any(
+    target_os = "windows",
+    all(unix, not(target_os = "macos"), not(target_os = "ios"), not(target_os = "android"))
+)))]
+fn restore_from_trash(path: &Path) -> Result<()> {
+    bail!("restoring {} from the trash is not supported on this platform", path.display())
+}
+
+/// Images inside archives aren't files of their own that could be trashed or moved
+fn ensure_not_archived(path: &Path) -> Result<()> {
+    if crate::archive::split(path).is_some() {
+        bail!("{} is inside an archive", path.display());
+    }
+    Ok(())
+}
@@ -0,0 +1,43 @@
+//! `veh find <dir> --where <query>`: listing the images in a directory whose metadata satisfies a query, read
+//! from their headers without decoding them. Other commands that go through directories list images here too.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+
+use crate::errors;
+use crate::loader::is_supported;
+use crate::metadata::Metadata;
+use crate::query::Filter;
+
+/// Scan `dir` for images whose metadata satisfies `filter`, in path order.
+/// Files whose headers can't be read are reported and skipped.
+pub fn find(dir: &Path, filter: &Filter, recursive: bool) -> Result<Vec<PathBuf>> {
+    let mut found = vec![];
+    for path in list_images(dir, recursive)? {
+        match Metadata::read(&path) {
+            Ok(metadata) if filter.matches(&metadata) => found.push(path),
+            Ok(_) => {}
+            Err(err) => errors::report(Some(&path), &err),
+        }
+    }
+    Ok(found)
+}
+
+/// The supported images in `dir`, sorted by path
+pub fn list_images(dir: &Path, recursive: bool) -> Result<Vec<PathBuf>> {
+    let mut images = vec![];
+    let entries = std::fs::read_dir(dir).with_context(|| format!("failed to read {}", dir.display()))?;
+    for entry in entries {
+        let path = entry?.path();
+        if path.is_dir() {
+            if recursive {
+                images.extend(list_images(&path, recursive)?);
+            }
+        } else if is_supported(&path) {
+            images.push(path);
+        }
+    }
+    images.sort();
+    Ok(images)
+}
@@ -0,0 +1,168 @@
+//! High dynamic range and high precision images: OpenEXR and Radiance HDR files, whose linear values go beyond
+//! white, and 16-bit PNGs and TIFFs. Their pixels are kept as floats, so that the exposure can be changed without
+//! losing precision, and mapped to the 8-bit sRGB veh draws in. Highlights of HDR images are compressed by a tone
+//! mapping operator rather than clipped to white.
+
+use std::cell::RefCell;
+use std::sync::Arc;
+
+use clap::ValueEnum;
+use image::{DynamicImage, Rgba32FImage};
+use vello::peniko::{Blob, Format, Image};
+
+/// Exposure steps of the `[` and `]` keys in EV
+pub const EXPOSURE_STEP: f32 = 1.0 / 3.0;
+/// The linear value auto-exposure brings the median luminance of an image to
+const MIDDLE_GREY: f32 = 0.18;
+/// The range and resolution of the histogram auto-exposure is found from, in EV
+const HISTOGRAM_RANGE: (f32, f32) = (-24.0, 24.0);
+const BUCKETS_PER_EV: f32 = 8.0;
+
+/// How linear values above 1 are brought into the range of the display
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Tonemap {
+    /// The filmic curve of the ACES reference rendering, fitted by Krzysztof Narkowicz
+    #[default]
+    Aces,
+    /// x / (1 + x), which keeps more of the midtones
+    Reinhard,
+    /// Clip to white
+    Clip,
+}
+
+impl Tonemap {
+    fn apply(self, value: f32) -> f32 {
+        match self {
+            Tonemap::Aces => (value * (2.51 * value + 0.03)) / (value * (2.43 * value + 0.59) + 0.14),
+            Tonemap::Reinhard => value / (1.0 + value),
+            Tonemap::Clip => value,
+        }
+    }
+}
+
+/// How an HDR image is shown in a window
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct View {
+    /// In EV, i.e. stops brighter (or darker if negative) than the image as stored
+    pub exposure: f32,
+    pub tonemap: Tonemap,
+}
+
+/// Whether `image` has more than 8 bits per channel, and is best shown through an [`HdrImage`]
+pub fn is_high_precision(image: &DynamicImage) -> bool {
+    matches!(
+        image,
+        DynamicImage::ImageLuma16(_)
+            | DynamicImage::ImageLumaA16(_)
+            | DynamicImage::ImageRgb16(_)
+            | DynamicImage::ImageRgba16(_)
+            | DynamicImage::ImageRgb32F(_)
+            | DynamicImage::ImageRgba32F(_)
+    )
+}
+
+pub struct HdrImage {
+    /// Linear, not premultiplied
+    pixels: Rgba32FImage,
+    /// Whether the values are scene-referred, i.e. go beyond white and need tone mapping, as those of float
+    /// images do. 16-bit images are only exposed.
+    scene_referred: bool,
+    /// The last image mapped for display, and the view it was mapped for
+    mapped: RefCell<Option<(View, Image)>>,
+}
+
+impl HdrImage {
+    pub fn new(image: DynamicImage) -> Self {
+        let scene_referred = matches!(image, DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_));
+        let mut pixels = image.into_rgba32f();
+        if !scene_referred {
+            for pixel in pixels.pixels_mut() {
+                for channel in &mut pixel.0[..3] {
+                    *channel = srgb_to_linear(*channel);
+                }
+            }
+        }
+        Self { pixels, scene_referred, mapped: RefCell::new(None) }
+    }
+
+    pub fn size(&self) -> (f64, f64) {
+        (self.pixels.width() as f64, self.pixels.height() as f64)
+    }
+
+    /// The image turned a quarter turn clockwise, or counter-clockwise
+    pub fn rotated(&self, clockwise: bool) -> Self {
+        let pixels = if clockwise { image::imageops::rotate90(&self.pixels) } else { image::imageops::rotate270(&self.pixels) };
+        Self { pixels, scene_referred: self.scene_referred, mapped: RefCell::new(None) }
+    }
+
+    /// The exposure that shows the median luminance of the image as middle grey, rounded to exposure steps. The
+    /// median is read from a histogram of the luminances, so that highlights and shadows don't sway it the way
+    /// they do the average. Black and transparent pixels don't count.
+    pub fn auto_exposure(&self) -> f32 {
+        let (low, high) = HISTOGRAM_RANGE;
+        let mut histogram = vec![0_usize; ((high - low) * BUCKETS_PER_EV) as usize];
+        let last = (histogram.len() - 1) as f32;
+        let mut count = 0;
+        for pixel in self.pixels.pixels() {
+            let [red, green, blue, alpha] = pixel.0;
+            let luminance = 0.2126 * red + 0.7152 * green + 0.0722 * blue;
+            if alpha <= 0.0 || luminance <= 0.0 || !luminance.is_finite() {
+                continue;
+            }
+            histogram[((luminance.log2() - low) * BUCKETS_PER_EV).clamp(0.0, last) as usize] += 1;
+            count += 1;
+        }
+        if count == 0 {
+            return 0.0;
+        }
+        let mut seen = 0;
+        let median = histogram
+            .iter()
+            .position(|&pixels| {
+                seen += pixels;
+                seen * 2 >= count
+            })
+            .unwrap_or_default();
+        let median_ev = low + (median as f32 + 0.5) / BUCKETS_PER_EV;
+        ((MIDDLE_GREY.log2() - median_ev) / EXPOSURE_STEP).round() * EXPOSURE_STEP
+    }
+
+    /// The image as shown with `view`
+    pub fn image(&self, view: View) -> Image {
+        if let Some((mapped_view, image)) = &*self.mapped.borrow() {
+            if *mapped_view == view {
+                return image.clone();
+            }
+        }
+        let gain = view.exposure.exp2();
+        let tonemap = if self.scene_referred { view.tonemap } else { Tonemap::Clip };
+        let mut data = Vec::with_capacity(self.pixels.len());
+        for pixel in self.pixels.pixels() {
+            let [red, green, blue, alpha] = pixel.0;
+            for channel in [red, green, blue] {
+                let mapped = tonemap.apply((channel * gain).max(0.0)).min(1.0);
+                data.push((linear_to_srgb(mapped) * 255.0).round() as u8);
+            }
+            data.push((alpha.clamp(0.0, 1.0) * 255.0).round() as u8);
+        }
+        let image = Image::new(Blob::new(Arc::new(data)), Format::Rgba8, self.pixels.width(), self.pixels.height());
+        *self.mapped.borrow_mut() = Some((view, image.clone()));
+        image
+    }
+}
+
+fn srgb_to_linear(value: f32) -> f32 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(value: f32) -> f32 {
+    if value <= 0.0031308 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    }
+}
@@ -0,0 +1,57 @@
+//! The global hotkey Ctrl+Alt+O, which toggles `--click-through` windows between letting clicks and keys pass to
+//! the windows below and taking them themselves. Windows that take no input can't see keys, so the key is grabbed
+//! from the X server. Wayland has no global hotkeys for clients; bind `veh --remote click-through` to a key in the
+//! compositor instead.
+
+use anyhow::{bail, Result};
+use winit::event_loop::EventLoopProxy;
+
+use crate::UserEvent;
+
+/// The keysym of the O key, pressed with Ctrl+Alt
+#[cfg(all(unix, not(target_os = "macos")))]
+const KEYSYM: u32 = 0x006f;
+
+/// Grab the hotkey and send [`UserEvent::ToggleClickThrough`] through `proxy` whenever it is pressed
+#[cfg(all(unix, not(target_os = "macos")))]
+pub fn listen(proxy: EventLoopProxy<UserEvent>) -> Result<()> {
+    use anyhow::Context;
+    use x11rb::connection::Connection;
+    use x11rb::protocol::xproto::{ConnectionExt, GrabMode, ModMask};
+    use x11rb::protocol::Event;
+
+    if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+        bail!("Wayland has no global hotkeys, bind `veh --remote click-through` to a key instead");
+    }
+    let (conn, screen_num) = x11rb::connect(None).context("failed to connect to the X server")?;
+    let setup = conn.setup();
+    let root = setup.roots[screen_num].root;
+    let (min_keycode, max_keycode) = (setup.min_keycode, setup.max_keycode);
+    let mapping = conn.get_keyboard_mapping(min_keycode, max_keycode - min_keycode + 1)?.reply()?;
+    let per_keycode = mapping.keysyms_per_keycode.max(1) as usize;
+    let Some(offset) = mapping.keysyms.chunks(per_keycode).position(|keysyms| keysyms.contains(&KEYSYM)) else {
+        bail!("the keyboard has no O key for the click-through hotkey");
+    };
+    let keycode = min_keycode + offset as u8;
+    // Caps Lock and Num Lock count as modifiers, so the key is grabbed with and without them
+    for locks in [ModMask::from(0u16), ModMask::LOCK, ModMask::M2, ModMask::LOCK | ModMask::M2] {
+        conn.grab_key(true, root, ModMask::CONTROL | ModMask::M1 | locks, keycode, GrabMode::ASYNC, GrabMode::ASYNC)?
+            .check()
+            .context("failed to grab Ctrl+Alt+O, another program uses it")?;
+    }
+    std::thread::spawn(move || {
+        while let Ok(event) = conn.wait_for_event() {
+            if let Event::KeyPress(_) = event {
+                if proxy.send_event(UserEvent::ToggleClickThrough).is_err() {
+                    break;
+                }
+            }
+        }
+    });
+    Ok(())
+}
+
+#[cfg(not(all(unix, not(target_os = "macos"))))]
+pub fn listen(_proxy: EventLoopProxy<UserEvent>) -> Result<()> {
+    bail!("there is no global hotkey on this platform, use `veh --remote click-through` instead")
+}
@@ -0,0 +1,156 @@
+//! `veh --info <images>`: what `identify` prints, read from the headers of the images without opening a window.
+//! Every image gets a line with its format, size, color type, frame count and file size, and a second line
+//! with the camera settings if it has EXIF. `--stats` decodes the images to add the statistics of their pixels.
+//! `--json` prints the same as a JSON array instead.
+
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use image::ImageDecoder;
+use serde_json::{json, Map, Value};
+
+use crate::metadata::{self, Metadata};
+use crate::stats::Stats;
+use crate::{animation, errors, loader};
+
+/// The EXIF fields of the summary, as named in [`metadata::FIELDS`]
+const EXIF_FIELDS: &[&str] = &["make", "model", "date", "exposure", "aperture", "iso", "focal", "orientation"];
+
+struct Info {
+    metadata: Metadata,
+    /// How the pixels are stored, e.g. Rgb8, if the image crate can read the header
+    color_type: Option<String>,
+    frames: usize,
+    pages: usize,
+    stats: Option<Stats>,
+}
+
+impl Info {
+    fn read(path: &Path, stats: bool) -> Result<Self> {
+        let metadata = Metadata::read(path)?;
+        let color_type = image::ImageReader::open(path)
+            .ok()
+            .and_then(|reader| reader.with_guessed_format().ok())
+            .and_then(|reader| reader.into_decoder().ok())
+            .map(|decoder| format!("{:?}", decoder.color_type()));
+        let frames = if animation::may_be_animated(&metadata.format) {
+            let data = std::fs::read(path).context("open image failed")?;
+            animation::frame_count(&data, &metadata.format)?
+        } else {
+            1
+        };
+        let pages = loader::page_count(path)?;
+        let stats = if stats { Some(Stats::read(path)?) } else { None };
+        Ok(Self { metadata, color_type, frames, pages, stats })
+    }
+
+    fn exif(&self) -> Vec<(&'static str, metadata::Value)> {
+        EXIF_FIELDS.iter().filter_map(|field| Some((*field, self.metadata.get(field)?))).collect()
+    }
+
+    fn to_json(&self, path: &Path) -> Value {
+        let exif: Map<String, Value> = self
+            .exif()
+            .into_iter()
+            .map(|(field, value)| {
+                let value = match value {
+                    metadata::Value::Number(number) => json!(number),
+                    metadata::Value::Text(text) => json!(text),
+                };
+                (field.to_string(), value)
+            })
+            .collect();
+        let mut value = json!({
+            "path": path,
+            "format": self.metadata.format,
+            "width": self.metadata.width,
+            "height": self.metadata.height,
+            "color_type": self.color_type,
+            "frames": self.frames,
+            "pages": self.pages,
+            "file_size": self.metadata.file_size,
+            "exif": exif,
+        });
+        if let Some(stats) = &self.stats {
+            value["stats"] = stats.to_json();
+        }
+        value
+    }
+
+    fn print(&self, path: &Path) {
+        let metadata = &self.metadata;
+        let mut line = format!("{}: {} {}×{}", path.display(), metadata.format, metadata.width, metadata.height);
+        if let Some(color_type) = &self.color_type {
+            line += &format!(" {color_type}");
+        }
+        if self.frames > 1 {
+            line += &format!(", {} frames", self.frames);
+        }
+        if self.pages > 1 {
+            line += &format!(", {} pages", self.pages);
+        }
+        line += &format!(", {}", file_size(metadata.file_size));
+        println!("{line}");
+
+        let exif: Vec<String> = self.exif().into_iter().map(|(field, value)| exif_text(field, value)).collect();
+        if !exif.is_empty() {
+            println!("  {}", exif.join(", "));
+        }
+        for line in self.stats.iter().flat_map(Stats::lines) {
+            println!("  {line}");
+        }
+    }
+}
+
+/// Print what the headers of the images at `paths` say, with the statistics of their pixels if `stats`, as JSON
+/// if `json`. Images that can't be read are reported and make it fail once all others are printed.
+pub fn print(paths: &[impl AsRef<Path>], json: bool, stats: bool) -> Result<()> {
+    let mut failed = 0;
+    let mut first_error = None;
+    let mut infos = vec![];
+    for path in paths {
+        let path = path.as_ref();
+        match Info::read(path, stats) {
+            Ok(info) if json => infos.push(info.to_json(path)),
+            Ok(info) => info.print(path),
+            Err(err) => {
+                errors::report(Some(path), &err);
+                failed += 1;
+                first_error.get_or_insert(err);
+            }
+        }
+    }
+    if json {
+        println!("{}", serde_json::to_string_pretty(&infos)?);
+    }
+    // The first error decides the exit code
+    match first_error {
+        Some(err) => Err(err.context(format!("{failed} of {} images couldn't be read", paths.len()))),
+        None => Ok(()),
+    }
+}
+
+fn file_size(bytes: u64) -> String {
+    match bytes {
+        0..=1023 => format!("{bytes} B"),
+        1024..=1_048_575 => format!("{:.1} KiB", bytes as f64 / 1024.0),
+        _ => format!("{:.1} MiB", bytes as f64 / 1_048_576.0),
+    }
+}
+
+/// An EXIF field as photographers write it, e.g. 1/250 s or f/2.8
+fn exif_text(field: &str, value: metadata::Value) -> String {
+    let number = match value {
+        metadata::Value::Text(text) => return text,
+        metadata::Value::Number(number) => number,
+    };
+    match field {
+        "exposure" if number > 0.0 && number < 1.0 => format!("1/{:.0} s", 1.0 / number),
+        "exposure" => format!("{number} s"),
+        "aperture" => format!("f/{number}"),
+        "iso" => format!("ISO {number}"),
+        "focal" => format!("{number} mm"),
+        "orientation" => format!("orientation {number}"),
+        _ => number.to_string(),
+    }
+}
@@ -0,0 +1,417 @@
+//! Remote control of a running veh over a Unix socket, speaking JSON-RPC 2.0 with one message per line.
+//!
+//! Besides the commands listed by the `list-commands` method, clients can `subscribe` to events, which
+//! are sent to them as JSON-RPC notifications, e.g.
+//! `{"jsonrpc":"2.0","method":"image-changed","params":{"window":1,"index":3,"path":"a.png"}}`.
+
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+
+use anyhow::{bail, Context, Result};
+use serde_json::{json, Value};
+use winit::event_loop::EventLoopProxy;
+
+use crate::UserEvent;
+
+// Error codes defined by JSON-RPC
+pub const PARSE_ERROR: i64 = -32700;
+pub const INVALID_REQUEST: i64 = -32600;
+pub const METHOD_NOT_FOUND: i64 = -32601;
+pub const INVALID_PARAMS: i64 = -32602;
+/// A valid command that failed, e.g. because there is no window to run it in
+pub const COMMAND_FAILED: i64 = -32000;
+
+/// A command a client can run, with its parameters
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    ListCommands,
+    Subscribe { events: Vec<String> },
+    Unsubscribe,
+    State,
+    Windows,
+    Playlist,
+    Next,
+    Prev,
+    Goto { index: usize },
+    /// Add an image to the end of the playlist and show it
+    Open { path: PathBuf },
+    /// Add images to the end of the playlist, and show the first of them if `show`
+    Append { paths: Vec<PathBuf>, show: bool },
+    Zoom { percent: f64 },
+    Slideshow,
+    /// Let clicks pass through all windows to the windows below, or stop it
+    ClickThrough,
+    ToggleMark,
+    Fit,
+    LockZoom,
+    NewWindow,
+    CloseWindow,
+    Quit,
+}
+
+/// Description of a command for `list-commands`
+pub struct CommandInfo {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub params: &'static [&'static str],
+}
+
+/// Every command clients can run, in the order `list-commands` reports them
+pub const COMMANDS: &[CommandInfo] = &[
+    CommandInfo { name: "list-commands", description: "List the available commands and events", params: &[] },
+    CommandInfo { name: "subscribe", description: "Receive the given events as notifications", params: &["events"] },
+    CommandInfo { name: "unsubscribe", description: "Stop receiving events", params: &[] },
+    CommandInfo { name: "state", description: "What the focused window shows: file, index, zoom and geometry", params: &[] },
+    CommandInfo { name: "windows", description: "The state of every window", params: &[] },
+    CommandInfo { name: "playlist", description: "Every image with whether it is marked and can be shown", params: &[] },
+    CommandInfo { name: "next", description: "Show the next image", params: &[] },
+    CommandInfo { name: "prev", description: "Show the previous image", params: &[] },
+    CommandInfo { name: "goto", description: "Show the image at a 0-based index of the playlist", params: &["index"] },
+    CommandInfo { name: "open", description: "Add the image at a path to the playlist and show it", params: &["path"] },
+    CommandInfo { name: "append", description: "Add images to the playlist, and show the first if `show`", params: &["paths", "show"] },
+    CommandInfo { name: "zoom", description: "Show the image at a zoom percentage", params: &["percent"] },
+    CommandInfo { name: "slideshow", description: "Start or stop the slideshow", params: &[] },
+    CommandInfo { name: "click-through", description: "Let clicks pass through the windows, or stop it", params: &[] },
+    CommandInfo { name: "toggle-mark", description: "Mark or unmark the current image", params: &[] },
+    CommandInfo { name: "fit", description: "Fit the image into the window", params: &[] },
+    CommandInfo { name: "lock-zoom", description: "Lock the current zoom for all images, or unlock it", params: &[] },
+    CommandInfo { name: "new-window", description: "Open the current image in a new window", params: &[] },
+    CommandInfo { name: "close-window", description: "Close the window", params: &[] },
+    CommandInfo { name: "quit", description: "Close all windows and exit", params: &[] },
+];
+
+/// Events clients can subscribe to
+pub const EVENTS: &[&str] = &["image-changed", "mark-changed", "window-opened", "window-closed"];
+
+/// A JSON-RPC error
+#[derive(Debug, Clone, PartialEq)]
+pub struct RpcError {
+    pub code: i64,
+    pub message: String,
+}
+
+impl RpcError {
+    pub fn new(code: i64, message: impl Into<String>) -> Self {
+        Self { code, message: message.into() }
+    }
+}
+
+impl Command {
+    pub fn parse(method: &str, params: &Value) -> Result<Self, RpcError> {
+        let command = match method {
+            "list-commands" => Command::ListCommands,
+            "subscribe" => {
+                let events: Vec<String> = match params.get("events") {
+                    Some(events) => serde_json::from_value(events.clone())
+                        .map_err(|_| RpcError::new(INVALID_PARAMS, "`events` must be a list of event names"))?,
+                    None => EVENTS.iter().map(|event| event.to_string()).collect(),
+                };
+                if let Some(unknown) = events.iter().find(|event| !EVENTS.contains(&event.as_str())) {
+                    return Err(RpcError::new(INVALID_PARAMS, format!("unknown event `{unknown}`")));
+                }
+                Command::Subscribe { events }
+            }
+            "unsubscribe" => Command::Unsubscribe,
+            "state" => Command::State,
+            "windows" => Command::Windows,
+            "playlist" => Command::Playlist,
+            "next" => Command::Next,
+            "prev" => Command::Prev,
+            "goto" => {
+                let index = params
+                    .get("index")
+                    .and_then(Value::as_u64)
+                    .ok_or_else(|| RpcError::new(INVALID_PARAMS, "`index` must be a non-negative integer"))?;
+                Command::Goto { index: index as usize }
+            }
+            "open" => {
+                let path = params
+                    .get("path")
+                    .and_then(Value::as_str)
+                    .ok_or_else(|| RpcError::new(INVALID_PARAMS, "`path` must be a string"))?;
+                Command::Open { path: PathBuf::from(path) }
+            }
+            "append" => {
+                let paths: Vec<PathBuf> = params
+                    .get("paths")
+                    .and_then(|paths| serde_json::from_value(paths.clone()).ok())
+                    .filter(|paths: &Vec<PathBuf>| !paths.is_empty())
+                    .ok_or_else(|| RpcError::new(INVALID_PARAMS, "`paths` must be a non-empty list of paths"))?;
+                let show = match params.get("show") {
+                    None => false,
+                    Some(show) => show.as_bool().ok_or_else(|| RpcError::new(INVALID_PARAMS, "`show` must be a boolean"))?,
+                };
+                Command::Append { paths, show }
+            }
+            "zoom" => {
+                let percent = params
+                    .get("percent")
+                    .and_then(Value::as_f64)
+                    .filter(|percent| *percent > 0.0)
+                    .ok_or_else(|| RpcError::new(INVALID_PARAMS, "`percent` must be a positive number"))?;
+                Command::Zoom { percent }
+            }
+            "slideshow" => Command::Slideshow,
+            "click-through" => Command::ClickThrough,
+            "toggle-mark" => Command::ToggleMark,
+            "fit" => Command::Fit,
+            "lock-zoom" => Command::LockZoom,
+            "new-window" => Command::NewWindow,
+            "close-window" => Command::CloseWindow,
+            "quit" => Command::Quit,
+            _ => return Err(RpcError::new(METHOD_NOT_FOUND, format!("unknown method `{method}`"))),
+        };
+        Ok(command)
+    }
+}
+
+/// The result of `list-commands`
+pub fn list_commands() -> Value {
+    let commands: Vec<Value> = COMMANDS
+        .iter()
+        .map(|info| json!({ "name": info.name, "description": info.description, "params": info.params }))
+        .collect();
+    json!({ "commands": commands, "events": EVENTS })
+}
+
+/// A connected client, through which replies and notifications are sent
+#[derive(Clone)]
+pub struct Client {
+    id: usize,
+    sender: mpsc::Sender<Value>,
+}
+
+impl Client {
+    /// Answer the request with `id`. Requests without an id are notifications, which get no answer.
+    pub fn reply(&self, id: &Value, result: Result<Value, RpcError>) {
+        if id.is_null() {
+            return;
+        }
+        let _ = self.sender.send(response(id, result));
+    }
+}
+
+fn response(id: &Value, result: Result<Value, RpcError>) -> Value {
+    match result {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err(err) => json!({ "jsonrpc": "2.0", "id": id, "error": { "code": err.code, "message": err.message } }),
+    }
+}
+
+/// A command received from a client, to be run by the event loop
+pub struct Request {
+    pub id: Value,
+    pub command: Command,
+    pub client: Client,
+}
+
+/// The clients subscribed to events
+#[derive(Default)]
+pub struct Subscriptions {
+    // Shared by all windows, which only hold on to it immutably
+    clients: RefCell<Vec<(Client, HashSet<String>)>>,
+}
+
+impl Subscriptions {
+    pub fn subscribe(&self, client: &Client, events: Vec<String>) {
+        let mut clients = self.clients.borrow_mut();
+        clients.retain(|(subscribed, _)| subscribed.id != client.id);
+        clients.push((client.clone(), events.into_iter().collect()));
+    }
+
+    pub fn unsubscribe(&self, client: &Client) {
+        self.clients.borrow_mut().retain(|(subscribed, _)| subscribed.id != client.id);
+    }
+
+    /// Notify the clients subscribed to `event`, forgetting those that have disconnected
+    pub fn emit(&self, event: &str, params: Value) {
+        let notification = json!({ "jsonrpc": "2.0", "method": event, "params": params });
+        self.clients.borrow_mut().retain(|(client, events)| {
+            !events.contains(event) || client.sender.send(notification.clone()).is_ok()
+        });
+    }
+}
+
+/// The socket veh listens on with `--ipc`: `veh.sock` in the runtime directory of the user
+pub fn default_socket_path() -> PathBuf {
+    let dir = std::env::var_os("XDG_RUNTIME_DIR").map(PathBuf::from).unwrap_or_else(std::env::temp_dir);
+    dir.join("veh.sock")
+}
+
+/// The listening socket, removed when dropped
+pub struct Server {
+    path: PathBuf,
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// Listen on `path` and pass the commands of every client to the event loop
+#[cfg(unix)]
+pub fn serve(path: &Path, proxy: EventLoopProxy<UserEvent>) -> Result<Server> {
+    use std::os::unix::net::{UnixListener, UnixStream};
+
+    if path.exists() {
+        // A socket left behind by a veh that didn't exit cleanly can be replaced, a live one can't
+        if UnixStream::connect(path).is_ok() {
+            bail!("another veh is already listening on {}", path.display());
+        }
+        std::fs::remove_file(path).with_context(|| format!("failed to remove {}", path.display()))?;
+    }
+    let listener = UnixListener::bind(path).with_context(|| format!("failed to listen on {}", path.display()))?;
+
+    static NEXT_CLIENT: AtomicUsize = AtomicUsize::new(0);
+    std::thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let Ok(writer) = stream.try_clone() else {
+                continue;
+            };
+            let (sender, receiver) = mpsc::channel();
+            let client = Client { id: NEXT_CLIENT.fetch_add(1, Ordering::Relaxed), sender };
+            std::thread::spawn(move || write_messages(writer, receiver));
+            let proxy = proxy.clone();
+            std::thread::spawn(move || read_requests(BufReader::new(stream), client, proxy));
+        }
+    });
+    Ok(Server { path: path.to_path_buf() })
+}
+
+#[cfg(not(unix))]
+pub fn serve(_path: &Path, _proxy: EventLoopProxy<UserEvent>) -> Result<Server> {
+    bail!("remote control is not supported on this platform")
+}
+
+/// Send the replies and notifications for a client until it disconnects
+fn write_messages(mut writer: impl Write, receiver: mpsc::Receiver<Value>) {
+    for message in receiver {
+        if writeln!(writer, "{message}").and_then(|()| writer.flush()).is_err() {
+            return;
+        }
+    }
+}
+
+/// Read the requests of a client, one per line, until it disconnects or veh exits
+fn read_requests(reader: impl BufRead, client: Client, proxy: EventLoopProxy<UserEvent>) {
+    for line in reader.lines() {
+        let Ok(line) = line else {
+            return;
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        let message: Value = match serde_json::from_str(&line) {
+            Ok(message) => message,
+            Err(err) => {
+                let _ = client.sender.send(response(&Value::Null, Err(RpcError::new(PARSE_ERROR, err.to_string()))));
+                continue;
+            }
+        };
+        let id = message.get("id").cloned().unwrap_or(Value::Null);
+        let command = parse_request(&message);
+        match command {
+            Ok(command) => {
+                if proxy.send_event(UserEvent::Ipc(Request { id, command, client: client.clone() })).is_err() {
+                    return;
+                }
+            }
+            Err(err) => {
+                // Invalid requests are answered even without an id, as the id may be what is wrong
+                let _ = client.sender.send(response(&id, Err(err)));
+            }
+        }
+    }
+}
+
+/// Run `command`, a command name followed by its parameters in the order `list-commands` gives them, in the veh
+/// listening on `path`, and print the result. This is `veh --remote`.
+pub fn remote(path: &Path, command: &[String]) -> Result<()> {
+    let (method, values) = command.split_first().context("no command given")?;
+    let info = COMMANDS
+        .iter()
+        .find(|info| info.name == method)
+        .with_context(|| format!("unknown command `{method}`, see `veh --remote list-commands`"))?;
+    if values.len() > info.params.len() {
+        bail!("`{method}` takes {} parameters", info.params.len());
+    }
+    let params: serde_json::Map<String, Value> =
+        info.params.iter().zip(values).map(|(name, value)| (name.to_string(), param_value(name, value))).collect();
+    match call(path, method, params.into())? {
+        Value::Null => {}
+        result => println!("{}", serde_json::to_string_pretty(&result)?),
+    }
+    Ok(())
+}
+
+/// Add `paths` to the playlist of the veh listening on `path`, and show the first of them if `show`. This is
+/// `veh --append`.
+pub fn append(path: &Path, paths: &[PathBuf], show: bool) -> Result<()> {
+    if paths.is_empty() {
+        bail!("no images to append");
+    }
+    let paths: Vec<PathBuf> = paths.iter().map(|path| absolute(path)).collect();
+    call(path, "append", json!({ "paths": paths, "show": show }))?;
+    Ok(())
+}
+
+/// Send the request for `method` with `params` to the veh listening on `path`, and return its result
+#[cfg(unix)]
+fn call(path: &Path, method: &str, params: Value) -> Result<Value> {
+    use std::os::unix::net::UnixStream;
+
+    let mut stream = UnixStream::connect(path)
+        .with_context(|| format!("no veh is listening on {}, start one with --ipc", path.display()))?;
+    let request = json!({ "jsonrpc": "2.0", "id": 1, "method": method, "params": params });
+    writeln!(stream, "{request}").context("failed to send the command")?;
+    let mut line = String::new();
+    BufReader::new(&stream).read_line(&mut line).context("failed to read the reply")?;
+    let mut reply: Value = serde_json::from_str(&line).context("veh sent an invalid reply")?;
+    if let Some(error) = reply.get("error") {
+        bail!("{}", error.get("message").and_then(Value::as_str).unwrap_or("the command failed"));
+    }
+    Ok(reply.get_mut("result").map(Value::take).unwrap_or_default())
+}
+
+#[cfg(not(unix))]
+fn call(_path: &Path, _method: &str, _params: Value) -> Result<Value> {
+    bail!("remote control is not supported on this platform")
+}
+
+/// A parameter given on the command line as JSON, e.g. a number, or else as a string
+fn param_value(name: &str, value: &str) -> Value {
+    // Paths are made absolute, as the running veh may have been started in another directory
+    match name {
+        "path" => json!(absolute(Path::new(value))),
+        // A list of paths, or a single one
+        "paths" => {
+            let paths: Vec<PathBuf> = serde_json::from_str(value).unwrap_or_else(|_| vec![PathBuf::from(value)]);
+            json!(paths.iter().map(|path| absolute(path)).collect::<Vec<_>>())
+        }
+        _ => serde_json::from_str(value).unwrap_or_else(|_| Value::String(value.to_string())),
+    }
+}
+
+/// `path` relative to the root rather than the current directory, if it can be made so
+fn absolute(path: &Path) -> PathBuf {
+    std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+fn parse_request(message: &Value) -> Result<Command, RpcError> {
+    if message.is_array() {
+        return Err(RpcError::new(INVALID_REQUEST, "batches are not supported"));
+    }
+    if message.get("jsonrpc").and_then(Value::as_str) != Some("2.0") {
+        return Err(RpcError::new(INVALID_REQUEST, "not a JSON-RPC 2.0 request"));
+    }
+    let method = message
+        .get("method")
+        .and_then(Value::as_str)
+        .ok_or_else(|| RpcError::new(INVALID_REQUEST, "`method` must be a string"))?;
+    Command::parse(method, message.get("params").unwrap_or(&Value::Null))
+}
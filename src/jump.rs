@@ -0,0 +1,30 @@
+//! Jumping straight to an image: `g` and `G` go to the first and last image, a count typed before a key repeats
+//! or targets it (`5n` skips five images ahead, `12g` goes to the twelfth), and `/` opens a prompt for an index
+//! or part of a file name.
+
+use crate::playlist::Playlist;
+
+/// The count typed so far, `count`, followed by `digit`. A leading 0 isn't a count.
+pub fn push_digit(count: Option<usize>, digit: u32) -> Option<usize> {
+    match count {
+        Some(count) => Some(count.saturating_mul(10).saturating_add(digit as usize)),
+        None => (digit > 0).then_some(digit as usize),
+    }
+}
+
+/// The index of the image the prompt `input` asks for: the image at the 1-based index it gives, or else the next
+/// one after `from` whose file name contains it, ignoring case
+pub fn find(playlist: &Playlist, from: usize, input: &str) -> Option<usize> {
+    let input = input.trim();
+    if input.is_empty() {
+        return None;
+    }
+    if let Ok(number) = input.parse::<usize>() {
+        return (1..=playlist.len()).contains(&number).then(|| number - 1);
+    }
+    let fragment = input.to_lowercase();
+    (1..=playlist.len()).map(|offset| (from + offset) % playlist.len()).find(|&index| {
+        let name = playlist.get(index).file_name().unwrap_or_default();
+        name.to_string_lossy().to_lowercase().contains(&fragment)
+    })
+}
@@ -0,0 +1,469 @@
+use std::collections::{HashMap, VecDeque};
+use std::fs::File;
+use std::io::{BufReader, Cursor, Read};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use image::DynamicImage;
+use vello::kurbo::Vec2;
+use vello::peniko::{Blob, Format, Image};
+use vello_svg::usvg;
+
+use crate::errors::Unsupported;
+use crate::hdr::HdrImage;
+use crate::{animation, archive, color, containers, external, hdr, raw};
+
+/// How many decoded images are kept around by [`ImageCache`]
+const CACHE_CAPACITY: usize = 8;
+/// With `--low-memory`, the longest side raster images are downscaled to when they are loaded
+const LOW_MEMORY_MAX_SIZE: u32 = 2048;
+
+/// Whether images are not cached, with `--low-memory`
+static LOW_MEMORY: AtomicBool = AtomicBool::new(false);
+/// The longest side raster images are downscaled to when they are loaded, if any
+static MAX_SIZE: AtomicU32 = AtomicU32::new(u32::MAX);
+
+/// Downscale images on load and don't cache them
+pub fn set_low_memory(low_memory: bool) {
+    LOW_MEMORY.store(low_memory, Ordering::Relaxed);
+    if low_memory {
+        limit_size(LOW_MEMORY_MAX_SIZE);
+    }
+}
+
+fn is_low_memory() -> bool {
+    LOW_MEMORY.load(Ordering::Relaxed)
+}
+
+/// Downscale raster images whose longer side is longer than `max` pixels when they are loaded
+pub fn limit_size(max: u32) {
+    MAX_SIZE.fetch_min(max, Ordering::Relaxed);
+}
+
+/// Whether large images are downscaled when they are loaded, so that they aren't shown at their full resolution
+pub fn limits_size() -> bool {
+    MAX_SIZE.load(Ordering::Relaxed) < u32::MAX
+}
+
+/// File extensions veh knows how to open
+pub const SUPPORTED_FORMATS: &[&str] =
+    &["svg", "svgz", "png", "jpg", "jpeg", "bmp", "gif", "ico", "tiff", "webp", "exr", "hdr"];
+
+/// Formats with several pages, shown one page at a time
+pub const DOCUMENT_FORMATS: &[&str] = if cfg!(feature = "pdf") { &["pdf"] } else { &[] };
+
+/// Fonts for the text in SVG images and veh's own texts, loaded on first use as loading the system fonts is slow
+static FONTS: OnceLock<SvgFonts> = OnceLock::new();
+
+struct SvgFonts {
+    database: usvg::fontdb::Database,
+    // Family for text that doesn't name one, instead of usvg's default of Times New Roman
+    default_family: Option<String>,
+}
+
+/// Use the fonts in `dirs` in addition to the system fonts for SVG text, and `default_family` for text that
+/// doesn't name a font. Has to be called before the first SVG is opened to take effect.
+pub fn init_fonts(dirs: &[PathBuf], default_family: Option<String>) {
+    let mut database = usvg::fontdb::Database::new();
+    database.load_system_fonts();
+    for dir in dirs {
+        database.load_fonts_dir(dir);
+    }
+    let _ = FONTS.set(SvgFonts { database, default_family });
+}
+
+/// The system fonts (and those added with `init_fonts`)
+pub fn font_database() -> &'static usvg::fontdb::Database {
+    &fonts().database
+}
+
+fn fonts() -> &'static SvgFonts {
+    FONTS.get_or_init(|| {
+        let mut database = usvg::fontdb::Database::new();
+        database.load_system_fonts();
+        SvgFonts { database, default_family: None }
+    })
+}
+
+pub enum VehImage {
+    Image(Image),
+    /// Shared with the threads that render its scenes ahead
+    Svg(Arc<usvg::Tree>),
+    /// Pages shown side by side as one image, e.g. the two pages of a spread of a comic, each at its offset
+    Spread(Vec<(Rc<VehImage>, Vec2)>),
+    /// The frames of an animated image and how long each is shown
+    Animation(Vec<(Image, Duration)>),
+    /// An HDR or 16-bit image, mapped to the display with the exposure of the window
+    Hdr(Box<HdrImage>),
+}
+
+impl VehImage {
+    /// The pages `left` and `right` next to each other, centered vertically
+    pub fn spread(left: Rc<VehImage>, right: Rc<VehImage>) -> Self {
+        let ((left_width, left_height), (_, right_height)) = (left.size(), right.size());
+        let height = left_height.max(right_height);
+        let left_offset = Vec2::new(0.0, (height - left_height) / 2.0);
+        let right_offset = Vec2::new(left_width, (height - right_height) / 2.0);
+        VehImage::Spread(vec![(left, left_offset), (right, right_offset)])
+    }
+
+    pub fn size(&self) -> (f64, f64) {
+        match self {
+            VehImage::Image(image) => (image.width as f64, image.height as f64),
+            VehImage::Hdr(image) => image.size(),
+            // All frames are the size of the whole animation
+            VehImage::Animation(frames) => (frames[0].0.width as f64, frames[0].0.height as f64),
+            VehImage::Svg(svg) => {
+                let size = svg.size();
+                (size.width() as f64, size.height() as f64)
+            }
+            VehImage::Spread(pages) => pages.iter().fold((0.0, 0.0), |(width, height), (page, offset)| {
+                let (page_width, page_height) = page.size();
+                (width.max(offset.x + page_width), height.max(offset.y + page_height))
+            }),
+        }
+    }
+
+    /// Whether the exposure applies to the image, or a page of it
+    pub fn is_hdr(&self) -> bool {
+        match self {
+            VehImage::Hdr(_) => true,
+            VehImage::Spread(pages) => pages.iter().any(|(page, _)| page.is_hdr()),
+            _ => false,
+        }
+    }
+
+    /// The exposure that shows the image, or its first HDR page, at a good brightness, if the exposure applies
+    pub fn auto_exposure(&self) -> Option<f32> {
+        match self {
+            VehImage::Hdr(image) => Some(image.auto_exposure()),
+            VehImage::Spread(pages) => pages.iter().find_map(|(page, _)| page.auto_exposure()),
+            _ => None,
+        }
+    }
+}
+
+/// The format of the image at `path`: what its first bytes say if they tell, else its lowercase extension.
+/// Phones often save HEIC photos with a .jpg extension, for example.
+pub fn format_of(path: &Path) -> Option<String> {
+    let extension = path.extension().and_then(|ext| ext.to_str()).map(|ext| ext.to_ascii_lowercase());
+    match (sniff(path), extension) {
+        // Keep the spelling of the extension, e.g. jpeg rather than jpg
+        (Some(sniffed), Some(extension)) if is_spelling_of(&extension, sniffed) => Some(extension),
+        (Some(sniffed), _) => Some(sniffed.to_string()),
+        (None, extension) => extension,
+    }
+}
+
+fn is_spelling_of(extension: &str, format: &str) -> bool {
+    // Most RAW formats are TIFF files, but can't be decoded as such
+    extension == format
+        || matches!((extension, format), ("jpeg", "jpg") | ("tif", "tiff") | ("heif", "heic"))
+        || (format == "tiff" && raw::is_raw(extension))
+}
+
+/// The format of the file at `path` judging by its first bytes, for the formats that can be told apart that way
+fn sniff(path: &Path) -> Option<&'static str> {
+    let mut header = [0; 32];
+    let read = File::open(path).ok()?.read(&mut header).ok()?;
+    sniff_bytes(&header[..read])
+}
+
+fn sniff_bytes(header: &[u8]) -> Option<&'static str> {
+    const JXL_CONTAINER: &[u8] = b"\0\0\0\x0cJXL \r\n\x87\n";
+    const AVIF_BRANDS: &[&[u8]] = &[b"avif", b"avis"];
+    const HEIF_BRANDS: &[&[u8]] = &[b"heic", b"heix", b"hevc", b"hevx", b"heim", b"heis", b"mif1", b"msf1"];
+
+    let format = match header {
+        [0x89, b'P', b'N', b'G', ..] => "png",
+        [0xff, 0xd8, 0xff, ..] => "jpg",
+        [b'G', b'I', b'F', b'8', ..] => "gif",
+        [b'B', b'M', ..] => "bmp",
+        [0, 0, 1, 0, ..] => "ico",
+        [b'I', b'I', b'*', 0, ..] | [b'M', b'M', 0, b'*', ..] => "tiff",
+        [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'E', b'B', b'P', ..] => "webp",
+        [b'%', b'P', b'D', b'F', ..] => "pdf",
+        [0xff, 0x0a, ..] => "jxl",
+        [0x76, 0x2f, 0x31, 0x01, ..] => "exr",
+        _ if header.starts_with(b"#?RADIANCE") || header.starts_with(b"#?RGBE") => "hdr",
+        _ if header.starts_with(JXL_CONTAINER) => "jxl",
+        // HEIF and AVIF are both ISO media files, told apart by the major and compatible brands of their ftyp box
+        [_, _, _, _, b'f', b't', b'y', b'p', major @ ..] if major.len() >= 8 => {
+            let brands: Vec<&[u8]> = std::iter::once(&major[..4]).chain(major[8..].chunks_exact(4)).collect();
+            if brands.iter().any(|brand| AVIF_BRANDS.contains(brand)) {
+                "avif"
+            } else if brands.iter().any(|brand| HEIF_BRANDS.contains(brand)) {
+                "heic"
+            } else {
+                return None;
+            }
+        }
+        _ => return None,
+    };
+    Some(format)
+}
+
+/// Whether `format` is SVG, plain or gzip-compressed
+pub fn is_svg(format: &str) -> bool {
+    format == "svg" || format == "svgz"
+}
+
+/// Parse the SVG (or gzip-compressed SVGZ) at `path`. Images it references by relative path are
+/// looked up next to it. Without `with_fonts` text is left out, which is enough to learn the size.
+pub fn parse_svg(path: &Path, with_fonts: bool) -> Result<usvg::Tree> {
+    let data = std::fs::read(path).context("read svg failed")?;
+    parse_svg_data(&data, path, with_fonts)
+}
+
+/// Parse the SVG `data` of the file at `path`
+fn parse_svg_data(data: &[u8], path: &Path, with_fonts: bool) -> Result<usvg::Tree> {
+    let mut options = usvg::Options {
+        resources_dir: Some(path.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new(".")).to_path_buf()),
+        ..Default::default()
+    };
+    let empty;
+    let fontdb = if with_fonts {
+        let fonts = fonts();
+        if let Some(family) = &fonts.default_family {
+            options.font_family = family.clone();
+        }
+        &fonts.database
+    } else {
+        empty = usvg::fontdb::Database::new();
+        &empty
+    };
+    // usvg decompresses SVGZ on its own
+    usvg::Tree::from_data(data, &options, fontdb).context("failed to parse svg file")
+}
+
+/// Whether `path` looks like a file veh can open, judging by its extension
+pub fn is_supported(path: &Path) -> bool {
+    format_of(path).is_some_and(|format| {
+        SUPPORTED_FORMATS.contains(&format.as_str())
+            || DOCUMENT_FORMATS.contains(&format.as_str())
+            || external::supports(&format)
+            || raw::is_raw(&format)
+    })
+}
+
+/// How many pages the image at `path` has, which is 1 for everything but documents, multi-page TIFFs and ICOs
+pub fn page_count(path: &Path) -> Result<usize> {
+    // Only the first page of images inside archives is shown
+    if archive::split(path).is_some() {
+        return Ok(1);
+    }
+    match format_of(path).as_deref() {
+        Some("tiff") => containers::tiff_page_count(path),
+        Some("ico") => containers::ico_entry_count(path),
+        #[cfg(feature = "pdf")]
+        Some("pdf") => crate::pdf::page_count(path),
+        _ => Ok(1),
+    }
+}
+
+/// Open the page with the 0-based index `page` of the image at `path`
+pub fn open_page(path: &Path, page: usize) -> Result<VehImage> {
+    if let Some((archive, entry)) = archive::split(path) {
+        return open_archived(path, &archive::read(archive, &entry)?);
+    }
+    match format_of(path).as_deref() {
+        #[cfg(feature = "pdf")]
+        Some("pdf") => Ok(VehImage::Image(to_peniko(crate::pdf::render_page(path, page)?))),
+        // The first page of a TIFF is left to the image crate, which supports more of them
+        Some("tiff") if page > 0 => Ok(to_veh_image(containers::tiff_page(path, page)?)),
+        Some("ico") => Ok(VehImage::Image(to_peniko(containers::ico_entry(path, page)?))),
+        _ if page > 0 => bail!("no such page"),
+        _ => open_image(path),
+    }
+}
+
+fn open_image(path: &Path) -> Result<VehImage> {
+    let format = format_of(path).context("no format given")?;
+    if external::supports(&format) {
+        return Ok(to_veh_image(external::decode(path, &format)?));
+    }
+    if raw::is_raw(&format) {
+        return Ok(VehImage::Image(to_peniko(raw::decode(path)?)));
+    }
+    if !SUPPORTED_FORMATS.contains(&format.as_str()) {
+        bail!(Unsupported(format!("{format} images aren't supported")));
+    }
+
+    if is_svg(&format) {
+        Ok(VehImage::Svg(Arc::new(parse_svg(path, true)?)))
+    } else if animation::may_be_animated(&format) {
+        // Only the decoder of the format can tell if an image is animated
+        let data = std::fs::read(path).context("open image failed")?;
+        decode_data(&data, &format)
+    } else {
+        // The content decides the decoder, not a possibly wrong extension
+        let reader = image::ImageReader::open(path)
+            .context("open image failed")?
+            .with_guessed_format()
+            .context("open image failed")?;
+        Ok(to_veh_image(color::decode(reader)?))
+    }
+}
+
+/// Decode the raster image at `path` as it is stored, for converting rather than viewing it: neither downscaled
+/// nor animated
+pub fn decode_still(path: &Path) -> Result<DynamicImage> {
+    decode_still_with(path, color::decode)
+}
+
+/// Decode the raster image at `path` like [`decode_still`], but with the values stored in the file: neither
+/// converted to sRGB nor turned upright, for measuring rather than viewing them
+pub fn decode_as_stored(path: &Path) -> Result<DynamicImage> {
+    decode_still_with(path, |reader| {
+        let decoder = reader.into_decoder().context("decode image failed")?;
+        DynamicImage::from_decoder(decoder).context("decode image failed")
+    })
+}
+
+fn decode_still_with(
+    path: &Path,
+    decode: impl FnOnce(image::ImageReader<BufReader<File>>) -> Result<DynamicImage>,
+) -> Result<DynamicImage> {
+    let format = format_of(path).context("no format given")?;
+    if external::supports(&format) {
+        return external::decode(path, &format);
+    }
+    if raw::is_raw(&format) {
+        return raw::decode(path);
+    }
+    if is_svg(&format) || !SUPPORTED_FORMATS.contains(&format.as_str()) {
+        bail!(Unsupported(format!("{format} images can't be converted")));
+    }
+    let reader = image::ImageReader::open(path)
+        .context("open image failed")?
+        .with_guessed_format()
+        .context("open image failed")?;
+    decode(reader)
+}
+
+/// Decode the image at `path` inside an archive from its decompressed `data`. Only the formats that can be
+/// decoded from memory are supported, not those left to external tools.
+fn open_archived(path: &Path, data: &[u8]) -> Result<VehImage> {
+    let format = format_of(path).context("no format given")?;
+    if is_svg(&format) {
+        return Ok(VehImage::Svg(Arc::new(parse_svg_data(data, path, true)?)));
+    }
+    if !SUPPORTED_FORMATS.contains(&format.as_str()) {
+        bail!(Unsupported(format!("{format} images can't be opened inside an archive")));
+    }
+    decode_data(data, &format)
+}
+
+/// Decode the raster image of `format` in `data`, with all its frames if it is animated
+fn decode_data(data: &[u8], format: &str) -> Result<VehImage> {
+    if let Some(frames) = animation::decode(data, format)? {
+        let frames = frames.into_iter().map(|(frame, delay)| (to_peniko(DynamicImage::ImageRgba8(frame)), delay));
+        return Ok(VehImage::Animation(frames.collect()));
+    }
+    let reader = image::ImageReader::new(Cursor::new(data)).with_guessed_format().context("open image failed")?;
+    Ok(to_veh_image(color::decode(reader)?))
+}
+
+/// Wrap a decoded image, keeping the precision of HDR and 16-bit ones
+fn to_veh_image(image: DynamicImage) -> VehImage {
+    let image = downscaled(image);
+    if hdr::is_high_precision(&image) {
+        VehImage::Hdr(Box::new(HdrImage::new(image)))
+    } else {
+        VehImage::Image(to_peniko(image))
+    }
+}
+
+/// `image`, downscaled to the size limit set with [`limit_size`] if there is one
+fn downscaled(image: DynamicImage) -> DynamicImage {
+    let max = MAX_SIZE.load(Ordering::Relaxed);
+    if image.width().max(image.height()) <= max {
+        return image;
+    }
+    image.resize(max, max, image::imageops::FilterType::Triangle)
+}
+
+/// Convert a decoded image into one vello can draw
+fn to_peniko(image: DynamicImage) -> Image {
+    let image = downscaled(image);
+    let width = image.width();
+    let height = image.height();
+    let data = Arc::new(image.into_rgba8().into_vec());
+    let blob = Blob::new(data);
+    Image::new(blob, Format::Rgba8, width, height)
+}
+
+/// Recently decoded images (or pages of documents), shared by all windows so that an image shown in
+/// several windows (or shown again shortly after) is decoded only once
+#[derive(Default)]
+pub struct ImageCache {
+    images: HashMap<(PathBuf, usize), Rc<VehImage>>,
+    // Least recently used first
+    order: VecDeque<(PathBuf, usize)>,
+    page_counts: HashMap<PathBuf, usize>,
+}
+
+impl ImageCache {
+    /// The decoded page `page` of the image at `path`, from the cache if possible
+    pub fn get(&mut self, path: &Path, page: usize) -> Result<Rc<VehImage>> {
+        let key = (path.to_path_buf(), page);
+        if let Some(image) = self.images.get(&key) {
+            let image = image.clone();
+            self.order.retain(|cached| *cached != key);
+            self.order.push_back(key);
+            return Ok(image);
+        }
+
+        let image = Rc::new(open_page(path, page)?);
+        self.insert(path, page, image.clone());
+        Ok(image)
+    }
+
+    /// The decoded page `page` of the image at `path` if it is cached, without decoding it otherwise
+    pub fn cached(&self, path: &Path, page: usize) -> Option<Rc<VehImage>> {
+        self.images.get(&(path.to_path_buf(), page)).cloned()
+    }
+
+    /// Whether page `page` of the image at `path` is decoded already
+    pub fn contains(&self, path: &Path, page: usize) -> bool {
+        self.images.contains_key(&(path.to_path_buf(), page))
+    }
+
+    /// Keep `image`, decoded from page `page` of the image at `path`
+    pub fn insert(&mut self, path: &Path, page: usize, image: Rc<VehImage>) {
+        // With --low-memory an image is dropped as soon as no window shows it anymore
+        if is_low_memory() {
+            return;
+        }
+        let key = (path.to_path_buf(), page);
+        self.order.retain(|cached| *cached != key);
+        if self.order.len() >= CACHE_CAPACITY {
+            if let Some(evicted) = self.order.pop_front() {
+                self.images.remove(&evicted);
+            }
+        }
+        self.images.insert(key.clone(), image);
+        self.order.push_back(key);
+    }
+
+    /// How many pages the image at `path` has
+    pub fn page_count(&mut self, path: &Path) -> Result<usize> {
+        if let Some(&count) = self.page_counts.get(path) {
+            return Ok(count);
+        }
+        let count = page_count(path)?;
+        self.page_counts.insert(path.to_path_buf(), count);
+        Ok(count)
+    }
+
+    /// Forget the image at `path`, e.g. because the file has changed
+    pub fn invalidate(&mut self, path: &Path) {
+        self.images.retain(|(cached, _), _| cached != path);
+        self.order.retain(|(cached, _)| cached != path);
+        self.page_counts.remove(path);
+    }
+}
@@ -1,331 +1,1218 @@
-use anyhow::Result;
-use std::num::NonZeroUsize;
+mod action;
+mod adapter;
+mod alpha;
+mod animation;
+mod app;
+mod archive;
+mod audit;
+mod caption;
+mod capture;
+mod cli;
+mod clipboard;
+mod color;
+mod compare;
+mod config;
+mod containers;
+mod crop;
+mod cue;
+mod dropzone;
+mod errors;
+mod export;
+mod external;
+mod faces;
+mod fileops;
+mod find;
+mod hdr;
+mod hotkey;
+mod info;
+mod ipc;
+mod jump;
+mod loader;
+mod manga;
+mod metadata;
+mod monitor;
+mod opacity;
+mod overlay;
+mod palette;
+#[cfg(feature = "pdf")]
+mod pdf;
+mod pixelated;
+mod playlist;
+mod prefetch;
+mod present;
+mod query;
+mod raw;
+mod render_ahead;
+mod rotate;
+mod scenes;
+mod sequence;
+mod session;
+mod slideshow;
+mod sort;
+mod state;
+mod stats;
+mod svg_tiles;
+mod text;
+mod texture;
+mod verify;
+mod viewport;
+mod wallpaper;
+mod watch;
+mod watchdog;
+
+use anyhow::{Context, Result};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::rc::Rc;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use clap::Parser;
+use vello::kurbo::{Affine, Point, Vec2};
+use vello::util::RenderContext;
+use vello::peniko::Image;
+use vello::{AaConfig, Scene};
+use winit::event_loop::EventLoopBuilder;
+use winit::keyboard::{Key, KeyCode, ModifiersState, NamedKey};
+use winit::window::Window;
+
+use app::{App, KeyIntent};
+use cli::{Args, Command, Gravity};
+use compare::Comparison;
+use crop::Crop;
+use cue::{CueEvent, Cues};
+use fileops::Journal;
+use loader::{ImageCache, VehImage};
+use manga::Bookmarks;
+use overlay::{Overlays, TimelinePosition};
+use palette::Palette;
+use playlist::Playlist;
+use prefetch::Prefetcher;
+use present::Output;
+use render_ahead::RenderAhead;
+use scenes::{Rendition, SceneCache};
+use sequence::Playback;
+use session::Session;
+use slideshow::Pacing;
+use sort::Sort;
+use stats::Stats;
+use svg_tiles::SvgTiles;
 
-use vello::kurbo::{Affine, Vec2};
-use vello::peniko::{Image, Color, Blob, Format};
-use vello::util::{RenderContext, RenderSurface};
-use vello::{AaConfig, Renderer, RendererOptions, Scene};
-use vello_svg::usvg;
-use winit::event::*;
-use winit::event_loop::{ControlFlow, EventLoop};
-use winit::keyboard::{PhysicalKey, KeyCode};
-use winit::window::{Window, WindowBuilder};
-use winit::dpi::LogicalSize;
-use pollster;
-
-
-pub enum VehImage {
-    Image(Image),
-    Svg(usvg::Tree),
+
+/// State shared by all windows
+struct Shared {
+    playlist: Playlist,
+    // The numbered image sequences in the playlist, as ranges of indices
+    sequences: Vec<Range<usize>>,
+    cache: ImageCache,
+    // The images above encoded for drawing, and the SVGs among them being encoded ahead of being drawn
+    scenes: SceneCache,
+    render_ahead: RenderAhead,
+    // With --compare all images are shown at once instead of one after the other
+    comparison: Option<Comparison>,
+    // When set, every image is shown at this scale instead of being fit into the window
+    locked_scale: Option<f64>,
+    // Where images are placed in the window when they don't fill it
+    gravity: Gravity,
+    // Pixels less opaque than this (but not clear) are highlighted by the alpha check
+    alpha_threshold: u8,
+    // With --tile-size, panning moves by whole tiles of this size in image pixels and snaps to them
+    tile_size: Option<Vec2>,
+    // File operations of this session, so that they can be undone
+    journal: Journal,
+    // IPC clients to notify of changes
+    subscriptions: ipc::Subscriptions,
+    // Widgets drawn over the image
+    overlays: Overlays,
+    // With --manga pages are shown as right-to-left spreads, and the last-read page of every archive is remembered
+    manga: bool,
+    bookmarks: Bookmarks,
+    // --cue commands fired on events
+    cues: Cues,
+    // The window, last image and views to restore in the next session
+    session: Session,
+    clipboard: clipboard::Clipboard,
+    faces: faces::Faces,
+    // How long images are shown in a slideshow
+    pacing: Pacing,
+    // With --caption-path, the directory captions are kept in, relative to the directory of every image
+    caption_path: Option<PathBuf>,
+    // With --auto-resize windows take the size of every image they show
+    auto_resize: bool,
+    // Decodes the images next to those shown in the background, unless --low-memory
+    prefetcher: Option<Prefetcher>,
+    // The color profiles of monitors given with --monitor-profile, by monitor name
+    monitor_profiles: Vec<(String, PathBuf)>,
 }
 
-impl VehImage {
-    pub fn size(&self) -> (f64, f64) {
-        match self {
-            VehImage::Image(image) => (image.width as f64, image.height as f64),
-            VehImage::Svg(svg) => {
-                let size = svg.size();
-                (size.width() as f64, size.height() as f64)
+impl Shared {
+    /// Load the image at `index`, or else the closest one after it (before it if not `forward`)
+    /// that can be loaded. Images that fail to load are reported and skipped from then on.
+    fn load(&mut self, mut index: usize, forward: bool) -> Option<(usize, Rc<VehImage>)> {
+        for _ in 0..self.playlist.len() {
+            if self.playlist.is_available(index) {
+                let path = self.playlist.get(index);
+                match self.cache.get(path, 0) {
+                    Ok(image) => return Some((index, image)),
+                    Err(err) => {
+                        eprintln!("veh: {}: {err:#}", path.display());
+                        self.cues.fire(CueEvent::Error, path);
+                        self.playlist.mark_broken(index);
+                    }
+                }
+            }
+            index = if forward { self.playlist.next(index) } else { self.playlist.prev(index) };
+        }
+        None
+    }
+
+    /// Forget the image at `path`, decoded or being decoded, e.g. because the file has changed
+    fn invalidate(&mut self, path: &Path) {
+        self.cache.invalidate(path);
+        if let Some(prefetcher) = &mut self.prefetcher {
+            prefetcher.forget(path);
+        }
+    }
+
+    /// Start decoding the images before and after the one at `index` in the background
+    fn prefetch_around(&mut self, index: usize) {
+        let Some(prefetcher) = &mut self.prefetcher else {
+            return;
+        };
+        for neighbor in [self.playlist.next(index), self.playlist.prev(index)] {
+            let path = self.playlist.get(neighbor);
+            if neighbor != index && self.playlist.is_available(neighbor) && !self.cache.contains(path, 0) {
+                prefetcher.fetch(path);
             }
         }
     }
+
+    /// In --manga mode, the page shown left of `image`, found at `index`, as a two-page spread
+    fn spread_partner(&mut self, index: usize, image: &VehImage) -> Option<(usize, Rc<VehImage>)> {
+        if !self.manga || !manga::may_pair(&self.playlist, index) || !manga::is_portrait(image) {
+            return None;
+        }
+        let partner = index + 1;
+        if !self.playlist.is_available(partner) {
+            return None;
+        }
+        let left = self.cache.get(self.playlist.get(partner), 0).ok()?;
+        manga::is_portrait(&left).then_some((partner, left))
+    }
+
+    /// The first page of the spread the page at `index` belongs to in --manga mode, going back from it
+    fn spread_start(&mut self, index: usize) -> usize {
+        let Some(first) = index.checked_sub(1) else {
+            return index;
+        };
+        let paired = self.playlist.is_available(first)
+            && self.cache.get(self.playlist.get(first), 0).is_ok_and(|image| self.spread_partner(first, &image).is_some());
+        if paired {
+            first
+        } else {
+            index
+        }
+    }
+
+    /// Load page `page` of the image at `index`, reporting if it can't be loaded
+    fn load_page(&mut self, index: usize, page: usize) -> Option<Rc<VehImage>> {
+        let path = self.playlist.get(index);
+        self.cache
+            .get(path, page)
+            .map_err(|err| {
+                eprintln!("veh: {} page {}: {err:#}", path.display(), page + 1);
+                self.cues.fire(CueEvent::Error, path);
+            })
+            .ok()
+    }
+}
+
+/// How frames are rendered and presented, as chosen on the command line
+struct RenderOptions {
+    // Index or name of the GPU adapter to use
+    adapter: Option<String>,
+    antialiasing: AaConfig,
+    present_mode: wgpu::PresentMode,
+    use_cpu: bool,
+    // Whether SVGs are rendered again at the current zoom
+    svg_rerender: bool,
+    // Whether SVGs are drawn from tiles rasterized in advance while they are panned or zoomed
+    svg_tiles: bool,
+}
+
+impl RenderOptions {
+    fn new(args: &Args) -> Self {
+        Self {
+            adapter: args.adapter.clone(),
+            antialiasing: args.antialiasing().into(),
+            present_mode: args.present_mode.into(),
+            use_cpu: args.cpu,
+            svg_rerender: args.svg_rerender,
+            svg_tiles: args.svg_tiles,
+        }
+    }
 }
 
-// Simple struct to hold the state of the renderer
+// Simple struct to hold the state of a window and its renderer
 pub struct ActiveRenderState<'s> {
-    // The fields MUST be in this order, so that the surface is dropped before the window
-    surface: RenderSurface<'s>,
+    // The fields MUST be in this order, so that the output is dropped before the window
+    output: Output<'s>,
     window: Arc<Window>,
     transform: Affine,
     prior_position: Option<Vec2>, // for mouse dragging
     mouse_down: bool,
+    modifiers: ModifiersState,
+    // Pixels per logical pixel of the monitor the window is on
+    scale_factor: f64,
+    // When the window was last drawn, and when to draw it again if a redraw was held back by --fps
+    last_frame: Option<Instant>,
+    redraw_at: Option<Instant>,
+    // Whether the image is still shown as fit into the window, rather than panned or zoomed by hand
+    fitted: bool,
+    // Whether the window has been resized to show the image at 100% with Shift+Z, so that it stays at 100% once
+    // the resize comes through
+    actual_size: bool,
+    // Every window has its own position in the playlist, and within multi-page images its own page
+    index: usize,
+    page: usize,
+    page_count: usize,
+    image: Rc<VehImage>,
+    subscene: Rc<Scene>,
+    // The zoom the image has been drawn into the subscene at: SVGs with --svg-rerender, and images enlarged pixel
+    // by pixel with --integer-scaling. 1 otherwise.
+    subscene_scale: f64,
+    // The zoom of the last frame of the image, to tell where a continuous zoom is heading
+    last_scale: Option<f64>,
+    // Whether to write a capture of the next frame for a bug report
+    capture_next_frame: bool,
+    // A crop of uniform borders waiting to be confirmed, drawn as an overlay until then
+    crop_proposal: Option<Crop>,
+    // Where a rubber band to zoom in on was started with the right mouse button, and where it is dragged to
+    selection: Option<(Vec2, Vec2)>,
+    // When and where the left mouse button was last pressed, to tell double-clicks
+    last_click: Option<(Instant, Vec2)>,
+    // In --manga mode, the index of the page shown left of the image as a two-page spread
+    spread_with: Option<usize>,
+    // The playback of the numbered sequence the image is a frame of, and whether its timeline is dragged
+    playback: Option<Playback>,
+    scrubbing: bool,
+    // The frame of an animated image shown, and its playback, which starts with the image
+    frame: usize,
+    animation: Option<Playback>,
+    // The exposure and tone mapping HDR images are shown with
+    hdr_view: hdr::View,
+    // The count typed before a key, and what has been typed into the goto prompt if it is open
+    count: Option<usize>,
+    prompt: Option<String>,
+    // The command palette of Ctrl+P, if it is open
+    palette: Option<Palette>,
+    // Whether the hint of the first launch is shown, until a key is pressed or the window clicked
+    welcome: bool,
+    // The caption of the image with --caption-path, and what has been typed for it if it is edited
+    caption: Option<String>,
+    caption_edit: Option<String>,
+    // When the slideshow goes on to the next image, if one is running
+    next_slide_at: Option<Instant>,
+    // The face zoomed to with `f`, counting from the left
+    face: Option<usize>,
+    // The threshold of the alpha check if it is on, and the semi-transparent pixels of the image it highlights
+    alpha_check: Option<u8>,
+    alpha_mask: Option<Image>,
+    // The texture preview of Shift+T if one is on, and the image it shows for the image or frame shown
+    texture: Option<texture::Preview>,
+    texture_image: Option<Image>,
+    // How opaque the window is, where the platform allows making it translucent
+    opacity: f64,
+    // Whether images smaller than the window are enlarged to fit it, which `e` toggles
+    upscale: bool,
+    // Whether the image is only shown at integer scales, which `i` toggles, and how far the mouse wheel has been
+    // scrolled towards the next one
+    integer_scaling: bool,
+    scroll_steps: f64,
+    // Why the image couldn't be reloaded with --auto-reload, while the last version that loaded is shown
+    load_error: Option<String>,
+    // The tiles an SVG is drawn from while it is panned or zoomed, with --svg-tiles
+    svg_tiles: SvgTiles,
+    // The monitor the window is on, and its color profile if it isn't sRGB
+    monitor: Option<String>,
+    display: Option<Rc<color::Display>>,
+    // The quarter turns clockwise the image has been turned by with `r` since it was loaded, and whether Ctrl+R has
+    // warned that saving them costs quality
+    rotation: usize,
+    rotation_warned: bool,
+    // The statistics of the pixels of the image while they are shown with Shift+I
+    stats: Option<Stats>,
 }
 
-enum RenderState<'s> {
-    Active(ActiveRenderState<'s>),
-    // Cache a window so that it can be reused when the app is resumed after being suspended
-    Suspended(Option<Arc<Window>>),
-}
+impl ActiveRenderState<'_> {
+    /// Show `image`, found at `index` of the playlist, fit into the window
+    fn show(&mut self, index: usize, image: Rc<VehImage>, shared: &mut Shared) {
+        self.index = index;
+        self.page = 0;
+        self.face = None;
+        self.caption_edit = None;
+        self.load_error = None;
+        self.rotation = 0;
+        self.rotation_warned = false;
+        self.stats = None;
+        self.caption = shared.caption_path.as_ref().and_then(|dir| caption::read(&caption::file(shared.playlist.get(index), dir)));
+        self.page_count = shared.cache.page_count(shared.playlist.get(index)).unwrap_or(1);
+        let partner = shared.spread_partner(index, &image);
+        self.spread_with = partner.as_ref().map(|(partner, _)| *partner);
+        let image = match partner {
+            Some((_, left)) => Rc::new(VehImage::spread(left, image)),
+            None => image,
+        };
+        if shared.manga {
+            shared.bookmarks.record(shared.playlist.get(index));
+        }
+        self.replace_image(image, shared);
+        if shared.auto_resize {
+            self.resize_to_image();
+        }
+        self.fit(shared);
+        // Images zoomed or panned in an earlier session are shown as they were left
+        let path = shared.playlist.get(index);
+        if let Some(transform) = shared.session.view(path) {
+            self.transform = transform;
+            self.fitted = false;
+        }
+        shared.session.record_last_file(path);
+        self.update_title(shared);
+        self.window.request_redraw();
+        shared.prefetch_around(index);
+        shared.subscriptions.emit(
+            "image-changed",
+            serde_json::json!({
+                "window": u64::from(self.window.id()),
+                "index": index,
+                "path": shared.playlist.get(index),
+            }),
+        );
+    }
 
-fn main() -> Result<()> {
-    // Setup a bunch of state:
+    /// Resize the window to show the image at 100%, or as large as the monitor allows. The image is fit into the
+    /// window again once it has been resized. Returns `false` if the window can't be resized.
+    fn resize_to_image(&self) -> bool {
+        if self.window.is_maximized() || self.window.fullscreen().is_some() {
+            return false;
+        }
+        let Some(monitor) = self.window.current_monitor() else {
+            return false;
+        };
+        let _ = self.window.request_inner_size(app::window_size_on(&monitor, self.image.size()));
+        true
+    }
 
-    // The vello RenderContext which is a global context that lasts for the lifetime of the application
-    let mut render_cx = RenderContext::new().unwrap();
+    /// Resize the window to show the image at 100% and show it at 100%, rather than fit into the window, so that
+    /// its pixels are drawn as they are. Images larger than the monitor can be panned in a window as large as the
+    /// monitor allows.
+    fn fit_window_to_image(&mut self, shared: &Shared) {
+        if !self.resize_to_image() {
+            eprintln!("veh: maximized and fullscreen windows can't be resized to the image");
+            return;
+        }
+        self.actual_size = true;
+        self.show_actual_size(shared);
+    }
 
-    // An array of renderers, one per wgpu device
-    let mut renderers: Vec<Option<Renderer>> = vec![];
+    /// Show the image at 100%, placed in the window as it is when fit into it
+    fn show_actual_size(&mut self, shared: &Shared) {
+        let transform = viewport::placed_transform(self.image.size(), self.window.inner_size(), 1.0, shared.gravity);
+        self.transform = viewport::snap_to_pixels(transform);
+        self.fitted = false;
+    }
 
-    // State for our example where we store the winit Window and the wgpu Surface
-    let mut render_state = RenderState::Suspended(None);
+    /// Show the next page of a multi-page image, or the previous one if not `forward`
+    fn turn_page(&mut self, forward: bool, shared: &mut Shared) {
+        let page = if forward { self.page + 1 } else { self.page.wrapping_sub(1) };
+        if page >= self.page_count {
+            return;
+        }
+        let Some(image) = shared.load_page(self.index, page) else {
+            return;
+        };
+        self.page = page;
+        // The page is shown as it is stored, so there is no turn to save
+        self.rotation = 0;
+        self.rotation_warned = false;
+        self.replace_image(image, shared);
+        self.fit(shared);
+        self.update_title(shared);
+        self.window.request_redraw();
+    }
 
-    // A vello Scene which is a data structure which allows one to build up a description a scene to be drawn
-    // (with paths, fills, images, text, etc) which is then passed to a renderer for rendering
-    let mut scene = Scene::new();
-    let mut subscene: Scene = Scene::new();
+    /// Show the next image that can be loaded, or the previous one if not `forward`.
+    /// Returns `false` if no image can be loaded anymore.
+    fn navigate(&mut self, forward: bool, shared: &mut Shared) -> bool {
+        // A spread is turned as a whole
+        let index = if forward {
+            shared.playlist.next(self.spread_with.unwrap_or(self.index))
+        } else if shared.manga {
+            let index = shared.playlist.prev(self.index);
+            shared.spread_start(index)
+        } else {
+            shared.playlist.prev(self.index)
+        };
+        self.go_to(index, forward, shared)
+    }
 
-    // Create and run a winit event loop
-    let event_loop = EventLoop::new()?;
-    event_loop
-        .run(move |event, event_loop| match event {
-            // Setup renderer. In winit apps it is recommended to do setup in Event::Resumed
-            // for best cross-platform compatibility
-            Event::Resumed => {
-                let RenderState::Suspended(cached_window) = &mut render_state else {
-                    return;
-                };
+    /// Show the image `count` places after the current one, or before it if not `forward`, wrapping around.
+    /// Returns `false` if no image can be loaded anymore.
+    fn step(&mut self, count: usize, forward: bool, shared: &mut Shared) -> bool {
+        let len = shared.playlist.len();
+        let offset = count % len;
+        let index = if forward { (self.index + offset) % len } else { (self.index + len - offset) % len };
+        self.go_to(index, forward, shared)
+    }
 
-                // Get the winit window cached in a previous Suspended event or else create a new window
-                let window = cached_window
-                    .take()
-                    .unwrap_or_else(|| create_winit_window(event_loop));
-
-                // Create a vello Surface
-                let size = window.inner_size();
-                let surface_future = render_cx.create_surface(
-                    window.clone(),
-                    size.width,
-                    size.height,
-                    wgpu::PresentMode::AutoVsync,
-                );
-                let surface = pollster::block_on(surface_future).expect("Error creating surface");
-
-                // Create a vello Renderer for the surface (using its device id)
-                renderers.resize_with(render_cx.devices.len(), || None);
-                renderers[surface.dev_id]
-                    .get_or_insert_with(|| create_vello_renderer(&render_cx, &surface));
-  
-                let image = open_image();              
-                add_image_to_subscene(&mut subscene, &image);
-
-                let (image_width, image_height) = image.size();
-                let x_scale = size.width as f64 / image_width as f64;
-                let y_scale = size.height as f64 / image_height as f64;
-                let scale = x_scale.min(y_scale);
-
-                let transform = Affine::translate(Vec2::new(size.width as f64 / 2., size.height as f64 / 2.)) * Affine::scale(scale) * Affine::translate(-Vec2::new(image_width / 2., image_height / 2.)) * Affine::IDENTITY;
-                render_state = RenderState::Active(ActiveRenderState { window, surface, transform, prior_position: None, mouse_down: false});
-
-                event_loop.set_control_flow(ControlFlow::Poll);
+    /// Handle a key pressed while the goto prompt is open: Enter jumps to the image typed, Escape closes the
+    /// prompt, and everything else is typed into it. Returns `false` if no image can be loaded anymore.
+    fn edit_prompt(&mut self, key: &Key, text: Option<&str>, shared: &mut Shared) -> bool {
+        let Some(prompt) = &mut self.prompt else {
+            return true;
+        };
+        match key {
+            Key::Named(NamedKey::Escape) => self.prompt = None,
+            Key::Named(NamedKey::Backspace) => {
+                prompt.pop();
+            }
+            Key::Named(NamedKey::Enter) => {
+                let input = self.prompt.take().unwrap_or_default();
+                self.window.request_redraw();
+                match jump::find(&shared.playlist, self.index, &input) {
+                    Some(index) => return self.go_to(index, true, shared),
+                    None => eprintln!("veh: no image matches `{input}`"),
+                }
             }
+            _ => prompt.extend(text.unwrap_or_default().chars().filter(|c| !c.is_control())),
+        }
+        self.window.request_redraw();
+        true
+    }
+
+    /// Handle a key pressed while the command palette is open: Enter closes it and returns the command selected,
+    /// Escape closes it, the arrow keys select a command, and everything else is typed into the query
+    fn edit_palette(&mut self, key: &Key, text: Option<&str>) -> Option<KeyIntent> {
+        let palette = self.palette.as_mut()?;
+        self.window.request_redraw();
+        match key {
+            Key::Named(NamedKey::Escape) => self.palette = None,
+            Key::Named(NamedKey::Enter) => return self.palette.take()?.chosen(),
+            Key::Named(NamedKey::ArrowUp) => palette.select(false),
+            Key::Named(NamedKey::ArrowDown | NamedKey::Tab) => palette.select(true),
+            Key::Named(NamedKey::Backspace) => palette.edit(None),
+            _ => palette.edit(Some(text.unwrap_or_default())),
+        }
+        None
+    }
 
-            // Save window state on suspend
-            Event::Suspended => {
-                if let RenderState::Active(state) = &render_state {
-                    render_state = RenderState::Suspended(Some(state.window.clone()));
+    /// Handle a key pressed while the caption is edited: Enter saves the caption, Escape discards the edit, and
+    /// everything else is typed into it
+    fn edit_caption(&mut self, key: &Key, text: Option<&str>, shared: &Shared) {
+        let (Some(edit), Some(dir)) = (&mut self.caption_edit, &shared.caption_path) else {
+            return;
+        };
+        match key {
+            Key::Named(NamedKey::Escape) => self.caption_edit = None,
+            Key::Named(NamedKey::Backspace) => {
+                edit.pop();
+            }
+            Key::Named(NamedKey::Enter) => {
+                let caption = self.caption_edit.take().unwrap_or_default();
+                let caption = caption.trim();
+                let file = caption::file(shared.playlist.get(self.index), dir);
+                match caption::write(&file, caption) {
+                    Ok(()) => self.caption = (!caption.is_empty()).then(|| caption.to_owned()),
+                    Err(err) => eprintln!("veh: failed to save the caption: {err:#}"),
                 }
-                event_loop.set_control_flow(ControlFlow::Wait);
             }
+            _ => edit.extend(text.unwrap_or_default().chars().filter(|c| !c.is_control())),
+        }
+        self.window.request_redraw();
+    }
 
-            Event::WindowEvent {
-                ref event,
-                window_id,
-            } => {
-                // Ignore the event (return from the function) if
-                //   - we have no render_state
-                //   - OR the window id of the event doesn't match the window id of our render_state
-                //
-                // Else extract a mutable reference to the render state from its containing option for use below
-                let render_state = match &mut render_state {
-                    RenderState::Active(state) if state.window.id() == window_id => state,
-                    _ => return,
-                };
+    /// Show the image at `index`, or the next one after it that can be loaded (before it if not `forward`).
+    /// Returns `false` if no image can be loaded anymore.
+    fn go_to(&mut self, index: usize, forward: bool, shared: &mut Shared) -> bool {
+        self.remember_view(shared);
+        let Some((index, image)) = shared.load(index, forward) else {
+            return false;
+        };
+        self.show(index, image, shared);
+        shared.cues.fire(CueEvent::Advance, shared.playlist.get(index));
+        // Every image gets its full time in a running slideshow, however it was got to
+        if self.next_slide_at.is_some() {
+            self.next_slide_at = Some(self.slide_deadline(shared));
+        }
+        true
+    }
 
-                match event {
-                    WindowEvent::MouseInput { state, button, .. } => {
-                        if button == &MouseButton::Left {
-                            render_state.mouse_down = state == &ElementState::Pressed;
-                        }
-                    }
-                    WindowEvent::MouseWheel { delta, .. } => {
-                        const BASE: f64 = 1.05;
-                        const PIXELS_PER_LINE: f64 = 20.0;
-
-                        if let Some(prior_position) = render_state.prior_position {
-                            let exponent = if let MouseScrollDelta::PixelDelta(delta) = delta {
-                                delta.y / PIXELS_PER_LINE
-                            } else if let MouseScrollDelta::LineDelta(_, y) = delta {
-                                *y as f64
-                            } else {
-                                0.0
-                            };
-                            render_state.transform = Affine::translate(prior_position)
-                                * Affine::scale(BASE.powf(exponent))
-                                * Affine::translate(-prior_position)
-                                * render_state.transform;
-                            render_state.window.request_redraw();
-                        }
-                    }
-                    WindowEvent::CursorLeft { .. } => {
-                        render_state.prior_position = None;
-                    }
-                    WindowEvent::CursorMoved { position, .. } => {
-                        let position = Vec2::new(position.x, position.y);
-                        if render_state.mouse_down {
-                            if let Some(prior) = render_state.prior_position {
-                                render_state.transform = Affine::translate(position - prior) * render_state.transform;
-                            }
-                        }
-                        render_state.prior_position = Some(position);
-                        render_state.window.request_redraw();
-                    }
-                    // Exit the event loop when a close is requested (e.g. window's close button is pressed)
-                    WindowEvent::KeyboardInput {
-                        event:
-                            KeyEvent {
-                                state: ElementState::Pressed,
-                                physical_key: PhysicalKey::Code(keycode),
-                                ..
-                            },
-                        ..
-                    } => {
-                        match keycode {
-                            KeyCode::Escape  => event_loop.exit(),
-                            KeyCode::ArrowUp | KeyCode::KeyK => {
-                                render_state.transform = render_state.transform * Affine::translate((0.0, -10.0));
-                                render_state.window.request_redraw();
-                            }
-                            KeyCode::ArrowDown | KeyCode::KeyJ => {
-                                render_state.transform = render_state.transform * Affine::translate((0.0, 10.0));
-                                render_state.window.request_redraw();
-                            }
-                            KeyCode::ArrowLeft | KeyCode::KeyH => {
-                                render_state.transform = render_state.transform * Affine::translate((-10.0, 0.0));
-                                render_state.window.request_redraw();
-                            }
-                            KeyCode::ArrowRight | KeyCode::KeyL => {
-                                render_state.transform = render_state.transform * Affine::translate((10.0, 0.0));
-                                render_state.window.request_redraw();
-                            }
-                            _ => {}
-                        }   
-                    }
-                    WindowEvent::CloseRequested => event_loop.exit(),
-                    WindowEvent::Resized(_size) => {
-                        let size = render_state.window.inner_size();
-                        render_cx.resize_surface(
-                            &mut render_state.surface,
-                            size.width,
-                            size.height,
-                        );
-                        render_state.window.request_redraw();
-                    }
+    /// The pixels shown, unless the image is a vector image or a spread
+    fn shown_pixels(&self) -> Option<Image> {
+        match &*self.image {
+            VehImage::Image(image) => Some(image.clone()),
+            VehImage::Hdr(image) => Some(image.image(self.hdr_view)),
+            VehImage::Animation(frames) => Some(frames[self.frame].0.clone()),
+            VehImage::Svg(_) | VehImage::Spread(_) => None,
+        }
+    }
 
-                    // This is where all the rendering happens
-                    WindowEvent::RedrawRequested => {
-                        // Empty the scene of objects to draw. You could create a new Scene each time, but in this case
-                        // the same Scene is reused so that the underlying memory allocation can also be reused.
-                        scene.reset();
-
-                        scene.append(&mut subscene, Some(render_state.transform));
-                        // Get the RenderSurface (surface + config)
-                        let surface = &render_state.surface;
-
-                        // Get the window size
-                        let width = surface.config.width;
-                        let height = surface.config.height;
-
-                        // Get a handle to the device
-                        let device_handle = &render_cx.devices[surface.dev_id];
-
-                        // Get the surface's texture
-                        let surface_texture = surface
-                            .surface
-                            .get_current_texture()
-                            .expect("failed to get surface texture");
-
-                        // Render to the surface's texture
-                        renderers[surface.dev_id]
-                            .as_mut()
-                            .unwrap()
-                            .render_to_surface(
-                                &device_handle.device,
-                                &device_handle.queue,
-                                &scene,
-                                &surface_texture,
-                                &vello::RenderParams {
-                                    base_color: Color::BLACK, // Background color
-                                    width,
-                                    height,
-                                    antialiasing_method: AaConfig::Msaa16,
-                                },
-                            )
-                            .expect("failed to render to surface");
-
-                        // Queue the texture to be presented on the surface
-                        surface_texture.present();
-
-                        device_handle.device.poll(wgpu::Maintain::Poll);
-                    }
-                    _ => {}
-                }
+    /// Zoom to the next face in the image at 100%, or to the first one after the last
+    fn next_face(&mut self, shared: &mut Shared) {
+        let path = shared.playlist.get(self.index);
+        let faces = match shared.faces.get(path) {
+            Ok(faces) => faces,
+            Err(err) => {
+                eprintln!("veh: {}: {err:#}", path.display());
+                return;
             }
-            _ => {}
+        };
+        if faces.is_empty() {
+            eprintln!("veh: no faces found in {}", path.display());
+            return;
+        }
+        let face = self.face.map_or(0, |face| (face + 1) % faces.len());
+        // Faces are found in the image as stored, which may have been downscaled since
+        let scale = image::image_dimensions(path).map_or(1.0, |(width, _)| self.image.size().0 / width as f64);
+        let center = faces[face].center();
+        let center = Point::new(center.x * scale, center.y * scale);
+        self.transform = viewport::centered_on(center, 1.0, self.window.inner_size());
+        self.fitted = false;
+        self.face = Some(face);
+        self.window.request_redraw();
+    }
+
+    /// Start a slideshow from the image shown, or stop it
+    fn toggle_slideshow(&mut self, shared: &Shared) {
+        self.next_slide_at = match self.next_slide_at {
+            Some(_) => None,
+            None => Some(self.slide_deadline(shared)),
+        };
+    }
+
+    /// When a slideshow going on from the image shown now goes on to the next one
+    fn slide_deadline(&self, shared: &Shared) -> Instant {
+        Instant::now() + shared.pacing.delay(shared.playlist.get(self.index), self.image.size())
+    }
+
+    /// Show the frame at `index` of a sequence, keeping the view if the image has been zoomed or panned
+    fn show_frame(&mut self, index: usize, shared: &mut Shared) {
+        let Some((index, image)) = shared.load(index, true) else {
+            return;
+        };
+        let (transform, fitted) = (self.transform, self.fitted);
+        self.show(index, image, shared);
+        if !fitted {
+            self.transform = transform;
+            self.fitted = false;
+        }
+    }
+
+    /// Show frame `frame` of an animated image
+    fn show_animation_frame(&mut self, frame: usize) {
+        let VehImage::Animation(frames) = &*self.image else {
+            return;
+        };
+        let mut subscene = Scene::new();
+        let image = &frames[frame].0;
+        subscene.draw_image(&self.display.as_ref().map_or_else(|| image.clone(), |display| display.convert(image)), Affine::IDENTITY);
+        self.subscene = Rc::new(subscene);
+        self.frame = frame;
+        self.update_alpha_mask();
+        self.update_texture_preview();
+        self.window.request_redraw();
+    }
+
+    /// Turn the alpha check on or off
+    fn toggle_alpha_check(&mut self, shared: &Shared) {
+        self.alpha_check = match self.alpha_check {
+            Some(_) => None,
+            None => Some(shared.alpha_threshold),
+        };
+        self.update_alpha_mask();
+        if self.alpha_check.is_some() && self.alpha_mask.is_none() {
+            eprintln!("veh: the alpha check only applies to raster images");
+        }
+        self.window.request_redraw();
+    }
+
+    /// Go on to the next texture preview, or back to the image as it is after the last one
+    fn cycle_texture_preview(&mut self) {
+        self.texture = texture::Preview::next(self.texture);
+        self.update_texture_preview();
+        if self.texture.is_some() && self.texture_image.is_none() {
+            eprintln!("veh: the texture previews only apply to raster images");
+        }
+        self.window.request_redraw();
+    }
+
+    /// Make the texture preview of the image or frame shown if one is on
+    fn update_texture_preview(&mut self) {
+        self.texture_image = self.texture.and_then(|preview| Some(preview.apply(&self.shown_pixels()?)));
+    }
+
+    /// Find the semi-transparent pixels of the image or frame shown if the alpha check is on
+    fn update_alpha_mask(&mut self) {
+        self.alpha_mask = self
+            .alpha_check
+            .and_then(|threshold| Some(alpha::fringe_mask(&self.shown_pixels()?, threshold)));
+    }
+
+    /// Which frame of an animated image is shown, or where the image is in its numbered sequence, played at
+    /// `play_interval` per frame, if it is a frame of one
+    fn timeline_position(&self, shared: &Shared, play_interval: Duration) -> Option<TimelinePosition> {
+        if let VehImage::Animation(frames) = &*self.image {
+            return Some(TimelinePosition {
+                frame: self.frame,
+                frames: frames.len(),
+                time: frames[..self.frame].iter().map(|(_, delay)| *delay).sum(),
+                duration: frames.iter().map(|(_, delay)| *delay).sum(),
+                playing: self.animation.is_some(),
+            });
+        }
+        let frames = sequence::containing(&shared.sequences, self.index)?;
+        let frame = self.index - frames.start;
+        Some(TimelinePosition {
+            frame,
+            frames: frames.len(),
+            time: play_interval * frame as u32,
+            duration: play_interval * frames.len() as u32,
+            playing: self.playback.is_some(),
         })
-        .expect("Couldn't run event loop");
-    Ok(())
+    }
+
+    /// Show the frame of the animated image or sequence under `x` on its timeline
+    fn scrub(&mut self, x: f64, shared: &mut Shared) {
+        let width = self.window.inner_size().width;
+        if let VehImage::Animation(frames) = &*self.image {
+            let frame = overlay::frame_at(x, width, frames.len());
+            if frame != self.frame {
+                self.show_animation_frame(frame);
+            }
+            return;
+        }
+        let Some(frames) = sequence::containing(&shared.sequences, self.index) else {
+            return;
+        };
+        let index = frames.start + overlay::frame_at(x, width, frames.len());
+        if index != self.index {
+            self.show_frame(index, shared);
+        }
+    }
+
+    /// Remember how the image is zoomed and panned for the next session
+    fn remember_view(&self, shared: &mut Shared) {
+        let transform = (!self.fitted).then_some(self.transform);
+        shared.session.record_view(shared.playlist.get(self.index), transform);
+    }
+
+    /// Remember the window and its image for the next session, e.g. when the window is closed
+    fn remember(&self, shared: &mut Shared) {
+        self.remember_view(shared);
+        shared.session.record_last_file(shared.playlist.get(self.index));
+        shared.session.record_window(&self.window);
+    }
+
+    fn toggle_mark(&mut self, shared: &mut Shared) {
+        let marked = shared.playlist.toggle_mark(self.index);
+        shared.cues.fire(CueEvent::Mark, shared.playlist.get(self.index));
+        self.update_title(shared);
+        self.window.request_redraw();
+        shared.subscriptions.emit(
+            "mark-changed",
+            serde_json::json!({ "index": self.index, "path": shared.playlist.get(self.index), "marked": marked }),
+        );
+    }
+
+    /// Lock the current zoom, so that all images are shown at the same scale, or unlock it
+    fn toggle_zoom_lock(&self, shared: &mut Shared) {
+        shared.locked_scale = match shared.locked_scale {
+            Some(_) => None,
+            None => Some(viewport::uniform_scale(self.transform)),
+        };
+        self.update_title(shared);
+    }
+
+    /// How the image is drawn into the subscene at `scale`
+    fn rendition(&self, scale: f64) -> Rendition {
+        Rendition { hdr_view: self.hdr_view, scale, display: self.display.as_ref().map(|display| display.id) }
+    }
+
+    /// Convert raster images to the color profile of the monitor the window is on, if it has moved to another one
+    fn update_display(&mut self, shared: &mut Shared) {
+        let monitor = monitor::current(&self.window);
+        if !color::is_enabled() || monitor == self.monitor {
+            return;
+        }
+        let display = monitor::profile(&self.window, &shared.monitor_profiles).and_then(|profile| color::Display::new(&profile));
+        self.monitor = monitor;
+        if display.as_ref().map(|display| display.id) == self.display.as_ref().map(|display| display.id) {
+            return;
+        }
+        self.display = display.map(Rc::new);
+        self.subscene = shared.scenes.get(&self.image, self.rendition(1.0), |scene| {
+            add_image_to_subscene(scene, &self.image, self.hdr_view, self.display.as_deref())
+        });
+        self.subscene_scale = 1.0;
+        self.window.request_redraw();
+    }
+
+    /// Swap the image shown for `image`, keeping the transform
+    fn replace_image(&mut self, image: Rc<VehImage>, shared: &mut Shared) {
+        let rendition = self.rendition(1.0);
+        self.subscene = shared.scenes.get(&image, rendition, |scene| add_image_to_subscene(scene, &image, self.hdr_view, self.display.as_deref()));
+        self.subscene_scale = 1.0;
+        self.last_scale = None;
+        self.svg_tiles = SvgTiles::new();
+        self.frame = 0;
+        self.animation = match &*image {
+            VehImage::Animation(frames) => Some(Playback::new(0..frames.len(), frames[0].1)),
+            _ => None,
+        };
+        self.image = image;
+        self.crop_proposal = None;
+        self.update_alpha_mask();
+        self.update_texture_preview();
+    }
+
+    /// Show HDR images `ev` stops brighter than now, or darker if negative
+    fn expose(&mut self, ev: f32, shared: &mut Shared) {
+        if !self.image.is_hdr() {
+            eprintln!("veh: the exposure only applies to HDR and 16-bit images");
+            return;
+        }
+        self.hdr_view.exposure += ev;
+        // Stay on the grid of exposure steps rather than drift off it by rounding errors
+        self.hdr_view.exposure = (self.hdr_view.exposure / hdr::EXPOSURE_STEP).round() * hdr::EXPOSURE_STEP;
+        let rendition = self.rendition(1.0);
+        self.subscene = shared.scenes.get(&self.image, rendition, |scene| add_image_to_subscene(scene, &self.image, self.hdr_view, self.display.as_deref()));
+        self.subscene_scale = 1.0;
+        self.window.request_redraw();
+    }
+
+    /// Turn the image a quarter turn clockwise, or counter-clockwise, and fit it into the window again
+    fn rotate(&mut self, clockwise: bool, shared: &mut Shared) {
+        let rotated = match &*self.image {
+            VehImage::Image(image) => VehImage::Image(rotate::rotate(image, clockwise)),
+            VehImage::Hdr(image) => VehImage::Hdr(Box::new(image.rotated(clockwise))),
+            _ => {
+                eprintln!("veh: only raster images can be turned");
+                return;
+            }
+        };
+        self.rotation = (self.rotation + if clockwise { 1 } else { 3 }) % 4;
+        self.rotation_warned = false;
+        self.replace_image(Rc::new(rotated), shared);
+        self.fit(shared);
+        self.window.request_redraw();
+    }
+
+    /// Save the turn of the image to its file. Where that costs quality, only warn the first time.
+    fn save_rotation(&mut self, shared: &mut Shared) {
+        if self.rotation == 0 {
+            eprintln!("veh: the image hasn't been turned");
+            return;
+        }
+        let path = shared.playlist.get(self.index).to_path_buf();
+        if !self.rotation_warned && rotate::is_lossy(&path).unwrap_or(false) {
+            eprintln!("veh: saving the turn encodes {} again, which costs quality; press Ctrl+R again to save it anyway", path.display());
+            self.rotation_warned = true;
+            return;
+        }
+        match rotate::save(&path, self.rotation) {
+            Ok(saved) => {
+                let how = match saved {
+                    rotate::Saved::Tagged => "changed the orientation tag of",
+                    rotate::Saved::Reencoded => "turned the pixels of",
+                };
+                eprintln!("veh: {how} {}", path.display());
+                self.rotation = 0;
+                self.rotation_warned = false;
+                shared.invalidate(&path);
+            }
+            Err(err) => eprintln!("veh: failed to save the turn of {}: {err:#}", path.display()),
+        }
+    }
+
+    /// Expose HDR images so that their median brightness comes out as middle grey
+    fn auto_expose(&mut self, shared: &mut Shared) {
+        match self.image.auto_exposure() {
+            Some(exposure) => self.expose(exposure - self.hdr_view.exposure, shared),
+            None => eprintln!("veh: the exposure only applies to HDR and 16-bit images"),
+        }
+    }
+
+    /// Reset the transform so that the image fits into the window, or is shown at the locked scale if there is one
+    fn fit(&mut self, shared: &Shared) {
+        let size = self.window.inner_size();
+        let viewport = shared.comparison.as_ref().map_or(size, |comparison| comparison.viewport_size(size));
+        // Pages are read at the height of the window in --manga mode
+        let scale = shared.locked_scale.or(shared.manga.then(|| viewport::fit_height_scale(self.image.size(), viewport)));
+        // Small images stay at 100% rather than being enlarged, with --no-upscale
+        let scale = match scale {
+            None if !self.upscale => Some(viewport::fit_scale(self.image.size(), viewport).min(1.0)),
+            Some(scale) if !self.upscale && shared.locked_scale.is_none() => Some(scale.min(1.0)),
+            scale => scale,
+        };
+        // and are only shown at integer scales with --integer-scaling, unless the zoom is locked
+        if self.integer_scaling && shared.locked_scale.is_none() {
+            let scale = viewport::integer_scale(scale.unwrap_or_else(|| viewport::fit_scale(self.image.size(), viewport)));
+            let transform = viewport::placed_transform(self.image.size(), viewport, scale, shared.gravity);
+            self.transform = viewport::snap_to_pixels(transform);
+        } else {
+            self.transform = viewport::initial_transform(self.image.size(), viewport, scale, shared.gravity);
+        }
+        self.fitted = true;
+        self.actual_size = false;
+    }
+
+    /// Zoom to the next integer scale about `position` in the window, or the next smaller one unless `zoom_in`
+    fn step_integer_scale(&mut self, position: Vec2, zoom_in: bool) {
+        let (x_scale, y_scale) = viewport::axis_scales(self.transform);
+        let scale = viewport::next_integer_scale(x_scale.max(y_scale), zoom_in);
+        let transform = viewport::zoom_about(self.transform, position, scale / x_scale, scale / y_scale);
+        self.transform = viewport::snap_to_pixels(transform);
+        self.fitted = false;
+    }
+
+    /// Turn integer scaling on or off. Turning it on zooms out to the nearest integer scale, unless the image is
+    /// fit into the window, which is fit again.
+    fn toggle_integer_scaling(&mut self, shared: &Shared) {
+        self.integer_scaling = !self.integer_scaling;
+        if self.fitted {
+            self.fit(shared);
+        } else if self.integer_scaling {
+            let size = self.window.inner_size();
+            let center = Vec2::new(size.width as f64 / 2.0, size.height as f64 / 2.0);
+            let (x_scale, y_scale) = viewport::axis_scales(self.transform);
+            let scale = viewport::integer_scale(x_scale.max(y_scale));
+            let transform = viewport::zoom_about(self.transform, center, scale / x_scale, scale / y_scale);
+            self.transform = viewport::snap_to_pixels(transform);
+        }
+        self.scroll_steps = 0.0;
+    }
+
+    fn update_title(&self, shared: &Shared) {
+        let name = shared
+            .playlist
+            .get(self.index)
+            .file_name()
+            .map(|name| name.to_string_lossy())
+            .unwrap_or_default();
+        let mark = if shared.playlist.is_marked(self.index) { " (marked)" } else { "" };
+        let page = if self.page_count > 1 {
+            format!(" (page {}/{})", self.page + 1, self.page_count)
+        } else {
+            String::new()
+        };
+        let lock = match shared.locked_scale {
+            Some(scale) => format!(" (zoom locked at {:.0}%)", scale * 100.0),
+            None => String::new(),
+        };
+        let (x_scale, y_scale) = viewport::axis_scales(self.transform);
+        let stretch = if (x_scale / y_scale - 1.0).abs() > 1e-3 {
+            format!(" (stretched {:.2}:1)", x_scale / y_scale)
+        } else {
+            String::new()
+        };
+        self.window.set_title(&format!(
+            "veh: {} [{}/{}]{}{}{}{}",
+            name,
+            self.index + 1,
+            shared.playlist.len(),
+            page,
+            mark,
+            lock,
+            stretch
+        ));
+    }
+
+    /// What the window shows, for IPC clients
+    fn state(&self, shared: &Shared) -> serde_json::Value {
+        let (x_scale, y_scale) = viewport::axis_scales(self.transform);
+        let (image_width, image_height) = self.image.size();
+        let size = self.window.inner_size();
+        // Wayland doesn't tell windows where they are
+        let position = self.window.outer_position().ok();
+        serde_json::json!({
+            "window": u64::from(self.window.id()),
+            "index": self.index,
+            "count": shared.playlist.len(),
+            "path": shared.playlist.get(self.index),
+            "marked": shared.playlist.is_marked(self.index),
+            "page": self.page,
+            "page_count": self.page_count,
+            "image": { "width": image_width, "height": image_height },
+            "zoom": { "x": x_scale, "y": y_scale, "locked": shared.locked_scale, "fitted": self.fitted },
+            "geometry": {
+                "x": position.map(|position| position.x),
+                "y": position.map(|position| position.y),
+                "width": size.width,
+                "height": size.height,
+                "scale_factor": self.scale_factor,
+            },
+        })
+    }
+
+    /// Render an SVG again with the current zoom baked into its geometry, rather than scaling the scene it was
+    /// rendered into, so that strokes and hairlines are encoded at screen resolution even at extreme zoom
+    fn rerender_svg(&mut self, shared: &mut Shared) {
+        let VehImage::Svg(svg) = &*self.image else {
+            return;
+        };
+        let (x_scale, y_scale) = viewport::axis_scales(self.transform);
+        let scale = x_scale.max(y_scale);
+        // While the zoom keeps changing, the next rendering is done ahead, where the zoom will be by then
+        let trend = self.last_scale.replace(scale).map_or(1.0, |last_scale| scale / last_scale);
+        if trend != 1.0 {
+            let step = if trend > 1.0 { 1.0 + scenes::RERENDER_THRESHOLD } else { 1.0 - scenes::RERENDER_THRESHOLD };
+            shared.render_ahead.encode(&self.image, self.rendition(self.subscene_scale * step * trend), &shared.scenes);
+        }
+        // Small zoom changes don't make a visible difference
+        if (scale / self.subscene_scale - 1.0).abs() < scenes::RERENDER_THRESHOLD {
+            return;
+        }
+        let rendition = self.rendition(scale);
+        let ahead = shared.scenes.find(&self.image, |cached| cached.is_close(&rendition, render_ahead::TOLERANCE));
+        (self.subscene, self.subscene_scale) = match ahead {
+            Some((rendition, scene)) => (scene, rendition.scale),
+            None => (shared.scenes.get(&self.image, rendition, |scene| scenes::draw_svg(scene, svg, scale)), scale),
+        };
+    }
+
+    /// Render the SVG a running slideshow or playback goes on to next ahead, once it has been decoded
+    fn render_next_ahead(&self, shared: &mut Shared, svg_rerender: bool) {
+        let next = match &self.playback {
+            Some(playback) => playback.next(self.index),
+            None => shared.playlist.next(self.spread_with.unwrap_or(self.index)),
+        };
+        let Some(image) = shared.cache.cached(shared.playlist.get(next), 0) else {
+            return;
+        };
+        shared.render_ahead.encode(&image, self.rendition(1.0), &shared.scenes);
+        // With --svg-rerender it is drawn again at the zoom it is fit into the window at
+        if svg_rerender {
+            let scale = viewport::fit_scale(image.size(), self.window.inner_size());
+            shared.render_ahead.encode(&image, self.rendition(scale), &shared.scenes);
+        }
+    }
+
+    /// Draw the image enlarged pixel by pixel at integer scales with --integer-scaling, so that its pixels stay
+    /// sharp, or as it is otherwise
+    fn enlarge_pixels(&mut self, scenes: &mut SceneCache) {
+        // Animation frames and spreads are drawn as they are
+        if !matches!(*self.image, VehImage::Image(_) | VehImage::Hdr(_)) {
+            return;
+        }
+        let factor = if self.integer_scaling { pixelated::factor(self.image.size(), viewport::uniform_scale(self.transform)) } else { 1 };
+        if factor as f64 == self.subscene_scale {
+            return;
+        }
+        let rendition = self.rendition(factor as f64);
+        self.subscene = scenes.get(&self.image, rendition, |scene| match self.shown_pixels() {
+            Some(pixels) if factor > 1 => {
+                let pixels = self.display.as_ref().map_or(pixels.clone(), |display| display.convert(&pixels));
+                scene.draw_image(&pixelated::enlarge(&pixels, factor), Affine::IDENTITY)
+            }
+            _ => add_image_to_subscene(scene, &self.image, self.hdr_view, self.display.as_deref()),
+        });
+        self.subscene_scale = factor as f64;
+    }
+
+    /// Undo any stretching by scaling both axes by the geometric mean of their scales, about the window center
+    fn unstretch(&mut self) {
+        let size = self.window.inner_size();
+        let center = Vec2::new(size.width as f64 / 2.0, size.height as f64 / 2.0);
+        self.transform = viewport::unstretch(self.transform, center);
+    }
+}
+
+/// Events sent to the event loop from other threads
+pub enum UserEvent {
+    /// An `--action` command run on `path` has exited
+    ActionFinished { path: PathBuf, reload: bool },
+    /// A command sent by an IPC client
+    Ipc(ipc::Request),
+    /// The global hotkey of `--click-through` was pressed
+    ToggleClickThrough,
+    /// The image at `path` has been decoded in the background, unless it failed to
+    Prefetched { path: PathBuf, fetch: u64, image: Option<prefetch::Decoded> },
+    /// A scene has been encoded ahead of being drawn
+    RenderedAhead { id: u64, scene: Box<Scene> },
 }
 
-/// Helper function that creates a Winit window and returns it (wrapped in an Arc for sharing between threads)
-fn create_winit_window(event_loop: &winit::event_loop::EventLoopWindowTarget<()>) -> Arc<Window> {
-    Arc::new(
-        WindowBuilder::new()
-            .with_inner_size(LogicalSize::new(1044, 800))
-            .with_resizable(true)
-            .with_title("veh")
-            .build(event_loop)
-            .unwrap(),
-    )
+enum RenderState<'s> {
+    Active(Box<ActiveRenderState<'s>>),
+    // Cache a window and its position in the playlist so that it can be reused when the app is resumed after being suspended
+    Suspended(Arc<Window>, usize),
 }
 
-/// Helper function that creates a vello `Renderer` for a given `RenderContext` and `RenderSurface`
-fn create_vello_renderer(render_cx: &RenderContext, surface: &RenderSurface) -> Renderer {
-    Renderer::new(
-        &render_cx.devices[surface.dev_id].device,
-        RendererOptions {
-            surface_format: Some(surface.format),
-            use_cpu: false,
-            antialiasing_support: vello::AaSupport::all(),
-            num_init_threads: NonZeroUsize::new(1),
-        },
-    )
-    .expect("Couldn't create renderer")
+fn main() -> ExitCode {
+    match run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            errors::report(None, &err);
+            errors::Category::of(&err).exit_code()
+        }
+    }
 }
 
+fn run() -> Result<()> {
+    let args = Args::parse_from(config::with_defaults(std::env::args_os().collect()));
+    errors::set_format(args.error_format);
+    export::set_conflict(args.on_conflict);
+    if args.compat {
+        adapter::use_gl_backend();
+        loader::limit_size(adapter::COMPAT_MAX_TEXTURE_SIZE);
+    }
+    if args.list_adapters {
+        adapter::list_adapters(&RenderContext::new().unwrap().instance);
+        return Ok(());
+    }
+    if let Some(command) = &args.remote {
+        let socket = args.ipc_socket.clone().unwrap_or_else(ipc::default_socket_path);
+        return ipc::remote(&socket, command);
+    }
 
-fn open_image() -> VehImage {
-    let path = std::env::args().nth(1).expect("no path given");
-    let valid_formats = vec!["svg", "png", "jpg", "jpeg", "bmp", "gif", "ico", "tiff", "webp"];
-    let format = path.split('.').last().expect("no format given");
-    if !valid_formats.contains(&format) {
-        panic!("invalid format given");
+    color::set_enabled(!args.no_color_management);
+    raw::set_demosaic(args.raw_demosaic);
+    loader::set_low_memory(args.low_memory);
+    if let Err(err) = prefetch::configure_pool(args.threads, args.nice) {
+        eprintln!("veh: {err:#}");
+    }
+    if args.info {
+        return info::print(&args.paths, args.json, args.stats);
     }
 
-    if format == "svg" {
-        let contents = &std::fs::read_to_string(path).expect("read svg failed");
-        let fontdb = usvg::fontdb::Database::new();
-        let svg = usvg::Tree::from_str(contents, &usvg::Options::default(), &fontdb)
-            .expect("failed to parse svg file");
-        VehImage::Svg(svg)
-    } else {
-        let image = image::io::Reader::open(path).expect("open image failed").decode().expect("decode image failed");
+    let mut paths = match &args.command {
+        Some(Command::Find { dir, filter, recursive, open }) => {
+            let found = find::find(dir, filter, *recursive)?;
+            if !open {
+                for path in found {
+                    println!("{}", path.display());
+                }
+                return Ok(());
+            }
+            found
+        }
+        Some(Command::DropZone { to, max_size, quality }) => {
+            let recipe = dropzone::Recipe { format: *to, max_size: *max_size, quality: *quality };
+            return dropzone::run(recipe, &args);
+        }
+        Some(Command::Verify { dir, recursive, delete, quarantine }) => {
+            let disposal = match (delete, quarantine) {
+                (true, _) => verify::Disposal::Trash,
+                (false, Some(quarantine)) => verify::Disposal::Quarantine(quarantine.clone()),
+                (false, None) => verify::Disposal::Keep,
+            };
+            return verify::verify(dir, *recursive, &disposal);
+        }
+        Some(Command::Audit { images, open }) => {
+            let outliers = audit::audit(images)?;
+            if !open {
+                return Ok(());
+            }
+            outliers
+        }
+        None => expand_paths(&args.paths),
+    };
+    // Timelapses play in the order the images were taken
+    if let Some(order) = args.sort.as_ref().or(args.timelapse.then_some(&Sort::Taken)) {
+        sort::sort(&mut paths, order, &args.sorter)?;
+    }
+    if args.append {
+        let socket = args.ipc_socket.clone().unwrap_or_else(ipc::default_socket_path);
+        return ipc::append(&socket, &paths, args.jump);
+    }
+    if !args.font_dir.is_empty() || args.default_font.is_some() {
+        loader::init_fonts(&args.font_dir, args.default_font.clone());
+    }
+    if let Some(mode) = args.background_mode() {
+        let path = paths.first().context("no image to set as wallpaper")?;
+        return wallpaper::set_wallpaper(path, mode);
+    }
+    view(paths, &args)
+}
 
-        let width = image.width();
-        let height = image.height();
-        let data = Arc::new(image.into_rgba8().into_vec());
-        let blob = Blob::new(data);
-        VehImage::Image(Image::new(blob, Format::Rgba8, width, height))
+/// Replace the archives and directories among `paths` with the images in them
+fn expand_paths(paths: &[PathBuf]) -> Vec<PathBuf> {
+    let mut expanded = vec![];
+    for path in paths {
+        let images = if archive::is_archive(path) {
+            archive::list_images(path)
+        } else if path.is_dir() {
+            find::list_images(path, false)
+        } else {
+            expanded.push(path.clone());
+            continue;
+        };
+        match images {
+            Ok(images) => expanded.extend(images),
+            Err(err) => eprintln!("veh: {}: {err:#}", path.display()),
+        }
     }
+    expanded
 }
 
-fn add_image_to_subscene(scene: &mut Scene, image: &VehImage) -> () {
+/// Open the viewer window on `paths` and run it until it is closed
+fn view(paths: Vec<PathBuf>, args: &Args) -> Result<()> {
+    // Create and run a winit event loop
+    let event_loop = EventLoopBuilder::<UserEvent>::with_user_event().build()?;
+    let proxy = event_loop.create_proxy();
+    let mut app = App::new(paths, args, proxy.clone())?;
+    let _server = match (&args.ipc_socket, args.ipc) {
+        (Some(path), _) => Some(ipc::serve(path, proxy)?),
+        (None, true) => Some(ipc::serve(&ipc::default_socket_path(), proxy)?),
+        (None, false) => None,
+    };
+    // The closure only borrows the app, as the marked images are still written once the event loop has finished
+    event_loop
+        .run(|event, event_loop| app.handle_event(event, event_loop))
+        .expect("Couldn't run event loop");
+    app.finish()
+}
+
+fn add_image_to_subscene(scene: &mut Scene, image: &VehImage, hdr_view: hdr::View, display: Option<&color::Display>) {
+    let converted = |image: &Image| display.map_or_else(|| image.clone(), |display| display.convert(image));
     match image {
         VehImage::Image(image) => {
-            scene.draw_image(&image, Affine::IDENTITY);
+            scene.draw_image(&converted(image), Affine::IDENTITY);
+        }
+        VehImage::Hdr(image) => {
+            scene.draw_image(&converted(&image.image(hdr_view)), Affine::IDENTITY);
         }
         VehImage::Svg(svg) => {
-            vello_svg::render_tree(scene, &svg);
+            scenes::draw_svg(scene, svg, 1.0);
+        }
+        VehImage::Animation(frames) => {
+            scene.draw_image(&converted(&frames[0].0), Affine::IDENTITY);
+        }
+        VehImage::Spread(pages) => {
+            for (page, offset) in pages {
+                let mut page_scene = Scene::new();
+                add_image_to_subscene(&mut page_scene, page, hdr_view, display);
+                scene.append(&page_scene, Some(Affine::translate(*offset)));
+            }
         }
     }
-}
\ No newline at end of file
+}
+
+/// The index into the `--action` list bound to `keycode`: Enter for `--action`, 1 to 9 for `--action1` to `--action9`
+fn action_index(keycode: KeyCode) -> Option<usize> {
+    let index = match keycode {
+        KeyCode::Enter => 0,
+        KeyCode::Digit1 => 1,
+        KeyCode::Digit2 => 2,
+        KeyCode::Digit3 => 3,
+        KeyCode::Digit4 => 4,
+        KeyCode::Digit5 => 5,
+        KeyCode::Digit6 => 6,
+        KeyCode::Digit7 => 7,
+        KeyCode::Digit8 => 8,
+        KeyCode::Digit9 => 9,
+        _ => return None,
+    };
+    Some(index)
+}
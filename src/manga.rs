@@ -0,0 +1,109 @@
+//! `--manga` mode for reading comics: pages are shown as two-page spreads read from right to left and fit
+//! to the height of the window, and the last-read page of every archive is remembered for next time.
+
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use serde_json::{Map, Value};
+
+use crate::{archive, state};
+use crate::loader::VehImage;
+use crate::playlist::Playlist;
+
+/// Whether the pages at `index` and `index + 1` of `playlist` may be shown as a spread, judging by their
+/// paths. A cover, i.e. the first page of its archive or directory, is shown alone, and so is the last one.
+pub fn may_pair(playlist: &Playlist, index: usize) -> bool {
+    let parent = |index: usize| playlist.get(index).parent();
+    let is_cover = index == 0 || parent(index - 1) != parent(index);
+    index + 1 < playlist.len() && !is_cover && parent(index + 1) == parent(index)
+}
+
+/// Whether `image` is taller than wide. Wider pages are spreads of their own.
+pub fn is_portrait(image: &VehImage) -> bool {
+    let (width, height) = image.size();
+    height > width
+}
+
+/// The last-read page of every archive read in `--manga` mode, by the absolute path of the archive
+#[derive(Default)]
+pub struct Bookmarks {
+    pages: HashMap<PathBuf, String>,
+    /// The archives read in this session, whose pages take the place of those saved by other processes
+    recorded: HashSet<PathBuf>,
+}
+
+impl Bookmarks {
+    /// The bookmarks of earlier sessions, none if there are none yet
+    pub fn load() -> Self {
+        let pages = state::read("manga.json").map(|(value, version)| pages(&value, version)).unwrap_or_default();
+        Self { pages, recorded: HashSet::new() }
+    }
+
+    pub fn save(&self) -> Result<()> {
+        state::update("manga.json", |current| {
+            let mut pages = current.map(|(value, version)| pages(&value, version)).unwrap_or_default();
+            let recorded = self.pages.iter().filter(|(archive, _)| self.recorded.contains(*archive));
+            pages.extend(recorded.map(|(archive, page)| (archive.clone(), page.clone())));
+            let pages: Map<String, Value> =
+                pages.into_iter().map(|(archive, page)| (archive.to_string_lossy().into_owned(), page.into())).collect();
+            Map::from_iter([("pages".to_string(), pages.into())])
+        })
+    }
+
+    /// Remember the image at `path` as the last-read page of its archive, if it is inside one
+    pub fn record(&mut self, path: &Path) {
+        if let Some((archive, entry)) = archive::split(path) {
+            let archive = std::fs::canonicalize(archive).unwrap_or_else(|_| archive.to_path_buf());
+            self.pages.insert(archive.clone(), entry);
+            self.recorded.insert(archive);
+        }
+    }
+
+    /// The index into `paths` of the last-read page of the archive the first of them is in, 0 if there is none
+    pub fn start_index(&self, paths: &[PathBuf]) -> usize {
+        let Some((archive, _)) = paths.first().and_then(|path| archive::split(path)) else {
+            return 0;
+        };
+        let absolute = std::fs::canonicalize(archive).unwrap_or_else(|_| archive.to_path_buf());
+        let Some(last_read) = self.pages.get(&absolute) else {
+            return 0;
+        };
+        paths
+            .iter()
+            .position(|path| archive::split(path).is_some_and(|(other, entry)| other == archive && entry == *last_read))
+            .unwrap_or(0)
+    }
+}
+
+/// The last-read pages in the contents of `manga.json` with the layout of `version`, which before versions were
+/// kept were all there was
+fn pages(contents: &Value, version: u64) -> HashMap<PathBuf, String> {
+    let pages = if version == 0 { Some(contents) } else { contents.get("pages") };
+    pages
+        .and_then(Value::as_object)
+        .into_iter()
+        .flatten()
+        .filter_map(|(archive, page)| Some((PathBuf::from(archive), page.as_str()?.to_string())))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn pages_are_read_from_every_version() {
+        let expected = HashMap::from([(PathBuf::from("/comic.cbz"), "002.png".to_string())]);
+        // Before versions were kept the file held nothing but the pages
+        assert_eq!(pages(&json!({ "/comic.cbz": "002.png" }), 0), expected);
+        assert_eq!(pages(&json!({ "pages": { "/comic.cbz": "002.png" }, "version": 1 }), 1), expected);
+    }
+
+    #[test]
+    fn pages_that_are_not_names_are_skipped() {
+        let contents = json!({ "pages": { "/a.cbz": 2, "/b.cbz": "001.png" }, "version": 1 });
+        assert_eq!(pages(&contents, 1), HashMap::from([(PathBuf::from("/b.cbz"), "001.png".to_string())]));
+    }
+}
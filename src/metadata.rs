@@ -0,0 +1,105 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use exif::{Exif, In, Tag};
+
+use crate::{external, raw};
+use crate::loader::{format_of, is_svg, parse_svg};
+
+/// Names of the fields that can be looked up with [`Metadata::get`]
+pub const FIELDS: &[&str] = &[
+    "width", "height", "megapixels", "aspect", "filesize", "format",
+    "iso", "aperture", "exposure", "focal", "make", "model", "date", "orientation",
+];
+
+/// A metadata value, either numeric or textual
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Number(f64),
+    Text(String),
+}
+
+/// Information about an image read from its headers, without decoding the pixels
+pub struct Metadata {
+    pub format: String,
+    pub width: u32,
+    pub height: u32,
+    pub file_size: u64,
+    pub exif: Option<Exif>,
+}
+
+impl Metadata {
+    pub fn read(path: &Path) -> Result<Self> {
+        let format = format_of(path).context("no format given")?;
+        let file_size = std::fs::metadata(path)?.len();
+        let (width, height) = if is_svg(&format) {
+            // SVGs have no header to speak of, the document has to be parsed to learn its size
+            let svg = parse_svg(path, false)?;
+            (svg.size().width().ceil() as u32, svg.size().height().ceil() as u32)
+        } else if external::supports(&format) {
+            // Neither is there a header the image crate can read for the formats decoded by other tools
+            let image = external::decode(path, &format)?;
+            (image.width(), image.height())
+        } else if raw::is_raw(&format) {
+            raw::dimensions(path)?
+        } else {
+            image::ImageReader::open(path)?
+                .with_guessed_format()?
+                .into_dimensions()
+                .context("failed to read image dimensions")?
+        };
+        // Most images carry no EXIF at all, which is not an error
+        let exif = File::open(path)
+            .ok()
+            .and_then(|file| exif::Reader::new().read_from_container(&mut BufReader::new(file)).ok());
+
+        Ok(Self { format, width, height, file_size, exif })
+    }
+
+    /// Look up one of [`FIELDS`]; `None` if the image doesn't carry that information
+    pub fn get(&self, name: &str) -> Option<Value> {
+        let (width, height) = (self.width as f64, self.height as f64);
+        match name {
+            "width" => Some(Value::Number(width)),
+            "height" => Some(Value::Number(height)),
+            "megapixels" => Some(Value::Number(width * height / 1e6)),
+            "aspect" => Some(Value::Number(width / height)),
+            "filesize" => Some(Value::Number(self.file_size as f64)),
+            "format" => Some(Value::Text(self.format.clone())),
+            "iso" => self.exif_number(Tag::PhotographicSensitivity),
+            "aperture" => self.exif_number(Tag::FNumber),
+            "exposure" => self.exif_number(Tag::ExposureTime),
+            "focal" => self.exif_number(Tag::FocalLength),
+            "make" => self.exif_text(Tag::Make),
+            "model" => self.exif_text(Tag::Model),
+            "date" => self.exif_text(Tag::DateTimeOriginal),
+            "orientation" => self.exif_number(Tag::Orientation),
+            _ => None,
+        }
+    }
+
+    fn exif_field(&self, tag: Tag) -> Option<&exif::Value> {
+        Some(&self.exif.as_ref()?.get_field(tag, In::PRIMARY)?.value)
+    }
+
+    fn exif_number(&self, tag: Tag) -> Option<Value> {
+        let number = match self.exif_field(tag)? {
+            exif::Value::Rational(values) => values.first()?.to_f64(),
+            exif::Value::SRational(values) => values.first()?.to_f64(),
+            value => value.get_uint(0)? as f64,
+        };
+        Some(Value::Number(number))
+    }
+
+    fn exif_text(&self, tag: Tag) -> Option<Value> {
+        match self.exif_field(tag)? {
+            exif::Value::Ascii(values) => {
+                let text = String::from_utf8_lossy(values.first()?);
+                Some(Value::Text(text.trim_end_matches('\0').trim().to_string()))
+            }
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,67 @@
+//! The color profiles of monitors, so that every window shows colors right on the monitor it is on, also on setups
+//! that mix e.g. a wide-gamut monitor with a laptop screen. `--monitor-profile NAME=FILE` gives the ICC profile of
+//! a monitor; on X11, profiles set by color managers such as colord are found through the `_ICC_PROFILE`
+//! properties of the root window otherwise.
+
+use std::path::PathBuf;
+
+use winit::window::Window;
+
+/// Parse a `--monitor-profile` of the form NAME=FILE
+pub fn parse_monitor_profile(value: &str) -> Result<(String, PathBuf), String> {
+    match value.split_once('=') {
+        Some((name, file)) if !name.is_empty() && !file.is_empty() => Ok((name.to_string(), PathBuf::from(file))),
+        _ => Err(format!("`{value}` is not of the form NAME=FILE")),
+    }
+}
+
+/// The name of the monitor `window` is on, if it can be told
+pub fn current(window: &Window) -> Option<String> {
+    window.current_monitor()?.name()
+}
+
+/// The ICC profile of the monitor `window` is on: the file `profiles` gives for its name, or else the one set on
+/// the X server
+pub fn profile(window: &Window, profiles: &[(String, PathBuf)]) -> Option<Vec<u8>> {
+    let monitor = window.current_monitor()?;
+    let name = monitor.name();
+    if let Some((_, file)) = profiles.iter().rev().find(|(profile_name, _)| Some(profile_name) == name.as_ref()) {
+        return match std::fs::read(file) {
+            Ok(profile) => Some(profile),
+            Err(err) => {
+                eprintln!("veh: failed to read {}: {err}", file.display());
+                None
+            }
+        };
+    }
+    let index = window.available_monitors().position(|other| other == monitor)?;
+    x11_profile(window, index)
+}
+
+/// The profile of the `index`th monitor set on the X server, `_ICC_PROFILE` for the first and `_ICC_PROFILE_<index>`
+/// for the others as the ICC Profiles in X specification says
+#[cfg(all(unix, not(target_os = "macos")))]
+fn x11_profile(window: &Window, index: usize) -> Option<Vec<u8>> {
+    use winit::raw_window_handle::{HasWindowHandle, RawWindowHandle};
+    use x11rb::connection::Connection;
+    use x11rb::protocol::xproto::{AtomEnum, ConnectionExt};
+
+    let handle = window.window_handle().ok()?.as_raw();
+    if !matches!(handle, RawWindowHandle::Xlib(_) | RawWindowHandle::Xcb(_)) {
+        return None;
+    }
+    let (conn, screen_num) = x11rb::connect(None).ok()?;
+    let root = conn.setup().roots.get(screen_num)?.root;
+    let name = if index == 0 { "_ICC_PROFILE".to_string() } else { format!("_ICC_PROFILE_{index}") };
+    let atom = conn.intern_atom(true, name.as_bytes()).ok()?.reply().ok()?.atom;
+    if atom == 0 {
+        return None;
+    }
+    let reply = conn.get_property(false, root, atom, AtomEnum::ANY, 0, u32::MAX / 4).ok()?.reply().ok()?;
+    (!reply.value.is_empty()).then_some(reply.value)
+}
+
+#[cfg(not(all(unix, not(target_os = "macos"))))]
+fn x11_profile(_window: &Window, _index: usize) -> Option<Vec<u8>> {
+    None
+}
@@ -0,0 +1,53 @@
+//! The opacity of whole windows, so that veh can float semi-transparent over other windows as a reference to
+//! trace. Only X11 lets windows ask for it, through the `_NET_WM_WINDOW_OPACITY` property that compositors such as
+//! picom and KWin honor.
+
+use anyhow::{bail, Result};
+use winit::window::Window;
+
+/// How much `,` and `.` change the opacity
+pub const STEP: f64 = 0.1;
+/// How opaque `--click-through` windows are unless `--opacity` is given
+pub const CLICK_THROUGH: f64 = 0.5;
+/// Windows don't get more transparent than this, so that they can still be found
+pub const MIN: f64 = 0.1;
+
+/// Parse an opacity given in percent, such as `60`
+pub fn parse_percent(value: &str) -> Result<f64, String> {
+    match value.trim().trim_end_matches('%').parse::<f64>() {
+        Ok(percent) if (MIN * 100.0..=100.0).contains(&percent) => Ok(percent / 100.0),
+        _ => Err(format!("`{value}` is not a percentage from {} to 100", MIN * 100.0)),
+    }
+}
+
+/// Make `window` as opaque as `opacity`, from [`MIN`] to 1
+#[cfg(all(unix, not(target_os = "macos")))]
+pub fn set(window: &Window, opacity: f64) -> Result<()> {
+    use anyhow::Context;
+    use winit::raw_window_handle::{HasWindowHandle, RawWindowHandle};
+    use x11rb::connection::Connection;
+    use x11rb::protocol::xproto::{AtomEnum, ConnectionExt, PropMode};
+    use x11rb::wrapper::ConnectionExt as _;
+
+    let handle = window.window_handle().map(|handle| handle.as_raw());
+    let id = match handle {
+        Ok(RawWindowHandle::Xlib(handle)) => handle.window as u32,
+        Ok(RawWindowHandle::Xcb(handle)) => handle.window.get(),
+        _ => bail!("window opacity is only supported on X11"),
+    };
+    let (conn, _) = x11rb::connect(None).context("failed to connect to the X server")?;
+    let atom = conn.intern_atom(false, b"_NET_WM_WINDOW_OPACITY")?.reply()?.atom;
+    if opacity >= 1.0 {
+        conn.delete_property(id, atom)?;
+    } else {
+        let value = (opacity.clamp(0.0, 1.0) * u32::MAX as f64) as u32;
+        conn.change_property32(PropMode::REPLACE, id, atom, AtomEnum::CARDINAL, &[value])?;
+    }
+    conn.flush().context("failed to set the window opacity")?;
+    Ok(())
+}
+
+#[cfg(not(all(unix, not(target_os = "macos"))))]
+pub fn set(_window: &Window, _opacity: f64) -> Result<()> {
+    bail!("window opacity is only supported on X11")
+}
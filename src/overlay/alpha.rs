@@ -0,0 +1,22 @@
+use vello::Scene;
+
+use super::{Overlay, OverlayContext};
+
+/// Paints the semi-transparent pixels of the image magenta while the alpha check is on
+pub struct AlphaFringes;
+
+impl Overlay for AlphaFringes {
+    fn name(&self) -> &'static str {
+        "alpha"
+    }
+
+    fn z_order(&self) -> i32 {
+        5
+    }
+
+    fn draw(&self, scene: &mut Scene, context: &OverlayContext) {
+        if let Some(mask) = context.alpha_mask {
+            scene.draw_image(mask, context.transform);
+        }
+    }
+}
@@ -0,0 +1,70 @@
+use vello::kurbo::{Affine, Rect};
+use vello::peniko::{Color, Fill};
+use vello::Scene;
+
+use super::{Overlay, OverlayContext};
+use crate::text::Text;
+
+/// Font size in logical pixels
+const FONT_SIZE: f64 = 15.0;
+/// Space around the text in logical pixels
+const PADDING: f64 = 6.0;
+
+/// The caption of the image with `--caption-path`, centered under the image, or at the bottom of the window if
+/// the image reaches down to it
+pub struct Caption {
+    text: Text,
+}
+
+impl Caption {
+    /// `None` if there is no font to draw captions with
+    pub fn new() -> Option<Self> {
+        Some(Self { text: Text::load()? })
+    }
+}
+
+impl Overlay for Caption {
+    fn name(&self) -> &'static str {
+        "caption"
+    }
+
+    fn z_order(&self) -> i32 {
+        22
+    }
+
+    fn draw(&self, scene: &mut Scene, context: &OverlayContext) {
+        let Some(caption) = context.caption else {
+            return;
+        };
+        let font_size = (FONT_SIZE * context.scale_factor) as f32;
+        let padding = PADDING * context.scale_factor;
+        let (ascent, descent) = self.text.vertical_metrics(font_size);
+        let line_height = ascent + descent;
+        let lines: Vec<&str> = if caption.is_empty() { vec![""] } else { caption.lines().collect() };
+        let widths: Vec<f64> = lines.iter().map(|line| self.text.width(line, font_size)).collect();
+        let box_width = widths.iter().copied().fold(0.0, f64::max) + 2.0 * padding;
+        let box_height = line_height * lines.len() as f64 + 2.0 * padding;
+
+        let (window_width, window_height) = (context.window_size.width as f64, context.window_size.height as f64);
+        let (width, height) = context.image_size;
+        let image = context.transform.transform_rect_bbox(Rect::new(0.0, 0.0, width, height));
+        let top = image.y1.min(window_height - box_height).max(0.0);
+        let center = image.center().x.clamp(box_width / 2.0, (window_width - box_width / 2.0).max(box_width / 2.0));
+        let backdrop = Rect::new(center - box_width / 2.0, top, center + box_width / 2.0, top + box_height);
+        scene.fill(Fill::NonZero, Affine::IDENTITY, Color::rgba8(0, 0, 0, 0xc0), None, &backdrop);
+
+        let mut end = (center, top + padding);
+        for (index, (line, width)) in lines.iter().zip(&widths).enumerate() {
+            let x = center - width / 2.0;
+            let baseline = top + padding + line_height * index as f64 + ascent;
+            self.text.draw(scene, line, font_size, Affine::translate((x, baseline)), Color::WHITE);
+            end = (x + width, baseline - ascent);
+        }
+        // The cursor after the text while the caption is edited
+        if context.editing_caption {
+            let cursor_x = end.0 + 1.0;
+            let cursor = Rect::new(cursor_x, end.1, cursor_x + context.scale_factor.max(1.0), end.1 + line_height);
+            scene.fill(Fill::NonZero, Affine::IDENTITY, Color::WHITE, None, &cursor);
+        }
+    }
+}
@@ -0,0 +1,33 @@
+use vello::kurbo::{Affine, Rect, Shape, Stroke};
+use vello::peniko::{Color, Fill};
+use vello::Scene;
+
+use super::{Overlay, OverlayContext};
+
+/// Shades the parts of the image a proposed crop would remove and outlines the part that is kept
+pub struct CropProposal;
+
+impl Overlay for CropProposal {
+    fn name(&self) -> &'static str {
+        "crop"
+    }
+
+    fn z_order(&self) -> i32 {
+        10
+    }
+
+    fn draw(&self, scene: &mut Scene, context: &OverlayContext) {
+        let Some(crop) = context.crop_proposal else {
+            return;
+        };
+        let (width, height) = context.image_size;
+        let bounds = context.transform.transform_rect_bbox(Rect::new(0.0, 0.0, width, height));
+        let kept = context.transform.transform_rect_bbox(crop.rect());
+
+        let mut removed = bounds.to_path(0.1);
+        removed.extend(kept.to_path(0.1));
+        scene.fill(Fill::EvenOdd, Affine::IDENTITY, Color::rgba8(0, 0, 0, 0xa0), None, &removed);
+        let stroke = Stroke::new(2.0 * context.scale_factor);
+        scene.stroke(&stroke, Affine::IDENTITY, Color::rgb8(0xff, 0xb0, 0x00), None, &kept);
+    }
+}
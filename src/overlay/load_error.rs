@@ -0,0 +1,72 @@
+use vello::kurbo::{Affine, Rect};
+use vello::peniko::{Color, Fill};
+use vello::Scene;
+
+use super::{Overlay, OverlayContext};
+use crate::text::Text;
+
+/// Font size in logical pixels
+const FONT_SIZE: f64 = 14.0;
+/// Space around the text in logical pixels
+const PADDING: f64 = 10.0;
+
+/// Why the image couldn't be reloaded with `--auto-reload`, e.g. where an SVG being edited doesn't parse, in a
+/// panel along the top of the window over the last version that loaded
+pub struct LoadError {
+    text: Text,
+}
+
+impl LoadError {
+    /// `None` if there is no font to draw the panel with
+    pub fn new() -> Option<Self> {
+        Some(Self { text: Text::load()? })
+    }
+
+    /// `message` broken into lines at most `width` wide where it has spaces
+    fn wrap(&self, message: &str, font_size: f32, width: f64) -> Vec<String> {
+        let mut lines = vec![];
+        for paragraph in message.lines() {
+            let mut line = String::new();
+            for word in paragraph.split(' ') {
+                let candidate = if line.is_empty() { word.to_string() } else { format!("{line} {word}") };
+                if !line.is_empty() && self.text.width(&candidate, font_size) > width {
+                    lines.push(std::mem::replace(&mut line, word.to_string()));
+                } else {
+                    line = candidate;
+                }
+            }
+            lines.push(line);
+        }
+        lines
+    }
+}
+
+impl Overlay for LoadError {
+    fn name(&self) -> &'static str {
+        "load-error"
+    }
+
+    fn z_order(&self) -> i32 {
+        35
+    }
+
+    fn draw(&self, scene: &mut Scene, context: &OverlayContext) {
+        let Some(error) = context.load_error else {
+            return;
+        };
+        let font_size = (FONT_SIZE * context.scale_factor) as f32;
+        let padding = PADDING * context.scale_factor;
+        let (ascent, descent) = self.text.vertical_metrics(font_size);
+        let line_height = ascent + descent;
+        let width = context.window_size.width as f64;
+
+        let mut lines = vec!["Reloading failed, showing the last version:".to_string()];
+        lines.extend(self.wrap(error, font_size, width - 2.0 * padding));
+        let panel_height = line_height * lines.len() as f64 + 2.0 * padding;
+        scene.fill(Fill::NonZero, Affine::IDENTITY, Color::rgba8(0x60, 0x00, 0x00, 0xe0), None, &Rect::new(0.0, 0.0, width, panel_height));
+        for (index, line) in lines.iter().enumerate() {
+            let baseline = padding + line_height * index as f64 + ascent;
+            self.text.draw(scene, line, font_size, Affine::translate((padding, baseline)), Color::WHITE);
+        }
+    }
+}
@@ -0,0 +1,35 @@
+use vello::kurbo::{Affine, BezPath};
+use vello::peniko::{Color, Fill};
+use vello::Scene;
+
+use super::{Overlay, OverlayContext};
+
+/// Size of the triangle in logical pixels
+const SIZE: f64 = 24.0;
+
+/// An orange triangle in the top right corner of the window while the image is marked
+pub struct MarkIndicator;
+
+impl Overlay for MarkIndicator {
+    fn name(&self) -> &'static str {
+        "mark"
+    }
+
+    fn z_order(&self) -> i32 {
+        20
+    }
+
+    fn draw(&self, scene: &mut Scene, context: &OverlayContext) {
+        if !context.marked {
+            return;
+        }
+        let size = SIZE * context.scale_factor;
+        let window_width = context.window_size.width as f64;
+        let mut triangle = BezPath::new();
+        triangle.move_to((window_width - size, 0.0));
+        triangle.line_to((window_width, 0.0));
+        triangle.line_to((window_width, size));
+        triangle.close_path();
+        scene.fill(Fill::NonZero, Affine::IDENTITY, Color::rgb8(0xff, 0xb0, 0x00), None, &triangle);
+    }
+}
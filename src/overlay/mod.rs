@@ -0,0 +1,155 @@
+//! Widgets drawn over the image, such as the status bar. Every widget is an [`Overlay`] registered
+//! in [`Overlays`], which draws them from the lowest to the highest z-order.
+
+mod alpha;
+mod caption;
+mod crop;
+mod load_error;
+mod mark;
+mod palette;
+mod prompt;
+mod selection;
+mod stats;
+mod status_bar;
+mod texture;
+mod timeline;
+mod welcome;
+
+use std::path::Path;
+
+use vello::kurbo::{Affine, Rect};
+use vello::peniko::Image;
+use vello::Scene;
+use winit::dpi::PhysicalSize;
+
+use crate::crop::Crop;
+use crate::palette::Palette;
+use crate::stats::Stats;
+use crate::texture::Preview;
+
+pub use alpha::AlphaFringes;
+pub use caption::Caption;
+pub use crop::CropProposal;
+pub use load_error::LoadError;
+pub use mark::MarkIndicator;
+pub use palette::CommandPalette;
+pub use prompt::Prompt;
+pub use selection::Selection;
+pub use stats::StatsPanel;
+pub use status_bar::StatusBar;
+pub use texture::TexturePreview;
+pub use timeline::{frame_at, is_on_timeline, timeline_height, Timeline, TimelinePosition};
+pub use welcome::Welcome;
+
+/// What overlays get to know about the window they are drawn in and the image it shows
+pub struct OverlayContext<'a> {
+    pub window_size: PhysicalSize<u32>,
+    /// Pixels per logical pixel, which sizes of overlays should be multiplied with
+    pub scale_factor: f64,
+    /// Maps image coordinates to window coordinates
+    pub transform: Affine,
+    pub image_size: (f64, f64),
+    pub path: &'a Path,
+    pub index: usize,
+    pub count: usize,
+    pub page: usize,
+    pub page_count: usize,
+    pub marked: bool,
+    pub crop_proposal: Option<Crop>,
+    /// The semi-transparent pixels of the image while the alpha check is on, see [`crate::alpha`]
+    pub alpha_mask: Option<&'a Image>,
+    /// The texture preview shown instead of the image, if one is on, see [`crate::texture`]
+    pub texture: Option<(Preview, &'a Image)>,
+    /// The rubber band being dragged to zoom in on, in window coordinates
+    pub selection: Option<Rect>,
+    /// Where the image is in a numbered image sequence or which frame of an animated image is shown
+    pub timeline: Option<TimelinePosition>,
+    /// The caption of the image with `--caption-path`, or what has been typed so far while it is edited
+    pub caption: Option<&'a str>,
+    pub editing_caption: bool,
+    /// What has been typed into the goto prompt, if it is open
+    pub prompt: Option<&'a str>,
+    /// The command palette, if it is open
+    pub palette: Option<&'a Palette>,
+    /// Whether the hint of the first launch is shown
+    pub welcome: bool,
+    /// The statistics of the pixels of the image, while they are shown
+    pub stats: Option<&'a Stats>,
+    /// Why the image shown couldn't be reloaded, if it couldn't
+    pub load_error: Option<&'a str>,
+    /// The exposure HDR and 16-bit images are shown with, in EV, if the image is one
+    pub exposure: Option<f32>,
+}
+
+pub trait Overlay {
+    /// Name to toggle the overlay by
+    fn name(&self) -> &'static str;
+
+    /// Overlays with a higher z-order are drawn on top of those with a lower one
+    fn z_order(&self) -> i32;
+
+    fn draw(&self, scene: &mut Scene, context: &OverlayContext);
+}
+
+/// The registered overlays, in drawing order, and whether they are shown
+#[derive(Default)]
+pub struct Overlays {
+    entries: Vec<(Box<dyn Overlay>, bool)>,
+}
+
+impl Overlays {
+    /// The overlays of veh; the status bar is only shown if `status_bar` is set
+    pub fn with_defaults(status_bar: bool) -> Self {
+        let mut overlays = Self::default();
+        overlays.register(Box::new(TexturePreview::new()), true);
+        overlays.register(Box::new(AlphaFringes), true);
+        overlays.register(Box::new(CropProposal), true);
+        overlays.register(Box::new(Selection), true);
+        overlays.register(Box::new(MarkIndicator), true);
+        overlays.register(Box::new(Timeline::new()), true);
+        if let Some(caption) = Caption::new() {
+            overlays.register(Box::new(caption), true);
+        }
+        if let Some(bar) = StatusBar::new() {
+            overlays.register(Box::new(bar), status_bar);
+        }
+        if let Some(stats) = StatsPanel::new() {
+            overlays.register(Box::new(stats), true);
+        }
+        if let Some(load_error) = LoadError::new() {
+            overlays.register(Box::new(load_error), true);
+        }
+        if let Some(prompt) = Prompt::new() {
+            overlays.register(Box::new(prompt), true);
+        }
+        if let Some(palette) = CommandPalette::new() {
+            overlays.register(Box::new(palette), true);
+        }
+        if let Some(welcome) = Welcome::new() {
+            overlays.register(Box::new(welcome), true);
+        }
+        overlays
+    }
+
+    pub fn register(&mut self, overlay: Box<dyn Overlay>, enabled: bool) {
+        self.entries.push((overlay, enabled));
+        // Stable, so overlays with the same z-order are drawn in the order they were registered
+        self.entries.sort_by_key(|(overlay, _)| overlay.z_order());
+    }
+
+    /// Show or hide the overlay called `name` and return whether it is shown now,
+    /// or `None` if there is no such overlay
+    pub fn toggle(&mut self, name: &str) -> Option<bool> {
+        let (_, enabled) = self.entries.iter_mut().find(|(overlay, _)| overlay.name() == name)?;
+        *enabled = !*enabled;
+        Some(*enabled)
+    }
+
+    pub fn draw(&self, scene: &mut Scene, context: &OverlayContext) {
+        for (overlay, enabled) in &self.entries {
+            if *enabled {
+                overlay.draw(scene, context);
+            }
+        }
+    }
+}
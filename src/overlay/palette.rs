@@ -0,0 +1,78 @@
+use vello::kurbo::{Affine, Rect};
+use vello::peniko::{Color, Fill};
+use vello::Scene;
+
+use super::{Overlay, OverlayContext};
+use crate::text::Text;
+
+/// Font size in logical pixels
+const FONT_SIZE: f64 = 15.0;
+/// Space around the text in logical pixels
+const PADDING: f64 = 8.0;
+/// The widest the panel gets in logical pixels
+const MAX_WIDTH: f64 = 560.0;
+/// How many commands are listed at a time
+const ROWS: usize = 12;
+
+/// The command palette of Ctrl+P, a panel at the top of the window with the query typed so far above the commands
+/// matching it and their keys
+pub struct CommandPalette {
+    text: Text,
+}
+
+impl CommandPalette {
+    /// `None` if there is no font to draw the palette with
+    pub fn new() -> Option<Self> {
+        Some(Self { text: Text::load()? })
+    }
+}
+
+impl Overlay for CommandPalette {
+    fn name(&self) -> &'static str {
+        "palette"
+    }
+
+    fn z_order(&self) -> i32 {
+        45
+    }
+
+    fn draw(&self, scene: &mut Scene, context: &OverlayContext) {
+        let Some(palette) = context.palette else {
+            return;
+        };
+        let font_size = (FONT_SIZE * context.scale_factor) as f32;
+        let padding = PADDING * context.scale_factor;
+        let (ascent, descent) = self.text.vertical_metrics(font_size);
+        let row_height = ascent + descent + padding;
+        let window_width = context.window_size.width as f64;
+        let width = (MAX_WIDTH * context.scale_factor).min(window_width - 2.0 * padding).max(0.0);
+        let left = (window_width - width) / 2.0;
+
+        // Scroll the list so that the selected command is in it
+        let first = palette.selected.saturating_sub(ROWS - 1);
+        let rows: Vec<_> = palette.matches().skip(first).take(ROWS).collect();
+        let panel_height = row_height * (rows.len() + 1) as f64 + padding;
+        let panel = Rect::new(left, padding, left + width, padding + panel_height);
+        scene.fill(Fill::NonZero, Affine::IDENTITY, Color::rgba8(0, 0, 0, 0xe0), None, &panel);
+
+        let baseline = panel.y0 + padding / 2.0 + ascent;
+        let query_width = self.text.draw(scene, &format!("> {}", palette.query), font_size, Affine::translate((left + padding, baseline)), Color::WHITE);
+        // The cursor after the query
+        let cursor_x = left + padding + query_width + 1.0;
+        let cursor = Rect::new(cursor_x, baseline - ascent, cursor_x + context.scale_factor.max(1.0), baseline + descent);
+        scene.fill(Fill::NonZero, Affine::IDENTITY, Color::WHITE, None, &cursor);
+
+        for (row, command) in rows.into_iter().enumerate() {
+            let top = panel.y0 + row_height * (row + 1) as f64;
+            if first + row == palette.selected {
+                let highlight = Rect::new(left, top + padding / 2.0, left + width, top + row_height + padding / 2.0);
+                scene.fill(Fill::NonZero, Affine::IDENTITY, Color::rgba8(0x40, 0x60, 0xa0, 0xe0), None, &highlight);
+            }
+            let baseline = top + padding + ascent;
+            self.text.draw(scene, &command.name, font_size, Affine::translate((left + padding, baseline)), Color::WHITE);
+            let keys_width = self.text.width(command.keys, font_size);
+            let keys_at = Affine::translate((left + width - padding - keys_width, baseline));
+            self.text.draw(scene, command.keys, font_size, keys_at, Color::rgb8(0xa0, 0xa0, 0xa0));
+        }
+    }
+}
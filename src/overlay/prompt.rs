@@ -0,0 +1,52 @@
+use vello::kurbo::{Affine, Rect};
+use vello::peniko::{Color, Fill};
+use vello::Scene;
+
+use super::{Overlay, OverlayContext};
+use crate::text::Text;
+
+/// Font size in logical pixels
+const FONT_SIZE: f64 = 15.0;
+/// Space around the text in logical pixels
+const PADDING: f64 = 8.0;
+
+/// The goto prompt of `/`, a bar along the top of the window with the index or part of a file name typed so far
+pub struct Prompt {
+    text: Text,
+}
+
+impl Prompt {
+    /// `None` if there is no font to draw the prompt with
+    pub fn new() -> Option<Self> {
+        Some(Self { text: Text::load()? })
+    }
+}
+
+impl Overlay for Prompt {
+    fn name(&self) -> &'static str {
+        "prompt"
+    }
+
+    fn z_order(&self) -> i32 {
+        40
+    }
+
+    fn draw(&self, scene: &mut Scene, context: &OverlayContext) {
+        let Some(input) = context.prompt else {
+            return;
+        };
+        let font_size = (FONT_SIZE * context.scale_factor) as f32;
+        let padding = PADDING * context.scale_factor;
+        let (ascent, descent) = self.text.vertical_metrics(font_size);
+        let bar_height = ascent + descent + 2.0 * padding;
+        let width = context.window_size.width as f64;
+
+        scene.fill(Fill::NonZero, Affine::IDENTITY, Color::rgba8(0, 0, 0, 0xe0), None, &Rect::new(0.0, 0.0, width, bar_height));
+        let baseline = padding + ascent;
+        let text_width = self.text.draw(scene, &format!("Go to: {input}"), font_size, Affine::translate((padding, baseline)), Color::WHITE);
+        // The cursor after the text
+        let cursor_x = padding + text_width + 1.0;
+        let cursor = Rect::new(cursor_x, baseline - ascent, cursor_x + context.scale_factor.max(1.0), baseline + descent);
+        scene.fill(Fill::NonZero, Affine::IDENTITY, Color::WHITE, None, &cursor);
+    }
+}
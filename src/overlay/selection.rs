@@ -0,0 +1,27 @@
+use vello::kurbo::{Affine, Stroke};
+use vello::peniko::{Color, Fill};
+use vello::Scene;
+
+use super::{Overlay, OverlayContext};
+
+/// The rubber band dragged with the right mouse button, which the view zooms in on once it is released
+pub struct Selection;
+
+impl Overlay for Selection {
+    fn name(&self) -> &'static str {
+        "selection"
+    }
+
+    fn z_order(&self) -> i32 {
+        15
+    }
+
+    fn draw(&self, scene: &mut Scene, context: &OverlayContext) {
+        let Some(selection) = context.selection else {
+            return;
+        };
+        scene.fill(Fill::NonZero, Affine::IDENTITY, Color::rgba8(0xff, 0xb0, 0x00, 0x30), None, &selection);
+        let stroke = Stroke::new(context.scale_factor);
+        scene.stroke(&stroke, Affine::IDENTITY, Color::rgb8(0xff, 0xb0, 0x00), None, &selection);
+    }
+}
@@ -0,0 +1,53 @@
+use vello::kurbo::{Affine, Rect};
+use vello::peniko::{Color, Fill};
+use vello::Scene;
+
+use super::{Overlay, OverlayContext};
+use crate::text::Text;
+
+/// Font size in logical pixels
+const FONT_SIZE: f64 = 13.0;
+/// Space around the text in logical pixels
+const PADDING: f64 = 8.0;
+
+/// The statistics of the pixels of the image of Shift+I, in a panel in the top left corner of the window
+pub struct StatsPanel {
+    text: Text,
+}
+
+impl StatsPanel {
+    /// `None` if there is no font to draw the panel with
+    pub fn new() -> Option<Self> {
+        Some(Self { text: Text::load()? })
+    }
+}
+
+impl Overlay for StatsPanel {
+    fn name(&self) -> &'static str {
+        "stats"
+    }
+
+    fn z_order(&self) -> i32 {
+        32
+    }
+
+    fn draw(&self, scene: &mut Scene, context: &OverlayContext) {
+        let Some(stats) = context.stats else {
+            return;
+        };
+        let font_size = (FONT_SIZE * context.scale_factor) as f32;
+        let padding = PADDING * context.scale_factor;
+        let (ascent, descent) = self.text.vertical_metrics(font_size);
+        let line_height = ascent + descent;
+
+        let lines = stats.lines();
+        let width = lines.iter().map(|line| self.text.width(line, font_size)).fold(0.0, f64::max) + 2.0 * padding;
+        let height = line_height * lines.len() as f64 + 2.0 * padding;
+        let panel = Rect::new(padding, padding, padding + width, padding + height);
+        scene.fill(Fill::NonZero, Affine::IDENTITY, Color::rgba8(0, 0, 0, 0xc0), None, &panel);
+        for (index, line) in lines.iter().enumerate() {
+            let baseline = panel.y0 + padding + line_height * index as f64 + ascent;
+            self.text.draw(scene, line, font_size, Affine::translate((panel.x0 + padding, baseline)), Color::WHITE);
+        }
+    }
+}
@@ -0,0 +1,69 @@
+use vello::kurbo::{Affine, Rect};
+use vello::peniko::{Color, Fill};
+use vello::Scene;
+
+use super::{Overlay, OverlayContext};
+use crate::text::Text;
+
+/// Font size in logical pixels
+const FONT_SIZE: f64 = 13.0;
+/// Space around the text in logical pixels
+const PADDING: f64 = 6.0;
+
+/// A bar along the bottom of the window with the file name, position, size and zoom of the image, and the
+/// exposure of HDR images
+pub struct StatusBar {
+    text: Text,
+}
+
+impl StatusBar {
+    /// `None` if there is no font to draw the bar with
+    pub fn new() -> Option<Self> {
+        Some(Self { text: Text::load()? })
+    }
+
+    fn contents(context: &OverlayContext) -> String {
+        let name = context.path.file_name().map(|name| name.to_string_lossy()).unwrap_or_default();
+        let (width, height) = context.image_size;
+        let zoom = context.transform.determinant().abs().sqrt() * 100.0;
+        let mut contents = format!("{name}   {}/{}", context.index + 1, context.count);
+        if context.page_count > 1 {
+            contents += &format!("   page {}/{}", context.page + 1, context.page_count);
+        }
+        contents += &format!("   {width:.0}×{height:.0}   {zoom:.0}%");
+        if let Some(exposure) = context.exposure {
+            contents += &format!("   {exposure:+.1} EV");
+        }
+        if context.marked {
+            contents += "   marked";
+        }
+        contents
+    }
+}
+
+impl Overlay for StatusBar {
+    fn name(&self) -> &'static str {
+        "status-bar"
+    }
+
+    fn z_order(&self) -> i32 {
+        30
+    }
+
+    fn draw(&self, scene: &mut Scene, context: &OverlayContext) {
+        let font_size = (FONT_SIZE * context.scale_factor) as f32;
+        let padding = PADDING * context.scale_factor;
+        let (ascent, descent) = self.text.vertical_metrics(font_size);
+        let bar_height = ascent + descent + 2.0 * padding;
+        let (width, mut height) = (context.window_size.width as f64, context.window_size.height as f64);
+        // Above the timeline of sequences and animations
+        if context.timeline.is_some() {
+            height -= super::timeline_height(context.scale_factor);
+        }
+
+        let bar = Rect::new(0.0, height - bar_height, width, height);
+        scene.fill(Fill::NonZero, Affine::IDENTITY, Color::rgba8(0, 0, 0, 0xc0), None, &bar);
+        let baseline = Affine::translate((padding, height - padding - descent));
+        self.text.draw(scene, &Self::contents(context), font_size, baseline, Color::WHITE);
+    }
+}
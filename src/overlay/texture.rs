@@ -0,0 +1,61 @@
+use vello::kurbo::{Affine, Point, Rect};
+use vello::peniko::{Color, Fill};
+use vello::Scene;
+
+use super::{Overlay, OverlayContext};
+use crate::text::Text;
+use crate::texture::{Preview, CHANNEL_LABELS};
+
+/// Font size of the labels in logical pixels
+const FONT_SIZE: f64 = 13.0;
+/// Space around the labels in logical pixels
+const PADDING: f64 = 4.0;
+
+/// The texture preview of Shift+T in place of the image, with the quadrants of the channels labeled
+pub struct TexturePreview {
+    /// The font of the labels, which are left out without one
+    text: Option<Text>,
+}
+
+impl TexturePreview {
+    pub fn new() -> Self {
+        Self { text: Text::load() }
+    }
+}
+
+impl Overlay for TexturePreview {
+    fn name(&self) -> &'static str {
+        "texture"
+    }
+
+    fn z_order(&self) -> i32 {
+        3
+    }
+
+    fn draw(&self, scene: &mut Scene, context: &OverlayContext) {
+        let Some((preview, image)) = context.texture else {
+            return;
+        };
+        if preview == Preview::Lit {
+            scene.draw_image(image, context.transform);
+            return;
+        }
+        // The preview is twice the size of the image, and covers it
+        scene.draw_image(image, context.transform * Affine::scale(0.5));
+        let Some(text) = &self.text else {
+            return;
+        };
+        let font_size = (FONT_SIZE * context.scale_factor) as f32;
+        let padding = PADDING * context.scale_factor;
+        let (ascent, descent) = text.vertical_metrics(font_size);
+        let (width, height) = context.image_size;
+        let corners = [(0.0, 0.0), (width / 2.0, 0.0), (0.0, height / 2.0), (width / 2.0, height / 2.0)];
+        for (label, (x, y)) in CHANNEL_LABELS.iter().zip(corners) {
+            let corner = context.transform * Point::new(x, y);
+            let label_width = text.width(label, font_size);
+            let background = Rect::new(corner.x, corner.y, corner.x + label_width + 2.0 * padding, corner.y + ascent + descent + 2.0 * padding);
+            scene.fill(Fill::NonZero, Affine::IDENTITY, Color::rgba8(0, 0, 0, 0xc0), None, &background);
+            text.draw(scene, label, font_size, Affine::translate((corner.x + padding, corner.y + padding + ascent)), Color::WHITE);
+        }
+    }
+}
@@ -0,0 +1,107 @@
+use std::time::Duration;
+
+use vello::kurbo::{Affine, Rect};
+use vello::peniko::{Color, Fill};
+use vello::Scene;
+use winit::dpi::PhysicalSize;
+
+use super::{Overlay, OverlayContext};
+use crate::text::Text;
+
+/// Height of the timeline in logical pixels, of which clicks and drags anywhere scrub
+const HEIGHT: f64 = 28.0;
+/// Height of the track showing the progress in logical pixels
+const TRACK_HEIGHT: f64 = 4.0;
+/// Font size in logical pixels
+const FONT_SIZE: f64 = 12.0;
+/// Space left of the text in logical pixels
+const PADDING: f64 = 8.0;
+
+/// Where the image is in a numbered image sequence, or which frame of an animated image is shown
+#[derive(Debug, Clone, Copy)]
+pub struct TimelinePosition {
+    /// The frame shown, counting from 0
+    pub frame: usize,
+    pub frames: usize,
+    /// When the frame is shown from the start, and how long all frames take
+    pub time: Duration,
+    pub duration: Duration,
+    pub playing: bool,
+}
+
+/// Height of the timeline in physical pixels, which the status bar is moved up by
+pub fn timeline_height(scale_factor: f64) -> f64 {
+    HEIGHT * scale_factor
+}
+
+/// Whether `position` in a window of `size` is on the timeline, where clicks and drags scrub
+pub fn is_on_timeline(position: (f64, f64), size: PhysicalSize<u32>, scale_factor: f64) -> bool {
+    let (x, y) = position;
+    let top = size.height as f64 - timeline_height(scale_factor);
+    (0.0..size.width as f64).contains(&x) && (top..size.height as f64).contains(&y)
+}
+
+/// The frame of `frames` under `x` on the timeline of a window `width` pixels wide
+pub fn frame_at(x: f64, width: u32, frames: usize) -> usize {
+    let fraction = (x / width as f64).clamp(0.0, 1.0);
+    ((fraction * frames as f64) as usize).min(frames - 1)
+}
+
+/// `duration` as minutes, seconds and hundredths, e.g. 0:01.25
+fn format_time(duration: Duration) -> String {
+    let seconds = duration.as_secs_f64();
+    format!("{}:{:05.2}", (seconds / 60.0) as u64, seconds % 60.0)
+}
+
+/// A timeline along the bottom of the window for numbered image sequences and animated images, with the
+/// current frame and time
+pub struct Timeline {
+    /// `None` if there is no font, in which case only the track is drawn
+    text: Option<Text>,
+}
+
+impl Timeline {
+    pub fn new() -> Self {
+        Self { text: Text::load() }
+    }
+}
+
+impl Overlay for Timeline {
+    fn name(&self) -> &'static str {
+        "timeline"
+    }
+
+    fn z_order(&self) -> i32 {
+        25
+    }
+
+    fn draw(&self, scene: &mut Scene, context: &OverlayContext) {
+        let Some(timeline) = context.timeline else {
+            return;
+        };
+        let (width, height) = (context.window_size.width as f64, context.window_size.height as f64);
+        let top = height - timeline_height(context.scale_factor);
+        let track_bottom = top + TRACK_HEIGHT * context.scale_factor;
+        let done = width * (timeline.frame + 1) as f64 / timeline.frames as f64;
+        let color = if timeline.playing { Color::rgb8(0xff, 0xb0, 0x00) } else { Color::WHITE };
+        scene.fill(Fill::NonZero, Affine::IDENTITY, Color::rgba8(0, 0, 0, 0xc0), None, &Rect::new(0.0, top, width, height));
+        scene.fill(Fill::NonZero, Affine::IDENTITY, color, None, &Rect::new(0.0, top, done, track_bottom));
+
+        let Some(text) = &self.text else {
+            return;
+        };
+        let label = format!(
+            "{}/{}   {} / {}",
+            timeline.frame + 1,
+            timeline.frames,
+            format_time(timeline.time),
+            format_time(timeline.duration)
+        );
+        let font_size = (FONT_SIZE * context.scale_factor) as f32;
+        let (ascent, descent) = text.vertical_metrics(font_size);
+        // Centered in the space below the track
+        let baseline = (track_bottom + height + ascent - descent) / 2.0;
+        let origin = Affine::translate((PADDING * context.scale_factor, baseline));
+        text.draw(scene, &label, font_size, origin, Color::WHITE);
+    }
+}
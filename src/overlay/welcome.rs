@@ -0,0 +1,91 @@
+use vello::kurbo::{Affine, Rect};
+use vello::peniko::{Color, Fill};
+use vello::Scene;
+
+use super::{Overlay, OverlayContext};
+use crate::config;
+use crate::text::Text;
+
+/// Font size in logical pixels
+const FONT_SIZE: f64 = 15.0;
+/// Space around the text in logical pixels
+const PADDING: f64 = 14.0;
+/// The basic controls, with the key or gesture first
+const CONTROLS: [(&str, &str); 6] = [
+    ("Drag", "Move the image"),
+    ("Wheel", "Zoom"),
+    ("n / p", "Next / previous image"),
+    ("Arrows", "Move the image by a step"),
+    ("Ctrl+p", "Find any command"),
+    ("Esc", "Close"),
+];
+
+/// The hint shown on the first launch, a panel in the middle of the window with the basic controls
+pub struct Welcome {
+    text: Text,
+    lines: Vec<(String, String)>,
+}
+
+impl Welcome {
+    /// `None` if there is no font to draw the hint with
+    pub fn new() -> Option<Self> {
+        let mut lines: Vec<(String, String)> = vec![("Welcome to veh".to_string(), String::new())];
+        lines.extend(CONTROLS.iter().map(|(key, action)| (key.to_string(), action.to_string())));
+        if let Some(file) = config::file() {
+            lines.push(("Options".to_string(), file.display().to_string()));
+        }
+        lines.push(("Press any key or click to start".to_string(), String::new()));
+        Some(Self { text: Text::load()?, lines })
+    }
+}
+
+impl Overlay for Welcome {
+    fn name(&self) -> &'static str {
+        "welcome"
+    }
+
+    fn z_order(&self) -> i32 {
+        50
+    }
+
+    fn draw(&self, scene: &mut Scene, context: &OverlayContext) {
+        if !context.welcome {
+            return;
+        }
+        let font_size = (FONT_SIZE * context.scale_factor) as f32;
+        let padding = PADDING * context.scale_factor;
+        let (ascent, descent) = self.text.vertical_metrics(font_size);
+        let line_height = (ascent + descent) * 1.4;
+        // The actions line up after the widest key
+        let key_width = self
+            .lines
+            .iter()
+            .filter(|(_, action)| !action.is_empty())
+            .map(|(key, _)| self.text.width(key, font_size))
+            .fold(0.0, f64::max)
+            + padding;
+        let line_width = |(key, action): &(String, String)| {
+            if action.is_empty() {
+                self.text.width(key, font_size)
+            } else {
+                key_width + self.text.width(action, font_size)
+            }
+        };
+        let width = self.lines.iter().map(line_width).fold(0.0, f64::max) + 2.0 * padding;
+        let height = line_height * self.lines.len() as f64 + 2.0 * padding;
+        let (window_width, window_height) = (context.window_size.width as f64, context.window_size.height as f64);
+        let left = ((window_width - width) / 2.0).max(0.0);
+        let top = ((window_height - height) / 2.0).max(0.0);
+
+        scene.fill(Fill::NonZero, Affine::IDENTITY, Color::rgba8(0, 0, 0, 0xe0), None, &Rect::new(left, top, left + width, top + height));
+        for (index, (key, action)) in self.lines.iter().enumerate() {
+            let baseline = top + padding + line_height * index as f64 + ascent;
+            if action.is_empty() {
+                self.text.draw(scene, key, font_size, Affine::translate((left + padding, baseline)), Color::WHITE);
+                continue;
+            }
+            self.text.draw(scene, key, font_size, Affine::translate((left + padding, baseline)), Color::rgb8(0xa0, 0xc0, 0xff));
+            self.text.draw(scene, action, font_size, Affine::translate((left + padding + key_width, baseline)), Color::WHITE);
+        }
+    }
+}
@@ -0,0 +1,187 @@
+//! The command palette of Ctrl+P: every command of the keyboard with its key, found by typing a few letters of
+//! its name, so that commands can be run without knowing their keys. Letters match in order but not necessarily
+//! next to each other, e.g. `trnc` finds "Turn clockwise".
+
+use vello::kurbo::Vec2;
+
+use crate::action::Action;
+use crate::app::KeyIntent;
+use crate::{hdr, opacity};
+
+/// A command of the palette
+#[derive(Debug, Clone)]
+pub struct Command {
+    pub name: String,
+    /// The key that runs the command without the palette, if there is one
+    pub keys: &'static str,
+    pub intent: KeyIntent,
+}
+
+impl Command {
+    fn new(name: &str, keys: &'static str, intent: KeyIntent) -> Self {
+        Self { name: name.to_string(), keys, intent }
+    }
+}
+
+/// Every command of the keyboard available in a window, with the `--action` commands given as `actions`. Like
+/// their keys, navigation and file operations aren't available while `comparing`.
+pub fn commands(actions: &[Option<Action>], comparing: bool) -> Vec<Command> {
+    let pan = |x: f64, y: f64| KeyIntent::Pan { direction: Vec2::new(x, y), fast: false };
+    let mut commands = vec![
+        Command::new("Next image", "n", KeyIntent::Navigate { forward: true }),
+        Command::new("Previous image", "p", KeyIntent::Navigate { forward: false }),
+        Command::new("First image", "g", KeyIntent::JumpToEnd { last: false }),
+        Command::new("Last image", "Shift+g", KeyIntent::JumpToEnd { last: true }),
+        Command::new("Go to image", "/", KeyIntent::OpenPrompt),
+        Command::new("Next page", "Page Down", KeyIntent::TurnPage { forward: true }),
+        Command::new("Previous page", "Page Up", KeyIntent::TurnPage { forward: false }),
+        Command::new("Move image up", "k", pan(0.0, -1.0)),
+        Command::new("Move image down", "j", pan(0.0, 1.0)),
+        Command::new("Move image left", "h", pan(-1.0, 0.0)),
+        Command::new("Move image right", "l", pan(1.0, 0.0)),
+        Command::new("Zoom in to the next integer scale", "+", KeyIntent::StepZoom { zoom_in: true }),
+        Command::new("Zoom out to the next integer scale", "-", KeyIntent::StepZoom { zoom_in: false }),
+        Command::new("Undo stretching", "=", KeyIntent::Unstretch),
+        Command::new("Lock zoom", "z", KeyIntent::ToggleZoomLock),
+        Command::new("Resize window to the image at 100%", "Shift+z", KeyIntent::FitWindowToImage),
+        Command::new("Enlarge small images", "e", KeyIntent::ToggleUpscale),
+        Command::new("Integer scaling", "i", KeyIntent::ToggleIntegerScaling),
+        Command::new("Image statistics", "Shift+i", KeyIntent::ToggleStats),
+        Command::new("Crop borders", "c", KeyIntent::Crop { save: false }),
+        Command::new("Save crop", "Shift+c", KeyIntent::Crop { save: true }),
+        Command::new("Turn clockwise", "r", KeyIntent::Rotate { clockwise: true }),
+        Command::new("Turn counter-clockwise", "Shift+r", KeyIntent::Rotate { clockwise: false }),
+        Command::new("Save turn to the file", "Ctrl+r", KeyIntent::SaveRotation),
+        Command::new("Brighten", "]", KeyIntent::Expose(hdr::EXPOSURE_STEP)),
+        Command::new("Darken", "[", KeyIntent::Expose(-hdr::EXPOSURE_STEP)),
+        Command::new("Auto-expose", "\\", KeyIntent::AutoExpose),
+        Command::new("Slideshow", "s", KeyIntent::ToggleSlideshow),
+        Command::new("Play or pause", "v", KeyIntent::TogglePlayback),
+        Command::new("Edit caption", "t", KeyIntent::EditCaption),
+        Command::new("Status bar", "b", KeyIntent::ToggleStatusBar),
+        Command::new("Alpha check", "a", KeyIntent::ToggleAlphaCheck),
+        Command::new("Texture preview: lit normal map, channels, off", "Shift+t", KeyIntent::CycleTexturePreview),
+        Command::new("Mark image", "m", KeyIntent::ToggleMark),
+        Command::new("Emit marked images", "Shift+m", KeyIntent::FlushMarks),
+        Command::new("Copy image", "Ctrl+c", KeyIntent::Copy { path: false }),
+        Command::new("Copy path", "Ctrl+Shift+c", KeyIntent::Copy { path: true }),
+        Command::new("Paste", "Ctrl+v", KeyIntent::Paste),
+        Command::new("Move to trash", "Delete", KeyIntent::Trash),
+        Command::new("Move to --move-to directory", "x", KeyIntent::MoveAway),
+        Command::new("Undo trashing or moving", "u", KeyIntent::Undo),
+        Command::new("Open in new window", "o", KeyIntent::OpenWindow),
+        Command::new("More transparent", ",", KeyIntent::ChangeOpacity(-opacity::STEP)),
+        Command::new("More opaque", ".", KeyIntent::ChangeOpacity(opacity::STEP)),
+        Command::new("Switch compare mode", "w", KeyIntent::ToggleCompareMode),
+        Command::new("Capture frame", "F12", KeyIntent::CaptureFrame),
+        Command::new("Close window", "Esc", KeyIntent::Close),
+    ];
+    if cfg!(feature = "faces") {
+        commands.push(Command::new("Next face", "f", KeyIntent::NextFace));
+    }
+    const ACTION_KEYS: [&str; 10] = ["Enter", "1", "2", "3", "4", "5", "6", "7", "8", "9"];
+    for (index, action) in actions.iter().enumerate() {
+        if let Some(action) = action {
+            commands.push(Command::new(&format!("Run {}", action.command()), ACTION_KEYS[index], KeyIntent::RunAction(index)));
+        }
+    }
+    commands.retain(|command| match command.intent {
+        KeyIntent::ToggleCompareMode => comparing,
+        KeyIntent::Navigate { .. }
+        | KeyIntent::JumpToEnd { .. }
+        | KeyIntent::OpenPrompt
+        | KeyIntent::EditCaption
+        | KeyIntent::Trash
+        | KeyIntent::MoveAway
+        | KeyIntent::Copy { .. }
+        | KeyIntent::Paste
+        | KeyIntent::SaveRotation
+        | KeyIntent::FitWindowToImage
+        | KeyIntent::TogglePlayback
+        | KeyIntent::ToggleSlideshow
+        | KeyIntent::NextFace
+        | KeyIntent::ToggleAlphaCheck => !comparing,
+        _ => true,
+    });
+    commands
+}
+
+/// The palette while it is open
+pub struct Palette {
+    pub query: String,
+    commands: Vec<Command>,
+    /// The indices of the commands matching the query, best match first
+    matches: Vec<usize>,
+    /// The index into the matches of the command Enter runs
+    pub selected: usize,
+}
+
+impl Palette {
+    pub fn new(commands: Vec<Command>) -> Self {
+        let mut palette = Self { query: String::new(), commands, matches: vec![], selected: 0 };
+        palette.update();
+        palette
+    }
+
+    /// Type `text` into the query, or with `None` remove its last character
+    pub fn edit(&mut self, text: Option<&str>) {
+        match text {
+            Some(text) => self.query.extend(text.chars().filter(|c| !c.is_control())),
+            None => drop(self.query.pop()),
+        }
+        self.update();
+    }
+
+    /// Select the next matching command, or the previous one if not `down`
+    pub fn select(&mut self, down: bool) {
+        let count = self.matches.len().max(1);
+        self.selected = if down { (self.selected + 1) % count } else { (self.selected + count - 1) % count };
+    }
+
+    /// The matching commands, best match first
+    pub fn matches(&self) -> impl Iterator<Item = &Command> {
+        self.matches.iter().map(|&index| &self.commands[index])
+    }
+
+    /// What the selected command asks for
+    pub fn chosen(&self) -> Option<KeyIntent> {
+        Some(self.commands[*self.matches.get(self.selected)?].intent)
+    }
+
+    fn update(&mut self) {
+        let mut scored: Vec<(i32, usize)> = self
+            .commands
+            .iter()
+            .enumerate()
+            .filter_map(|(index, command)| Some((score(&self.query, &command.name)?, index)))
+            .collect();
+        // Stable, so that equally good matches keep the order of the list
+        scored.sort_by_key(|(score, _)| -score);
+        self.matches = scored.into_iter().map(|(_, index)| index).collect();
+        self.selected = 0;
+    }
+}
+
+/// How well `query` matches `name`, if its letters are all in it in order. Letters at the start of words and
+/// right after the letter before them count most.
+fn score(query: &str, name: &str) -> Option<i32> {
+    let name: Vec<char> = name.chars().flat_map(char::to_lowercase).collect();
+    let mut score = 0;
+    let mut position = 0;
+    let mut last_match: Option<usize> = None;
+    for wanted in query.chars().flat_map(char::to_lowercase).filter(|c| !c.is_whitespace()) {
+        let found = position + name[position..].iter().position(|&c| c == wanted)?;
+        score += 1;
+        if found == 0 || !name[found - 1].is_alphanumeric() {
+            score += 8;
+        }
+        if last_match.is_some_and(|last| last + 1 == found) {
+            score += 4;
+        }
+        // Letters far apart match less well
+        score -= (found - position).min(8) as i32 / 2;
+        last_match = Some(found);
+        position = found + 1;
+    }
+    Some(score)
+}
@@ -0,0 +1,45 @@
+//! PDF pages rendered with pdfium, which has to be installed as a shared library (or placed next to
+//! the veh executable).
+
+use std::cell::OnceCell;
+use std::path::Path;
+use std::rc::Rc;
+
+use anyhow::{Context, Result};
+use image::DynamicImage;
+use pdfium_render::prelude::*;
+
+/// Pages are rendered at this many pixels per point, i.e. at 144 DPI, so that text stays legible when zoomed in
+const PIXELS_PER_POINT: f32 = 2.0;
+
+thread_local! {
+    // The bindings to pdfium can't be shared between threads, so every thread loads them once
+    static PDFIUM: OnceCell<Rc<Pdfium>> = const { OnceCell::new() };
+}
+
+fn pdfium() -> Result<Rc<Pdfium>> {
+    if let Some(pdfium) = PDFIUM.with(|pdfium| pdfium.get().cloned()) {
+        return Ok(pdfium);
+    }
+    let executable_dir = std::env::current_exe()?.parent().map(Path::to_path_buf).unwrap_or_default();
+    let bindings = Pdfium::bind_to_library(Pdfium::pdfium_platform_library_name_at_path(&executable_dir))
+        .or_else(|_| Pdfium::bind_to_system_library())
+        .context("failed to load the pdfium library, which is needed to view PDFs")?;
+    Ok(PDFIUM.with(|pdfium| pdfium.get_or_init(|| Rc::new(Pdfium::new(bindings))).clone()))
+}
+
+pub fn page_count(path: &Path) -> Result<usize> {
+    let pdfium = pdfium()?;
+    let document = pdfium.load_pdf_from_file(path, None).context("failed to open pdf")?;
+    Ok(document.pages().len() as usize)
+}
+
+/// Render the page with the 0-based index `page`
+pub fn render_page(path: &Path, page: usize) -> Result<DynamicImage> {
+    let pdfium = pdfium()?;
+    let document = pdfium.load_pdf_from_file(path, None).context("failed to open pdf")?;
+    let page = document.pages().get(page as u16).context("no such page")?;
+    let config = PdfRenderConfig::new().scale_page_by_factor(PIXELS_PER_POINT);
+    let bitmap = page.render_with_config(&config).context("failed to render page")?;
+    Ok(bitmap.as_image())
+}
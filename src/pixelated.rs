@@ -0,0 +1,43 @@
+//! Enlarging images pixel by pixel for `--integer-scaling`. Vello blends neighboring pixels when it draws an
+//! image larger than it is, which blurs pixel art and emulator screenshots, so at integer scales the image is
+//! enlarged here and drawn at 100% instead.
+
+use std::sync::Arc;
+
+use vello::peniko::{Blob, Format, Image};
+
+/// Enlarged images aren't wider or taller than this, so that they fit into the image atlas of the renderer
+const MAX_SIZE: f64 = 4096.0;
+
+/// How many times an image of `image_size` is enlarged to be shown at `scale`: the scale if it is an integer
+/// above 1 and the enlarged image isn't too large, and 1 otherwise
+pub fn factor(image_size: (f64, f64), scale: f64) -> u32 {
+    let factor = scale.round();
+    if (scale - factor).abs() > 1e-6 || factor < 2.0 {
+        return 1;
+    }
+    if image_size.0.max(image_size.1) * factor > MAX_SIZE {
+        return 1;
+    }
+    factor as u32
+}
+
+/// `image` with every pixel repeated `factor` times on both axes
+pub fn enlarge(image: &Image, factor: u32) -> Image {
+    let factor = factor as usize;
+    let data = image.data.data();
+    let row_bytes = image.width as usize * 4;
+    let mut enlarged = Vec::with_capacity(data.len() * factor * factor);
+    for row in data.chunks_exact(row_bytes) {
+        let start = enlarged.len();
+        for pixel in row.chunks_exact(4) {
+            for _ in 0..factor {
+                enlarged.extend_from_slice(pixel);
+            }
+        }
+        for _ in 1..factor {
+            enlarged.extend_from_within(start..start + row_bytes * factor);
+        }
+    }
+    Image::new(Blob::new(Arc::new(enlarged)), Format::Rgba8, image.width * factor as u32, image.height * factor as u32)
+}
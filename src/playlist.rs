@@ -0,0 +1,96 @@
+use std::collections::{BTreeSet, HashSet};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+
+/// The list of images given to veh, together with the marked ones.
+///
+/// Every window keeps its own position in the playlist, so entries are addressed by index.
+pub struct Playlist {
+    paths: Vec<PathBuf>,
+    // Indices into `paths`, kept ordered so the marked list is emitted in playlist order
+    marked: BTreeSet<usize>,
+    // Entries that failed to load, which navigation skips
+    broken: HashSet<usize>,
+    // Entries whose file was moved to the trash, also skipped until the trashing is undone
+    removed: HashSet<usize>,
+}
+
+impl Playlist {
+    pub fn new(paths: Vec<PathBuf>) -> Self {
+        Self { paths, marked: BTreeSet::new(), broken: HashSet::new(), removed: HashSet::new() }
+    }
+
+    pub fn len(&self) -> usize {
+        self.paths.len()
+    }
+
+    pub fn get(&self, index: usize) -> &Path {
+        &self.paths[index]
+    }
+
+    /// Add `path` to the end of the playlist
+    pub fn push(&mut self, path: PathBuf) {
+        self.paths.push(path);
+    }
+
+    /// The index after `index`, wrapping around at the end
+    pub fn next(&self, index: usize) -> usize {
+        (index + 1) % self.paths.len()
+    }
+
+    /// The index before `index`, wrapping around at the start
+    pub fn prev(&self, index: usize) -> usize {
+        (index + self.paths.len() - 1) % self.paths.len()
+    }
+
+    /// Whether the image at `index` can be shown, i.e. it is neither broken nor removed
+    pub fn is_available(&self, index: usize) -> bool {
+        !self.broken.contains(&index) && !self.removed.contains(&index)
+    }
+
+    /// Point the entry at `index` to the new location of its file
+    pub fn set_path(&mut self, index: usize, path: PathBuf) {
+        self.paths[index] = path;
+    }
+
+    /// Skip the entry at `index` from now on, as its file is gone
+    pub fn remove(&mut self, index: usize) {
+        self.removed.insert(index);
+    }
+
+    /// Bring back the entry at `index` after its file has been restored
+    pub fn restore(&mut self, index: usize) {
+        self.removed.remove(&index);
+    }
+
+    /// Remember that the image at `index` can't be loaded, so that it is skipped from now on
+    pub fn mark_broken(&mut self, index: usize) {
+        self.broken.insert(index);
+    }
+
+    pub fn is_marked(&self, index: usize) -> bool {
+        self.marked.contains(&index)
+    }
+
+    /// Toggle the mark of the image at `index` and return whether it is now marked
+    pub fn toggle_mark(&mut self, index: usize) -> bool {
+        if !self.marked.remove(&index) {
+            self.marked.insert(index);
+        }
+        self.is_marked(index)
+    }
+
+    /// Write the marked paths to `out`, one per line, and clear the marks so that
+    /// every path is emitted only once per session.
+    pub fn flush_marked(&mut self, out: &mut dyn Write) -> Result<()> {
+        // Trashed images are left out, there is nothing left to do with them
+        for &i in self.marked.iter().filter(|i| !self.removed.contains(i)) {
+            writeln!(out, "{}", self.paths[i].display())?;
+        }
+        out.flush()?;
+        self.marked.clear();
+        Ok(())
+    }
+}
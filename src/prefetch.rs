@@ -0,0 +1,123 @@
+//! Decoding the images next to the one shown in the background, so that going on to them is instant. Decoding
+//! runs on a pool of threads, which the decoders of EXR images also split their work over. `--threads` sizes the
+//! pool, by default to one thread per CPU core, and `--nice` runs it at a lower priority, so that veh stays out
+//! of the way of builds and renders on the same machine.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use vello::peniko::Image;
+use vello_svg::usvg;
+use winit::event_loop::EventLoopProxy;
+
+use crate::hdr::HdrImage;
+use crate::loader::{self, VehImage};
+use crate::UserEvent;
+
+/// An image decoded on a thread of the pool. Only the kinds of images that own their data can be sent between
+/// threads, so spreads, whose pages are shared with `Rc`, aren't prefetched.
+pub enum Decoded {
+    Image(Image),
+    Svg(Arc<usvg::Tree>),
+    Animation(Vec<(Image, Duration)>),
+    Hdr(Box<HdrImage>),
+}
+
+impl Decoded {
+    fn new(image: VehImage) -> Option<Self> {
+        match image {
+            VehImage::Image(image) => Some(Self::Image(image)),
+            VehImage::Svg(tree) => Some(Self::Svg(tree)),
+            VehImage::Animation(frames) => Some(Self::Animation(frames)),
+            VehImage::Hdr(image) => Some(Self::Hdr(image)),
+            VehImage::Spread(_) => None,
+        }
+    }
+
+    pub fn into_image(self) -> VehImage {
+        match self {
+            Self::Image(image) => VehImage::Image(image),
+            Self::Svg(tree) => VehImage::Svg(tree),
+            Self::Animation(frames) => VehImage::Animation(frames),
+            Self::Hdr(image) => VehImage::Hdr(image),
+        }
+    }
+}
+
+/// Size the pool of decoder threads to `threads`, and lower their priority by `nice` as the command of that
+/// name does
+pub fn configure_pool(threads: Option<u32>, nice: Option<i32>) -> Result<()> {
+    if nice.is_some() && !cfg!(target_os = "linux") {
+        bail!("--nice is only supported on Linux");
+    }
+    let mut builder = rayon::ThreadPoolBuilder::new().thread_name(|index| format!("veh-decoder-{index}"));
+    if let Some(threads) = threads {
+        builder = builder.num_threads(threads as usize);
+    }
+    if let Some(nice) = nice {
+        builder = builder.start_handler(move |_| lower_priority(nice));
+    }
+    builder.build_global().context("failed to start the decoder threads")
+}
+
+/// Lower the priority of the calling thread, and of the processes it starts, e.g. external decoders
+#[cfg(target_os = "linux")]
+fn lower_priority(nice: i32) {
+    // On Linux the priority is per thread, and the thread ID is the "process" to set it for
+    let thread = unsafe { libc::gettid() } as libc::id_t;
+    if unsafe { libc::setpriority(libc::PRIO_PROCESS, thread, nice) } != 0 {
+        eprintln!("veh: failed to lower the priority of a decoder thread: {}", std::io::Error::last_os_error());
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn lower_priority(_nice: i32) {}
+
+/// Decodes images on the pool and sends them to the event loop as [`UserEvent::Prefetched`]
+pub struct Prefetcher {
+    proxy: EventLoopProxy<UserEvent>,
+    // The images being decoded, and the number of the decode that is still wanted for each
+    pending: HashMap<PathBuf, u64>,
+    fetches: u64,
+}
+
+impl Prefetcher {
+    pub fn new(proxy: EventLoopProxy<UserEvent>) -> Self {
+        Self { proxy, pending: HashMap::new(), fetches: 0 }
+    }
+
+    /// Start decoding the image at `path`, unless that is underway already
+    pub fn fetch(&mut self, path: &Path) {
+        if self.pending.contains_key(path) {
+            return;
+        }
+        self.fetches += 1;
+        let fetch = self.fetches;
+        self.pending.insert(path.to_path_buf(), fetch);
+        let path = path.to_path_buf();
+        let proxy = self.proxy.clone();
+        rayon::spawn(move || {
+            // Images that fail are reported once they are shown
+            let image = loader::open_page(&path, 0).ok().and_then(Decoded::new);
+            let _ = proxy.send_event(UserEvent::Prefetched { path, fetch, image });
+        });
+    }
+
+    /// Note that the decode `fetch` of the image at `path` has finished. Returns whether its image is still
+    /// wanted, rather than of a version of the file that has been forgotten since.
+    pub fn finished(&mut self, path: &Path, fetch: u64) -> bool {
+        if self.pending.get(path) != Some(&fetch) {
+            return false;
+        }
+        self.pending.remove(path);
+        true
+    }
+
+    /// Throw away the image at `path` that is being decoded, e.g. because the file has changed
+    pub fn forget(&mut self, path: &Path) {
+        self.pending.remove(path);
+    }
+}
@@ -0,0 +1,408 @@
+//! Getting vello's output onto a window, whatever texture format its surface has.
+//!
+//! vello renders into an Rgba8Unorm texture holding sRGB encoded colors, and only knows how to copy that
+//! onto surfaces with a linear format. Surfaces with an sRGB format would encode the colors a second
+//! time, which washes them out, and vello panics on platforms that offer no linear format at all. So
+//! veh creates the surfaces and copies the colors onto them itself, decoding them for sRGB surfaces.
+//!
+//! Where a window can't have a surface at all, e.g. in virtual machines without GPU acceleration, the
+//! frames are rendered by vello on the CPU, read back and shown through softbuffer instead.
+
+use std::num::NonZeroU32;
+use std::sync::{mpsc, Arc};
+
+use anyhow::{anyhow, Context, Result};
+use vello::peniko::{Blob, Format, Image};
+use vello::util::{RenderContext, RenderSurface};
+use vello::{RenderParams, Renderer, Scene};
+use wgpu::{Device, Queue, SurfaceTexture, TextureFormat};
+use winit::dpi::PhysicalSize;
+use winit::window::Window;
+
+/// Where the frames of a window go
+pub enum Output<'s> {
+    /// A wgpu surface of the window
+    Gpu { surface: Box<RenderSurface<'s>>, presenter: Presenter },
+    /// A softbuffer surface, for windows without a wgpu surface
+    Software(SoftwareOutput),
+}
+
+impl Output<'_> {
+    /// The device the frames are rendered on
+    pub fn dev_id(&self) -> usize {
+        match self {
+            Output::Gpu { surface, .. } => surface.dev_id,
+            Output::Software(software) => software.dev_id,
+        }
+    }
+
+    /// The size of the frames in pixels
+    pub fn size(&self) -> (u32, u32) {
+        match self {
+            Output::Gpu { surface, .. } => (surface.config.width, surface.config.height),
+            Output::Software(software) => (software.width, software.height),
+        }
+    }
+
+    /// The configuration of the wgpu surface, if there is one
+    pub fn config(&self) -> Option<&wgpu::SurfaceConfiguration> {
+        match self {
+            Output::Gpu { surface, .. } => Some(&surface.config),
+            Output::Software(_) => None,
+        }
+    }
+
+    pub fn resize(&mut self, render_cx: &RenderContext, width: u32, height: u32) {
+        match self {
+            Output::Gpu { surface, .. } => render_cx.resize_surface(surface, width, height),
+            Output::Software(software) => (software.width, software.height) = (width, height),
+        }
+    }
+
+    /// Render `scene` with `renderer` and show it in the window, which is `window_size` big by now. Returns
+    /// `false` if the frame was dropped and should be drawn again, e.g. because the surface had to be configured
+    /// again after the window moved to another monitor.
+    pub fn present(
+        &mut self,
+        render_cx: &RenderContext,
+        renderer: &mut Renderer,
+        scene: &Scene,
+        params: &RenderParams,
+        window_size: PhysicalSize<u32>,
+    ) -> Result<bool> {
+        let device_handle = &render_cx.devices[self.dev_id()];
+        match self {
+            Output::Gpu { surface, presenter } => {
+                let surface_texture = match surface.surface.get_current_texture() {
+                    Ok(surface_texture) => surface_texture,
+                    Err(wgpu::SurfaceError::Outdated | wgpu::SurfaceError::Lost) => {
+                        render_cx.resize_surface(surface, window_size.width, window_size.height);
+                        return Ok(false);
+                    }
+                    Err(wgpu::SurfaceError::Timeout) => return Ok(false),
+                    Err(err) => return Err(anyhow!("failed to get surface texture: {err}")),
+                };
+                presenter
+                    .render(renderer, &device_handle.device, &device_handle.queue, scene, &surface_texture, params)
+                    .context("failed to render to surface")?;
+                // Queue the texture to be presented on the surface
+                surface_texture.present();
+            }
+            Output::Software(software) => software
+                .render(renderer, &device_handle.device, &device_handle.queue, scene, params)
+                .context("failed to render to surface")?,
+        }
+        Ok(true)
+    }
+}
+
+/// Create the output of `window`: a surface on a device of `render_cx`, or a softbuffer surface if the
+/// window can't have one
+pub async fn create_output(
+    render_cx: &mut RenderContext,
+    window: Arc<Window>,
+    width: u32,
+    height: u32,
+    present_mode: wgpu::PresentMode,
+) -> Result<Output<'static>> {
+    match create_surface(render_cx, window.clone(), width, height, present_mode).await {
+        Ok(surface) => {
+            let presenter = Presenter::new(&render_cx.devices[surface.dev_id].device, surface.format);
+            Ok(Output::Gpu { surface: Box::new(surface), presenter })
+        }
+        Err(err) => {
+            eprintln!("veh: {err:#}, rendering on the CPU instead");
+            Ok(Output::Software(SoftwareOutput::new(render_cx, window, width, height).await?))
+        }
+    }
+}
+
+/// Create a surface for `window` on a device of `render_cx`, in the best format the window offers
+pub async fn create_surface(
+    render_cx: &mut RenderContext,
+    window: Arc<Window>,
+    width: u32,
+    height: u32,
+    present_mode: wgpu::PresentMode,
+) -> Result<RenderSurface<'static>> {
+    let surface = render_cx.instance.create_surface(window).context("failed to create a surface for the window")?;
+    // The formats a surface supports depend on the adapter, which vello picks the same way
+    let adapter = wgpu::util::initialize_adapter_from_env_or_default(&render_cx.instance, Some(&surface))
+        .await
+        .context("no GPU adapter can draw to the window")?;
+    let formats = surface.get_capabilities(&adapter).formats;
+    let format = choose_format(&formats).context("the window supports no texture format")?;
+    let dev_id = render_cx.device(Some(&surface)).await.context("failed to create a GPU device")?;
+
+    let config = wgpu::SurfaceConfiguration {
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+        format,
+        width,
+        height,
+        present_mode,
+        desired_maximum_frame_latency: 2,
+        alpha_mode: wgpu::CompositeAlphaMode::Auto,
+        view_formats: vec![],
+    };
+    surface.configure(&render_cx.devices[dev_id].device, &config);
+    Ok(RenderSurface { surface, config, dev_id, format })
+}
+
+/// The format to configure a surface with: a linear 8-bit one if possible, as it takes vello's colors as
+/// they are, else an sRGB one, else whatever comes first
+fn choose_format(formats: &[TextureFormat]) -> Option<TextureFormat> {
+    const PREFERRED: [TextureFormat; 4] = [
+        TextureFormat::Rgba8Unorm,
+        TextureFormat::Bgra8Unorm,
+        TextureFormat::Rgba8UnormSrgb,
+        TextureFormat::Bgra8UnormSrgb,
+    ];
+    PREFERRED.into_iter().find(|format| formats.contains(format)).or_else(|| formats.first().copied())
+}
+
+/// The texture vello renders into, recreated when the size of the frames changes
+struct Target {
+    texture: wgpu::Texture,
+    view: wgpu::TextureView,
+}
+
+impl Target {
+    /// The target in `target` if it has the size of `params`, after creating it if not
+    fn ensure<'t>(target: &'t mut Option<Target>, device: &Device, params: &RenderParams) -> &'t Target {
+        let size = wgpu::Extent3d { width: params.width, height: params.height, depth_or_array_layers: 1 };
+        if target.as_ref().is_some_and(|target| target.texture.size() == size) {
+            return target.as_ref().unwrap();
+        }
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: None,
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            usage: wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_SRC,
+            format: TextureFormat::Rgba8Unorm,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        target.insert(Target { texture, view })
+    }
+}
+
+/// Copies frames rendered by vello onto a surface of one format
+pub struct Presenter {
+    bind_layout: wgpu::BindGroupLayout,
+    pipeline: wgpu::RenderPipeline,
+    target: Option<Target>,
+}
+
+impl Presenter {
+    pub fn new(device: &Device, format: TextureFormat) -> Self {
+        // The texture vello renders into holds sRGB encoded colors with separate alpha. Writing to an sRGB
+        // surface encodes the colors, so they are decoded first to end up the same.
+        let shader = format!(
+            r#"
+            const DECODE_SRGB: bool = {decode};
+
+            @vertex
+            fn vs_main(@builtin(vertex_index) ix: u32) -> @builtin(position) vec4<f32> {{
+                // A full screen quad in normalized device coordinates
+                var vertex = vec2(-1.0, 1.0);
+                switch ix {{
+                    case 1u: {{
+                        vertex = vec2(-1.0, -1.0);
+                    }}
+                    case 2u, 4u: {{
+                        vertex = vec2(1.0, -1.0);
+                    }}
+                    case 5u: {{
+                        vertex = vec2(1.0, 1.0);
+                    }}
+                    default: {{}}
+                }}
+                return vec4(vertex, 0.0, 1.0);
+            }}
+
+            @group(0) @binding(0)
+            var fine_output: texture_2d<f32>;
+
+            fn srgb_to_linear(color: vec3<f32>) -> vec3<f32> {{
+                return select(pow((color + 0.055) / 1.055, vec3(2.4)), color / 12.92, color <= vec3(0.04045));
+            }}
+
+            @fragment
+            fn fs_main(@builtin(position) pos: vec4<f32>) -> @location(0) vec4<f32> {{
+                let rgba_sep = textureLoad(fine_output, vec2<i32>(pos.xy), 0);
+                var rgb = rgba_sep.rgb;
+                if DECODE_SRGB {{
+                    rgb = srgb_to_linear(rgb);
+                }}
+                return vec4(rgb * rgba_sep.a, rgba_sep.a);
+            }}
+            "#,
+            decode = format.is_srgb(),
+        );
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("present shaders"),
+            source: wgpu::ShaderSource::Wgsl(shader.into()),
+        });
+        let bind_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: None,
+            entries: &[wgpu::BindGroupLayoutEntry {
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                binding: 0,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            }],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[&bind_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: None,
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState { module: &shader, entry_point: "vs_main", buffers: &[] },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+        Self { bind_layout, pipeline, target: None }
+    }
+
+    /// Render `scene` with `renderer` and copy it onto `surface_texture`
+    pub fn render(
+        &mut self,
+        renderer: &mut Renderer,
+        device: &Device,
+        queue: &Queue,
+        scene: &Scene,
+        surface_texture: &SurfaceTexture,
+        params: &RenderParams,
+    ) -> Result<()> {
+        let target = &Target::ensure(&mut self.target, device, params).view;
+        renderer.render_to_texture(device, queue, scene, target, params).map_err(|err| anyhow!("{err}"))?;
+
+        let surface_view = surface_texture.texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &self.bind_layout,
+            entries: &[wgpu::BindGroupEntry { binding: 0, resource: wgpu::BindingResource::TextureView(target) }],
+        });
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: None,
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &surface_view,
+                    resolve_target: None,
+                    ops: wgpu::Operations { load: wgpu::LoadOp::Clear(wgpu::Color::default()), store: wgpu::StoreOp::Store },
+                })],
+                depth_stencil_attachment: None,
+                occlusion_query_set: None,
+                timestamp_writes: None,
+            });
+            render_pass.set_pipeline(&self.pipeline);
+            render_pass.set_bind_group(0, &bind_group, &[]);
+            render_pass.draw(0..6, 0..1);
+        }
+        queue.submit(Some(encoder.finish()));
+        Ok(())
+    }
+}
+
+/// Shows frames rendered by vello through softbuffer, for windows without a wgpu surface
+pub struct SoftwareOutput {
+    surface: softbuffer::Surface<Arc<Window>, Arc<Window>>,
+    dev_id: usize,
+    width: u32,
+    height: u32,
+    target: Option<Target>,
+}
+
+impl SoftwareOutput {
+    async fn new(render_cx: &mut RenderContext, window: Arc<Window>, width: u32, height: u32) -> Result<Self> {
+        let context = softbuffer::Context::new(window.clone()).map_err(|err| anyhow!("failed to connect softbuffer to the display: {err}"))?;
+        let surface = softbuffer::Surface::new(&context, window).map_err(|err| anyhow!("failed to create a softbuffer surface: {err}"))?;
+        // Any device will do, even one without a surface, e.g. a software adapter such as llvmpipe
+        let dev_id = render_cx.device(None).await.context("failed to create a device to render with")?;
+        Ok(Self { surface, dev_id, width, height, target: None })
+    }
+
+    /// Render `scene` with `renderer`, read the frame back from the device and show it in the window
+    pub fn render(&mut self, renderer: &mut Renderer, device: &Device, queue: &Queue, scene: &Scene, params: &RenderParams) -> Result<()> {
+        let (Some(width), Some(height)) = (NonZeroU32::new(params.width), NonZeroU32::new(params.height)) else {
+            // Nothing to show in a minimized window
+            return Ok(());
+        };
+        let target = Target::ensure(&mut self.target, device, params);
+        renderer.render_to_texture(device, queue, scene, &target.view, params).map_err(|err| anyhow!("{err}"))?;
+        let data = read_back(device, queue, &target.texture)?;
+
+        self.surface.resize(width, height).map_err(|err| anyhow!("failed to resize the softbuffer surface: {err}"))?;
+        let mut frame = self.surface.buffer_mut().map_err(|err| anyhow!("failed to get a softbuffer buffer: {err}"))?;
+        for (row, pixels) in data.chunks_exact(params.width as usize * 4).zip(frame.chunks_exact_mut(params.width as usize)) {
+            // softbuffer takes pixels as 0RGB, the colors are sRGB encoded already
+            for (rgba, pixel) in row.chunks_exact(4).zip(pixels) {
+                *pixel = (rgba[0] as u32) << 16 | (rgba[1] as u32) << 8 | rgba[2] as u32;
+            }
+        }
+        frame.present().map_err(|err| anyhow!("failed to show the frame: {err}"))
+    }
+}
+
+/// Render `scene` off screen into an image of the size of `params`, e.g. a tile of an SVG for `--svg-tiles`
+pub fn render_to_image(renderer: &mut Renderer, device: &Device, queue: &Queue, scene: &Scene, params: &RenderParams) -> Result<Image> {
+    let mut target = None;
+    let target = Target::ensure(&mut target, device, params);
+    renderer.render_to_texture(device, queue, scene, &target.view, params).map_err(|err| anyhow!("{err}"))?;
+    let data = read_back(device, queue, &target.texture)?;
+    Ok(Image::new(Blob::new(Arc::new(data)), Format::Rgba8, params.width, params.height))
+}
+
+/// The pixels of `texture`, read back from the device as rows of 4 bytes per pixel
+fn read_back(device: &Device, queue: &Queue, texture: &wgpu::Texture) -> Result<Vec<u8>> {
+    let size = texture.size();
+    // Rows of a texture are copied to a buffer with a stride of a multiple of 256 bytes
+    let row_bytes = size.width * 4;
+    let stride = row_bytes.next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT);
+    let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: None,
+        size: stride as u64 * size.height as u64,
+        usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+    encoder.copy_texture_to_buffer(
+        texture.as_image_copy(),
+        wgpu::ImageCopyBuffer {
+            buffer: &buffer,
+            layout: wgpu::ImageDataLayout { offset: 0, bytes_per_row: Some(stride), rows_per_image: None },
+        },
+        size,
+    );
+    queue.submit(Some(encoder.finish()));
+    let (sender, receiver) = mpsc::channel();
+    buffer.slice(..).map_async(wgpu::MapMode::Read, move |mapped| {
+        let _ = sender.send(mapped);
+    });
+    device.poll(wgpu::Maintain::Wait);
+    receiver.recv().context("the frame was never read back")?.context("failed to read back the frame")?;
+
+    let data = buffer.slice(..).get_mapped_range();
+    Ok(data.chunks_exact(stride as usize).flat_map(|row| &row[..row_bytes as usize]).copied().collect())
+}
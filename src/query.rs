@@ -0,0 +1,281 @@
+//! The little expression language of `veh find --where`, e.g. `iso>1600 && width>4000`.
+//!
+//! Comparisons of a metadata field against a literal can be combined with `&&`, `||`, `!`
+//! and parentheses. Numeric literals may be written as fractions (`exposure<1/250`), text
+//! is compared case-insensitively and `~` tests whether a text field contains the literal.
+
+use anyhow::{bail, Context, Result};
+
+use crate::metadata::{Metadata, Value, FIELDS};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Contains,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Filter {
+    And(Box<Filter>, Box<Filter>),
+    Or(Box<Filter>, Box<Filter>),
+    Not(Box<Filter>),
+    Compare { field: String, op: Op, literal: String },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Op(Op),
+    And,
+    Or,
+    Not,
+    Open,
+    Close,
+}
+
+impl Filter {
+    pub fn parse(input: &str) -> Result<Filter> {
+        let tokens = tokenize(input)?;
+        let mut parser = Parser { tokens: &tokens, pos: 0 };
+        let filter = parser.or()?;
+        if parser.pos != tokens.len() {
+            bail!("unexpected {:?} in query", tokens[parser.pos]);
+        }
+        Ok(filter)
+    }
+
+    /// Whether the image described by `metadata` satisfies the filter.
+    /// Comparisons against fields the image doesn't have are false.
+    pub fn matches(&self, metadata: &Metadata) -> bool {
+        match self {
+            Filter::And(a, b) => a.matches(metadata) && b.matches(metadata),
+            Filter::Or(a, b) => a.matches(metadata) || b.matches(metadata),
+            Filter::Not(a) => !a.matches(metadata),
+            Filter::Compare { field, op, literal } => match metadata.get(field) {
+                Some(Value::Number(value)) => match parse_number(literal) {
+                    Some(literal) => compare(value.partial_cmp(&literal), *op, false),
+                    None => false,
+                },
+                Some(Value::Text(value)) => {
+                    let (value, literal) = (value.to_lowercase(), literal.to_lowercase());
+                    compare(Some(value.cmp(&literal)), *op, value.contains(&literal))
+                }
+                None => false,
+            },
+        }
+    }
+}
+
+fn compare(ordering: Option<std::cmp::Ordering>, op: Op, contains: bool) -> bool {
+    use std::cmp::Ordering::*;
+    let Some(ordering) = ordering else {
+        return false;
+    };
+    match op {
+        Op::Eq => ordering == Equal,
+        Op::Ne => ordering != Equal,
+        Op::Lt => ordering == Less,
+        Op::Le => ordering != Greater,
+        Op::Gt => ordering == Greater,
+        Op::Ge => ordering != Less,
+        Op::Contains => contains,
+    }
+}
+
+fn parse_number(literal: &str) -> Option<f64> {
+    match literal.split_once('/') {
+        Some((num, denom)) => Some(num.parse::<f64>().ok()? / denom.parse::<f64>().ok()?),
+        None => literal.parse().ok(),
+    }
+}
+
+// Longer symbols come first, so that `<=` isn't read as `<` followed by `=`
+const SYMBOLS: &[(&str, Token)] = &[
+    ("&&", Token::And),
+    ("||", Token::Or),
+    ("==", Token::Op(Op::Eq)),
+    ("!=", Token::Op(Op::Ne)),
+    ("<=", Token::Op(Op::Le)),
+    (">=", Token::Op(Op::Ge)),
+    ("=", Token::Op(Op::Eq)),
+    ("<", Token::Op(Op::Lt)),
+    (">", Token::Op(Op::Gt)),
+    ("~", Token::Op(Op::Contains)),
+    ("!", Token::Not),
+    ("(", Token::Open),
+    (")", Token::Close),
+];
+
+fn tokenize(input: &str) -> Result<Vec<Token>> {
+    let mut tokens = vec![];
+    let mut rest = input.trim_start();
+    while let Some(first) = rest.chars().next() {
+        if let Some((symbol, token)) = SYMBOLS.iter().find(|(symbol, _)| rest.starts_with(symbol)) {
+            tokens.push(token.clone());
+            rest = &rest[symbol.len()..];
+        } else if first == '"' || first == '\'' {
+            let quoted = &rest[1..];
+            let end = quoted.find(first).context("unterminated string in query")?;
+            tokens.push(Token::Word(quoted[..end].to_string()));
+            rest = &quoted[end + 1..];
+        } else {
+            let end = rest
+                .find(|c: char| c.is_whitespace() || "&|=!<>~()\"'".contains(c))
+                .unwrap_or(rest.len());
+            if end == 0 {
+                bail!("unexpected `{first}` in query");
+            }
+            tokens.push(Token::Word(rest[..end].to_string()));
+            rest = &rest[end..];
+        }
+        rest = rest.trim_start();
+    }
+    if tokens.is_empty() {
+        bail!("empty query");
+    }
+    Ok(tokens)
+}
+
+struct Parser<'t> {
+    tokens: &'t [Token],
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn bump(&mut self) -> Option<&Token> {
+        self.pos += 1;
+        self.tokens.get(self.pos - 1)
+    }
+
+    fn or(&mut self) -> Result<Filter> {
+        let mut filter = self.and()?;
+        while self.peek() == Some(&Token::Or) {
+            self.bump();
+            filter = Filter::Or(Box::new(filter), Box::new(self.and()?));
+        }
+        Ok(filter)
+    }
+
+    fn and(&mut self) -> Result<Filter> {
+        let mut filter = self.unary()?;
+        while self.peek() == Some(&Token::And) {
+            self.bump();
+            filter = Filter::And(Box::new(filter), Box::new(self.unary()?));
+        }
+        Ok(filter)
+    }
+
+    fn unary(&mut self) -> Result<Filter> {
+        match self.bump().cloned() {
+            Some(Token::Not) => Ok(Filter::Not(Box::new(self.unary()?))),
+            Some(Token::Open) => {
+                let filter = self.or()?;
+                if self.bump() != Some(&Token::Close) {
+                    bail!("missing ')' in query");
+                }
+                Ok(filter)
+            }
+            Some(Token::Word(field)) => {
+                let field = field.to_lowercase();
+                if !FIELDS.contains(&field.as_str()) {
+                    bail!("unknown field `{field}`, expected one of: {}", FIELDS.join(", "));
+                }
+                let Some(Token::Op(op)) = self.bump().cloned() else {
+                    bail!("expected a comparison after `{field}`");
+                };
+                let Some(Token::Word(literal)) = self.bump().cloned() else {
+                    bail!("expected a value to compare `{field}` with");
+                };
+                Ok(Filter::Compare { field, op, literal })
+            }
+            Some(token) => bail!("unexpected {token:?} in query"),
+            None => bail!("unexpected end of query"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn comparison(field: &str, op: Op, literal: &str) -> Filter {
+        Filter::Compare { field: field.to_string(), op, literal: literal.to_string() }
+    }
+
+    fn metadata() -> Metadata {
+        Metadata { format: "jpeg".to_string(), width: 6000, height: 4000, file_size: 8_000_000, exif: None }
+    }
+
+    fn matches(query: &str) -> bool {
+        Filter::parse(query).unwrap().matches(&metadata())
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        let width = comparison("width", Op::Gt, "1");
+        let height = comparison("height", Op::Gt, "2");
+        let format = comparison("format", Op::Eq, "png");
+        assert_eq!(
+            Filter::parse("width>1 || height>2 && format=png").unwrap(),
+            Filter::Or(
+                Box::new(width.clone()),
+                Box::new(Filter::And(Box::new(height.clone()), Box::new(format.clone()))),
+            ),
+        );
+        assert_eq!(
+            Filter::parse("(width>1 || height>2) && format=png").unwrap(),
+            Filter::And(Box::new(Filter::Or(Box::new(width), Box::new(height))), Box::new(format)),
+        );
+        assert!(matches("width>1 || height>2 && format=png"));
+        assert!(!matches("(width>1 || height>2) && format=png"));
+    }
+
+    #[test]
+    fn comparisons() {
+        let tokens: Vec<Token> = tokenize("== != <= >= = < > ~").unwrap();
+        let ops = [Op::Eq, Op::Ne, Op::Le, Op::Ge, Op::Eq, Op::Lt, Op::Gt, Op::Contains];
+        assert_eq!(tokens, ops.map(Token::Op));
+        assert!(matches("width>=6000 && width<=6000 && width==6000 && width!=4000"));
+        assert!(!matches("width<6000") && !matches("width>6000"));
+        assert!(matches("megapixels = 24 && aspect > 3/2.1"));
+        // Text is compared case-insensitively
+        assert!(matches("format = JPEG && format ~ 'PE' && !(format ~ png)"));
+    }
+
+    #[test]
+    fn missing_fields_match_nothing() {
+        assert!(!matches("iso > 0") && !matches("iso <= 0"));
+        assert!(matches("!(iso > 0)"));
+        // Text isn't a number
+        assert!(!matches("width = wide"));
+    }
+
+    #[test]
+    fn malformed_queries_are_refused() {
+        for query in [
+            "",
+            "   ",
+            "width",
+            "width >",
+            "width > > 1",
+            "size > 1",
+            "(width > 1",
+            "width > 1)",
+            "width > 1 &&",
+            "width > 1 height > 2",
+            "make = 'Canon",
+            "&& width > 1",
+        ] {
+            assert!(Filter::parse(query).is_err(), "{query:?}");
+        }
+    }
+}
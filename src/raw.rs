@@ -0,0 +1,177 @@
+//! Camera RAW files. They are shown by the JPEG preview the camera embeds in them, which is quick to
+//! decode and looks like the camera's own rendering. With `--raw-demosaic` they are developed from the
+//! sensor data by dcraw instead, which is slow but shows everything the sensor captured.
+
+use std::collections::HashSet;
+use std::io::Cursor;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use anyhow::{bail, Context, Result};
+use image::{DynamicImage, ImageFormat};
+
+use crate::color;
+
+/// Extensions of the RAW formats of the common camera makers
+pub const RAW_FORMATS: &[&str] = &[
+    "3fr", "arw", "cr2", "dng", "erf", "iiq", "kdc", "mos", "nef", "nrw", "orf", "pef", "raf", "rw2", "sr2", "srf", "srw",
+];
+
+/// Whether RAW files are developed with dcraw rather than shown by their preview
+static DEMOSAIC: AtomicBool = AtomicBool::new(false);
+
+pub fn set_demosaic(demosaic: bool) {
+    DEMOSAIC.store(demosaic, Ordering::Relaxed);
+}
+
+pub fn is_raw(format: &str) -> bool {
+    RAW_FORMATS.contains(&format)
+}
+
+/// Decode the RAW file at `path`: its embedded preview, or the developed sensor data with `--raw-demosaic`
+pub fn decode(path: &Path) -> Result<DynamicImage> {
+    if DEMOSAIC.load(Ordering::Relaxed) {
+        return demosaic(path);
+    }
+    let data = std::fs::read(path).context("open image failed")?;
+    // Some of the embedded JPEGs may be losslessly compressed sensor data, which isn't a preview
+    previews(&data)
+        .into_iter()
+        .find_map(|jpeg| color::decode(image::ImageReader::with_format(Cursor::new(jpeg), ImageFormat::Jpeg)).ok())
+        .context("no embedded preview found, --raw-demosaic develops the image instead")
+}
+
+/// The size of the image shown for the RAW file at `path`, without decoding it
+pub fn dimensions(path: &Path) -> Result<(u32, u32)> {
+    let data = std::fs::read(path).context("open image failed")?;
+    previews(&data)
+        .into_iter()
+        .find_map(|jpeg| image::ImageReader::with_format(Cursor::new(jpeg), ImageFormat::Jpeg).into_dimensions().ok())
+        .context("no embedded preview found")
+}
+
+fn demosaic(path: &Path) -> Result<DynamicImage> {
+    // dcraw writes the developed image to stdout as a PPM, with the white balance of the camera
+    let output = Command::new("dcraw")
+        .args(["-c", "-w"])
+        .arg(path)
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .context("failed to run dcraw, which is needed for --raw-demosaic")?;
+    if !output.status.success() {
+        bail!("dcraw failed to develop the image ({})", output.status);
+    }
+    image::load_from_memory_with_format(&output.stdout, ImageFormat::Pnm).context("decode image failed")
+}
+
+/// The JPEGs embedded in a RAW file, largest first
+fn previews(data: &[u8]) -> Vec<&[u8]> {
+    let mut previews = vec![];
+    if data.starts_with(b"FUJIFILMCCD-RAW") {
+        // RAF has a header of its own, with the offset and length of the preview at a fixed place
+        let word = |at: usize| data.get(at..at + 4).map(|bytes| u32::from_be_bytes(bytes.try_into().unwrap()) as usize);
+        if let (Some(offset), Some(length)) = (word(84), word(88)) {
+            previews.extend(data.get(offset..offset.saturating_add(length)));
+        }
+    } else {
+        // Most other formats are TIFF files
+        tiff_previews(data, &mut previews);
+    }
+    previews.retain(|jpeg| jpeg.starts_with(&[0xff, 0xd8]));
+    previews.sort_by_key(|jpeg| std::cmp::Reverse(jpeg.len()));
+    previews
+}
+
+/// Reads the numbers of a TIFF file in its byte order
+struct TiffReader<'a> {
+    data: &'a [u8],
+    little_endian: bool,
+}
+
+impl TiffReader<'_> {
+    fn u16(&self, at: usize) -> Option<u16> {
+        let bytes = self.data.get(at..at + 2)?.try_into().ok()?;
+        Some(if self.little_endian { u16::from_le_bytes(bytes) } else { u16::from_be_bytes(bytes) })
+    }
+
+    fn u32(&self, at: usize) -> Option<u32> {
+        let bytes = self.data.get(at..at + 4)?.try_into().ok()?;
+        Some(if self.little_endian { u32::from_le_bytes(bytes) } else { u32::from_be_bytes(bytes) })
+    }
+
+    /// The first value of the IFD entry at `entry`, if it is a number
+    fn value(&self, entry: usize) -> Option<u32> {
+        match self.u16(entry + 2)? {
+            3 => self.u16(entry + 8).map(u32::from),
+            4 | 13 => self.u32(entry + 8),
+            _ => None,
+        }
+    }
+
+    /// All values of the IFD entry at `entry`, which are stored elsewhere if they don't fit into the entry
+    fn offsets(&self, entry: usize) -> Vec<usize> {
+        let count = self.u32(entry + 4).unwrap_or(0) as usize;
+        let start = if count > 1 { self.u32(entry + 8).map_or(usize::MAX, |at| at as usize) } else { entry + 8 };
+        (0..count.min(64)).map_while(|i| self.u32(start.checked_add(4 * i)?)).map(|offset| offset as usize).collect()
+    }
+}
+
+/// Collect the JPEGs referenced by the IFDs of a TIFF-based RAW file into `previews`
+fn tiff_previews<'a>(data: &'a [u8], previews: &mut Vec<&'a [u8]>) {
+    const COMPRESSION: u16 = 0x103;
+    const STRIP_OFFSETS: u16 = 0x111;
+    const STRIP_BYTE_COUNTS: u16 = 0x117;
+    const SUB_IFDS: u16 = 0x14a;
+    const JPEG_OFFSET: u16 = 0x201;
+    const JPEG_LENGTH: u16 = 0x202;
+    const EXIF_IFD: u16 = 0x8769;
+
+    // The magic number after the byte order differs between makers, e.g. ORF and RW2 have their own
+    let little_endian = match data.get(..2) {
+        Some(b"II") => true,
+        Some(b"MM") => false,
+        _ => return,
+    };
+    let reader = TiffReader { data, little_endian };
+    let Some(first) = reader.u32(4) else {
+        return;
+    };
+    let mut pending = vec![first as usize];
+    let mut visited = HashSet::new();
+    while let Some(ifd) = pending.pop() {
+        // Guard against IFDs pointing at each other in broken files
+        if ifd == 0 || visited.len() > 64 || !visited.insert(ifd) {
+            continue;
+        }
+        let Some(count) = reader.u16(ifd) else {
+            continue;
+        };
+        let entries: Vec<(u16, usize)> =
+            (0..count as usize).map(|i| ifd + 2 + 12 * i).filter_map(|entry| Some((reader.u16(entry)?, entry))).collect();
+        let find = |tag: u16| entries.iter().find(|(found, _)| *found == tag).map(|(_, entry)| *entry);
+        let value = |tag: u16| find(tag).and_then(|entry| reader.value(entry));
+
+        let mut add = |offset: Option<u32>, length: Option<u32>| {
+            if let (Some(offset), Some(length)) = (offset, length) {
+                previews.extend(data.get(offset as usize..(offset as usize).saturating_add(length as usize)));
+            }
+        };
+        add(value(JPEG_OFFSET), value(JPEG_LENGTH));
+        // An image stored as a single JPEG compressed strip
+        let single_strip = find(STRIP_OFFSETS).is_some_and(|entry| reader.u32(entry + 4) == Some(1));
+        if matches!(value(COMPRESSION), Some(6 | 7)) && single_strip {
+            add(value(STRIP_OFFSETS), value(STRIP_BYTE_COUNTS));
+        }
+
+        for tag in [SUB_IFDS, EXIF_IFD] {
+            if let Some(entry) = find(tag) {
+                pending.extend(reader.offsets(entry));
+            }
+        }
+        if let Some(next) = reader.u32(ifd + 2 + 12 * count as usize) {
+            pending.push(next as usize);
+        }
+    }
+}
@@ -0,0 +1,66 @@
+//! Rendering ahead: the scenes of SVGs that are about to be drawn are encoded on the thread pool while the window
+//! goes on drawing the current frame, so that a continuous zoom with `--svg-rerender`, a slideshow or the playback
+//! of a sequence doesn't stall on encoding an SVG of many paths. Raster images are quick to encode and are drawn
+//! as they come.
+
+use std::collections::HashMap;
+use std::rc::{Rc, Weak};
+
+use vello::Scene;
+use winit::event_loop::EventLoopProxy;
+
+use crate::loader::VehImage;
+use crate::scenes::{self, Rendition, SceneCache};
+use crate::UserEvent;
+
+/// How far the zoom of a scene rendered ahead may be off from the zoom it is drawn at
+pub const TOLERANCE: f64 = 0.1;
+
+/// Encodes scenes on the pool and sends them to the event loop as [`UserEvent::RenderedAhead`]
+pub struct RenderAhead {
+    proxy: EventLoopProxy<UserEvent>,
+    next_id: u64,
+    // The scenes being encoded, by the ID they are sent back with
+    pending: HashMap<u64, (Weak<VehImage>, Rendition)>,
+}
+
+impl RenderAhead {
+    pub fn new(proxy: EventLoopProxy<UserEvent>) -> Self {
+        Self { proxy, next_id: 0, pending: HashMap::new() }
+    }
+
+    /// Start encoding the scene of `image` drawn as `rendition`, unless it isn't an SVG, a scene close enough is
+    /// cached, or another scene of it is being encoded already
+    pub fn encode(&mut self, image: &Rc<VehImage>, rendition: Rendition, scenes: &SceneCache) {
+        let VehImage::Svg(svg) = &**image else {
+            return;
+        };
+        let weak = Rc::downgrade(image);
+        if self.pending.values().any(|(pending, _)| pending.ptr_eq(&weak))
+            || scenes.contains(image, |cached| cached.is_close(&rendition, TOLERANCE))
+        {
+            return;
+        }
+        let id = self.next_id;
+        self.next_id += 1;
+        self.pending.insert(id, (weak, rendition));
+        let svg = svg.clone();
+        let proxy = self.proxy.clone();
+        rayon::spawn(move || {
+            let mut scene = Scene::new();
+            scenes::draw_svg(&mut scene, &svg, rendition.scale);
+            // The event loop is gone if veh is exiting
+            let _ = proxy.send_event(UserEvent::RenderedAhead { id, scene: Box::new(scene) });
+        });
+    }
+
+    /// Keep the scene sent back with `id` in `scenes`, unless its image has been dropped in the meantime
+    pub fn finished(&mut self, id: u64, scene: Scene, scenes: &mut SceneCache) {
+        let Some((image, rendition)) = self.pending.remove(&id) else {
+            return;
+        };
+        if let Some(image) = image.upgrade() {
+            scenes.insert(&image, rendition, scene);
+        }
+    }
+}
@@ -0,0 +1,309 @@
+//! Turning images by quarter turns with `r`, and saving the turn to the file with Ctrl+R so that other viewers show
+//! the image turned as well. Where the file has an EXIF orientation tag, as photos of cameras and phones do, only
+//! the tag is changed. Other PNGs, TIFFs, WebPs and JPEGs are encoded again with their pixels turned, keeping their
+//! color profile and metadata. That costs JPEGs some quality, and WebPs are saved losslessly, which may make them
+//! larger. Turns of multi-page TIFFs aren't saved, as only their first page could be.
+
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::sync::Arc;
+
+use anyhow::{bail, Context, Result};
+use image::codecs::jpeg::JpegEncoder;
+use image::codecs::png::PngEncoder;
+use image::codecs::tiff::TiffEncoder;
+use image::codecs::webp::WebPEncoder;
+use image::metadata::Orientation;
+use image::{DynamicImage, ImageDecoder, ImageEncoder, ImageReader};
+use vello::peniko::{Blob, Format, Image};
+
+use crate::errors::Unsupported;
+use crate::export::{self, Conflict};
+use crate::{containers, loader};
+
+/// The EXIF orientations of an image turned by 0, 1, 2 and 3 quarter turns clockwise
+const TURNS: [u16; 4] = [1, 6, 3, 8];
+/// The EXIF tag of the orientation
+const ORIENTATION: u16 = 0x0112;
+/// The quality JPEGs are encoded again with
+const JPEG_QUALITY: u8 = 95;
+
+/// `image` turned a quarter turn clockwise, or counter-clockwise
+pub fn rotate(image: &Image, clockwise: bool) -> Image {
+    let (width, height) = (image.width as usize, image.height as usize);
+    let pixels = image.data.data();
+    let mut rotated = Vec::with_capacity(pixels.len());
+    // The rows of the turned image are the columns of the original
+    for y in 0..width {
+        for x in 0..height {
+            let (source_x, source_y) = if clockwise { (y, height - 1 - x) } else { (width - 1 - y, x) };
+            let offset = (source_y * width + source_x) * 4;
+            rotated.extend_from_slice(&pixels[offset..offset + 4]);
+        }
+    }
+    Image::new(Blob::new(Arc::new(rotated)), Format::Rgba8, image.height, image.width)
+}
+
+/// How a turn was saved to a file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Saved {
+    /// Its orientation tag was changed
+    Tagged,
+    /// It was encoded again
+    Reencoded,
+}
+
+/// Whether saving a turn of the image at `path` costs quality, as it does for JPEGs without an orientation tag
+pub fn is_lossy(path: &Path) -> Result<bool> {
+    let format = loader::format_of(path).unwrap_or_default();
+    if !matches!(format.as_str(), "jpg" | "jpeg") {
+        return Ok(false);
+    }
+    let data = fs::read(path).context("open image failed")?;
+    Ok(turned_orientation(&data, &format, 0).is_none())
+}
+
+/// Save the image at `path` turned by `quarter_turns` clockwise
+pub fn save(path: &Path, quarter_turns: usize) -> Result<Saved> {
+    let format = loader::format_of(path).context("no format given")?;
+    // Only the first page would be turned, or kept at all if it is encoded again
+    if matches!(format.as_str(), "tiff" | "tif") && containers::tiff_page_count(path)? > 1 {
+        bail!(Unsupported("turns of multi-page images can't be saved".to_string()));
+    }
+    let mut data = fs::read(path).context("open image failed")?;
+    if let Some((tag, orientation)) = turned_orientation(&data, &format, quarter_turns) {
+        tag.write(&mut data, orientation);
+        if format == "png" {
+            update_exif_crc(&mut data);
+        }
+        export::save_with(path, Conflict::Overwrite, |writer| Ok(writer.write_all(&data)?))?;
+        return Ok(Saved::Tagged);
+    }
+    reencode(path, &format, quarter_turns)?;
+    Ok(Saved::Reencoded)
+}
+
+/// The orientation tag of the file `data` of `format` and the orientation it needs to show the image turned by
+/// `quarter_turns` more, if it has a tag that only turns the image. Mirrored images are encoded again instead.
+fn turned_orientation(data: &[u8], format: &str, quarter_turns: usize) -> Option<(Tag, u16)> {
+    let tag = Tag::find(data, exif_start(data, format)?)?;
+    let turns = TURNS.iter().position(|&orientation| orientation == tag.read(data))?;
+    Some((tag, TURNS[(turns + quarter_turns) % 4]))
+}
+
+fn reencode(path: &Path, format: &str, quarter_turns: usize) -> Result<()> {
+    if !matches!(format, "png" | "tiff" | "tif" | "webp" | "jpg" | "jpeg") {
+        bail!(Unsupported(format!("turning {format} images can't be saved")));
+    }
+    let reader = ImageReader::open(path).context("open image failed")?.with_guessed_format().context("open image failed")?;
+    let mut decoder = reader.into_decoder().context("decode image failed")?;
+    let profile = decoder.icc_profile().ok().flatten();
+    let mut exif = decoder.exif_metadata().ok().flatten();
+    let orientation = decoder.orientation().unwrap_or(Orientation::NoTransforms);
+    let mut image = DynamicImage::from_decoder(decoder).context("decode image failed")?;
+    image.apply_orientation(orientation);
+    let image = match quarter_turns % 4 {
+        1 => image.rotate90(),
+        2 => image.rotate180(),
+        3 => image.rotate270(),
+        _ => image,
+    };
+    // The pixels are upright now
+    if let Some(exif) = &mut exif {
+        if let Some(tag) = Tag::find(exif, 0) {
+            tag.write(exif, 1);
+        }
+    }
+    export::save_with(path, Conflict::Overwrite, |writer| match format {
+        "png" => encode(&image, PngEncoder::new(writer), profile, exif),
+        "tiff" | "tif" => encode(&image, TiffEncoder::new(writer), profile, exif),
+        "webp" => encode(&image, WebPEncoder::new_lossless(writer), profile, exif),
+        _ => encode(&image, JpegEncoder::new_with_quality(writer, JPEG_QUALITY), profile, exif),
+    })?;
+    Ok(())
+}
+
+fn encode(image: &DynamicImage, mut encoder: impl ImageEncoder, profile: Option<Vec<u8>>, exif: Option<Vec<u8>>) -> Result<()> {
+    // Not every encoder can store both
+    if let Some(profile) = profile {
+        let _ = encoder.set_icc_profile(profile);
+    }
+    if let Some(exif) = exif {
+        let _ = encoder.set_exif_metadata(exif);
+    }
+    image.write_with_encoder(encoder)?;
+    Ok(())
+}
+
+/// Where the EXIF metadata, which is laid out like a TIFF file, starts in the file `data` of `format`
+fn exif_start(data: &[u8], format: &str) -> Option<usize> {
+    match format {
+        "tiff" | "tif" => Some(0),
+        "jpg" | "jpeg" => {
+            // The metadata is in an APP1 segment before the image data
+            let mut position = 2;
+            while data.get(position) == Some(&0xff) {
+                let marker = *data.get(position + 1)?;
+                if marker == 0xda {
+                    return None;
+                }
+                let length = u16::from_be_bytes(data.get(position + 2..position + 4)?.try_into().ok()?) as usize;
+                if marker == 0xe1 && data.get(position + 4..)?.starts_with(b"Exif\0\0") {
+                    return Some(position + 10);
+                }
+                position += 2 + length;
+            }
+            None
+        }
+        "png" => png_exif_chunk(data).map(|(start, _)| start),
+        "webp" => {
+            let mut position = 12;
+            while let Some(header) = data.get(position..position + 8) {
+                let size = u32::from_le_bytes(header[4..].try_into().ok()?) as usize;
+                if &header[..4] == b"EXIF" {
+                    // Some writers put the prefix of JPEG segments in front
+                    let start = position + 8;
+                    return Some(if data.get(start..)?.starts_with(b"Exif\0\0") { start + 6 } else { start });
+                }
+                // Chunks are padded to an even size
+                position += 8 + size + (size & 1);
+            }
+            None
+        }
+        _ => None,
+    }
+}
+
+/// The start and length of the data of the eXIf chunk of the PNG file `data`, `None` if the chunk and its checksum
+/// run past the end of the file
+fn png_exif_chunk(data: &[u8]) -> Option<(usize, usize)> {
+    let mut position = 8;
+    while let Some(header) = data.get(position..position + 8) {
+        let length = u32::from_be_bytes(header[..4].try_into().ok()?) as usize;
+        match &header[4..] {
+            b"eXIf" => {
+                data.get(position + 8..position + 12 + length)?;
+                return Some((position + 8, length));
+            }
+            b"IEND" => return None,
+            _ => position += 12 + length,
+        }
+    }
+    None
+}
+
+/// Recompute the checksum of the eXIf chunk of the PNG file `data` once its orientation has changed
+fn update_exif_crc(data: &mut [u8]) {
+    let Some((start, length)) = png_exif_chunk(data) else {
+        return;
+    };
+    let crc = crc32(&data[start - 4..start + length]);
+    data[start + length..start + length + 4].copy_from_slice(&crc.to_be_bytes());
+}
+
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = u32::MAX;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ 0xedb8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+/// The value of the orientation tag in the first directory of EXIF metadata
+struct Tag {
+    offset: usize,
+    big_endian: bool,
+}
+
+impl Tag {
+    /// The orientation tag of the EXIF metadata starting at `start` of `data`, if it has one
+    fn find(data: &[u8], start: usize) -> Option<Self> {
+        let tiff = data.get(start..)?;
+        let big_endian = match tiff.get(..2)? {
+            b"MM" => true,
+            b"II" => false,
+            _ => return None,
+        };
+        let u16_at = |offset: usize| {
+            let bytes = tiff.get(offset..offset + 2)?.try_into().ok()?;
+            Some(if big_endian { u16::from_be_bytes(bytes) } else { u16::from_le_bytes(bytes) })
+        };
+        let bytes = tiff.get(4..8)?.try_into().ok()?;
+        let directory = if big_endian { u32::from_be_bytes(bytes) } else { u32::from_le_bytes(bytes) } as usize;
+        for entry in (0..u16_at(directory)? as usize).map(|index| directory + 2 + 12 * index) {
+            // The orientation is a single SHORT, stored in the entry itself, which may be cut short
+            if u16_at(entry)? == ORIENTATION && u16_at(entry + 2)? == 3 {
+                u16_at(entry + 8)?;
+                return Some(Self { offset: start + entry + 8, big_endian });
+            }
+        }
+        None
+    }
+
+    fn read(&self, data: &[u8]) -> u16 {
+        let bytes = [data[self.offset], data[self.offset + 1]];
+        if self.big_endian { u16::from_be_bytes(bytes) } else { u16::from_le_bytes(bytes) }
+    }
+
+    fn write(&self, data: &mut [u8], value: u16) {
+        let bytes = if self.big_endian { value.to_be_bytes() } else { value.to_le_bytes() };
+        data[self.offset..self.offset + 2].copy_from_slice(&bytes);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A little-endian TIFF header and a directory with a single orientation entry of `orientation`
+    fn tiff(orientation: u16) -> Vec<u8> {
+        let mut data = b"II*\0".to_vec();
+        data.extend(8u32.to_le_bytes());
+        data.extend(1u16.to_le_bytes());
+        data.extend(ORIENTATION.to_le_bytes());
+        data.extend(3u16.to_le_bytes());
+        data.extend(1u32.to_le_bytes());
+        data.extend(orientation.to_le_bytes());
+        data.extend([0, 0]);
+        data
+    }
+
+    #[test]
+    fn orientation_is_turned() {
+        let mut data = tiff(6);
+        let (tag, orientation) = turned_orientation(&data, "tiff", 1).unwrap();
+        assert_eq!(orientation, 3);
+        tag.write(&mut data, orientation);
+        assert_eq!(Tag::find(&data, 0).unwrap().read(&data), 3);
+    }
+
+    #[test]
+    fn multi_page_images_are_left_alone() {
+        let dir = std::env::temp_dir().join(format!("veh-rotate-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("pages.tiff");
+        let mut encoder = tiff::encoder::TiffEncoder::new(fs::File::create(&path).unwrap()).unwrap();
+        for value in [0u8, 255] {
+            encoder.write_image::<tiff::encoder::colortype::Gray8>(2, 1, &[value, value]).unwrap();
+        }
+        drop(encoder);
+
+        assert!(save(&path, 1).is_err());
+        assert_eq!(containers::tiff_page_count(&path).unwrap(), 2);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn truncated_directories_have_no_tag() {
+        let data = tiff(6);
+        // Cut anywhere before the end of the value, including right after the type of the entry
+        for length in 0..data.len() - 2 {
+            assert!(Tag::find(&data[..length], 0).is_none(), "{length} bytes");
+            assert!(turned_orientation(&data[..length], "tiff", 1).is_none(), "{length} bytes");
+        }
+    }
+}
@@ -0,0 +1,90 @@
+//! Encoded scenes of recently shown images, shared by all windows. Encoding a large SVG walks thousands of paths,
+//! so an image is only encoded again when it is reloaded or drawn differently, e.g. with another exposure, and not
+//! when it is shown again or in another window.
+
+use std::collections::VecDeque;
+use std::rc::{Rc, Weak};
+
+use vello::Scene;
+use vello_svg::usvg;
+
+use crate::hdr;
+use crate::loader::VehImage;
+
+/// How many scenes are kept around, the most recently used ones
+const CAPACITY: usize = 16;
+
+/// How far the zoom may change before an SVG is rendered again with `--svg-rerender`, relative to the zoom it was
+/// rendered at
+pub const RERENDER_THRESHOLD: f64 = 0.25;
+
+/// How an image is drawn into its scene
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rendition {
+    pub hdr_view: hdr::View,
+    /// The zoom an SVG is rendered at, or an image enlarged to pixel by pixel, 1 otherwise
+    pub scale: f64,
+    /// The ID of the color profile of the monitor raster images are converted to, if it isn't sRGB
+    pub display: Option<u64>,
+}
+
+#[derive(Default)]
+pub struct SceneCache {
+    // Least recently used first. Scenes of images that are no longer loaded are dropped along with them.
+    scenes: VecDeque<(Weak<VehImage>, Rendition, Rc<Scene>)>,
+}
+
+impl Rendition {
+    /// Whether a scene drawn as `self` can stand in for one drawn as `other`, with a zoom off by at most `tolerance`
+    pub fn is_close(&self, other: &Rendition, tolerance: f64) -> bool {
+        self.hdr_view == other.hdr_view && self.display == other.display && (self.scale / other.scale - 1.0).abs() <= tolerance
+    }
+}
+
+impl SceneCache {
+    /// The scene of `image` drawn as `rendition`, encoded by `encode` unless it is cached
+    pub fn get(&mut self, image: &Rc<VehImage>, rendition: Rendition, encode: impl FnOnce(&mut Scene)) -> Rc<Scene> {
+        if let Some((_, scene)) = self.find(image, |cached| *cached == rendition) {
+            return scene;
+        }
+        let mut scene = Scene::new();
+        encode(&mut scene);
+        self.insert(image, rendition, scene)
+    }
+
+    /// A cached scene of `image` whose rendition `matches`, and how it is drawn
+    pub fn find(&mut self, image: &Rc<VehImage>, matches: impl Fn(&Rendition) -> bool) -> Option<(Rendition, Rc<Scene>)> {
+        let index = self.position(image, matches)?;
+        let entry = self.scenes.remove(index)?;
+        let found = (entry.1, entry.2.clone());
+        self.scenes.push_back(entry);
+        Some(found)
+    }
+
+    /// Whether a scene of `image` whose rendition `matches` is cached
+    pub fn contains(&self, image: &Rc<VehImage>, matches: impl Fn(&Rendition) -> bool) -> bool {
+        self.position(image, matches).is_some()
+    }
+
+    /// Keep `scene`, `image` drawn as `rendition`
+    pub fn insert(&mut self, image: &Rc<VehImage>, rendition: Rendition, scene: Scene) -> Rc<Scene> {
+        self.scenes.retain(|(cached, ..)| cached.strong_count() > 0);
+        let scene = Rc::new(scene);
+        if self.scenes.len() >= CAPACITY {
+            self.scenes.pop_front();
+        }
+        self.scenes.push_back((Rc::downgrade(image), rendition, scene.clone()));
+        scene
+    }
+
+    fn position(&self, image: &Rc<VehImage>, matches: impl Fn(&Rendition) -> bool) -> Option<usize> {
+        let image = Rc::downgrade(image);
+        self.scenes.iter().position(|(cached, rendition, _)| cached.ptr_eq(&image) && matches(rendition))
+    }
+}
+
+/// Draw `svg` into `scene` at `scale`, with its geometry scaled rather than the scene
+pub fn draw_svg(scene: &mut Scene, svg: &usvg::Tree, scale: f64) {
+    let transform = usvg::Transform::from_scale(scale as f32, scale as f32);
+    let Ok(()) = vello_svg::render_tree_with(scene, svg, &transform, &mut vello_svg::default_error_handler);
+}
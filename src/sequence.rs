@@ -0,0 +1,124 @@
+//! Numbered image sequences, such as the frames `frame_0001.png`, `frame_0002.png`, ... of a render,
+//! which can be played back like a video. With `--timelapse`, the whole list is one sequence, ordered by when
+//! the images were taken, and frames that can't be decoded in time are skipped to keep the playback real-time.
+
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// Fewer numbered images than this are not taken for a sequence
+const MIN_FRAMES: usize = 3;
+
+/// What a frame of a sequence is recognized by: its directory, the parts of its name before and after its
+/// frame number, and the frame number
+fn frame_number(path: &Path) -> Option<((PathBuf, String, String), u64)> {
+    let name = path.file_name()?.to_str()?;
+    let stem_len = path.file_stem()?.to_str()?.len();
+    // The last run of digits in the file stem, e.g. 0001 in shot2_0001.png
+    let end = name[..stem_len].rfind(|c: char| c.is_ascii_digit())? + 1;
+    let start = name[..end].rfind(|c: char| !c.is_ascii_digit()).map_or(0, |i| i + 1);
+    let number = name[start..end].parse().ok()?;
+    let key = (path.parent()?.to_path_buf(), name[..start].to_string(), name[end..].to_string());
+    Some((key, number))
+}
+
+/// The runs of consecutive entries of `paths` that are frames of one sequence, numbered in increasing order
+pub fn find_sequences(paths: &[PathBuf]) -> Vec<Range<usize>> {
+    let mut sequences = vec![];
+    let mut start = 0;
+    for end in 1..=paths.len() {
+        let continues = end < paths.len()
+            && match (frame_number(&paths[end - 1]), frame_number(&paths[end])) {
+                (Some((key, number)), Some((next_key, next_number))) => key == next_key && next_number > number,
+                _ => false,
+            };
+        if continues {
+            continue;
+        }
+        if end - start >= MIN_FRAMES && frame_number(&paths[start]).is_some() {
+            sequences.push(start..end);
+        }
+        start = end;
+    }
+    sequences
+}
+
+/// The sequence among `sequences` that the entry at `index` is a frame of
+pub fn containing(sequences: &[Range<usize>], index: usize) -> Option<Range<usize>> {
+    sequences.iter().find(|sequence| sequence.contains(&index)).cloned()
+}
+
+/// The playback of a sequence, or of the frames of an animated image, in a window
+pub struct Playback {
+    pub frames: Range<usize>,
+    /// When the next frame is due
+    pub next_frame_at: Instant,
+    /// Whether frames whose time has passed are skipped, rather than the playback slowing down
+    pub catch_up: bool,
+}
+
+impl Playback {
+    pub fn new(frames: Range<usize>, interval: Duration) -> Self {
+        Self { frames, next_frame_at: Instant::now() + interval, catch_up: false }
+    }
+
+    /// The frame after `index`, starting over at the end
+    pub fn next(&self, index: usize) -> usize {
+        if index + 1 < self.frames.end {
+            index + 1
+        } else {
+            self.frames.start
+        }
+    }
+
+    /// The frame after `index`, which is shown for `interval`, and schedule the one after it
+    pub fn advance(&mut self, index: usize, interval: Duration) -> usize {
+        let now = Instant::now();
+        if self.catch_up {
+            // The frames that were due while the last one was decoded are left out
+            let late = now.saturating_duration_since(self.next_frame_at);
+            // Skipping more than all frames would come back to the same one
+            let skipped = (late.as_secs_f64() / interval.as_secs_f64()).min(self.frames.len() as f64) as usize;
+            if skipped < self.frames.len() {
+                self.next_frame_at += interval * (skipped + 1) as u32;
+            } else {
+                // After a stall of a whole loop or more, e.g. while the system was suspended, the schedule starts
+                // over rather than playing the frames it is behind on back to back
+                self.next_frame_at = now + interval;
+            }
+            return (0..=skipped).fold(index, |index, _| self.next(index));
+        }
+        // If decoding fell behind, the schedule starts over rather than rushing through frames to catch up
+        self.next_frame_at = Some(self.next_frame_at + interval).filter(|next| *next > now).unwrap_or(now + interval);
+        self.next(index)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INTERVAL: Duration = Duration::from_secs(1);
+
+    fn late_playback(late: Duration) -> Playback {
+        let next_frame_at = Instant::now().checked_sub(late).unwrap();
+        Playback { frames: 0..4, next_frame_at, catch_up: true }
+    }
+
+    #[test]
+    fn late_frames_are_skipped() {
+        let mut playback = late_playback(INTERVAL * 2 + INTERVAL / 2);
+        assert_eq!(playback.advance(0, INTERVAL), 3);
+        // The frame after that is due half an interval from now
+        assert!(playback.next_frame_at > Instant::now());
+        assert!(playback.next_frame_at <= Instant::now() + INTERVAL);
+    }
+
+    #[test]
+    fn long_stalls_start_the_schedule_over() {
+        let mut playback = late_playback(INTERVAL * 10);
+        playback.advance(0, INTERVAL);
+        assert!(playback.next_frame_at > Instant::now());
+    }
+}
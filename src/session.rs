@@ -0,0 +1,198 @@
+//! The state of the last session, restored on the next launch unless `--fresh` is given: the size and position of
+//! the window, the image last viewed, and the zoom and pan of the images that were zoomed or panned by hand.
+//! With several veh processes, the last one closed decides the window and image, and the views of all are kept.
+
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use serde_json::{json, Map, Value};
+use vello::kurbo::Affine;
+use winit::window::Window;
+
+use crate::state;
+
+/// How many images the zoom and pan is remembered for, the most recently viewed ones
+const MAX_VIEWS: usize = 500;
+
+/// `path` made absolute, so that it is recognized when veh is started from another directory. Images inside
+/// archives can't be resolved and are kept as they are.
+fn absolute(path: &Path) -> PathBuf {
+    std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// The size and position of a window in physical pixels. Wayland doesn't tell windows where they are.
+#[derive(Debug, Clone, Copy)]
+pub struct Geometry {
+    pub position: Option<(i32, i32)>,
+    pub size: (u32, u32),
+}
+
+#[derive(Default)]
+pub struct Session {
+    pub window: Option<Geometry>,
+    last_file: Option<PathBuf>,
+    /// The transforms of images that were zoomed or panned, the most recently viewed last
+    views: Vec<(PathBuf, Affine)>,
+    /// The images whose view was recorded in this session, which take the place of those saved by other processes
+    recorded: HashSet<PathBuf>,
+}
+
+impl Session {
+    /// The state of the last session, none if there was none
+    pub fn load() -> Self {
+        state::read("session.json").and_then(Self::from_state).unwrap_or_default()
+    }
+
+    /// The session in the contents of `session.json` with the layout of `version`, none if that is of a newer veh.
+    /// The layout hasn't changed since before versions were kept.
+    fn from_state((value, version): (Value, u64)) -> Option<Self> {
+        (version <= state::VERSION).then(|| Self::from_json(&value))
+    }
+
+    fn from_json(value: &Value) -> Self {
+        let window = value.get("window").and_then(|window| {
+            let size = (window.get("width")?.as_u64()? as u32, window.get("height")?.as_u64()? as u32);
+            let position = window.get("x").and_then(Value::as_i64).zip(window.get("y").and_then(Value::as_i64));
+            let position = position.map(|(x, y)| (x as i32, y as i32));
+            Some(Geometry { position, size })
+        });
+        let last_file = value.get("last_file").and_then(Value::as_str).map(PathBuf::from);
+        let views = value
+            .get("views")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+            .filter_map(|view| {
+                let path = PathBuf::from(view.get("path")?.as_str()?);
+                let coeffs: Vec<f64> = view.get("transform")?.as_array()?.iter().filter_map(Value::as_f64).collect();
+                Some((path, Affine::new(coeffs.try_into().ok()?)))
+            })
+            .collect();
+        Self { window, last_file, views, recorded: HashSet::new() }
+    }
+
+    pub fn save(&self) -> Result<()> {
+        state::update("session.json", |current| self.to_json(current.and_then(Self::from_state)))
+    }
+
+    /// The session as JSON, with the views of the `saved` session that weren't recorded in this one
+    fn to_json(&self, saved: Option<Session>) -> Map<String, Value> {
+        let mut views = saved.map(|saved| saved.views).unwrap_or_default();
+        views.retain(|(path, _)| !self.recorded.contains(path));
+        views.extend(self.views.iter().filter(|(path, _)| self.recorded.contains(path)).cloned());
+        views.drain(..views.len().saturating_sub(MAX_VIEWS));
+
+        let window = self.window.map(|window| {
+            json!({
+                "x": window.position.map(|(x, _)| x),
+                "y": window.position.map(|(_, y)| y),
+                "width": window.size.0,
+                "height": window.size.1,
+            })
+        });
+        let views: Vec<Value> = views
+            .iter()
+            .map(|(path, transform)| json!({ "path": path, "transform": transform.as_coeffs() }))
+            .collect();
+        let mut contents = Map::new();
+        contents.insert("window".to_string(), json!(window));
+        contents.insert("last_file".to_string(), json!(self.last_file));
+        contents.insert("views".to_string(), views.into());
+        contents
+    }
+
+    /// Remember the size and position of `window`
+    pub fn record_window(&mut self, window: &Window) {
+        let size = window.inner_size();
+        let position = window.outer_position().ok().map(|position| (position.x, position.y));
+        self.window = Some(Geometry { position, size: (size.width, size.height) });
+    }
+
+    pub fn record_last_file(&mut self, path: &Path) {
+        self.last_file = Some(absolute(path));
+    }
+
+    /// Remember how the image at `path` is zoomed and panned, or forget it if the image is fit into the window
+    pub fn record_view(&mut self, path: &Path, transform: Option<Affine>) {
+        let path = absolute(path);
+        self.views.retain(|(viewed, _)| *viewed != path);
+        if let Some(transform) = transform {
+            self.views.push((path.clone(), transform));
+        }
+        self.recorded.insert(path);
+        if self.views.len() > MAX_VIEWS {
+            self.views.remove(0);
+        }
+    }
+
+    /// How the image at `path` was zoomed and panned when it was last viewed, if it wasn't fit into the window
+    pub fn view(&self, path: &Path) -> Option<Affine> {
+        let path = absolute(path);
+        self.views.iter().find(|(viewed, _)| *viewed == path).map(|(_, transform)| *transform)
+    }
+
+    /// The index into `paths` of the image last viewed, 0 if it isn't among them
+    pub fn start_index(&self, paths: &[PathBuf]) -> usize {
+        let Some(last_file) = &self.last_file else {
+            return 0;
+        };
+        // Comparing file names first saves resolving every path
+        paths
+            .iter()
+            .position(|path| path.file_name() == last_file.file_name() && absolute(path) == *last_file)
+            .unwrap_or(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn view(path: &str, zoom: f64) -> (PathBuf, Affine) {
+        (PathBuf::from(path), Affine::scale(zoom))
+    }
+
+    fn views(contents: Map<String, Value>) -> Vec<(PathBuf, Affine)> {
+        Session::from_json(&Value::Object(contents)).views
+    }
+
+    #[test]
+    fn views_of_other_processes_are_kept() {
+        let saved = Session { views: vec![view("/a", 2.0), view("/b", 2.0), view("/c", 2.0)], ..Session::default() };
+        // This process zoomed b differently, fit c into the window and zoomed d
+        let session = Session {
+            views: vec![view("/b", 3.0), view("/d", 3.0)],
+            recorded: ["/b", "/c", "/d"].into_iter().map(PathBuf::from).collect(),
+            ..Session::default()
+        };
+        assert_eq!(views(session.to_json(Some(saved))), [view("/a", 2.0), view("/b", 3.0), view("/d", 3.0)]);
+    }
+
+    #[test]
+    fn views_not_recorded_are_left_to_the_file() {
+        // Views loaded from the file and not recorded again are left to the saved file
+        let session = Session { views: vec![view("/a", 2.0)], ..Session::default() };
+        assert_eq!(views(session.to_json(None)), []);
+    }
+
+    #[test]
+    fn the_oldest_views_are_dropped() {
+        let saved = (0..MAX_VIEWS).map(|index| view(&format!("/{index}"), 2.0)).collect();
+        let saved = Session { views: saved, ..Session::default() };
+        let recorded = HashSet::from([PathBuf::from("/new")]);
+        let session = Session { views: vec![view("/new", 3.0)], recorded, ..Session::default() };
+        let views = views(session.to_json(Some(saved)));
+        assert_eq!(views.len(), MAX_VIEWS);
+        assert_eq!(views.first(), Some(&view("/1", 2.0)));
+        assert_eq!(views.last(), Some(&view("/new", 3.0)));
+    }
+
+    #[test]
+    fn sessions_of_newer_versions_are_ignored() {
+        let contents = json!({ "last_file": "/a", "version": state::VERSION + 1 });
+        assert!(Session::from_state((contents, state::VERSION + 1)).is_none());
+        let contents = json!({ "last_file": "/a" });
+        assert_eq!(Session::from_state((contents, 0)).unwrap().last_file, Some(PathBuf::from("/a")));
+    }
+}
@@ -0,0 +1,48 @@
+//! How long every image of a slideshow is shown: a base delay, plus time for every megapixel with
+//! `--delay-per-megapixel` so that dense infographics stay up longer than simple photos, unless the image has a
+//! sidecar file next to it giving its delay, e.g. `chart.png.delay` containing `20`.
+
+use std::path::Path;
+use std::time::Duration;
+
+/// No image is shown shorter than this, whatever the delays add up to
+const MIN_DELAY: Duration = Duration::from_millis(100);
+
+/// Parse a number of seconds such as `2.5`
+pub fn parse_seconds(value: &str) -> Result<Duration, String> {
+    match value.trim().parse::<f64>() {
+        Ok(seconds) if seconds >= 0.0 && seconds.is_finite() => Ok(Duration::from_secs_f64(seconds)),
+        _ => Err(format!("`{value}` is not a number of seconds")),
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Pacing {
+    pub delay: Duration,
+    pub per_megapixel: Duration,
+}
+
+impl Pacing {
+    /// How long the image at `path`, of `size` pixels, is shown
+    pub fn delay(&self, path: &Path, size: (f64, f64)) -> Duration {
+        let delay = sidecar_delay(path).unwrap_or_else(|| {
+            let megapixels = size.0 * size.1 / 1e6;
+            self.delay + self.per_megapixel.mul_f64(megapixels)
+        });
+        delay.max(MIN_DELAY)
+    }
+}
+
+/// The delay in the sidecar file of the image at `path`, which is named like the image with `.delay` appended
+fn sidecar_delay(path: &Path) -> Option<Duration> {
+    let mut sidecar = path.as_os_str().to_owned();
+    sidecar.push(".delay");
+    let content = std::fs::read_to_string(&sidecar).ok()?;
+    match parse_seconds(&content) {
+        Ok(delay) => Some(delay),
+        Err(err) => {
+            eprintln!("veh: ignoring {}: {err}", Path::new(&sidecar).display());
+            None
+        }
+    }
+}
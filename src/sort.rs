@@ -0,0 +1,208 @@
+//! `--sort`: the order of the playlist. Besides the built-in orders, `--sorter NAME=COMMAND` adds orders of one's
+//! own, selected with `--sort custom:NAME`: the command runs for every image, with the placeholders of
+//! `--action`, and prints its sort key, e.g. the aspect ratio or the dominant hue. Keys that are numbers are
+//! compared as numbers, and come before the keys that aren't.
+
+use std::cmp::Ordering;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use anyhow::{bail, Context, Result};
+use exif::{In, Tag};
+use rayon::prelude::*;
+
+use crate::action;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Sort {
+    /// By path
+    Name,
+    /// Oldest modification first
+    Mtime,
+    /// Smallest file first
+    Size,
+    /// Earliest first, by the date in the EXIF or else the modification time
+    Taken,
+    /// By the keys the `--sorter` of this name prints
+    Custom(String),
+}
+
+/// Parse a `--sort` order: name, mtime, size, taken or custom:NAME
+pub fn parse_sort(value: &str) -> Result<Sort, String> {
+    match value {
+        "name" => Ok(Sort::Name),
+        "mtime" => Ok(Sort::Mtime),
+        "size" => Ok(Sort::Size),
+        "taken" => Ok(Sort::Taken),
+        _ => match value.strip_prefix("custom:") {
+            Some(name) if !name.is_empty() => Ok(Sort::Custom(name.to_string())),
+            _ => Err(format!("`{value}` is not an order such as name, mtime, size, taken or custom:NAME")),
+        },
+    }
+}
+
+/// Parse a `--sorter` of the form NAME=COMMAND
+pub fn parse_sorter(value: &str) -> Result<(String, String), String> {
+    match value.split_once('=') {
+        Some((name, command)) if !name.is_empty() => Ok((name.to_string(), command.to_string())),
+        _ => Err(format!("`{value}` is not of the form NAME=COMMAND")),
+    }
+}
+
+/// The key an image is sorted by. Images without one, e.g. where the sorter failed, go last.
+#[derive(Debug, PartialEq)]
+enum Key {
+    Number(f64),
+    Text(String),
+    Missing,
+}
+
+impl Key {
+    fn parse(output: &str) -> Self {
+        let output = output.trim();
+        if output.is_empty() {
+            return Key::Missing;
+        }
+        output.parse().map_or_else(|_| Key::Text(output.to_string()), Key::Number)
+    }
+
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self, other) {
+            (Key::Number(a), Key::Number(b)) => a.total_cmp(b),
+            (Key::Text(a), Key::Text(b)) => a.cmp(b),
+            (Key::Number(_), _) | (Key::Text(_), Key::Missing) => Ordering::Less,
+            (_, Key::Number(_)) | (Key::Missing, Key::Text(_)) => Ordering::Greater,
+            (Key::Missing, Key::Missing) => Ordering::Equal,
+        }
+    }
+}
+
+/// Put `paths` in the order `sort`, with the custom orders in `sorters`. Images with the same key keep their order.
+pub fn sort(paths: &mut Vec<PathBuf>, sort: &Sort, sorters: &[(String, String)]) -> Result<()> {
+    let command = match sort {
+        Sort::Name => {
+            paths.sort();
+            return Ok(());
+        }
+        Sort::Mtime => {
+            let modified = |path: &Path| path.metadata().and_then(|metadata| metadata.modified()).ok();
+            paths.sort_by_cached_key(|path| modified(path).unwrap_or(SystemTime::UNIX_EPOCH));
+            return Ok(());
+        }
+        Sort::Size => {
+            paths.sort_by_cached_key(|path| path.metadata().map_or(0, |metadata| metadata.len()));
+            return Ok(());
+        }
+        Sort::Taken => None,
+        Sort::Custom(name) => match sorters.iter().rev().find(|(sorter, _)| sorter == name) {
+            Some((_, command)) => Some(command),
+            None => bail!("no --sorter named {name}"),
+        },
+    };
+    // The keys are found on the decoder threads, as they may take a while each for large galleries
+    let keys: Vec<Key> = match command {
+        Some(command) => paths.par_iter().map(|path| run(command, path)).collect(),
+        None => paths.par_iter().map(|path| taken(path).map_or(Key::Missing, |time| Key::Number(time as f64))).collect(),
+    };
+    let mut keyed: Vec<(Key, PathBuf)> = keys.into_iter().zip(paths.drain(..)).collect();
+    keyed.sort_by(|(a, _), (b, _)| a.cmp(b));
+    paths.extend(keyed.into_iter().map(|(_, path)| path));
+    Ok(())
+}
+
+/// When the image at `path` was taken, in seconds since 1970: the date in its EXIF, taken to be UTC, or else the
+/// time it was last modified
+fn taken(path: &Path) -> Option<i64> {
+    let exif = File::open(path).ok().and_then(|file| exif::Reader::new().read_from_container(&mut BufReader::new(file)).ok());
+    let date = exif.as_ref().and_then(|exif| match &exif.get_field(Tag::DateTimeOriginal, In::PRIMARY)?.value {
+        exif::Value::Ascii(values) => exif_seconds(&String::from_utf8_lossy(values.first()?)),
+        _ => None,
+    });
+    date.or_else(|| {
+        let modified = path.metadata().and_then(|metadata| metadata.modified()).ok()?;
+        Some(modified.duration_since(SystemTime::UNIX_EPOCH).ok()?.as_secs() as i64)
+    })
+}
+
+/// The seconds since 1970 of an EXIF date such as `2024:05:31 18:04:59`
+fn exif_seconds(date: &str) -> Option<i64> {
+    let numbers: Vec<i64> = date.trim_end_matches('\0').split([':', ' ']).map(|part| part.trim().parse().ok()).collect::<Option<_>>()?;
+    let [year, month, day, hour, minute, second] = numbers[..] else {
+        return None;
+    };
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+    // Days since 1970 of the proleptic Gregorian calendar, counting years from March so that leap days come last
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146_097 + day_of_era - 719_468;
+    Some(days * 86_400 + hour * 3_600 + minute * 60 + second)
+}
+
+/// The key the sorter `command` prints for the image at `path`
+fn run(command: &str, path: &Path) -> Key {
+    let command = action::expand(command, path);
+    let output = action::shell(&command).output().with_context(|| format!("failed to run `{command}`"));
+    match output {
+        Ok(output) if output.status.success() => Key::parse(&String::from_utf8_lossy(&output.stdout)),
+        Ok(output) => {
+            eprintln!("veh: `{command}` exited with {}", output.status);
+            Key::Missing
+        }
+        Err(err) => {
+            eprintln!("veh: {err:#}");
+            Key::Missing
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dates_are_counted_from_1970() {
+        assert_eq!(exif_seconds("1970:01:01 00:00:00"), Some(0));
+        assert_eq!(exif_seconds("2024:02:29 12:00:00"), Some(1_709_208_000));
+        // The leap day is one of the days between
+        let before = exif_seconds("2024:02:28 00:00:00").unwrap();
+        assert_eq!(exif_seconds("2024:03:01 00:00:00"), Some(before + 2 * 86_400));
+        // Cameras pad the field with zeros
+        assert_eq!(exif_seconds("1970:01:01 00:00:01\0"), Some(1));
+    }
+
+    #[test]
+    fn dates_before_1970_are_negative() {
+        assert_eq!(exif_seconds("1969:12:31 23:59:59"), Some(-1));
+        assert_eq!(exif_seconds("1900:03:01 00:00:00"), Some(-2_203_891_200));
+    }
+
+    #[test]
+    fn malformed_dates_have_no_time() {
+        for date in ["", "2024:13:01 00:00:00", "2024:02:00 00:00:00", "2024:02:29", "    :  :     :  :  ", "today"] {
+            assert_eq!(exif_seconds(date), None, "{date:?}");
+        }
+    }
+
+    #[test]
+    fn numbers_come_before_text_before_missing_keys() {
+        let mut keys: Vec<Key> = ["b", "", "10", "a", "9.5", " ", "-1"].into_iter().map(Key::parse).collect();
+        keys.sort_by(Key::cmp);
+        let expected = [
+            Key::Number(-1.0),
+            Key::Number(9.5),
+            Key::Number(10.0),
+            Key::Text("a".to_string()),
+            Key::Text("b".to_string()),
+            Key::Missing,
+            Key::Missing,
+        ];
+        assert_eq!(keys, expected);
+    }
+}
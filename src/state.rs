@@ -0,0 +1,91 @@
+//! Files of state kept between sessions in `$XDG_STATE_HOME/veh`, such as the last session and the last-read
+//! pages of comics. Several veh processes often run at once, so every file is changed under a lock, merging what
+//! other processes saved in the meantime, and written atomically. The files say which version of their layout
+//! they have, and files of a newer veh are left alone rather than overwritten.
+
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use serde_json::{Map, Value};
+
+use crate::export::{self, Conflict};
+
+/// The version of the layout of the state files. Files without one are from before versions were kept.
+pub const VERSION: u64 = 1;
+
+/// The file `name` in veh's directory for state kept between sessions, `$XDG_STATE_HOME/veh`
+pub fn file(name: &str) -> Option<PathBuf> {
+    let state = std::env::var_os("XDG_STATE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/state")))?;
+    Some(state.join("veh").join(name))
+}
+
+/// The contents of the state file `name` and the version of their layout, `None` if there is no such file, it
+/// can't be read or it is of a newer veh, which is reported. A file that can't be locked is read all the same.
+pub fn read(name: &str) -> Option<(Value, u64)> {
+    let path = file(name)?;
+    let _lock = lock(&path, false).map_err(|err| eprintln!("veh: reading {} unlocked: {err:#}", path.display())).ok();
+    match parse(&path) {
+        Ok(Some((_, version))) if version > VERSION => {
+            eprintln!("veh: ignoring {}: it is of a newer version of veh", path.display());
+            None
+        }
+        Ok(contents) => contents,
+        Err(err) => {
+            eprintln!("veh: ignoring {}: {err:#}", path.display());
+            None
+        }
+    }
+}
+
+/// Change the state file `name` to what `merge` makes of its current contents and their version, if it exists.
+/// No other process changes the file in the meantime.
+pub fn update(name: &str, merge: impl FnOnce(Option<(Value, u64)>) -> Map<String, Value>) -> Result<()> {
+    let path = file(name).context("no directory to keep state in, set XDG_STATE_HOME")?;
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).with_context(|| format!("failed to create {}", dir.display()))?;
+    }
+    let _lock = lock(&path, true)?;
+    // A broken file is replaced, but one of a newer veh is kept for it
+    let current = match parse(&path) {
+        Ok(Some((_, version))) if version > VERSION => bail!("{} is of a newer version of veh", path.display()),
+        Ok(current) => current,
+        Err(_) => None,
+    };
+    let mut contents = merge(current);
+    contents.insert("version".to_string(), VERSION.into());
+    let data = serde_json::to_vec_pretty(&contents)?;
+    export::save_with(&path, Conflict::Overwrite, |writer| Ok(writer.write_all(&data)?))?;
+    Ok(())
+}
+
+fn parse(path: &Path) -> Result<Option<(Value, u64)>> {
+    let data = match std::fs::read(path) {
+        Ok(data) => data,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err.into()),
+    };
+    let value: Value = serde_json::from_slice(&data)?;
+    let version = value.get("version").and_then(Value::as_u64).unwrap_or(0);
+    Ok(Some((value, version)))
+}
+
+/// Lock the state file at `path` until the returned file is dropped, shared by readers unless `exclusive`. The
+/// lock is taken on a file of its own, as the state file is replaced when it is written.
+fn lock(path: &Path, exclusive: bool) -> Result<File> {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".lock");
+    let lock_path = path.with_file_name(name);
+    let file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(&lock_path)
+        .with_context(|| format!("failed to open {}", lock_path.display()))?;
+    let locked = if exclusive { file.lock() } else { file.lock_shared() };
+    locked.with_context(|| format!("failed to lock {}", lock_path.display()))?;
+    Ok(file)
+}
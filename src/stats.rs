@@ -0,0 +1,170 @@
+//! Statistics of the pixels of an image: the minimum, maximum, mean and standard deviation of every channel and
+//! the number of unique colors, to verify masks, normal maps and scientific rasters. Values are those stored in
+//! the file, e.g. 0 to 65535 for 16-bit images, shown with `Shift+i` or printed with `--info --stats`.
+
+use std::collections::HashSet;
+use std::path::Path;
+
+use anyhow::Result;
+use image::DynamicImage;
+use serde_json::{json, Value};
+
+use crate::loader;
+
+/// The statistics of one channel
+#[derive(Debug, Clone, PartialEq)]
+pub struct Channel {
+    pub name: &'static str,
+    pub min: f64,
+    pub max: f64,
+    pub mean: f64,
+    pub stddev: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stats {
+    pub channels: Vec<Channel>,
+    pub unique_colors: usize,
+}
+
+impl Stats {
+    /// The statistics of the image at `path`, decoded as it is stored: without color management or turning it
+    pub fn read(path: &Path) -> Result<Self> {
+        Ok(Self::compute(&loader::decode_as_stored(path)?))
+    }
+
+    pub fn compute(image: &DynamicImage) -> Self {
+        let color = image.color();
+        let names: &[&'static str] = match (color.has_color(), color.has_alpha()) {
+            (false, false) => &["L"],
+            (false, true) => &["L", "A"],
+            (true, false) => &["R", "G", "B"],
+            (true, true) => &["R", "G", "B", "A"],
+        };
+        let channels = if let Some(samples) = image.as_flat_samples_u8() {
+            channels(samples.samples, names)
+        } else if let Some(samples) = image.as_flat_samples_u16() {
+            channels(samples.samples, names)
+        } else if let Some(samples) = image.as_flat_samples_f32() {
+            channels(samples.samples, names)
+        } else {
+            channels(image.to_rgba32f().as_raw(), &["R", "G", "B", "A"])
+        };
+        // Colors are told apart by their bytes, which also works for floats
+        let bytes_per_pixel = color.bytes_per_pixel().max(1) as usize;
+        let unique_colors = image.as_bytes().chunks_exact(bytes_per_pixel).collect::<HashSet<_>>().len();
+        Self { channels, unique_colors }
+    }
+
+    /// A line per channel and one with the number of colors, for people to read
+    pub fn lines(&self) -> Vec<String> {
+        let mut lines: Vec<String> = self
+            .channels
+            .iter()
+            .map(|channel| {
+                format!(
+                    "{}: min {} max {} mean {:.2} stddev {:.2}",
+                    channel.name,
+                    number(channel.min),
+                    number(channel.max),
+                    channel.mean,
+                    channel.stddev
+                )
+            })
+            .collect();
+        lines.push(format!("{} unique colors", self.unique_colors));
+        lines
+    }
+
+    pub fn to_json(&self) -> Value {
+        let channels: serde_json::Map<String, Value> = self
+            .channels
+            .iter()
+            .map(|channel| {
+                let stats = json!({ "min": channel.min, "max": channel.max, "mean": channel.mean, "stddev": channel.stddev });
+                (channel.name.to_string(), stats)
+            })
+            .collect();
+        json!({ "channels": channels, "unique_colors": self.unique_colors })
+    }
+}
+
+/// The statistics of the interleaved `samples` of channels called `names`
+fn channels<T: Copy + Into<f64>>(samples: &[T], names: &[&'static str]) -> Vec<Channel> {
+    let count = names.len();
+    let mut min = vec![f64::INFINITY; count];
+    let mut max = vec![f64::NEG_INFINITY; count];
+    let mut sum = vec![0.0; count];
+    let mut sum_of_squares = vec![0.0; count];
+    for pixel in samples.chunks_exact(count) {
+        for (channel, &value) in pixel.iter().enumerate() {
+            let value: f64 = value.into();
+            min[channel] = min[channel].min(value);
+            max[channel] = max[channel].max(value);
+            sum[channel] += value;
+            sum_of_squares[channel] += value * value;
+        }
+    }
+    let pixels = (samples.len() / count).max(1) as f64;
+    names
+        .iter()
+        .enumerate()
+        .map(|(channel, &name)| {
+            let mean = sum[channel] / pixels;
+            // Rounding may make the variance of a flat channel slightly negative
+            let variance = (sum_of_squares[channel] / pixels - mean * mean).max(0.0);
+            Channel { name, min: min[channel], max: max[channel], mean, stddev: variance.sqrt() }
+        })
+        .collect()
+}
+
+/// `value` without decimals if it has none, as the values of integer images don't
+fn number(value: f64) -> String {
+    if value.fract() == 0.0 {
+        format!("{value}")
+    } else {
+        format!("{value:.4}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{GrayImage, ImageBuffer, Luma, Rgb, RgbImage};
+
+    #[test]
+    fn flat_channels_have_no_deviation() {
+        let image = DynamicImage::ImageLuma8(GrayImage::from_pixel(3, 2, Luma([128])));
+        let stats = Stats::compute(&image);
+        assert_eq!(stats.channels, [Channel { name: "L", min: 128.0, max: 128.0, mean: 128.0, stddev: 0.0 }]);
+        assert_eq!(stats.unique_colors, 1);
+    }
+
+    #[test]
+    fn sixteen_bit_values_are_kept() {
+        let image = ImageBuffer::<Luma<u16>, _>::from_raw(2, 1, vec![0, 65535]).unwrap();
+        let stats = Stats::compute(&DynamicImage::ImageLuma16(image));
+        let channel = &stats.channels[0];
+        assert_eq!((channel.min, channel.max, channel.mean, channel.stddev), (0.0, 65535.0, 32767.5, 32767.5));
+        assert_eq!(stats.unique_colors, 2);
+    }
+
+    #[test]
+    fn colors_are_counted_once() {
+        let mut image = RgbImage::from_pixel(4, 4, Rgb([1, 2, 3]));
+        image.put_pixel(0, 0, Rgb([3, 2, 1]));
+        image.put_pixel(1, 0, Rgb([3, 2, 1]));
+        image.put_pixel(2, 0, Rgb([1, 2, 4]));
+        let stats = Stats::compute(&DynamicImage::ImageRgb8(image));
+        let names: Vec<_> = stats.channels.iter().map(|channel| channel.name).collect();
+        assert_eq!(names, ["R", "G", "B"]);
+        assert_eq!(stats.unique_colors, 3);
+    }
+
+    #[test]
+    fn channels_are_interleaved() {
+        let channels = channels(&[1u8, 10, 3, 30], &["L", "A"]);
+        assert_eq!((channels[0].min, channels[0].max, channels[0].mean), (1.0, 3.0, 2.0));
+        assert_eq!((channels[1].min, channels[1].max, channels[1].mean), (10.0, 30.0, 20.0));
+    }
+}
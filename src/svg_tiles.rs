@@ -0,0 +1,116 @@
+//! `--svg-tiles`: SVGs too complex to be drawn smoothly every frame are rasterized into tiles at the current zoom
+//! whenever the view is idle. While the image is panned or zoomed, the tiles are drawn instead of the SVG, scaled
+//! if the zoom has changed, and the SVG itself is drawn again once the view has settled.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use vello::kurbo::{Affine, Rect};
+use vello::peniko::Image;
+use vello::Scene;
+use winit::dpi::PhysicalSize;
+
+/// The width and height of a tile in window pixels
+pub const TILE_SIZE: u32 = 512;
+/// How long the view has to stay still to count as settled
+const SETTLE: Duration = Duration::from_millis(250);
+/// How far around the window tiles are rendered in advance, in tiles
+const MARGIN: f64 = 1.0;
+
+pub struct SvgTiles {
+    // The zoom the tiles are rendered at, and the tiles rendered so far by column and row
+    scale: f64,
+    tiles: HashMap<(u32, u32), Image>,
+    // The transform of the last frame, and when it last changed if the view hasn't settled since
+    last_transform: Affine,
+    moved_at: Option<Instant>,
+}
+
+impl SvgTiles {
+    pub fn new() -> Self {
+        Self { scale: 1.0, tiles: HashMap::new(), last_transform: Affine::IDENTITY, moved_at: None }
+    }
+
+    /// Note the transform of a frame about to be drawn, and return whether the view is moving
+    pub fn observe(&mut self, transform: Affine) -> bool {
+        if transform != self.last_transform {
+            self.last_transform = transform;
+            self.moved_at = Some(Instant::now());
+        }
+        self.moved_at.is_some_and(|moved_at| moved_at.elapsed() < SETTLE)
+    }
+
+    /// When the view settles if it is moving
+    pub fn settles_at(&self) -> Option<Instant> {
+        self.moved_at.map(|moved_at| moved_at + SETTLE)
+    }
+
+    /// Note that the view has settled, so that the SVG is drawn again
+    pub fn settle(&mut self) {
+        self.moved_at = None;
+    }
+
+    /// The tiles the window shows at `scale`, with the tiles around them if `margin`
+    fn visible(&self, transform: Affine, size: PhysicalSize<u32>, image_size: (f64, f64), scale: f64, margin: bool) -> Vec<(u32, u32)> {
+        let tile = TILE_SIZE as f64 / scale;
+        let window = Rect::new(0.0, 0.0, size.width as f64, size.height as f64);
+        let shown = transform.inverse().transform_rect_bbox(window).intersect(Rect::new(0.0, 0.0, image_size.0, image_size.1));
+        if shown.is_zero_area() {
+            return vec![];
+        }
+        let margin = if margin { MARGIN } else { 0.0 };
+        let columns = (image_size.0 / tile).ceil();
+        let rows = (image_size.1 / tile).ceil();
+        let first_column = ((shown.x0 / tile).floor() - margin).max(0.0) as u32;
+        let last_column = ((shown.x1 / tile).ceil() + margin).min(columns) as u32;
+        let first_row = ((shown.y0 / tile).floor() - margin).max(0.0) as u32;
+        let last_row = ((shown.y1 / tile).ceil() + margin).min(rows) as u32;
+        (first_row..last_row).flat_map(|row| (first_column..last_column).map(move |column| (column, row))).collect()
+    }
+
+    /// The next tile to render for the view of `transform`, where the tiles visible in the window come first. The
+    /// tiles rendered at another zoom are dropped first if the zoom has changed noticeably.
+    pub fn next_missing(&mut self, transform: Affine, size: PhysicalSize<u32>, image_size: (f64, f64)) -> Option<((u32, u32), f64)> {
+        let (x_scale, y_scale) = crate::viewport::axis_scales(transform);
+        let scale = x_scale.max(y_scale);
+        if (scale / self.scale - 1.0).abs() > 0.25 || self.tiles.is_empty() {
+            self.scale = scale;
+            self.tiles.clear();
+        }
+        let wanted = [false, true].map(|margin| self.visible(transform, size, image_size, self.scale, margin));
+        // Tiles that scrolled far out of view aren't kept
+        self.tiles.retain(|tile, _| wanted[1].contains(tile));
+        let missing = wanted.iter().flatten().find(|tile| !self.tiles.contains_key(tile))?;
+        Some((*missing, self.scale))
+    }
+
+    /// Keep the rendered `tile`, unless the zoom has changed while it was rendered
+    pub fn insert(&mut self, tile: (u32, u32), scale: f64, image: Image) {
+        if scale == self.scale {
+            self.tiles.insert(tile, image);
+        }
+    }
+
+    /// The scene to render `tile` at `scale` from: `subscene`, which has the image drawn into it at `subscene_scale`,
+    /// moved so that the tile is at the top left
+    pub fn tile_scene(subscene: &Scene, subscene_scale: f64, tile: (u32, u32), scale: f64) -> Scene {
+        let offset = (-((tile.0 * TILE_SIZE) as f64), -((tile.1 * TILE_SIZE) as f64));
+        let mut scene = Scene::new();
+        scene.append(subscene, Some(Affine::translate(offset) * Affine::scale(scale / subscene_scale)));
+        scene
+    }
+
+    /// Draw the tiles into `scene` with `transform`, if there is a tile for all of the window
+    pub fn draw(&self, scene: &mut Scene, transform: Affine, size: PhysicalSize<u32>, image_size: (f64, f64)) -> bool {
+        let visible = self.visible(transform, size, image_size, self.scale, false);
+        if visible.is_empty() || !visible.iter().all(|tile| self.tiles.contains_key(tile)) {
+            return false;
+        }
+        for (column, row) in visible {
+            let offset = ((column * TILE_SIZE) as f64, (row * TILE_SIZE) as f64);
+            let placement = transform * Affine::scale(1.0 / self.scale) * Affine::translate(offset);
+            scene.draw_image(&self.tiles[&(column, row)], placement);
+        }
+        true
+    }
+}
@@ -0,0 +1,74 @@
+//! Drawing short single-line texts (e.g. in the status bar) with a sans-serif system font.
+
+use std::sync::Arc;
+
+use vello::glyph::Glyph;
+use vello::kurbo::Affine;
+use vello::peniko::{Blob, Color, Fill, Font};
+use vello::skrifa::prelude::{FontRef, LocationRef, MetadataProvider, Size};
+use vello::Scene;
+use vello_svg::usvg::fontdb;
+
+pub struct Text {
+    font: Font,
+}
+
+impl Text {
+    /// Load the sans-serif system font, or `None` if there is none
+    pub fn load() -> Option<Self> {
+        let database = crate::loader::font_database();
+        let query = fontdb::Query { families: &[fontdb::Family::SansSerif], ..Default::default() };
+        let id = database.query(&query)?;
+        let (data, index) = database.with_face_data(id, |data, index| (data.to_vec(), index))?;
+        Some(Self { font: Font::new(Blob::new(Arc::new(data)), index) })
+    }
+
+    fn font_ref(&self) -> Option<FontRef<'_>> {
+        FontRef::from_index(self.font.data.as_ref(), self.font.index).ok()
+    }
+
+    /// The ascent and descent (as a positive number) of the font at `size` pixels
+    pub fn vertical_metrics(&self, size: f32) -> (f64, f64) {
+        let Some(font) = self.font_ref() else {
+            return (size as f64, 0.0);
+        };
+        let metrics = font.metrics(Size::new(size), LocationRef::default());
+        (metrics.ascent as f64, -metrics.descent as f64)
+    }
+
+    fn layout(&self, text: &str, size: f32) -> (Vec<Glyph>, f64) {
+        let Some(font) = self.font_ref() else {
+            return (vec![], 0.0);
+        };
+        let charmap = font.charmap();
+        let metrics = font.glyph_metrics(Size::new(size), LocationRef::default());
+        let mut x = 0.0;
+        let glyphs = text
+            .chars()
+            .map(|ch| {
+                let id = charmap.map(ch).unwrap_or_default();
+                let glyph = Glyph { id: id.to_u16() as u32, x, y: 0.0 };
+                x += metrics.advance_width(id).unwrap_or_default();
+                glyph
+            })
+            .collect();
+        (glyphs, x as f64)
+    }
+
+    /// The width of `text` at `size` pixels
+    pub fn width(&self, text: &str, size: f32) -> f64 {
+        self.layout(text, size).1
+    }
+
+    /// Draw `text` at `size` pixels with the start of its baseline at `transform`'s origin. Returns its width.
+    pub fn draw(&self, scene: &mut Scene, text: &str, size: f32, transform: Affine, color: Color) -> f64 {
+        let (glyphs, width) = self.layout(text, size);
+        scene
+            .draw_glyphs(&self.font)
+            .font_size(size)
+            .transform(transform)
+            .brush(color)
+            .draw(Fill::NonZero, glyphs.into_iter());
+        width
+    }
+}
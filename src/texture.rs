@@ -0,0 +1,106 @@
+//! Previews of game textures, to validate exports without opening a DCC tool: normal maps shown as a surface lit
+//! from the top left, and channel-packed ORM textures split into one quadrant per channel. Normal maps are read
+//! the OpenGL way, with green pointing up.
+
+use std::sync::Arc;
+
+use vello::peniko::{Blob, Format, Image};
+
+/// How a texture is previewed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Preview {
+    /// As a normal map, lit
+    Lit,
+    /// Every channel in a quadrant of its own, twice the size of the image
+    Channels,
+}
+
+impl Preview {
+    /// The preview after `preview`, or `None` to show the texture as it is after the last one
+    pub fn next(preview: Option<Preview>) -> Option<Preview> {
+        match preview {
+            None => Some(Preview::Lit),
+            Some(Preview::Lit) => Some(Preview::Channels),
+            Some(Preview::Channels) => None,
+        }
+    }
+
+    /// The preview of `image`
+    pub fn apply(self, image: &Image) -> Image {
+        match self {
+            Preview::Lit => lit(image),
+            Preview::Channels => channels(image),
+        }
+    }
+}
+
+/// The labels of the quadrants of [`Preview::Channels`], left to right and top to bottom, named for the usual
+/// packing of occlusion, roughness and metallic
+pub const CHANNEL_LABELS: [&str; 4] = ["R  occlusion", "G  roughness", "B  metallic", "A"];
+
+/// The light of [`Preview::Lit`], from the top left and in front, normalized
+const LIGHT: [f32; 3] = [-0.485, 0.485, 0.728];
+/// How bright the parts of the surface facing away from the light are
+const AMBIENT: f32 = 0.15;
+
+/// The normal map `image` shaded as a grey surface
+fn lit(image: &Image) -> Image {
+    let data: Vec<u8> = image
+        .data
+        .data()
+        .chunks_exact(4)
+        .flat_map(|pixel| {
+            let normal = [0, 1, 2].map(|channel| pixel[channel] as f32 / 255.0 * 2.0 - 1.0);
+            let length = normal.iter().map(|value| value * value).sum::<f32>().sqrt();
+            // Pixels without a direction are taken to face the viewer
+            let normal = if length > 0.0 { normal.map(|value| value / length) } else { [0.0, 0.0, 1.0] };
+            let diffuse = normal.iter().zip(LIGHT).map(|(a, b)| a * b).sum::<f32>().max(0.0);
+            let shade = (AMBIENT + (1.0 - AMBIENT) * diffuse).powf(1.0 / 2.2);
+            let value = (shade * 255.0).round() as u8;
+            [value, value, value, 0xff]
+        })
+        .collect();
+    Image::new(Blob::new(Arc::new(data)), Format::Rgba8, image.width, image.height)
+}
+
+/// The channels of `image` as grey images in the quadrants of one twice its width and height
+fn channels(image: &Image) -> Image {
+    let (width, height) = (image.width as usize, image.height as usize);
+    let pixels = image.data.data();
+    let mut data = vec![0; width * height * 16];
+    for (channel, (left, top)) in [(0, 0), (width, 0), (0, height), (width, height)].into_iter().enumerate() {
+        for y in 0..height {
+            // Rows of the preview are twice as long as those of the image
+            let row = (top + y) * 2 * width + left;
+            for x in 0..width {
+                let value = pixels[(y * width + x) * 4 + channel];
+                let offset = (row + x) * 4;
+                data[offset..offset + 4].copy_from_slice(&[value, value, value, 0xff]);
+            }
+        }
+    }
+    Image::new(Blob::new(Arc::new(data)), Format::Rgba8, image.width * 2, image.height * 2)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn channels_fill_one_quadrant_each() {
+        // Two pixels side by side, every channel of them different
+        let data = vec![10, 20, 30, 40, 11, 21, 31, 41];
+        let image = Image::new(Blob::new(Arc::new(data)), Format::Rgba8, 2, 1);
+        let preview = channels(&image);
+        assert_eq!((preview.width, preview.height), (4, 2));
+        let grey = |x: usize, y: usize| {
+            let offset = (y * 4 + x) * 4;
+            let pixel = &preview.data.data()[offset..offset + 4];
+            let value = pixel[0];
+            assert_eq!(pixel, [value, value, value, 0xff]);
+            value
+        };
+        let rows: Vec<Vec<u8>> = (0..2).map(|y| (0..4).map(|x| grey(x, y)).collect()).collect();
+        assert_eq!(rows, [[10, 11, 20, 21], [30, 31, 40, 41]]);
+    }
+}
@@ -0,0 +1,203 @@
+//! `veh verify <dir>`: a health check for photo archives. Every image is decoded completely, every page of it,
+//! and the images that fail are reported, and trashed or moved into a quarantine directory if asked to. Decoders
+//! fill in the missing part of some truncated images rather than failing, so JPEG and PNG files are also checked
+//! for the marker that ends them.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use rayon::prelude::*;
+
+use crate::{errors, fileops, find, loader};
+
+/// What happens to the images that fail the check
+pub enum Disposal {
+    Keep,
+    Trash,
+    /// Move them into this directory, at their path relative to the directory checked
+    Quarantine(PathBuf),
+}
+
+/// Check the images in `dir` and dispose of the broken ones. Fails with the first broken image if there are any.
+pub fn verify(dir: &Path, recursive: bool, disposal: &Disposal) -> Result<()> {
+    let paths = find::list_images(dir, recursive)?;
+    // Images are checked on the decoder threads, and reported in path order
+    let results: Vec<Result<()>> = paths.par_iter().map(|path| check(path)).collect();
+    let mut first_error = None;
+    let mut broken = 0;
+    for (path, result) in paths.iter().zip(results) {
+        let Err(err) = result else {
+            continue;
+        };
+        errors::report(Some(path), &err);
+        broken += 1;
+        if let Err(err) = dispose(path, dir, disposal) {
+            errors::report(Some(path), &err);
+        }
+        first_error.get_or_insert(err);
+    }
+    eprintln!("veh: checked {} images, {broken} broken", paths.len());
+    match first_error {
+        Some(err) => Err(err.context(format!("{broken} of {} images are broken", paths.len()))),
+        None => Ok(()),
+    }
+}
+
+/// Decode every page of the image at `path`, after checking that it isn't cut short
+fn check(path: &Path) -> Result<()> {
+    check_end(path)?;
+    let pages = loader::page_count(path)?;
+    for page in 0..pages {
+        loader::open_page(path, page).with_context(|| format!("page {} is broken", page + 1))?;
+    }
+    Ok(())
+}
+
+/// Check that a JPEG or PNG file has the marker or chunk that ends the image. Anything after it is accepted: motion
+/// photos append a video after the JPEG, and some cameras and editors pad files with zeros or append trailers.
+fn check_end(path: &Path) -> Result<()> {
+    let has_end: fn(&[u8]) -> bool = match loader::format_of(path).as_deref() {
+        Some("jpg" | "jpeg") => jpeg_has_end,
+        Some("png") => png_has_end,
+        _ => return Ok(()),
+    };
+    let data = fs::read(path).context("open image failed")?;
+    if !has_end(&data) {
+        bail!(std::io::Error::new(std::io::ErrorKind::UnexpectedEof, "the image is truncated"));
+    }
+    Ok(())
+}
+
+/// Walk the segments of a JPEG up to its end of image marker
+fn jpeg_has_end(data: &[u8]) -> bool {
+    let mut at = 2;
+    if !data.starts_with(&[0xff, 0xd8]) {
+        return false;
+    }
+    loop {
+        // Markers may be preceded by any number of fill bytes
+        while data.get(at) == Some(&0xff) && data.get(at + 1) == Some(&0xff) {
+            at += 1;
+        }
+        let (Some(&0xff), Some(&marker)) = (data.get(at), data.get(at + 1)) else {
+            return false;
+        };
+        at += 2;
+        match marker {
+            0xd9 => return true,
+            // Restart markers and TEM carry no length
+            0xd0..=0xd7 | 0x01 => continue,
+            _ => {}
+        }
+        let Some(length) = data.get(at..at + 2).map(|length| u16::from_be_bytes([length[0], length[1]]) as usize) else {
+            return false;
+        };
+        at += length;
+        if marker == 0xda {
+            // The entropy-coded scan runs up to the next marker that isn't a stuffed zero or a restart marker
+            loop {
+                match (data.get(at), data.get(at + 1)) {
+                    (Some(&0xff), Some(&(0x00 | 0xd0..=0xd7 | 0xff))) => at += 1,
+                    (Some(&0xff), Some(_)) => break,
+                    (Some(_), _) => at += 1,
+                    (None, _) => return false,
+                }
+            }
+        }
+    }
+}
+
+/// Walk the chunks of a PNG up to its `IEND` chunk
+fn png_has_end(data: &[u8]) -> bool {
+    const SIGNATURE: &[u8] = &[0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+    if !data.starts_with(SIGNATURE) {
+        return false;
+    }
+    let mut at = SIGNATURE.len();
+    while let Some(header) = data.get(at..at + 8) {
+        let length = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as usize;
+        // The length, type, data and CRC
+        let Some(next) = (at + 12).checked_add(length).filter(|&next| next <= data.len()) else {
+            return false;
+        };
+        if &header[4..] == b"IEND" {
+            return true;
+        }
+        at = next;
+    }
+    false
+}
+
+fn dispose(path: &Path, dir: &Path, disposal: &Disposal) -> Result<()> {
+    match disposal {
+        Disposal::Keep => Ok(()),
+        Disposal::Trash => trash::delete(path).with_context(|| format!("failed to move {} to the trash", path.display())),
+        Disposal::Quarantine(quarantine) => {
+            let target = quarantine.join(path.strip_prefix(dir).unwrap_or(path));
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent).with_context(|| format!("failed to create {}", parent.display()))?;
+            }
+            fileops::move_file(path, &target)?;
+            eprintln!("veh: moved {} to {}", path.display(), target.display());
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// SOI, an APP1 segment holding a thumbnail with its own end marker, a scan with a stuffed zero and a restart
+    /// marker, and EOI
+    const JPEG: &[u8] = &[
+        0xff, 0xd8, 0xff, 0xe1, 0x00, 0x06, 0xff, 0xd8, 0xff, 0xd9, 0xff, 0xda, 0x00, 0x02, 0x12, 0xff, 0x00, 0x34,
+        0xff, 0xd0, 0x56, 0xff, 0xd9,
+    ];
+
+    fn png() -> Vec<u8> {
+        let mut data = vec![0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+        data.extend([0, 0, 0, 2, b'I', b'D', b'A', b'T', 1, 2, 0, 0, 0, 0]);
+        data.extend([0, 0, 0, 0, b'I', b'E', b'N', b'D', 0xae, 0x42, 0x60, 0x82]);
+        data
+    }
+
+    #[test]
+    fn complete_images_have_an_end() {
+        assert!(jpeg_has_end(JPEG));
+        assert!(png_has_end(&png()));
+    }
+
+    #[test]
+    fn truncated_images_have_no_end() {
+        for length in 0..JPEG.len() {
+            assert!(!jpeg_has_end(&JPEG[..length]), "{length} bytes");
+        }
+        let png = png();
+        for length in 0..png.len() {
+            assert!(!png_has_end(&png[..length]), "{length} bytes");
+        }
+    }
+
+    #[test]
+    fn zero_padding_is_accepted() {
+        let mut jpeg = JPEG.to_vec();
+        jpeg.extend([0; 64]);
+        assert!(jpeg_has_end(&jpeg));
+        let mut png = png();
+        png.extend([0; 64]);
+        assert!(png_has_end(&png));
+    }
+
+    #[test]
+    fn appended_trailers_are_accepted() {
+        // A motion photo's video
+        let mut jpeg = JPEG.to_vec();
+        jpeg.extend(b"\0\0\0\x18ftypmp42 trailing video");
+        assert!(jpeg_has_end(&jpeg));
+        let mut png = png();
+        png.extend(b"editor trailer");
+        assert!(png_has_end(&png));
+    }
+}
@@ -0,0 +1,339 @@
+//! The transform math of a view. A view's transform maps image pixels to window pixels; these functions
+//! place an image in a window and change the transform as the image is panned and zoomed, or the window
+//! moves to a monitor with another scale factor.
+
+use vello::kurbo::{Affine, Point, Rect, Vec2};
+use winit::dpi::PhysicalSize;
+
+use crate::cli::Gravity;
+
+/// The scale at which an image of `image_size` fits into a window of `size`
+pub fn fit_scale(image_size: (f64, f64), size: PhysicalSize<u32>) -> f64 {
+    let (image_width, image_height) = image_size;
+    let x_scale = size.width as f64 / image_width;
+    let y_scale = size.height as f64 / image_height;
+    x_scale.min(y_scale)
+}
+
+/// The size of a window that shows an image of `image_size` at 100%, shrunk to fit into `screen` if it is larger
+/// and at least `min` pixels on each side
+pub fn window_size_for(image_size: (f64, f64), screen: PhysicalSize<u32>, min: f64) -> PhysicalSize<u32> {
+    let scale = fit_scale(image_size, screen).min(1.0);
+    let width = (image_size.0 * scale).max(min).min(screen.width as f64);
+    let height = (image_size.1 * scale).max(min).min(screen.height as f64);
+    PhysicalSize::new(width.round() as u32, height.round() as u32)
+}
+
+/// The scale at which an image of `image_size` is as tall as a window of `size`
+pub fn fit_height_scale(image_size: (f64, f64), size: PhysicalSize<u32>) -> f64 {
+    size.height as f64 / image_size.1
+}
+
+/// The transform that places an image of `image_size` in a window of `size` at `scale`, anchored according to `gravity`
+pub fn placed_transform(image_size: (f64, f64), size: PhysicalSize<u32>, scale: f64, gravity: Gravity) -> Affine {
+    let (image_width, image_height) = image_size;
+    // The anchor point of the image, e.g. its top right corner, goes to the same point of the window
+    let (x, y) = gravity.anchor();
+    Affine::translate(Vec2::new(size.width as f64 * x, size.height as f64 * y)) * Affine::scale(scale) * Affine::translate(-Vec2::new(image_width * x, image_height * y))
+}
+
+/// The transform that shows an image at `scale` with its `point` in the middle of a window of `size`
+pub fn centered_on(point: Point, scale: f64, size: PhysicalSize<u32>) -> Affine {
+    let center = Vec2::new(size.width as f64, size.height as f64) / 2.0;
+    Affine::translate(center) * Affine::scale(scale) * Affine::translate(-point.to_vec2())
+}
+
+/// The transform an image is first shown with: fit into the window, unless the scale is locked
+pub fn initial_transform(image_size: (f64, f64), size: PhysicalSize<u32>, locked_scale: Option<f64>, gravity: Gravity) -> Affine {
+    let scale = locked_scale.unwrap_or_else(|| fit_scale(image_size, size));
+    placed_transform(image_size, size, scale, gravity)
+}
+
+/// How much `transform` scales the x and y axis of the image
+pub fn axis_scales(transform: Affine) -> (f64, f64) {
+    let [a, b, c, d, _, _] = transform.as_coeffs();
+    (a.hypot(b), c.hypot(d))
+}
+
+/// The scale of `transform` averaged over both axes, i.e. how much it scales areas, square rooted
+pub fn uniform_scale(transform: Affine) -> f64 {
+    transform.determinant().abs().sqrt()
+}
+
+/// Scale `transform` by `x_factor` and `y_factor` about `position` in the window, which stays where it is
+pub fn zoom_about(transform: Affine, position: Vec2, x_factor: f64, y_factor: f64) -> Affine {
+    Affine::translate(position) * Affine::scale_non_uniform(x_factor, y_factor) * Affine::translate(-position) * transform
+}
+
+/// Zoom to 100%, one image pixel per window pixel, about `position` in the window, which stays where it is
+pub fn actual_size_about(transform: Affine, position: Vec2) -> Affine {
+    let (x_scale, y_scale) = axis_scales(transform);
+    zoom_about(transform, position, 1.0 / x_scale, 1.0 / y_scale)
+}
+
+/// Zoom in on `selection`, a rectangle in window pixels, so that it fills a window of `size` on one axis and is
+/// centered on the other
+pub fn zoom_to(transform: Affine, selection: Rect, size: PhysicalSize<u32>) -> Affine {
+    let factor = (size.width as f64 / selection.width()).min(size.height as f64 / selection.height());
+    let center = Vec2::new(size.width as f64, size.height as f64) / 2.0;
+    Affine::translate(center) * Affine::scale(factor) * Affine::translate(-selection.center().to_vec2()) * transform
+}
+
+/// Move the image by `offset` window pixels, e.g. along with the cursor
+pub fn pan(transform: Affine, offset: Vec2) -> Affine {
+    Affine::translate(offset) * transform
+}
+
+/// Move the image by `offset` image pixels, so that the distance on screen follows the zoom
+pub fn pan_image(transform: Affine, offset: Vec2) -> Affine {
+    transform * Affine::translate(offset)
+}
+
+/// Pan the image by less than a tile so that a corner of a tile of size `tile` in image pixels is at the top left
+/// of the window, e.g. to step through a sprite sheet cell by cell
+pub fn snap_to_grid(transform: Affine, tile: Vec2) -> Affine {
+    let origin = transform.inverse() * Point::ORIGIN;
+    let snapped = Point::new((origin.x / tile.x).round() * tile.x, (origin.y / tile.y).round() * tile.y);
+    pan_image(transform, origin - snapped)
+}
+
+// Scales this close to an integer count as one, as zooming in and out doesn't land on integers exactly
+const INTEGER_TOLERANCE: f64 = 1e-9;
+
+/// The largest integer scale up to `scale`, or for images that don't fit at 100%, the largest integer fraction
+/// such as 1/2 or 1/3, so that every image pixel covers whole window pixels or the other way round
+pub fn integer_scale(scale: f64) -> f64 {
+    if scale >= 1.0 - INTEGER_TOLERANCE {
+        (scale + INTEGER_TOLERANCE).floor()
+    } else {
+        1.0 / (1.0 / scale - INTEGER_TOLERANCE).ceil()
+    }
+}
+
+/// The next integer scale or integer fraction above `scale`, or below it unless `zoom_in`
+pub fn next_integer_scale(scale: f64, zoom_in: bool) -> f64 {
+    if zoom_in {
+        let scale = scale * (1.0 + INTEGER_TOLERANCE);
+        if scale >= 1.0 {
+            scale.floor() + 1.0
+        } else {
+            1.0 / ((1.0 / scale).ceil() - 1.0).max(1.0)
+        }
+    } else {
+        let scale = scale * (1.0 - INTEGER_TOLERANCE);
+        if scale > 1.0 {
+            scale.ceil() - 1.0
+        } else {
+            1.0 / ((1.0 / scale).floor() + 1.0)
+        }
+    }
+}
+
+/// Move the image by less than a pixel so that its pixels line up with those of the window
+pub fn snap_to_pixels(transform: Affine) -> Affine {
+    let [a, b, c, d, x, y] = transform.as_coeffs();
+    Affine::new([a, b, c, d, x.round(), y.round()])
+}
+
+/// Keep the size of the image in logical pixels when the scale factor of the window changes by `ratio`
+pub fn rescale(transform: Affine, ratio: f64) -> Affine {
+    Affine::scale(ratio) * transform
+}
+
+/// Undo any stretching by scaling both axes by the geometric mean of their scales, about `center` in the window
+pub fn unstretch(transform: Affine, center: Vec2) -> Affine {
+    let (x_scale, y_scale) = axis_scales(transform);
+    let scale = (x_scale * y_scale).sqrt();
+    zoom_about(transform, center, scale / x_scale, scale / y_scale)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::ValueEnum;
+    use proptest::prelude::*;
+
+    const EPSILON: f64 = 1e-6;
+
+    fn close(a: Affine, b: Affine) -> bool {
+        a.as_coeffs().into_iter().zip(b.as_coeffs()).all(|(a, b)| (a - b).abs() <= EPSILON * (1.0 + a.abs().max(b.abs())))
+    }
+
+    fn transform() -> impl Strategy<Value = Affine> {
+        (0.01..100.0, 0.01..100.0, -1e4..1e4, -1e4..1e4)
+            .prop_map(|(x_scale, y_scale, x, y)| Affine::translate((x, y)) * Affine::scale_non_uniform(x_scale, y_scale))
+    }
+
+    fn position() -> impl Strategy<Value = Vec2> {
+        (-1e4..1e4, -1e4..1e4).prop_map(|(x, y)| Vec2::new(x, y))
+    }
+
+    fn factor() -> impl Strategy<Value = f64> {
+        0.05..20.0
+    }
+
+    fn image_size() -> impl Strategy<Value = (f64, f64)> {
+        (1.0..1e4, 1.0..1e4)
+    }
+
+    fn window_size() -> impl Strategy<Value = PhysicalSize<u32>> {
+        (1..4000u32, 1..4000u32).prop_map(|(width, height)| PhysicalSize::new(width, height))
+    }
+
+    fn gravity() -> impl Strategy<Value = Gravity> {
+        proptest::sample::select(Gravity::value_variants())
+    }
+
+    proptest! {
+        #[test]
+        fn zooming_in_and_out_restores_the_transform(
+            transform in transform(), position in position(), x_factor in factor(), y_factor in factor(),
+        ) {
+            let zoomed = zoom_about(transform, position, x_factor, y_factor);
+            prop_assert!(close(zoom_about(zoomed, position, 1.0 / x_factor, 1.0 / y_factor), transform));
+        }
+
+        #[test]
+        fn zooming_keeps_the_point_under_the_cursor(
+            transform in transform(), position in position(), x_factor in factor(), y_factor in factor(),
+        ) {
+            let under_cursor = transform.inverse() * position.to_point();
+            let zoomed = zoom_about(transform, position, x_factor, y_factor);
+            prop_assert!((zoomed * under_cursor - position.to_point()).hypot() <= EPSILON * (1.0 + position.hypot()));
+        }
+
+        #[test]
+        fn zooming_scales_the_axes(transform in transform(), position in position(), x_factor in factor(), y_factor in factor()) {
+            let (x_scale, y_scale) = axis_scales(transform);
+            let (zoomed_x, zoomed_y) = axis_scales(zoom_about(transform, position, x_factor, y_factor));
+            prop_assert!((zoomed_x / x_scale - x_factor).abs() <= EPSILON * x_factor);
+            prop_assert!((zoomed_y / y_scale - y_factor).abs() <= EPSILON * y_factor);
+        }
+
+        #[test]
+        fn panning_back_restores_the_transform(transform in transform(), offset in position()) {
+            prop_assert!(close(pan(pan(transform, offset), -offset), transform));
+            prop_assert!(close(pan_image(pan_image(transform, offset), -offset), transform));
+        }
+
+        #[test]
+        fn panning_moves_by_window_or_image_pixels(transform in transform(), offset in position()) {
+            let origin = Point::ORIGIN;
+            prop_assert!((pan(transform, offset) * origin - (transform * origin + offset)).hypot() <= EPSILON * (1.0 + offset.hypot()));
+            prop_assert!((pan_image(transform, offset) * origin - transform * offset.to_point()).hypot() <= EPSILON * (1.0 + (transform * offset.to_point()).to_vec2().hypot()));
+        }
+
+        #[test]
+        fn actual_size_is_one_to_one_about_the_cursor(transform in transform(), position in position()) {
+            let under_cursor = transform.inverse() * position.to_point();
+            let actual = actual_size_about(transform, position);
+            let (x_scale, y_scale) = axis_scales(actual);
+            prop_assert!((x_scale - 1.0).abs() <= EPSILON && (y_scale - 1.0).abs() <= EPSILON);
+            prop_assert!((actual * under_cursor - position.to_point()).hypot() <= EPSILON * (1.0 + position.hypot()));
+        }
+
+        #[test]
+        fn zooming_to_a_selection_fits_it_into_the_window(
+            transform in transform(), corner in position(), selection_size in image_size(), size in window_size(),
+        ) {
+            let selection = Rect::from_origin_size(corner.to_point(), selection_size);
+            // Where the selected part of the image ends up
+            let selected = transform.inverse().transform_rect_bbox(selection);
+            let bounds = zoom_to(transform, selection, size).transform_rect_bbox(selected);
+            let (width, height) = (size.width as f64, size.height as f64);
+            prop_assert!((bounds.center() - Point::new(width / 2.0, height / 2.0)).hypot() <= EPSILON * (1.0 + width + height));
+            prop_assert!(bounds.width() <= width * (1.0 + EPSILON) && bounds.height() <= height * (1.0 + EPSILON));
+            prop_assert!((bounds.width() - width).abs() <= EPSILON * width || (bounds.height() - height).abs() <= EPSILON * height);
+        }
+
+        #[test]
+        fn fitted_images_fill_the_window_on_one_axis(image_size in image_size(), size in window_size(), gravity in gravity()) {
+            let transform = initial_transform(image_size, size, None, gravity);
+            let (width, height) = (size.width as f64, size.height as f64);
+            let bounds = transform.transform_rect_bbox(Rect::new(0.0, 0.0, image_size.0, image_size.1));
+            prop_assert!(bounds.x0 >= -EPSILON * width && bounds.x1 <= width * (1.0 + EPSILON));
+            prop_assert!(bounds.y0 >= -EPSILON * height && bounds.y1 <= height * (1.0 + EPSILON));
+            prop_assert!((bounds.width() - width).abs() <= EPSILON * width || (bounds.height() - height).abs() <= EPSILON * height);
+        }
+
+        #[test]
+        fn gravity_anchors_the_image(image_size in image_size(), size in window_size(), scale in factor(), gravity in gravity()) {
+            let (x, y) = gravity.anchor();
+            let anchor = placed_transform(image_size, size, scale, gravity) * Point::new(image_size.0 * x, image_size.1 * y);
+            let expected = Point::new(size.width as f64 * x, size.height as f64 * y);
+            prop_assert!((anchor - expected).hypot() <= EPSILON * (1.0 + expected.to_vec2().hypot()));
+        }
+
+        #[test]
+        fn centering_puts_the_point_in_the_middle(point in position(), size in window_size(), scale in factor()) {
+            let transform = centered_on(point.to_point(), scale, size);
+            let middle = Point::new(size.width as f64 / 2.0, size.height as f64 / 2.0);
+            prop_assert!((transform * point.to_point() - middle).hypot() <= EPSILON * (1.0 + point.hypot() * scale));
+            prop_assert!((uniform_scale(transform) - scale).abs() <= EPSILON * scale);
+        }
+
+        #[test]
+        fn locked_scale_is_kept(image_size in image_size(), size in window_size(), scale in factor(), gravity in gravity()) {
+            let transform = initial_transform(image_size, size, Some(scale), gravity);
+            prop_assert!((uniform_scale(transform) - scale).abs() <= EPSILON * scale);
+        }
+
+        #[test]
+        fn snapping_aligns_the_grid_with_the_window(transform in transform(), tile in (1.0..64.0, 1.0..64.0)) {
+            let tile = Vec2::new(tile.0, tile.1);
+            let snapped = snap_to_grid(transform, tile);
+            let origin = snapped.inverse() * Point::ORIGIN;
+            let off_grid = |value: f64, size: f64| (value / size - (value / size).round()).abs();
+            prop_assert!(off_grid(origin.x, tile.x) <= 1e-6 && off_grid(origin.y, tile.y) <= 1e-6);
+            prop_assert!(close(snap_to_grid(snapped, tile), snapped));
+        }
+
+        #[test]
+        fn windows_fit_small_images_and_the_screen(image_size in image_size(), screen in window_size(), min in 0.0..300.0) {
+            let size = window_size_for(image_size, screen, min);
+            prop_assert!(size.width <= screen.width && size.height <= screen.height);
+            let (width, height) = image_size;
+            if width <= screen.width as f64 && height <= screen.height as f64 && width.min(height) >= min {
+                prop_assert_eq!(size, PhysicalSize::new(width.round() as u32, height.round() as u32));
+            }
+        }
+
+        #[test]
+        fn integer_scales_are_whole_pixels(scale in factor()) {
+            let whole = |scale: f64| (scale - scale.round()).abs() <= EPSILON || (1.0 / scale - (1.0 / scale).round()).abs() <= EPSILON;
+            let integer = integer_scale(scale);
+            prop_assert!(whole(integer) && integer <= scale * (1.0 + EPSILON));
+            let (larger, smaller) = (next_integer_scale(scale, true), next_integer_scale(scale, false));
+            prop_assert!(whole(larger) && larger > scale && larger > integer);
+            prop_assert!(whole(smaller) && smaller < scale);
+            prop_assert!((next_integer_scale(larger, false) - integer).abs() <= EPSILON * integer);
+        }
+
+        #[test]
+        fn snapping_to_pixels_keeps_the_scale(transform in transform()) {
+            let snapped = snap_to_pixels(transform);
+            let offset = snapped * Point::ORIGIN - transform * Point::ORIGIN;
+            prop_assert!(offset.x.abs() <= 0.5 && offset.y.abs() <= 0.5);
+            prop_assert!(snapped.translation().x.fract() == 0.0 && snapped.translation().y.fract() == 0.0);
+            prop_assert_eq!(&snapped.as_coeffs()[..4], &transform.as_coeffs()[..4]);
+        }
+
+        #[test]
+        fn rescaling_keeps_the_logical_size(transform in transform(), ratio in factor()) {
+            let (x_scale, y_scale) = axis_scales(transform);
+            let (rescaled_x, rescaled_y) = axis_scales(rescale(transform, ratio));
+            prop_assert!((rescaled_x - x_scale * ratio).abs() <= EPSILON * rescaled_x);
+            prop_assert!((rescaled_y - y_scale * ratio).abs() <= EPSILON * rescaled_y);
+            prop_assert!(close(rescale(rescale(transform, ratio), 1.0 / ratio), transform));
+        }
+
+        #[test]
+        fn unstretching_evens_out_the_axes(transform in transform(), center in position()) {
+            let unstretched = unstretch(transform, center);
+            let (x_scale, y_scale) = axis_scales(unstretched);
+            prop_assert!((x_scale - y_scale).abs() <= EPSILON * x_scale);
+            prop_assert!((uniform_scale(unstretched) - uniform_scale(transform)).abs() <= EPSILON * uniform_scale(transform));
+            prop_assert!(close(unstretch(unstretched, center), unstretched));
+        }
+    }
+}
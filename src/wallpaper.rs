@@ -0,0 +1,144 @@
+//! Setting the desktop wallpaper with `veh --bg-fill|--bg-center|--bg-scale <image>`, as a drop-in
+//! replacement for feh in wallpaper scripts.
+//!
+//! On X11 the wallpaper is drawn into a pixmap that is set as background of the root window and
+//! advertised through the `_XROOTPMAP_ID`/`ESETROOT_PMAP_ID` atoms, like feh and Esetroot do, so
+//! that pseudo-transparent programs pick it up. Wayland has no such mechanism, so the work is
+//! delegated to `swaybg` there.
+
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+use image::imageops::FilterType;
+use image::{DynamicImage, RgbaImage};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    /// Scale the image to cover the whole screen, cropping what doesn't fit
+    Fill,
+    /// Show the image unscaled in the middle of the screen
+    Center,
+    /// Stretch the image to the size of the screen, ignoring its aspect ratio
+    Scale,
+}
+
+/// Set `path` as wallpaper of the current desktop
+pub fn set_wallpaper(path: &Path, mode: Mode) -> Result<()> {
+    if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+        return set_with_swaybg(path, mode);
+    }
+    let image = image::open(path).with_context(|| format!("failed to open {}", path.display()))?;
+    set_root_pixmap(image, mode)
+}
+
+/// Render `image` onto a `width`x`height` canvas according to `mode`
+fn compose(image: DynamicImage, width: u32, height: u32, mode: Mode) -> RgbaImage {
+    match mode {
+        Mode::Fill => image.resize_to_fill(width, height, FilterType::Lanczos3).into_rgba8(),
+        Mode::Scale => image.resize_exact(width, height, FilterType::Lanczos3).into_rgba8(),
+        Mode::Center => {
+            let mut canvas = RgbaImage::from_pixel(width, height, image::Rgba([0, 0, 0, 255]));
+            let x = (width as i64 - image.width() as i64) / 2;
+            let y = (height as i64 - image.height() as i64) / 2;
+            image::imageops::overlay(&mut canvas, &image.into_rgba8(), x, y);
+            canvas
+        }
+    }
+}
+
+fn set_with_swaybg(path: &Path, mode: Mode) -> Result<()> {
+    let mode = match mode {
+        Mode::Fill => "fill",
+        Mode::Center => "center",
+        Mode::Scale => "stretch",
+    };
+    // swaybg has to keep running to show the wallpaper, so replace any running instance
+    let _ = std::process::Command::new("pkill").args(["-x", "swaybg"]).status();
+    std::process::Command::new("swaybg")
+        .arg("--image")
+        .arg(path)
+        .args(["--mode", mode])
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::null())
+        .spawn()
+        .context("failed to run swaybg, which is needed to set the wallpaper on Wayland")?;
+    Ok(())
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+fn set_root_pixmap(image: DynamicImage, mode: Mode) -> Result<()> {
+    use x11rb::connection::{Connection, RequestConnection};
+    use x11rb::protocol::xproto::{
+        AtomEnum, ChangeWindowAttributesAux, CloseDown, ConnectionExt, CreateGCAux, ImageFormat,
+        ImageOrder, PropMode,
+    };
+    use x11rb::wrapper::ConnectionExt as _;
+
+    let (conn, screen_num) = x11rb::connect(None).context("failed to connect to the X server")?;
+    let setup = conn.setup();
+    let screen = &setup.roots[screen_num];
+    let (root, depth) = (screen.root, screen.root_depth);
+    let (width, height) = (screen.width_in_pixels, screen.height_in_pixels);
+
+    let bits_per_pixel = setup
+        .pixmap_formats
+        .iter()
+        .find(|format| format.depth == depth)
+        .map(|format| format.bits_per_pixel);
+    if depth < 24 || bits_per_pixel != Some(32) {
+        bail!("unsupported root window depth {depth}");
+    }
+
+    // X expects 32-bit pixels in the byte order of the server, with the unused byte first
+    let little_endian = setup.image_byte_order == ImageOrder::LSB_FIRST;
+    let canvas = compose(image, width as u32, height as u32, mode);
+    let data: Vec<u8> = canvas
+        .pixels()
+        .flat_map(|pixel| {
+            let [r, g, b, _] = pixel.0;
+            if little_endian { [b, g, r, 0] } else { [0, r, g, b] }
+        })
+        .collect();
+
+    let pixmap = conn.generate_id()?;
+    conn.create_pixmap(depth, pixmap, root, width, height)?;
+    let gc = conn.generate_id()?;
+    conn.create_gc(gc, pixmap, &CreateGCAux::new())?;
+    // Upload the image in strips of rows that fit into a single request
+    let stride = width as usize * 4;
+    let rows_per_request = (conn.maximum_request_bytes().saturating_sub(64) / stride).max(1);
+    for (i, strip) in data.chunks(stride * rows_per_request).enumerate() {
+        let y = (i * rows_per_request) as i16;
+        let rows = (strip.len() / stride) as u16;
+        conn.put_image(ImageFormat::Z_PIXMAP, pixmap, gc, width, rows, 0, y, 0, depth, strip)?;
+    }
+    conn.free_gc(gc)?;
+
+    let xrootpmap = conn.intern_atom(false, b"_XROOTPMAP_ID")?.reply()?.atom;
+    let esetroot = conn.intern_atom(false, b"ESETROOT_PMAP_ID")?.reply()?.atom;
+
+    // A previous setter may have left its pixmap alive, free it as Esetroot does
+    let old_pixmap = |atom| -> Result<Option<u32>> {
+        let reply = conn.get_property(false, root, atom, AtomEnum::PIXMAP, 0, 1)?.reply()?;
+        Ok(reply.value32().and_then(|mut values| values.next()))
+    };
+    if let (Some(old), Some(old_esetroot)) = (old_pixmap(xrootpmap)?, old_pixmap(esetroot)?) {
+        if old == old_esetroot {
+            conn.kill_client(old)?;
+        }
+    }
+
+    conn.change_property32(PropMode::REPLACE, root, xrootpmap, AtomEnum::PIXMAP, &[pixmap])?;
+    conn.change_property32(PropMode::REPLACE, root, esetroot, AtomEnum::PIXMAP, &[pixmap])?;
+    conn.change_window_attributes(root, &ChangeWindowAttributesAux::new().background_pixmap(pixmap))?;
+    conn.clear_area(false, root, 0, 0, 0, 0)?;
+    // The pixmap has to outlive veh, which exits right after
+    conn.set_close_down_mode(CloseDown::RETAIN_PERMANENT)?;
+    conn.sync()?;
+    Ok(())
+}
+
+#[cfg(not(all(unix, not(target_os = "macos"))))]
+fn set_root_pixmap(_image: DynamicImage, _mode: Mode) -> Result<()> {
+    bail!("setting the wallpaper is not supported on this platform")
+}
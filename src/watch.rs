@@ -0,0 +1,50 @@
+//! `--auto-reload`: the images shown are reloaded when they change on disk, e.g. while an SVG is edited in a text
+//! editor next to veh. Their modification times are polled, which works the same on every platform and file system.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::{Instant, SystemTime};
+
+/// How often the images are checked
+const INTERVAL: std::time::Duration = std::time::Duration::from_millis(500);
+
+pub struct Watch {
+    // When the images shown at the last check were modified
+    modified: HashMap<PathBuf, SystemTime>,
+    next_check: Instant,
+}
+
+impl Watch {
+    pub fn new() -> Self {
+        Self { modified: HashMap::new(), next_check: Instant::now() }
+    }
+
+    /// When the images should be checked again
+    pub fn next_check(&self) -> Instant {
+        self.next_check
+    }
+
+    /// Which of the images at `paths` were modified since the last check. Images that weren't shown then haven't.
+    pub fn changed<'a>(&mut self, paths: impl IntoIterator<Item = &'a Path>) -> Vec<PathBuf> {
+        self.next_check = Instant::now() + INTERVAL;
+        let mut changed = vec![];
+        let mut modified = HashMap::new();
+        for path in paths {
+            let previous = self.modified.get(path).copied();
+            let time = match std::fs::metadata(path).and_then(|metadata| metadata.modified()) {
+                Ok(time) => time,
+                // Editors that save by replacing the file leave it missing for a moment
+                Err(_) => match previous {
+                    Some(previous) => previous,
+                    None => continue,
+                },
+            };
+            if previous.is_some_and(|previous| previous != time) && !changed.iter().any(|changed| changed == path) {
+                changed.push(path.to_path_buf());
+            }
+            modified.insert(path.to_path_buf(), time);
+        }
+        self.modified = modified;
+        changed
+    }
+}
@@ -0,0 +1,58 @@
+//! Detection of GPU hangs and lost devices, so that the renderer can be recreated instead of the
+//! window freezing forever on flaky drivers.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// How long a frame may take on the GPU before the device is considered hung
+const TIMEOUT: Duration = Duration::from_secs(3);
+/// How often the device is polled while a frame is pending
+const CHECK_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Keeps track of the frames submitted to one device
+pub struct Watchdog {
+    // When the oldest unfinished frame was submitted, and whether it has finished since
+    pending: Option<(Instant, Arc<AtomicBool>)>,
+    lost: Arc<AtomicBool>,
+}
+
+impl Watchdog {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let lost = Arc::new(AtomicBool::new(false));
+        let flag = lost.clone();
+        device.set_device_lost_callback(move |_reason, message| {
+            eprintln!("veh: the GPU device was lost: {message}");
+            flag.store(true, Ordering::Relaxed);
+        });
+        Self { pending: None, lost }
+    }
+
+    /// Start timing the work just submitted to `queue`, unless an earlier frame is still being waited for
+    pub fn frame_submitted(&mut self, queue: &wgpu::Queue) {
+        if self.pending.is_some() {
+            return;
+        }
+        let done = Arc::new(AtomicBool::new(false));
+        let flag = done.clone();
+        queue.on_submitted_work_done(move || flag.store(true, Ordering::Relaxed));
+        self.pending = Some((Instant::now(), done));
+    }
+
+    /// When to check on the device next, if a frame is still pending
+    pub fn next_check(&self) -> Option<Instant> {
+        self.pending.as_ref().map(|_| Instant::now() + CHECK_INTERVAL)
+    }
+
+    /// Whether the device has been lost or a frame hasn't finished within the timeout.
+    /// The device has to be polled for finished frames to be noticed.
+    pub fn has_failed(&mut self) -> bool {
+        if let Some((_, done)) = &self.pending {
+            if done.load(Ordering::Relaxed) {
+                self.pending = None;
+            }
+        }
+        let hung = self.pending.as_ref().is_some_and(|(submitted, _)| submitted.elapsed() > TIMEOUT);
+        hung || self.lost.load(Ordering::Relaxed)
+    }
+}